@@ -0,0 +1,612 @@
+use tokio::prelude::{future, Future};
+
+use crate::error::{
+    DdnsError, DdnsFuture, RequestError, RequestResult, ResponseError, StatusError,
+};
+use crate::request::{decode_response, make_request_future};
+
+/// Credentials a registrar request is authenticated with.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegistrarAuth {
+    /// HTTP basic authentication.
+    Basic { username: String, password: String },
+    /// A static bearer token.
+    Bearer { token: String },
+    /// A bearer token obtained with the OAuth2 client credentials grant, cached
+    /// until shortly before it expires.
+    Oauth2ClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+        audience: Option<String>,
+    },
+    /// Requests signed with AWS Signature Version 4.
+    Sigv4 {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+        region: String,
+        service: String,
+    },
+}
+
+/// Credentials requests to the DDNS service are authenticated with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServiceAuth {
+    /// A static bearer token.
+    Bearer(String),
+    /// A per-client API key, sent in the `X-API-Key` header.
+    ApiKey(String),
+    /// Requests signed with HMAC-SHA256, using a per-client key when `key_id`
+    /// is set, or the service's shared secret otherwise.
+    Hmac {
+        key_id: Option<String>,
+        secret: String,
+    },
+}
+
+impl ServiceAuth {
+    pub(crate) fn to_request_auth(&self) -> RequestAuth {
+        match self {
+            ServiceAuth::Bearer(token) => RequestAuth::Bearer(token.clone()),
+            ServiceAuth::ApiKey(key) => RequestAuth::ApiKey(key.clone()),
+            ServiceAuth::Hmac { key_id, secret } => RequestAuth::Hmac {
+                key_id: key_id.clone(),
+                secret: secret.clone(),
+            },
+        }
+    }
+}
+
+/// Credentials resolved for a single request.
+#[derive(Clone, Debug)]
+pub(crate) enum RequestAuth {
+    Basic(String, String),
+    Bearer(String),
+    ApiKey(String),
+    Hmac {
+        key_id: Option<String>,
+        secret: String,
+    },
+    Sigv4 {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+        region: String,
+        service: String,
+    },
+}
+
+type TokenKey = (String, String, Option<String>, Option<String>);
+
+/// OAuth2 access tokens, by token URL, client ID, scope and audience.
+#[derive(Debug, Default)]
+pub(crate) struct TokenCache {
+    tokens: std::sync::Mutex<std::collections::HashMap<TokenKey, (String, std::time::Instant)>>,
+}
+
+impl TokenCache {
+    fn get(&self, key: &TokenKey) -> Option<String> {
+        let now = std::time::Instant::now();
+        self.tokens
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|(_, refresh_at)| *refresh_at > now)
+            .map(|(token, _)| token.to_owned())
+    }
+
+    /// Caches a token until shortly before it expires, so that it is refreshed
+    /// before the registrar starts rejecting it.
+    fn insert(&self, key: TokenKey, token: String, expires_in: std::time::Duration) {
+        let margin = std::cmp::min(std::time::Duration::from_secs(60), expires_in / 2);
+        let refresh_at = std::time::Instant::now() + (expires_in - margin);
+        self.tokens.lock().unwrap().insert(key, (token, refresh_at));
+    }
+
+    /// Forgets the cached token for the given credentials, if any.
+    pub(crate) fn invalidate(&self, auth: &RegistrarAuth) {
+        if let Some(key) = get_token_key(auth) {
+            self.tokens.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+fn get_token_key(auth: &RegistrarAuth) -> Option<TokenKey> {
+    match auth {
+        RegistrarAuth::Oauth2ClientCredentials {
+            token_url,
+            client_id,
+            scope,
+            audience,
+            ..
+        } => Some((
+            token_url.to_owned(),
+            client_id.to_owned(),
+            scope.to_owned(),
+            audience.to_owned(),
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+pub(crate) fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(decoded.as_slice()).to_string()
+}
+
+/// Requests an access token from an OAuth2 token endpoint with the client
+/// credentials grant, authenticating the client with HTTP basic authentication.
+fn make_token_future(
+    token_url: &str,
+    client_id: &str,
+    client_secret: &str,
+    scope: Option<&str>,
+    audience: Option<&str>,
+) -> impl DdnsFuture<TokenResponse> {
+    log::trace!(
+        "fn make_token_future(token_url={:?}, client_id={:?}, scope={:?}, audience={:?})",
+        token_url,
+        client_id,
+        scope,
+        audience,
+    );
+
+    let mut body = "grant_type=client_credentials".to_owned();
+    if let Some(scope) = scope {
+        body.push_str(format!("&scope={}", percent_encode(scope)).as_str());
+    }
+    if let Some(audience) = audience {
+        body.push_str(format!("&audience={}", percent_encode(audience)).as_str());
+    }
+    let auth = RequestAuth::Basic(percent_encode(client_id), percent_encode(client_secret));
+    make_request_future(
+        token_url,
+        "POST",
+        &[
+            (
+                "Content-Type".to_owned(),
+                "application/x-www-form-urlencoded".to_owned(),
+            ),
+            ("Accept".to_owned(), "application/json".to_owned()),
+        ],
+        body,
+        Some(auth),
+        None,
+    )
+    .and_then(|response| {
+        let status_code = response.status().as_u16();
+        decode_response(response).and_then(move |response_bytes| {
+            if !(200..300).contains(&status_code) {
+                log::warn!("Failed to obtain an OAuth2 access token");
+                Err(DdnsError::ResponseError(ResponseError::StatusError(
+                    StatusError::new(
+                        status_code,
+                        String::from_utf8_lossy(response_bytes.as_ref()).to_string(),
+                        None,
+                    ),
+                )))
+            } else {
+                serde_json::from_slice::<TokenResponse>(response_bytes.as_ref())
+                    .map_err(|error| DdnsError::ResponseError(ResponseError::SerdeJsonError(error)))
+            }
+        })
+    })
+}
+
+/// Resolves the credentials for a request, obtaining an OAuth2 access token if
+/// none is cached.
+pub(crate) fn make_request_auth_future(
+    auth: RegistrarAuth,
+    tokens: std::sync::Arc<TokenCache>,
+) -> impl DdnsFuture<RequestAuth> {
+    match auth {
+        RegistrarAuth::Basic { username, password } => {
+            future::Either::A(future::ok(RequestAuth::Basic(username, password)))
+        }
+        RegistrarAuth::Bearer { token } => {
+            future::Either::A(future::ok(RequestAuth::Bearer(token)))
+        }
+        RegistrarAuth::Sigv4 {
+            access_key_id,
+            secret_access_key,
+            session_token,
+            region,
+            service,
+        } => future::Either::A(future::ok(RequestAuth::Sigv4 {
+            access_key_id,
+            secret_access_key,
+            session_token,
+            region,
+            service,
+        })),
+        RegistrarAuth::Oauth2ClientCredentials {
+            ref token_url,
+            ref client_id,
+            ref client_secret,
+            ref scope,
+            ref audience,
+        } => {
+            let key = get_token_key(&auth).unwrap();
+            match tokens.get(&key) {
+                Some(token) => future::Either::A(future::ok(RequestAuth::Bearer(token))),
+                None => future::Either::B(
+                    make_token_future(
+                        token_url.as_str(),
+                        client_id.as_str(),
+                        client_secret.as_str(),
+                        scope.as_deref(),
+                        audience.as_deref(),
+                    )
+                    .map(move |response| {
+                        log::debug!("Obtained an OAuth2 access token from {}", key.0);
+                        let expires_in =
+                            std::time::Duration::from_secs(response.expires_in.unwrap_or(3600));
+                        tokens.insert(key, response.access_token.clone(), expires_in);
+                        RequestAuth::Bearer(response.access_token)
+                    }),
+                ),
+            }
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> RequestResult<Vec<u8>> {
+    let key = openssl::pkey::PKey::hmac(key).map_err(RequestError::SigningError)?;
+    let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)
+        .map_err(RequestError::SigningError)?;
+    signer.update(data).map_err(RequestError::SigningError)?;
+    signer.sign_to_vec().map_err(RequestError::SigningError)
+}
+
+/// Formats a time as the `YYYYMMDD` date and `YYYYMMDD'T'HHMMSS'Z'` timestamp
+/// used by AWS Signature Version 4.
+fn format_amz_date(time: std::time::SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // Converts days since the epoch to a civil date, per Howard Hinnant's
+    // `civil_from_days` algorithm.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let timestamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+    );
+    (date, timestamp)
+}
+
+/// Adds AWS Signature Version 4 headers to a request.
+#[allow(clippy::too_many_arguments)]
+fn sign_sigv4(
+    request: &mut hyper::Request<hyper::Body>,
+    body: &[u8],
+    access_key_id: &str,
+    secret_access_key: &str,
+    session_token: Option<&str>,
+    region: &str,
+    service: &str,
+    time: std::time::SystemTime,
+) -> RequestResult<()> {
+    let (date, timestamp) = format_amz_date(time);
+    let payload_hash = to_hex(&openssl::sha::sha256(body));
+
+    let uri = request.uri().clone();
+    let host = uri
+        .authority_part()
+        .map(|authority| authority.as_str().to_owned())
+        .unwrap_or_default();
+    let headers = request.headers_mut();
+    let mut insert_header = |name: &'static str, value: &str| -> RequestResult<()> {
+        let value = hyper::header::HeaderValue::from_str(value)
+            .map_err(|error| RequestError::HttpError(error.into()))?;
+        headers.insert(name, value);
+        Ok(())
+    };
+    insert_header("host", host.as_str())?;
+    insert_header("x-amz-date", timestamp.as_str())?;
+    // Only S3 requires the payload hash as a header; other services take it
+    // from the canonical request alone.
+    if service == "s3" {
+        insert_header("x-amz-content-sha256", payload_hash.as_str())?;
+    }
+    if let Some(session_token) = session_token {
+        insert_header("x-amz-security-token", session_token)?;
+    }
+
+    let canonical_uri = match uri.path() {
+        "" => "/".to_owned(),
+        path => path
+            .split('/')
+            .map(percent_encode)
+            .collect::<Vec<_>>()
+            .join("/"),
+    };
+    let mut query = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                percent_encode(percent_decode(key).as_str()),
+                percent_encode(percent_decode(value).as_str()),
+            )
+        })
+        .collect::<Vec<_>>();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut signed_headers = std::collections::BTreeMap::new();
+    for (name, value) in request.headers() {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        signed_headers
+            .entry(name.as_str().to_owned())
+            .and_modify(|values: &mut String| {
+                values.push(',');
+                values.push_str(value.as_str());
+            })
+            .or_insert(value);
+    }
+    let canonical_headers: String = signed_headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_header_names = signed_headers.keys().cloned().collect::<Vec<_>>().join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method().as_str(),
+        canonical_uri,
+        canonical_query,
+        canonical_headers,
+        signed_header_names,
+        payload_hash,
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        to_hex(&openssl::sha::sha256(canonical_request.as_bytes())),
+    );
+
+    let mut key = format!("AWS4{}", secret_access_key).into_bytes();
+    for part in &[date.as_str(), region, service, "aws4_request"] {
+        key = hmac_sha256(key.as_slice(), part.as_bytes())?;
+    }
+    let signature = to_hex(hmac_sha256(key.as_slice(), string_to_sign.as_bytes())?.as_slice());
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id, scope, signed_header_names, signature,
+    );
+    let authorization = hyper::header::HeaderValue::from_str(authorization.as_str())
+        .map_err(|error| RequestError::HttpError(error.into()))?;
+    request
+        .headers_mut()
+        .insert(hyper::header::AUTHORIZATION, authorization);
+    Ok(())
+}
+
+/// Authenticates a request with the given credentials. `body` must be the
+/// request's body, which signed requests are signed over.
+pub(crate) fn authorize_request(
+    request: &mut hyper::Request<hyper::Body>,
+    body: &[u8],
+    auth: &RequestAuth,
+) -> RequestResult<()> {
+    let authorization = match auth {
+        RequestAuth::Basic(username, password) => format!(
+            "Basic {}",
+            openssl::base64::encode_block(format!("{}:{}", username, password).as_bytes()),
+        ),
+        RequestAuth::Bearer(token) => format!("Bearer {}", token),
+        RequestAuth::ApiKey(key) => {
+            let key = hyper::header::HeaderValue::from_str(key.as_str())
+                .map_err(|error| RequestError::HttpError(error.into()))?;
+            request
+                .headers_mut()
+                .insert(ddns_common::auth::API_KEY_HEADER, key);
+            return Ok(());
+        }
+        RequestAuth::Hmac { key_id, secret } => {
+            let path_and_query = request
+                .uri()
+                .path_and_query()
+                .map(|path_and_query| path_and_query.as_str())
+                .unwrap_or("/");
+            ddns_common::auth::make_hmac_authorization(
+                key_id.as_deref(),
+                secret.as_str(),
+                request.method().as_str(),
+                path_and_query,
+            )
+            .map_err(RequestError::SigningError)?
+        }
+        RequestAuth::Sigv4 {
+            access_key_id,
+            secret_access_key,
+            session_token,
+            region,
+            service,
+        } => {
+            return sign_sigv4(
+                request,
+                body,
+                access_key_id.as_str(),
+                secret_access_key.as_str(),
+                session_token.as_deref(),
+                region.as_str(),
+                service.as_str(),
+                std::time::SystemTime::now(),
+            );
+        }
+    };
+    let authorization = hyper::header::HeaderValue::from_str(authorization.as_str())
+        .map_err(|error| RequestError::HttpError(error.into()))?;
+    request
+        .headers_mut()
+        .insert(hyper::header::AUTHORIZATION, authorization);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signs a request of the AWS Signature Version 4 test suite, whose requests
+    /// are all made at 2015-08-30T12:36:00Z with the same example credentials.
+    fn sign_suite_request(
+        method: &str,
+        path_and_query: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> String {
+        let mut request = hyper::Request::builder();
+        request
+            .method(method)
+            .uri(format!("https://example.amazonaws.com{}", path_and_query));
+        for (name, value) in headers {
+            request.header(*name, *value);
+        }
+        let mut request = request.body(hyper::Body::from(body.to_owned())).unwrap();
+        sign_sigv4(
+            &mut request,
+            body.as_bytes(),
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            None,
+            "us-east-1",
+            "service",
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_440_938_160),
+        )
+        .unwrap();
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+        request.headers()[hyper::header::AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn suite_authorization(signed_headers: &str, signature: &str) -> String {
+        format!(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders={}, Signature={}",
+            signed_headers, signature,
+        )
+    }
+
+    #[test]
+    fn signs_get_vanilla() {
+        assert_eq!(
+            sign_suite_request("GET", "/", &[], ""),
+            suite_authorization(
+                "host;x-amz-date",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            ),
+        );
+    }
+
+    #[test]
+    fn signs_get_vanilla_query_order_key_case() {
+        assert_eq!(
+            sign_suite_request("GET", "/?Param2=value2&Param1=value1", &[], ""),
+            suite_authorization(
+                "host;x-amz-date",
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500",
+            ),
+        );
+    }
+
+    #[test]
+    fn signs_post_x_www_form_urlencoded() {
+        assert_eq!(
+            sign_suite_request(
+                "POST",
+                "/",
+                &[("Content-Type", "application/x-www-form-urlencoded")],
+                "Param1=value1",
+            ),
+            suite_authorization(
+                "content-type;host;x-amz-date",
+                "ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a",
+            ),
+        );
+    }
+
+    #[test]
+    fn formats_amz_dates() {
+        assert_eq!(
+            format_amz_date(std::time::UNIX_EPOCH + std::time::Duration::from_secs(951_825_600)),
+            ("20000229".to_owned(), "20000229T120000Z".to_owned()),
+        );
+    }
+}
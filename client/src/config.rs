@@ -0,0 +1,72 @@
+use crate::auth::ServiceAuth;
+use crate::hooks::Hooks;
+use crate::notify::NotifierConfig;
+use crate::provider::ProviderConfig;
+use crate::request::ClientCertificate;
+use crate::scheduler::RegistrarLimits;
+use crate::stun::StunFamily;
+use crate::update::ChangeDamping;
+use ddns_common::signing::ResponseVerifier;
+
+/// Configuration of a `Ddns` client.
+#[derive(Debug)]
+pub struct Config {
+    /// Time interval (in seconds) between address checks.
+    pub update_interval_secs: u64,
+    /// URL of the DDNS service used by `Ddns::from_config`, unless STUN servers
+    /// are configured.
+    pub service_address: String,
+    /// Credentials presented to the DDNS service, if it requires
+    /// authentication.
+    pub service_auth: Option<ServiceAuth>,
+    /// Client certificate presented to the DDNS service, if it requires mutual
+    /// TLS.
+    pub service_client_certificate: Option<ClientCertificate>,
+    /// Verifier of the DDNS service's response signatures, if responses must
+    /// be signed.
+    pub service_verifier: Option<ResponseVerifier>,
+    /// STUN servers used by `Ddns::from_config` instead of the DDNS service, as
+    /// `host` or `host:port`.
+    pub stun_servers: Vec<String>,
+    /// Number of STUN servers that must agree on the address, if not a
+    /// majority.
+    pub stun_quorum: Option<usize>,
+    /// Address family STUN servers are asked over.
+    pub stun_family: StunFamily,
+    /// IP address already registered with the registrar, if known.
+    pub initial_address: Option<String>,
+    /// Providers used by `Ddns::from_config`.
+    pub provider_configs: Vec<ProviderConfig>,
+    /// Socket address to serve the status API on, if any.
+    pub status_socket_address: Option<std::net::SocketAddr>,
+    pub notifier_configs: Vec<NotifierConfig>,
+    /// Default minimum time interval (in seconds) between repeated notifications.
+    pub notification_interval_secs: u64,
+    pub hooks: Hooks,
+    pub damping: ChangeDamping,
+    /// Limits used by `Ddns::from_config`.
+    pub registrar_limits: RegistrarLimits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            update_interval_secs: 60,
+            service_address: "http://0.0.0.0:3000".to_owned(),
+            service_auth: None,
+            service_client_certificate: None,
+            service_verifier: None,
+            stun_servers: Vec::new(),
+            stun_quorum: None,
+            stun_family: StunFamily::Any,
+            initial_address: None,
+            provider_configs: Vec::new(),
+            status_socket_address: None,
+            notifier_configs: Vec::new(),
+            notification_interval_secs: 300,
+            hooks: Hooks::default(),
+            damping: ChangeDamping::default(),
+            registrar_limits: RegistrarLimits::default(),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use tokio::prelude::{Future, Stream};
+
+#[derive(Debug, new)]
+pub struct TemplateError {
+    pub template: String,
+    pub variables: std::collections::HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    TemplateError(TemplateError),
+    SerdeJsonError(serde_json::Error),
+    FormatError,
+    JsonPathError(String),
+    RegexError(regex::Error),
+    StepReferenceError(String),
+    SigningError(openssl::error::ErrorStack),
+    CertificateError(std::path::PathBuf, std::io::Error),
+    HttpError(http::Error),
+    HyperError(hyper::Error),
+    HyperTlsError(hyper_tls::Error),
+}
+
+pub type RequestResult<T> = Result<T, RequestError>;
+
+#[derive(Debug, new)]
+pub struct StatusError {
+    pub status: u16,
+    pub body: String,
+    /// Why the response was rejected, preferring the registrar's own error
+    /// message where it can be extracted.
+    pub message: Option<String>,
+}
+
+/// An error response of an address service, as decoded from its error
+/// envelope.
+#[derive(Debug, new)]
+pub struct ServiceError {
+    pub status: u16,
+    /// Error code as on the wire, empty for services that predate codes.
+    pub code: String,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub enum ResponseError {
+    HyperError(hyper::Error),
+    SerdeJsonError(serde_json::Error),
+    StatusError(StatusError),
+    SignatureError(String),
+    /// The service rejected the credentials of the client.
+    UnauthorizedError(ServiceError),
+    /// The service does not answer the address of the client.
+    ForbiddenError(ServiceError),
+    /// The service asked the client to slow down, and to retry after the given
+    /// number of seconds if it said.
+    RateLimitedError(ServiceError, Option<u64>),
+    /// The service has no address for the client, in the requested family or
+    /// at all.
+    AddressUnavailableError(ServiceError),
+    /// The service found several addresses, listed, and could not choose one.
+    AmbiguousAddressError(ServiceError, Vec<String>),
+    /// Any other error response of the service.
+    ServiceError(ServiceError),
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    IoError(std::io::Error),
+    TimeoutError,
+    StatusError(std::process::ExitStatus, String, String),
+}
+
+#[derive(Debug)]
+pub enum SmtpError {
+    IoError(std::io::Error),
+    ResolveError(String),
+    ReplyError(String, String),
+}
+
+#[derive(Debug)]
+pub enum StunError {
+    IoError(std::io::Error),
+    ResolveError(String),
+    /// The server did not answer any transmission of the request.
+    TimeoutError(String),
+    /// The server answered with a malformed response.
+    InvalidResponseError(String, String),
+    /// The server answered with an error response, with its code and reason.
+    BindingError(String, u16, String),
+    /// Fewer servers than the quorum agreed on an address. Holds the quorum and
+    /// the address each server that answered reported.
+    ConsensusError(usize, Vec<(String, std::net::IpAddr)>),
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    UnknownProviderError(String),
+    OptionError(String, String),
+    DuplicateRecordError(String),
+    UnknownRecordError(String),
+    UnsupportedError(String, String),
+    InvalidResponseError(String, String),
+    SyncTimeoutError(String, String),
+}
+
+#[derive(Debug)]
+pub enum DdnsError {
+    IntervalError(tokio::timer::Error),
+    DelayError(tokio::timer::Error),
+    TriggerError(tokio::sync::mpsc::error::UnboundedRecvError),
+    RequestError(RequestError),
+    ResponseError(ResponseError),
+    CommandError(CommandError),
+    SmtpError(SmtpError),
+    StunError(StunError),
+    ProviderError(ProviderError),
+}
+
+pub(crate) trait DdnsStream<T> = Stream<Item = T, Error = DdnsError>;
+
+pub(crate) trait DdnsFuture<T> = Future<Item = T, Error = DdnsError>;
+
+pub type DdnsResult<T> = Result<T, DdnsError>;
+
+pub type DdnsBoxFuture<T> = Box<dyn Future<Item = T, Error = DdnsError> + Send>;
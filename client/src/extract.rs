@@ -0,0 +1,477 @@
+use crate::error::{RequestError, RequestResult};
+
+/// A single step of a JSONPath expression.
+#[derive(Clone, Debug, PartialEq)]
+enum JsonPathSegment {
+    /// `.name` or `['name']`
+    Member(String),
+    /// `[0]`, or `[-1]` counting from the end
+    Index(i64),
+    /// `.*` or `[*]`
+    Wildcard,
+    /// `..name`
+    Descendant(String),
+    /// `[?(@.name == 'value')]` or `[?(@.name != 'value')]`
+    Filter(Vec<String>, bool, serde_json::Value),
+}
+
+/// A parsed JSONPath expression. The supported subset covers member and index
+/// access, wildcards, recursive descent by member name, and equality filters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonPath {
+    expression: String,
+    segments: Vec<JsonPathSegment>,
+}
+
+fn is_member_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn parse_member(chars: &[char], mut index: usize) -> Option<(String, usize)> {
+    let start = index;
+    while index < chars.len() && is_member_char(chars[index]) {
+        index += 1;
+    }
+    if index == start {
+        None
+    } else {
+        Some((chars[start..index].iter().collect(), index))
+    }
+}
+
+fn parse_quoted(chars: &[char], index: usize) -> Option<(String, usize)> {
+    let quote = *chars.get(index).filter(|c| **c == '\'' || **c == '"')?;
+    let end = index + 1 + chars[index + 1..].iter().position(|c| *c == quote)?;
+    Some((chars[index + 1..end].iter().collect(), end + 1))
+}
+
+fn skip_whitespace(chars: &[char], mut index: usize) -> usize {
+    while chars.get(index).is_some_and(|c| c.is_whitespace()) {
+        index += 1;
+    }
+    index
+}
+
+/// Parses a filter, from its opening parenthesis to the bracket closing it.
+/// The literal is read before the filter's end is looked for, so that quoted
+/// literals may contain operators and closing brackets.
+fn parse_filter(chars: &[char], index: usize) -> Option<(JsonPathSegment, usize)> {
+    let index = skip_whitespace(chars, index);
+    let mut index = (chars.get(index) == Some(&'(')).then_some(index + 1)?;
+    index = skip_whitespace(chars, index);
+    index = (chars.get(index) == Some(&'@')).then_some(index + 1)?;
+
+    let mut keys = Vec::new();
+    while chars.get(index) == Some(&'.') {
+        let (key, next) = parse_member(chars, index + 1)?;
+        keys.push(key);
+        index = next;
+    }
+
+    index = skip_whitespace(chars, index);
+    let equal = match (chars.get(index)?, chars.get(index + 1)?) {
+        ('=', '=') => true,
+        ('!', '=') => false,
+        _ => return None,
+    };
+    index = skip_whitespace(chars, index + 2);
+
+    let (value, index) = match parse_quoted(chars, index) {
+        Some((string, next)) => (serde_json::Value::String(string), next),
+        None => {
+            let end = index + chars[index..].iter().position(|c| *c == ')')?;
+            let literal: String = chars[index..end].iter().collect();
+            (serde_json::from_str(literal.trim()).ok()?, end)
+        }
+    };
+
+    let index = skip_whitespace(chars, index);
+    match (chars.get(index)?, chars.get(index + 1)?) {
+        (')', ']') => Some((JsonPathSegment::Filter(keys, equal, value), index + 2)),
+        _ => None,
+    }
+}
+
+fn parse_bracket(chars: &[char], index: usize) -> Option<(JsonPathSegment, usize)> {
+    let close = |index: usize| (chars.get(index) == Some(&']')).then_some(index + 1);
+    match chars.get(index)? {
+        '*' => Some((JsonPathSegment::Wildcard, close(index + 1)?)),
+        '\'' | '"' => {
+            let (member, index) = parse_quoted(chars, index)?;
+            Some((JsonPathSegment::Member(member), close(index)?))
+        }
+        '?' => parse_filter(chars, index + 1),
+        _ => {
+            let end = index + chars[index..].iter().position(|c| *c == ']')?;
+            let number: String = chars[index..end].iter().collect();
+            let number = number.trim().parse::<i64>().ok()?;
+            Some((JsonPathSegment::Index(number), end + 1))
+        }
+    }
+}
+
+impl JsonPath {
+    /// Parses a JSONPath expression, which must start at the root (`$`).
+    pub fn parse(expression: &str) -> Option<Self> {
+        let chars: Vec<char> = expression.trim().chars().collect();
+        if chars.first() != Some(&'$') {
+            return None;
+        }
+
+        let mut segments = Vec::new();
+        let mut index = 1;
+        while index < chars.len() {
+            let (segment, next) = match (chars[index], chars.get(index + 1)) {
+                ('.', Some('.')) => {
+                    let (member, next) = parse_member(&chars, index + 2)?;
+                    (JsonPathSegment::Descendant(member), next)
+                }
+                ('.', Some('*')) => (JsonPathSegment::Wildcard, index + 2),
+                ('.', _) => {
+                    let (member, next) = parse_member(&chars, index + 1)?;
+                    (JsonPathSegment::Member(member), next)
+                }
+                ('[', _) => parse_bracket(&chars, index + 1)?,
+                _ => return None,
+            };
+            segments.push(segment);
+            index = next;
+        }
+
+        Some(JsonPath {
+            expression: expression.to_owned(),
+            segments,
+        })
+    }
+
+    /// Returns every value in `document` matched by the expression, in document
+    /// order.
+    pub fn find<'a>(&self, document: &'a serde_json::Value) -> Vec<&'a serde_json::Value> {
+        self.segments
+            .iter()
+            .fold(vec![document], |values, segment| {
+                values
+                    .into_iter()
+                    .flat_map(|value| find_segment(segment, value))
+                    .collect()
+            })
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+fn get_children(value: &serde_json::Value) -> Vec<&serde_json::Value> {
+    match value {
+        serde_json::Value::Array(array) => array.iter().collect(),
+        serde_json::Value::Object(object) => object.values().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn find_descendants<'a>(
+    name: &str,
+    value: &'a serde_json::Value,
+    found: &mut Vec<&'a serde_json::Value>,
+) {
+    if let Some(member) = value.as_object().and_then(|object| object.get(name)) {
+        found.push(member);
+    }
+    get_children(value)
+        .into_iter()
+        .for_each(|child| find_descendants(name, child, found));
+}
+
+fn find_segment<'a>(
+    segment: &JsonPathSegment,
+    value: &'a serde_json::Value,
+) -> Vec<&'a serde_json::Value> {
+    match segment {
+        JsonPathSegment::Member(name) => value.get(name.as_str()).into_iter().collect(),
+        JsonPathSegment::Index(index) => value
+            .as_array()
+            .and_then(|array| {
+                let index = if *index < 0 {
+                    array.len() as i64 + index
+                } else {
+                    *index
+                };
+                Some(index)
+                    .filter(|index| *index >= 0)
+                    .and_then(|index| array.get(index as usize))
+            })
+            .into_iter()
+            .collect(),
+        JsonPathSegment::Wildcard => get_children(value),
+        JsonPathSegment::Descendant(name) => {
+            let mut found = Vec::new();
+            find_descendants(name.as_str(), value, &mut found);
+            found
+        }
+        JsonPathSegment::Filter(keys, equal, expected) => get_children(value)
+            .into_iter()
+            .filter(|child| {
+                let actual = keys
+                    .iter()
+                    .try_fold(*child, |value, key| value.get(key.as_str()));
+                (actual == Some(expected)) == *equal
+            })
+            .collect(),
+    }
+}
+
+/// Converts a JSON value to the text it represents. Strings are unquoted, and
+/// other values are JSON-encoded.
+fn json_value_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(string) => string.to_owned(),
+        value => value.to_string(),
+    }
+}
+
+/// Extracts a value from the body of a registrar response.
+#[derive(Clone, Debug)]
+pub enum ValueExtractor {
+    /// The first value matched by a JSONPath expression in a JSON body.
+    JsonPath(JsonPath),
+    /// The first capture group of the first match of a regular expression, or
+    /// the entire match if it has no capture groups.
+    Regex(regex::Regex),
+}
+
+impl ValueExtractor {
+    /// Makes an extractor from the `json_path` or `regex` property of the given
+    /// object, if either is present.
+    pub(crate) fn from_json(object: &serde_json::Value) -> RequestResult<Option<Self>> {
+        if let Some(expression) = object.get("json_path") {
+            let expression = expression.as_str().ok_or(RequestError::FormatError)?;
+            JsonPath::parse(expression)
+                .map(|json_path| Some(ValueExtractor::JsonPath(json_path)))
+                .ok_or_else(|| RequestError::JsonPathError(expression.to_owned()))
+        } else if let Some(expression) = object.get("regex") {
+            let expression = expression.as_str().ok_or(RequestError::FormatError)?;
+            regex::Regex::new(expression)
+                .map(|regex| Some(ValueExtractor::Regex(regex)))
+                .map_err(RequestError::RegexError)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the extracted value, or `None` if the body does not contain it.
+    pub fn extract(&self, body: &str) -> Option<String> {
+        match self {
+            ValueExtractor::JsonPath(json_path) => {
+                serde_json::from_str(body)
+                    .ok()
+                    .and_then(|document: serde_json::Value| {
+                        json_path
+                            .find(&document)
+                            .first()
+                            .map(|value| json_value_to_string(value))
+                    })
+            }
+            ValueExtractor::Regex(regex) => regex.captures(body).map(|captures| {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|capture| capture.as_str().to_owned())
+                    .unwrap_or_default()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(expression: &str, document: &serde_json::Value) -> Vec<serde_json::Value> {
+        JsonPath::parse(expression)
+            .unwrap_or_else(|| panic!("Failed to parse {}", expression))
+            .find(document)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    fn document() -> serde_json::Value {
+        serde_json::json!({
+            "result": {
+                "records": [
+                    { "name": "www", "id": "1", "ttl": 300, "meta": { "locked": false } },
+                    { "name": "a!=b", "id": "2", "ttl": 60 },
+                    { "name": "x)]", "id": "3", "ttl": 60 },
+                ],
+            },
+            "count": 3,
+        })
+    }
+
+    #[test]
+    fn finds_members() {
+        let document = document();
+        assert_eq!(find("$.count", &document), vec![serde_json::json!(3)]);
+        assert_eq!(
+            find("$['result'][\"records\"][0].name", &document),
+            vec![serde_json::json!("www")]
+        );
+        assert!(find("$.missing", &document).is_empty());
+        assert_eq!(find("$", &document), vec![document.clone()]);
+    }
+
+    #[test]
+    fn finds_indices() {
+        let document = document();
+        assert_eq!(
+            find("$.result.records[1].id", &document),
+            vec![serde_json::json!("2")]
+        );
+        assert_eq!(
+            find("$.result.records[-1].id", &document),
+            vec![serde_json::json!("3")]
+        );
+        assert_eq!(
+            find("$.result.records[ -3 ].id", &document),
+            vec![serde_json::json!("1")]
+        );
+        assert!(find("$.result.records[3]", &document).is_empty());
+        assert!(find("$.result.records[-4]", &document).is_empty());
+        assert!(find("$.count[0]", &document).is_empty());
+    }
+
+    #[test]
+    fn finds_wildcards() {
+        let document = document();
+        assert_eq!(
+            find("$.result.records[*].id", &document),
+            vec![
+                serde_json::json!("1"),
+                serde_json::json!("2"),
+                serde_json::json!("3"),
+            ]
+        );
+        assert_eq!(
+            find("$.result.records.*.ttl", &document),
+            vec![
+                serde_json::json!(300),
+                serde_json::json!(60),
+                serde_json::json!(60),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_descendants() {
+        let document = document();
+        assert_eq!(find("$..locked", &document), vec![serde_json::json!(false)]);
+        assert_eq!(find("$..id", &document).len(), 3);
+        assert_eq!(
+            find("$.result..name", &document)[0],
+            serde_json::json!("www")
+        );
+    }
+
+    #[test]
+    fn finds_filtered_values() {
+        let document = document();
+        assert_eq!(
+            find("$.result.records[?(@.name == 'www')].id", &document),
+            vec![serde_json::json!("1")]
+        );
+        assert_eq!(
+            find("$.result.records[?(@.ttl==60)].id", &document),
+            vec![serde_json::json!("2"), serde_json::json!("3")]
+        );
+        assert_eq!(
+            find("$.result.records[?(@.ttl != 60)].id", &document),
+            vec![serde_json::json!("1")]
+        );
+        assert_eq!(
+            find("$.result.records[?(@.meta.locked == false)].id", &document),
+            vec![serde_json::json!("1")]
+        );
+        assert_eq!(
+            find("$.result.records[?(@.name==\"www\")].ttl", &document),
+            vec![serde_json::json!(300)]
+        );
+    }
+
+    #[test]
+    fn finds_filtered_values_with_operators_in_literals() {
+        let document = document();
+        assert_eq!(
+            find("$.result.records[?(@.name=='a!=b')].id", &document),
+            vec![serde_json::json!("2")]
+        );
+        assert_eq!(
+            find("$.result.records[?(@.name=='x)]')].id", &document),
+            vec![serde_json::json!("3")]
+        );
+        assert_eq!(
+            find("$.result.records[?(@.name!='a!=b')].id", &document),
+            vec![serde_json::json!("1"), serde_json::json!("3")]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in &[
+            "",
+            "result",
+            "$.",
+            "$[",
+            "$[1",
+            "$[one]",
+            "$['name'",
+            "$[?(@.name)]",
+            "$[?(@.name == 'www')",
+            "$[?(@.name == 'www)]",
+            "$[?(name == 'www')]",
+            "$[?(@.name == www)]",
+        ] {
+            assert_eq!(JsonPath::parse(expression), None, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn extracts_strings_and_json() {
+        let extractor =
+            |object: serde_json::Value| ValueExtractor::from_json(&object).unwrap().unwrap();
+        let body = document().to_string();
+        assert_eq!(
+            extractor(serde_json::json!({ "json_path": "$.result.records[0].name" }))
+                .extract(body.as_str()),
+            Some("www".to_owned())
+        );
+        assert_eq!(
+            extractor(serde_json::json!({ "json_path": "$.result.records[0].meta" }))
+                .extract(body.as_str()),
+            Some("{\"locked\":false}".to_owned())
+        );
+        assert_eq!(
+            extractor(serde_json::json!({ "json_path": "$.count" })).extract("not json"),
+            None
+        );
+        assert_eq!(
+            extractor(serde_json::json!({ "regex": "id=(\\d+)" })).extract("name=www id=42"),
+            Some("42".to_owned())
+        );
+        assert_eq!(
+            extractor(serde_json::json!({ "regex": "good" })).extract("good 192.0.2.1"),
+            Some("good".to_owned())
+        );
+        assert_eq!(
+            extractor(serde_json::json!({ "regex": "good" })).extract("badauth"),
+            None
+        );
+        assert!(ValueExtractor::from_json(&serde_json::json!({}))
+            .unwrap()
+            .is_none());
+        assert!(ValueExtractor::from_json(&serde_json::json!({ "json_path": "$[" })).is_err());
+        assert!(ValueExtractor::from_json(&serde_json::json!({ "regex": "(" })).is_err());
+    }
+}
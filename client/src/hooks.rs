@@ -0,0 +1,166 @@
+use tokio::prelude::{future, Future};
+
+use crate::error::{CommandError, DdnsError, DdnsFuture, DdnsResult};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HookFailurePolicy {
+    Abort,
+    #[default]
+    Continue,
+}
+
+/// Shell commands run before and after each registrar update.
+#[derive(Debug, new)]
+pub struct Hooks {
+    pub pre_update_hook: String,
+    pub post_update_hook: String,
+    pub timeout: std::time::Duration,
+    pub failure_policy: HookFailurePolicy,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks::new(
+            String::new(),
+            String::new(),
+            std::time::Duration::from_secs(30),
+            HookFailurePolicy::default(),
+        )
+    }
+}
+
+fn get_address_family(ip_address: &str) -> &'static str {
+    match ip_address.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(_)) => "ipv4",
+        Ok(std::net::IpAddr::V6(_)) => "ipv6",
+        Err(_) => "unknown",
+    }
+}
+
+pub(crate) fn make_hook_environment(
+    old_address: Option<&String>,
+    ip_address: &str,
+) -> Vec<(String, String)> {
+    vec![
+        (
+            "DDNS_OLD_ADDRESS".to_owned(),
+            old_address.cloned().unwrap_or_default(),
+        ),
+        ("DDNS_NEW_ADDRESS".to_owned(), ip_address.to_owned()),
+        (
+            "DDNS_FAMILY".to_owned(),
+            get_address_family(ip_address).to_owned(),
+        ),
+    ]
+}
+
+pub(crate) fn make_hook_result_environment(
+    results: &[(String, DdnsResult<()>)],
+) -> Vec<(String, String)> {
+    let names = |success: bool| {
+        results
+            .iter()
+            .filter(|(_, result)| result.is_ok() == success)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let results_json = results
+        .iter()
+        .map(|(name, result)| {
+            serde_json::json!({
+                "name": name,
+                "success": result.is_ok(),
+                "error": result.as_ref().err().map(|error| format!("{:?}", error)),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    vec![
+        ("DDNS_SUCCEEDED_RECORDS".to_owned(), names(true)),
+        ("DDNS_FAILED_RECORDS".to_owned(), names(false)),
+        (
+            "DDNS_RESULTS".to_owned(),
+            serde_json::Value::Array(results_json).to_string(),
+        ),
+    ]
+}
+
+pub(crate) fn make_hook_future(
+    hook_name: &'static str,
+    hooks: &Hooks,
+    command: &str,
+    environment: Vec<(String, String)>,
+) -> impl DdnsFuture<()> {
+    log::trace!(
+        "fn make_hook_future(hook_name={:?}, command={:?}, environment={:?})",
+        hook_name,
+        command,
+        environment,
+    );
+
+    if command.is_empty() {
+        return future::Either::A(future::ok(()));
+    }
+
+    log::info!("Running {} hook", hook_name);
+    future::Either::B(
+        make_command_future(command, environment.as_slice(), hooks.timeout).map(move |output| {
+            log::info!(
+                "The {} hook succeeded: stdout={:?}, stderr={:?}",
+                hook_name,
+                String::from_utf8_lossy(output.stdout.as_slice()),
+                String::from_utf8_lossy(output.stderr.as_slice()),
+            );
+        }),
+    )
+}
+
+pub(crate) fn make_command_future(
+    command: &str,
+    environment: &[(String, String)],
+    timeout: std::time::Duration,
+) -> impl DdnsFuture<std::process::Output> {
+    log::trace!(
+        "fn make_command_future(command={:?}, environment={:?}, timeout={:?})",
+        command,
+        environment,
+        timeout,
+    );
+
+    use tokio_process::CommandExt;
+
+    let command = command.to_owned();
+    tokio::timer::Timeout::new(
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command.as_str())
+            .envs(environment.iter().cloned())
+            .stdin(std::process::Stdio::null())
+            .output_async(),
+        timeout,
+    )
+    .map_err(|error| match error.into_inner() {
+        Some(error) => CommandError::IoError(error),
+        None => CommandError::TimeoutError,
+    })
+    .and_then(move |output| {
+        log::debug!(
+            "Command {:?} exited with {}: stdout={:?}, stderr={:?}",
+            command,
+            output.status,
+            String::from_utf8_lossy(output.stdout.as_slice()),
+            String::from_utf8_lossy(output.stderr.as_slice()),
+        );
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(CommandError::StatusError(
+                output.status,
+                String::from_utf8_lossy(output.stdout.as_slice()).to_string(),
+                String::from_utf8_lossy(output.stderr.as_slice()).to_string(),
+            ))
+        }
+    })
+    .map_err(DdnsError::CommandError)
+}
@@ -0,0 +1,226 @@
+//! Dynamic DNS client. A `Ddns` client periodically retrieves the current IP
+//! address from an `AddressSource`, and when it changes, points registrar
+//! records at it through an `Updater`.
+
+#![feature(trait_alias)]
+
+#[macro_use]
+extern crate derive_new;
+
+extern crate bytes;
+extern crate envsubst;
+extern crate http;
+extern crate httpdate;
+extern crate hyper;
+extern crate hyper_tls;
+extern crate log;
+extern crate native_tls;
+extern crate openssl;
+extern crate regex;
+extern crate roxmltree;
+extern crate serde;
+extern crate serde_json;
+extern crate tokio;
+extern crate tokio_process;
+
+extern crate ddns_common;
+
+mod auth;
+mod config;
+mod error;
+mod extract;
+mod hooks;
+mod notify;
+mod provider;
+mod request;
+mod route53;
+mod scheduler;
+mod source;
+mod status;
+mod stun;
+mod template;
+mod update;
+mod validate;
+mod workflow;
+
+use tokio::prelude::{future, stream, Future, Stream};
+
+pub use crate::auth::{RegistrarAuth, ServiceAuth};
+pub use crate::config::Config;
+pub use crate::error::{
+    CommandError, DdnsBoxFuture, DdnsError, DdnsResult, ProviderError, RequestError, ResponseError,
+    ServiceError, SmtpError, StatusError, StunError, TemplateError,
+};
+pub use crate::extract::{JsonPath, ValueExtractor};
+pub use crate::hooks::{HookFailurePolicy, Hooks};
+pub use crate::notify::{NotificationEventKind, NotifierConfig, NotifierKind};
+pub use crate::provider::{
+    Provider, ProviderConfig, ProviderContext, ProviderFactory, ProviderRegistry, ProviderUpdater,
+};
+pub use crate::request::ClientCertificate;
+pub use crate::route53::Route53Provider;
+pub use crate::scheduler::RegistrarLimits;
+pub use crate::source::{AddressSource, HttpAddressSource};
+pub use crate::status::{ClientStatus, RecordStatus, SharedStatus};
+pub use crate::stun::{StunAddressSource, StunFamily};
+pub use crate::template::{
+    make_registrar_requests, render_registrar_requests, GenericHttpProvider, RegistrarRequest,
+};
+pub use crate::update::{ChangeDamping, UpdateFuture, UpdateResults, Updater};
+pub use crate::validate::ResponseValidation;
+pub use ddns_common::signing::{ResponseVerifier, SigningError};
+
+use crate::notify::{Notifier, SharedNotifiers};
+use crate::status::{make_status_server_future, unix_timestamp};
+use crate::update::{make_interval_timer_stream, process_address, UpdateContext};
+
+/// A dynamic DNS client, driven by a tokio runtime.
+pub struct Ddns {
+    update_interval_secs: u64,
+    status_socket_address: Option<std::net::SocketAddr>,
+    source: std::sync::Arc<dyn AddressSource>,
+    context: UpdateContext,
+}
+
+impl Ddns {
+    /// Creates a client that retrieves IP addresses from the configured DDNS
+    /// service and updates records with the configured providers.
+    pub fn from_config(config: Config) -> DdnsResult<Self> {
+        Self::from_config_with_registry(config, &ProviderRegistry::default())
+    }
+
+    /// Creates a client that retrieves IP addresses from the configured DDNS
+    /// service and updates records with the configured providers, selected from
+    /// the given registry.
+    pub fn from_config_with_registry(
+        config: Config,
+        registry: &ProviderRegistry,
+    ) -> DdnsResult<Self> {
+        let source: std::sync::Arc<dyn AddressSource> = if config.stun_servers.is_empty() {
+            let mut source = HttpAddressSource::new(config.service_address.clone());
+            if let Some(auth) = config.service_auth.clone() {
+                source = source.with_auth(auth);
+            }
+            if let Some(client_certificate) = config.service_client_certificate.clone() {
+                source = source.with_client_certificate(client_certificate);
+            }
+            if let Some(verifier) = config.service_verifier.clone() {
+                source = source.with_verifier(verifier);
+            }
+            std::sync::Arc::new(source)
+        } else {
+            let mut source =
+                StunAddressSource::new(config.stun_servers.clone()).with_family(config.stun_family);
+            if let Some(quorum) = config.stun_quorum {
+                source = source.with_quorum(quorum);
+            }
+            std::sync::Arc::new(source)
+        };
+        let updater =
+            registry.make_updater(config.provider_configs.as_slice(), config.registrar_limits)?;
+        Self::new(config, source, std::sync::Arc::new(updater))
+    }
+
+    /// Creates a client that retrieves IP addresses from the given source and
+    /// updates records with the given updater.
+    pub fn new(
+        config: Config,
+        source: std::sync::Arc<dyn AddressSource>,
+        updater: std::sync::Arc<dyn Updater>,
+    ) -> DdnsResult<Self> {
+        log::trace!("fn Ddns::new(config={:?})", config);
+
+        let mut initial_status = ClientStatus::default();
+        initial_status.desired_address = config.initial_address;
+        initial_status.records = updater
+            .record_names()?
+            .into_iter()
+            .map(|name| RecordStatus::new(name, initial_status.desired_address.clone()))
+            .collect();
+        let status: SharedStatus = std::sync::Arc::new(std::sync::Mutex::new(initial_status));
+
+        let notification_interval_secs = config.notification_interval_secs;
+        let notifiers: SharedNotifiers = std::sync::Arc::new(
+            config
+                .notifier_configs
+                .into_iter()
+                .map(|notifier_config| Notifier::new(notifier_config, notification_interval_secs))
+                .collect(),
+        );
+
+        Ok(Ddns {
+            update_interval_secs: config.update_interval_secs,
+            status_socket_address: config.status_socket_address,
+            source,
+            context: UpdateContext::new(
+                updater,
+                status,
+                notifiers,
+                std::sync::Arc::new(config.hooks),
+                config.damping,
+            ),
+        })
+    }
+
+    /// Returns the status shared with the status API.
+    pub fn status(&self) -> SharedStatus {
+        self.context.status.clone()
+    }
+
+    /// Returns a future that checks the address source once, starting an update
+    /// of any records that are not yet pointed at the current IP address. Must
+    /// be run on a tokio runtime.
+    pub fn check(&self) -> impl Future<Item = (), Error = DdnsError> {
+        let context = self.context.clone();
+        self.source
+            .get_address()
+            .and_then(move |ip_address| future::result(process_address(&context, ip_address)))
+    }
+
+    /// Returns a future that serves the status API, if configured, and checks
+    /// the address source every update interval, forever.
+    pub fn into_future(self) -> impl Future<Item = (), Error = ()> {
+        future::lazy(move || {
+            let (trigger_sender, trigger_receiver) = tokio::sync::mpsc::unbounded_channel();
+            if let Some(socket_address) = self.status_socket_address {
+                tokio::spawn(make_status_server_future(
+                    &socket_address,
+                    self.context.clone(),
+                    trigger_sender,
+                ));
+            }
+
+            let update_interval_secs = self.update_interval_secs;
+            let check_status = self.context.status.clone();
+            let error_status = self.context.status.clone();
+            make_interval_timer_stream(update_interval_secs)
+                .map(move |_| {
+                    let now = std::time::SystemTime::now();
+                    check_status
+                        .lock()
+                        .unwrap()
+                        .next_check
+                        .replace(unix_timestamp(
+                            now + std::time::Duration::from_secs(update_interval_secs),
+                        ));
+                })
+                .select(trigger_receiver.map_err(DdnsError::TriggerError))
+                .and_then(move |_| self.check())
+                .map_err(move |error| {
+                    log::error!("{:?}", error);
+                    error_status
+                        .lock()
+                        .unwrap()
+                        .last_error
+                        .replace(format!("{:?}", error));
+                })
+                .then(|r| future::ok(stream::iter_ok::<_, ()>(r)))
+                .for_each(|_| Ok(()))
+        })
+    }
+
+    /// Runs the client on a new tokio runtime, blocking forever.
+    pub fn run(self) {
+        tokio::run(self.into_future())
+    }
+}
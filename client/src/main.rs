@@ -1,37 +1,51 @@
-#![feature(trait_alias)]
-
-#[macro_use]
-extern crate derive_new;
-
-extern crate bytes;
 extern crate clap;
-extern crate envsubst;
-extern crate http;
-extern crate hyper;
-extern crate hyper_tls;
 extern crate log;
 extern crate pretty_env_logger;
 extern crate serde_json;
-extern crate tokio;
-
-extern crate ddns_common;
 
-use tokio::prelude::{future, stream, Future, Stream};
+extern crate ddns_client;
 
-#[derive(Default, Debug, new)]
-struct Config {
+#[derive(Default, Debug)]
+struct Arguments {
     update_interval: String,
     service_address: String,
+    service_token: String,
+    service_api_key: String,
+    service_hmac_secret: String,
+    service_hmac_key_id: String,
+    service_client_certificate: String,
+    service_client_key: String,
+    service_public_key: String,
+    service_max_response_age: String,
+    stun_servers: String,
+    stun_quorum: String,
+    stun_family: String,
     initial_address: String,
     registrar_request: String,
-    update_interval_secs: u64,
-    registrar_request_template: String,
+    providers: String,
+    status_address: String,
+    notifiers: String,
+    notification_interval: String,
+    pre_update_hook: String,
+    post_update_hook: String,
+    hook_timeout: String,
+    hook_failure_policy: String,
+    confirmation_count: String,
+    confirmation_period: String,
+    min_update_interval: String,
+    registrar_concurrency: String,
+    registrar_rate_limit: String,
+    registrar_burst: String,
+    registrar_max_retries: String,
 }
 
 enum ConfigError {
     ArgumentError(String),
     ParseError(String, std::num::ParseIntError),
+    AddressParseError(String, std::net::AddrParseError),
     ReadError(String, std::io::Error),
+    JsonError(String, serde_json::Error),
+    ValueError(String, String),
 }
 
 impl std::fmt::Debug for ConfigError {
@@ -47,110 +61,30 @@ impl std::fmt::Debug for ConfigError {
                 "ConfigError(ParseError(Failed to parse argument '{}': {:?}))",
                 argument, inner_error,
             ),
+            ConfigError::AddressParseError(argument, inner_error) => write!(
+                f,
+                "ConfigError(AddressParseError(Failed to parse socket address from argument '{}': {:?}))",
+                argument, inner_error,
+            ),
             ConfigError::ReadError(argument, inner_error) => write!(
                 f,
                 "ConfigError(ReadError(Failed to read file from argument '{}': {:?}))",
                 argument, inner_error,
             ),
+            ConfigError::JsonError(argument, inner_error) => write!(
+                f,
+                "ConfigError(JsonError(Failed to decode file from argument '{}': {:?}))",
+                argument, inner_error,
+            ),
+            ConfigError::ValueError(argument, value) => write!(
+                f,
+                "ConfigError(ValueError(Invalid value for argument '{}': '{}'))",
+                argument, value,
+            ),
         }
     }
 }
 
-type ServiceResponse = ddns_common::AddressResponse;
-
-#[derive(Debug, new)]
-struct RegistrarRequest {
-    name: String,
-    method: String,
-    address: String,
-    headers: Vec<(String, String)>,
-    body: hyper::Body,
-}
-
-fn make_registrar_request(request_json: &serde_json::Value) -> RequestResult<RegistrarRequest> {
-    let headers = request_json["headers"]
-        .as_object()
-        .ok_or(RequestError::FormatError)?;
-    let headers_vec = headers
-        .into_iter()
-        .map(|(key, value)| (key.to_owned(), value.as_str().unwrap().to_owned()))
-        .collect();
-
-    Ok(RegistrarRequest {
-        name: request_json["name"]
-            .as_str()
-            .ok_or(RequestError::FormatError)
-            .map(|value| value.to_owned())?,
-        method: request_json["method"]
-            .as_str()
-            .ok_or(RequestError::FormatError)
-            .map(|value| value.to_owned())?,
-        address: request_json["address"]
-            .as_str()
-            .ok_or(RequestError::FormatError)
-            .map(|value| value.to_owned())?,
-        headers: headers_vec,
-        body: request_json["body"].to_string().into(),
-    })
-}
-
-#[derive(Debug, new)]
-struct TemplateError {
-    template: String,
-    variables: std::collections::HashMap<String, String>,
-}
-
-#[derive(Debug)]
-enum RequestError {
-    TemplateError(TemplateError),
-    SerdeJsonError(serde_json::Error),
-    FormatError,
-    HttpError(http::Error),
-    HyperError(hyper::Error),
-    HyperTlsError(hyper_tls::Error),
-}
-
-type RequestResult<T> = Result<T, RequestError>;
-
-#[derive(Debug, new)]
-struct StatusError {
-    status: u16,
-    body: String,
-}
-
-#[derive(Debug)]
-enum ResponseError {
-    HyperError(hyper::Error),
-    SerdeJsonError(serde_json::Error),
-    StatusError(StatusError),
-}
-
-#[derive(Debug)]
-enum DdnsError {
-    IntervalError(tokio::timer::Error),
-    RequestError(RequestError),
-    ResponseError(ResponseError),
-}
-
-trait DdnsStream<T> = Stream<Item = T, Error = DdnsError>;
-trait DdnsFuture<T> = Future<Item = T, Error = DdnsError>;
-type DdnsResult<T> = Result<T, DdnsError>;
-
-enum RequestScheme {
-    Http,
-    Https,
-}
-
-fn get_request_scheme(scheme_option: Option<&http::uri::Scheme>) -> RequestScheme {
-    scheme_option.map_or(RequestScheme::Http, |scheme| {
-        if scheme.as_str() == "https" {
-            RequestScheme::Https
-        } else {
-            RequestScheme::Http
-        }
-    })
-}
-
 fn get_args() -> clap::ArgMatches<'static> {
     log::trace!("fn get_args()");
 
@@ -176,6 +110,105 @@ fn get_args() -> clap::ArgMatches<'static> {
                 .default_value("http//0.0.0.0:3000")
                 .help("URL of DDNS service"),
         )
+        .arg(
+            clap::Arg::with_name("service_token")
+                .long("service_token")
+                .env("DDNS_CLIENT__SERVICE_TOKEN")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Bearer token to authenticate to the DDNS service with"),
+        )
+        .arg(
+            clap::Arg::with_name("service_api_key")
+                .long("service_api_key")
+                .env("DDNS_CLIENT__SERVICE_API_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("API key to authenticate to the DDNS service with"),
+        )
+        .arg(
+            clap::Arg::with_name("service_hmac_secret")
+                .long("service_hmac_secret")
+                .env("DDNS_CLIENT__SERVICE_HMAC_SECRET")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Secret to sign requests to the DDNS service with"),
+        )
+        .arg(
+            clap::Arg::with_name("service_hmac_key_id")
+                .long("service_hmac_key_id")
+                .env("DDNS_CLIENT__SERVICE_HMAC_KEY_ID")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Client name the DDNS service knows the signing secret by"),
+        )
+        .arg(
+            clap::Arg::with_name("service_client_certificate")
+                .long("service_client_certificate")
+                .env("DDNS_CLIENT__SERVICE_CLIENT_CERTIFICATE")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded client certificate to present to the DDNS service"),
+        )
+        .arg(
+            clap::Arg::with_name("service_client_key")
+                .long("service_client_key")
+                .env("DDNS_CLIENT__SERVICE_CLIENT_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded PKCS #8 private key of the client certificate"),
+        )
+        .arg(
+            clap::Arg::with_name("service_public_key")
+                .long("service_public_key")
+                .env("DDNS_CLIENT__SERVICE_PUBLIC_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded Ed25519 public key DDNS service responses must be signed with"),
+        )
+        .arg(
+            clap::Arg::with_name("service_max_response_age")
+                .long("service_max_response_age")
+                .env("DDNS_CLIENT__SERVICE_MAX_RESPONSE_AGE")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("60")
+                .help("Maximum age (in seconds) of signed DDNS service responses"),
+        )
+        .arg(
+            clap::Arg::with_name("stun_servers")
+                .long("stun_servers")
+                .env("DDNS_CLIENT__STUN_SERVERS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Comma-separated STUN servers (host or host:port) to discover the IP address with, instead of the DDNS service"),
+        )
+        .arg(
+            clap::Arg::with_name("stun_quorum")
+                .long("stun_quorum")
+                .env("DDNS_CLIENT__STUN_QUORUM")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Number of STUN servers that must agree on the IP address (default: a majority)"),
+        )
+        .arg(
+            clap::Arg::with_name("stun_family")
+                .long("stun_family")
+                .env("DDNS_CLIENT__STUN_FAMILY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Address family (ipv4 or ipv6) to ask STUN servers over (default: either)"),
+        )
         .arg(
             clap::Arg::with_name("initial_address")
                 .long("initial_address")
@@ -191,313 +224,527 @@ fn get_args() -> clap::ArgMatches<'static> {
                 .env("DDNS_CLIENT__REGISTRAR_REQUEST")
                 .case_insensitive(true)
                 .takes_value(true)
+                .default_value("")
                 .help("Filepath of registrar request template"),
         )
+        .arg(
+            clap::Arg::with_name("providers")
+                .long("providers")
+                .env("DDNS_CLIENT__PROVIDERS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of provider configuration"),
+        )
+        .arg(
+            clap::Arg::with_name("status_address")
+                .long("status_address")
+                .env("DDNS_CLIENT__STATUS_ADDRESS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Socket address the status API should listen on"),
+        )
+        .arg(
+            clap::Arg::with_name("notifiers")
+                .long("notifiers")
+                .env("DDNS_CLIENT__NOTIFIERS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of notifier configuration"),
+        )
+        .arg(
+            clap::Arg::with_name("notification_interval")
+                .long("notification_interval")
+                .env("DDNS_CLIENT__NOTIFICATION_INTERVAL")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("300")
+                .help("Minimum time interval (in seconds) between notifications of the same event"),
+        )
+        .arg(
+            clap::Arg::with_name("pre_update_hook")
+                .long("pre_update_hook")
+                .env("DDNS_CLIENT__PRE_UPDATE_HOOK")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Shell command to run before updating registrar records"),
+        )
+        .arg(
+            clap::Arg::with_name("post_update_hook")
+                .long("post_update_hook")
+                .env("DDNS_CLIENT__POST_UPDATE_HOOK")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Shell command to run after updating registrar records"),
+        )
+        .arg(
+            clap::Arg::with_name("hook_timeout")
+                .long("hook_timeout")
+                .env("DDNS_CLIENT__HOOK_TIMEOUT")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("30")
+                .help("Time (in seconds) to wait for an update hook to exit before killing it"),
+        )
+        .arg(
+            clap::Arg::with_name("hook_failure_policy")
+                .long("hook_failure_policy")
+                .env("DDNS_CLIENT__HOOK_FAILURE_POLICY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .possible_values(&["abort", "continue"])
+                .default_value("continue")
+                .help("Whether a failed pre-update hook aborts the registrar update"),
+        )
+        .arg(
+            clap::Arg::with_name("confirmation_count")
+                .long("confirmation_count")
+                .env("DDNS_CLIENT__CONFIRMATION_COUNT")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("1")
+                .help(
+                    "Number of consecutive observations required to confirm an IP address change",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("confirmation_period")
+                .long("confirmation_period")
+                .env("DDNS_CLIENT__CONFIRMATION_PERIOD")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "Time (in seconds) an IP address change must be observed for to be confirmed",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("min_update_interval")
+                .long("min_update_interval")
+                .env("DDNS_CLIENT__MIN_UPDATE_INTERVAL")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "Minimum time interval (in seconds) between updates to each registrar record",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("registrar_concurrency")
+                .long("registrar_concurrency")
+                .env("DDNS_CLIENT__REGISTRAR_CONCURRENCY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("4")
+                .help("Maximum number of concurrent requests to each registrar host"),
+        )
+        .arg(
+            clap::Arg::with_name("registrar_rate_limit")
+                .long("registrar_rate_limit")
+                .env("DDNS_CLIENT__REGISTRAR_RATE_LIMIT")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("0")
+                .help("Maximum number of requests per minute to each registrar host"),
+        )
+        .arg(
+            clap::Arg::with_name("registrar_burst")
+                .long("registrar_burst")
+                .env("DDNS_CLIENT__REGISTRAR_BURST")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("1")
+                .help("Number of requests to each registrar host allowed in a burst"),
+        )
+        .arg(
+            clap::Arg::with_name("registrar_max_retries")
+                .long("registrar_max_retries")
+                .env("DDNS_CLIENT__REGISTRAR_MAX_RETRIES")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("3")
+                .help("Maximum number of retries of a rate-limited registrar request"),
+        )
         .get_matches()
 }
 
-fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
+fn make_config_from_args() -> Result<ddns_client::Config, Vec<ConfigError>> {
     log::trace!("fn make_config_from_args()");
 
     let args = get_args();
 
-    let mut config = Config::default();
+    let mut arguments = Arguments::default();
+    let mut config = ddns_client::Config::default();
     let mut errors = Vec::default();
 
     match args.value_of("update_interval") {
-        Some(value) => config.update_interval = value.to_owned(),
+        Some(value) => arguments.update_interval = value.to_owned(),
         None => errors.push(ConfigError::ArgumentError("update_interval".to_owned())),
     }
     match args.value_of("service_address") {
-        Some(value) => config.service_address = value.to_owned(),
+        Some(value) => arguments.service_address = value.to_owned(),
         None => errors.push(ConfigError::ArgumentError("service_address".to_owned())),
     }
+    match args.value_of("service_token") {
+        Some(value) => arguments.service_token = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("service_token".to_owned())),
+    }
+    match args.value_of("service_api_key") {
+        Some(value) => arguments.service_api_key = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("service_api_key".to_owned())),
+    }
+    match args.value_of("service_hmac_secret") {
+        Some(value) => arguments.service_hmac_secret = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("service_hmac_secret".to_owned())),
+    }
+    match args.value_of("service_hmac_key_id") {
+        Some(value) => arguments.service_hmac_key_id = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("service_hmac_key_id".to_owned())),
+    }
+    match args.value_of("service_client_certificate") {
+        Some(value) => arguments.service_client_certificate = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError(
+            "service_client_certificate".to_owned(),
+        )),
+    }
+    match args.value_of("service_client_key") {
+        Some(value) => arguments.service_client_key = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("service_client_key".to_owned())),
+    }
+    match args.value_of("service_public_key") {
+        Some(value) => arguments.service_public_key = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("service_public_key".to_owned())),
+    }
+    match args.value_of("service_max_response_age") {
+        Some(value) => arguments.service_max_response_age = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError(
+            "service_max_response_age".to_owned(),
+        )),
+    }
+    match args.value_of("stun_servers") {
+        Some(value) => arguments.stun_servers = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("stun_servers".to_owned())),
+    }
+    match args.value_of("stun_quorum") {
+        Some(value) => arguments.stun_quorum = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("stun_quorum".to_owned())),
+    }
+    match args.value_of("stun_family") {
+        Some(value) => arguments.stun_family = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("stun_family".to_owned())),
+    }
     match args.value_of("initial_address") {
-        Some(value) => config.initial_address = value.to_owned(),
+        Some(value) => arguments.initial_address = value.to_owned(),
         None => errors.push(ConfigError::ArgumentError("initial_address".to_owned())),
     }
     match args.value_of("registrar_request") {
-        Some(value) => config.registrar_request = value.to_owned(),
+        Some(value) => arguments.registrar_request = value.to_owned(),
         None => errors.push(ConfigError::ArgumentError("registrar_request".to_owned())),
     }
+    match args.value_of("providers") {
+        Some(value) => arguments.providers = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("providers".to_owned())),
+    }
+    match args.value_of("status_address") {
+        Some(value) => arguments.status_address = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("status_address".to_owned())),
+    }
+    match args.value_of("notifiers") {
+        Some(value) => arguments.notifiers = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("notifiers".to_owned())),
+    }
+    match args.value_of("notification_interval") {
+        Some(value) => arguments.notification_interval = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError(
+            "notification_interval".to_owned(),
+        )),
+    }
+    match args.value_of("pre_update_hook") {
+        Some(value) => arguments.pre_update_hook = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("pre_update_hook".to_owned())),
+    }
+    match args.value_of("post_update_hook") {
+        Some(value) => arguments.post_update_hook = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("post_update_hook".to_owned())),
+    }
+    match args.value_of("hook_timeout") {
+        Some(value) => arguments.hook_timeout = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("hook_timeout".to_owned())),
+    }
+    match args.value_of("hook_failure_policy") {
+        Some(value) => arguments.hook_failure_policy = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("hook_failure_policy".to_owned())),
+    }
+    match args.value_of("confirmation_count") {
+        Some(value) => arguments.confirmation_count = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("confirmation_count".to_owned())),
+    }
+    match args.value_of("confirmation_period") {
+        Some(value) => arguments.confirmation_period = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("confirmation_period".to_owned())),
+    }
+    match args.value_of("min_update_interval") {
+        Some(value) => arguments.min_update_interval = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("min_update_interval".to_owned())),
+    }
+    match args.value_of("registrar_concurrency") {
+        Some(value) => arguments.registrar_concurrency = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError(
+            "registrar_concurrency".to_owned(),
+        )),
+    }
+    match args.value_of("registrar_rate_limit") {
+        Some(value) => arguments.registrar_rate_limit = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError(
+            "registrar_rate_limit".to_owned(),
+        )),
+    }
+    match args.value_of("registrar_burst") {
+        Some(value) => arguments.registrar_burst = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("registrar_burst".to_owned())),
+    }
+    match args.value_of("registrar_max_retries") {
+        Some(value) => arguments.registrar_max_retries = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError(
+            "registrar_max_retries".to_owned(),
+        )),
+    }
 
     if !errors.is_empty() {
         return Err(errors);
     }
 
-    match config.update_interval.parse::<u64>() {
-        Ok(value) => config.update_interval_secs = value,
-        Err(error) => errors.push(ConfigError::ParseError("update_interval".to_owned(), error)),
+    config.service_address = arguments.service_address.clone();
+    match (
+        arguments.service_token.is_empty(),
+        arguments.service_api_key.is_empty(),
+        arguments.service_hmac_secret.is_empty(),
+    ) {
+        (true, true, true) => {}
+        (false, true, true) => {
+            config.service_auth = Some(ddns_client::ServiceAuth::Bearer(
+                arguments.service_token.clone(),
+            ))
+        }
+        (true, false, true) => {
+            config.service_auth = Some(ddns_client::ServiceAuth::ApiKey(
+                arguments.service_api_key.clone(),
+            ))
+        }
+        (true, true, false) => {
+            config.service_auth = Some(ddns_client::ServiceAuth::Hmac {
+                key_id: Some(arguments.service_hmac_key_id.clone())
+                    .filter(|key_id| !key_id.is_empty()),
+                secret: arguments.service_hmac_secret.clone(),
+            })
+        }
+        _ => errors.push(ConfigError::ValueError(
+            "service_token".to_owned(),
+            "Only one of service_token, service_api_key and service_hmac_secret may be set"
+                .to_owned(),
+        )),
+    }
+    if !arguments.service_hmac_key_id.is_empty() && arguments.service_hmac_secret.is_empty() {
+        errors.push(ConfigError::ArgumentError("service_hmac_secret".to_owned()));
     }
-    match std::fs::read_to_string(config.registrar_request.as_str()) {
-        Ok(value) => config.registrar_request_template = value,
-        Err(error) => errors.push(ConfigError::ReadError(
-            "registrar_request".to_owned(),
+    match arguments.service_max_response_age.parse::<u64>() {
+        Ok(_) if arguments.service_public_key.is_empty() => {}
+        Ok(value) => match ddns_client::ResponseVerifier::from_file(
+            arguments.service_public_key.as_ref(),
+            std::time::Duration::from_secs(value),
+        ) {
+            Ok(verifier) => config.service_verifier = Some(verifier),
+            Err(ddns_client::SigningError::IoError(_, error)) => errors.push(
+                ConfigError::ReadError("service_public_key".to_owned(), error),
+            ),
+            Err(ddns_client::SigningError::SslError(error)) => errors.push(
+                ConfigError::ValueError("service_public_key".to_owned(), error.to_string()),
+            ),
+        },
+        Err(error) => errors.push(ConfigError::ParseError(
+            "service_max_response_age".to_owned(),
             error,
         )),
     }
-
-    if errors.is_empty() {
-        Ok(config)
-    } else {
-        Err(errors)
+    match (
+        arguments.service_client_certificate.is_empty(),
+        arguments.service_client_key.is_empty(),
+    ) {
+        (true, true) => {}
+        (false, false) => {
+            config.service_client_certificate = Some(ddns_client::ClientCertificate::new(
+                arguments.service_client_certificate.as_str().into(),
+                arguments.service_client_key.as_str().into(),
+            ))
+        }
+        (true, false) => errors.push(ConfigError::ArgumentError(
+            "service_client_certificate".to_owned(),
+        )),
+        (false, true) => errors.push(ConfigError::ArgumentError("service_client_key".to_owned())),
     }
-}
-
-fn render_and_make_registrar_requests(
-    registrar_request_template: &str,
-    ip_address: String,
-) -> DdnsResult<()> {
-    log::trace!(
-        "fn render_and_make_registrar_requests(registrar_request_template={:?}, ip_address={:?})",
-        registrar_request_template,
-        ip_address.as_str(),
-    );
-    render_registrar_requests(registrar_request_template, ip_address)
-        .map_err(|error| DdnsError::RequestError(error))
-        .and_then(|rendered_registrar_requests| {
-            make_registrar_requests(rendered_registrar_requests.as_str())
-                .map_err(|error| DdnsError::RequestError(error))
-        })
-        .map(make_registrar_futures)
-}
-
-fn render_registrar_requests(request_template: &str, ip_address: String) -> RequestResult<String> {
-    log::trace!(
-        "fn render_registrar_requests(request_template={:?}, ip_address={:?})",
-        request_template,
-        ip_address.as_str(),
-    );
-
-    let mut template_variables = std::collections::HashMap::new();
-    template_variables.insert("ip_address".to_owned(), ip_address);
-
-    envsubst::substitute(request_template, &template_variables).map_err(|_| {
-        RequestError::TemplateError(TemplateError::new(
-            request_template.to_owned(),
-            template_variables,
-        ))
-    })
-}
-
-// TODO: document what this JSON format is supposed to be in a README
-fn make_registrar_requests(requests_str: &str) -> RequestResult<Vec<RegistrarRequest>> {
-    log::trace!(
-        "fn make_registrar_requests(requests_str={:?})",
-        requests_str,
-    );
-
-    let requests_result = serde_json::from_str::<serde_json::Value>(requests_str)
-        .map_err(|error| RequestError::SerdeJsonError(error))?;
-    let requests = requests_result
-        .as_array()
-        .ok_or(RequestError::FormatError)
-        .map(|requests_array| {
-            requests_array
-                .iter()
-                .map(|request_json| make_registrar_request(request_json))
-                .fold(
-                    (Vec::new(), Vec::new()),
-                    |(mut values, mut errors), result| {
-                        match result {
-                            Ok(value) => values.push(value),
-                            Err(error) => errors.push(error),
-                        }
-                        (values, errors)
-                    },
-                )
-        });
-    match requests {
-        Ok((values, mut errors)) => {
-            // Only report the first error. RequestResult only allows a singular
-            // error to be returned. We could work around this by making a new
-            // plural error type, but there's not much benefit for that amount
-            // of boilerplate.
-            if let Some(error) = errors.pop() {
-                Err(error)
-            } else {
-                Ok(values)
+    config.stun_servers = arguments
+        .stun_servers
+        .split(',')
+        .map(|server| server.trim().to_owned())
+        .filter(|server| !server.is_empty())
+        .collect();
+    if !arguments.stun_quorum.is_empty() {
+        match arguments.stun_quorum.parse::<usize>() {
+            Ok(value) if value >= 1 && value <= config.stun_servers.len() => {
+                config.stun_quorum = Some(value)
             }
+            Ok(_) => errors.push(ConfigError::ValueError(
+                "stun_quorum".to_owned(),
+                arguments.stun_quorum.clone(),
+            )),
+            Err(error) => errors.push(ConfigError::ParseError("stun_quorum".to_owned(), error)),
         }
-        Err(error) => Err(error),
     }
-}
-
-fn make_interval_timer_stream(update_interval: u64) -> impl DdnsStream<()> {
-    log::trace!(
-        "fn make_interval_timer_stream(update_interval={:?})",
-        update_interval
-    );
-
-    tokio::timer::Interval::new(
-        std::time::Instant::now(),
-        std::time::Duration::from_secs(update_interval),
-    )
-    .map_err(|error| DdnsError::IntervalError(error))
-    .map(|_| ())
-}
-
-fn make_request_future(
-    address: &str,
-    method: &str,
-    headers: &[(String, String)],
-    body: hyper::Body,
-) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
-    log::trace!(
-        "fn make_request_future(address={:?}, method={:?}, headers={:?}, body={:?})",
-        address,
-        method,
-        headers,
-        &body,
-    );
+    match arguments.stun_family.to_ascii_lowercase().as_str() {
+        "" => {}
+        "ipv4" => config.stun_family = ddns_client::StunFamily::V4,
+        "ipv6" => config.stun_family = ddns_client::StunFamily::V6,
+        _ => errors.push(ConfigError::ValueError(
+            "stun_family".to_owned(),
+            arguments.stun_family.clone(),
+        )),
+    }
+    if !arguments.initial_address.is_empty() {
+        config.initial_address = Some(arguments.initial_address.clone());
+    }
+    config.hooks.pre_update_hook = arguments.pre_update_hook.clone();
+    config.hooks.post_update_hook = arguments.post_update_hook.clone();
 
-    future::result(
-        make_request(address, method, headers, body)
-            .map_err(|error| RequestError::HttpError(error)),
-    )
-    .and_then(
-        |request| match get_request_scheme(request.uri().scheme_part()) {
-            RequestScheme::Http => {
-                let connector = hyper::client::HttpConnector::new(4);
-                future::Either::A(
-                    hyper::Client::builder()
-                        .build::<_, hyper::Body>(connector)
-                        .request(request)
-                        .map_err(|error| RequestError::HyperError(error)),
-                )
+    match arguments.update_interval.parse::<u64>() {
+        Ok(value) => config.update_interval_secs = value,
+        Err(error) => errors.push(ConfigError::ParseError("update_interval".to_owned(), error)),
+    }
+    if !arguments.registrar_request.is_empty() {
+        match std::fs::read_to_string(arguments.registrar_request.as_str()) {
+            Ok(value) => {
+                let mut options = serde_json::Map::new();
+                options.insert("requests".to_owned(), serde_json::Value::String(value));
+                config
+                    .provider_configs
+                    .push(ddns_client::ProviderConfig::new(
+                        "generic_http".to_owned(),
+                        options,
+                    ));
             }
-            RequestScheme::Https => future::Either::B(
-                future::result(
-                    hyper_tls::HttpsConnector::new(4)
-                        .map_err(|error| RequestError::HyperTlsError(error)),
-                )
-                .and_then(|connector| {
-                    hyper::Client::builder()
-                        .build::<_, hyper::Body>(connector)
-                        .request(request)
-                        .map_err(|error| RequestError::HyperError(error))
-                }),
-            ),
-        },
-    )
-    .map_err(|error| DdnsError::RequestError(error))
-}
-
-fn make_request(
-    address: &str,
-    method: &str,
-    headers: &[(String, String)],
-    body: hyper::Body,
-) -> http::Result<hyper::Request<hyper::Body>> {
-    let mut builder = hyper::Request::builder();
-    builder.uri(address).method(method);
-    headers.into_iter().for_each(|(key, value)| {
-        builder.header(key.as_str(), value.as_str());
-    });
-    builder.body(body)
-}
-
-fn decode_response(response: hyper::Response<hyper::Body>) -> impl DdnsFuture<bytes::Bytes> {
-    response
-        .into_body()
-        .fold(
-            bytes::Bytes::new(),
-            |mut accumulator, chunk| -> hyper::Result<bytes::Bytes> {
-                accumulator.extend_from_slice(chunk.into_bytes().as_ref());
-                Ok(accumulator)
+            Err(error) => errors.push(ConfigError::ReadError(
+                "registrar_request".to_owned(),
+                error,
+            )),
+        }
+    }
+    if !arguments.providers.is_empty() {
+        match std::fs::read_to_string(arguments.providers.as_str()) {
+            Ok(value) => {
+                match serde_json::from_str::<Vec<ddns_client::ProviderConfig>>(value.as_str()) {
+                    Ok(value) => config.provider_configs.extend(value),
+                    Err(error) => {
+                        errors.push(ConfigError::JsonError("providers".to_owned(), error))
+                    }
+                }
+            }
+            Err(error) => errors.push(ConfigError::ReadError("providers".to_owned(), error)),
+        }
+    }
+    if arguments.registrar_request.is_empty() && arguments.providers.is_empty() {
+        errors.push(ConfigError::ArgumentError("registrar_request".to_owned()));
+    }
+    if !arguments.status_address.is_empty() {
+        match arguments.status_address.parse() {
+            Ok(value) => config.status_socket_address = Some(value),
+            Err(error) => errors.push(ConfigError::AddressParseError(
+                "status_address".to_owned(),
+                error,
+            )),
+        }
+    }
+    match arguments.notification_interval.parse::<u64>() {
+        Ok(value) => config.notification_interval_secs = value,
+        Err(error) => errors.push(ConfigError::ParseError(
+            "notification_interval".to_owned(),
+            error,
+        )),
+    }
+    match arguments.hook_timeout.parse::<u64>() {
+        Ok(value) => config.hooks.timeout = std::time::Duration::from_secs(value),
+        Err(error) => errors.push(ConfigError::ParseError("hook_timeout".to_owned(), error)),
+    }
+    match arguments.hook_failure_policy.to_lowercase().as_str() {
+        "abort" => config.hooks.failure_policy = ddns_client::HookFailurePolicy::Abort,
+        "continue" => config.hooks.failure_policy = ddns_client::HookFailurePolicy::Continue,
+        _ => errors.push(ConfigError::ValueError(
+            "hook_failure_policy".to_owned(),
+            arguments.hook_failure_policy.clone(),
+        )),
+    }
+    match arguments.confirmation_count.parse::<u64>() {
+        Ok(value) => config.damping.confirmation_count = value,
+        Err(error) => errors.push(ConfigError::ParseError(
+            "confirmation_count".to_owned(),
+            error,
+        )),
+    }
+    match arguments.confirmation_period.parse::<u64>() {
+        Ok(value) => config.damping.confirmation_period = std::time::Duration::from_secs(value),
+        Err(error) => errors.push(ConfigError::ParseError(
+            "confirmation_period".to_owned(),
+            error,
+        )),
+    }
+    match arguments.min_update_interval.parse::<u64>() {
+        Ok(value) => config.damping.min_update_interval = std::time::Duration::from_secs(value),
+        Err(error) => errors.push(ConfigError::ParseError(
+            "min_update_interval".to_owned(),
+            error,
+        )),
+    }
+    match arguments.registrar_concurrency.parse::<usize>() {
+        Ok(value) => config.registrar_limits.concurrency = value,
+        Err(error) => errors.push(ConfigError::ParseError(
+            "registrar_concurrency".to_owned(),
+            error,
+        )),
+    }
+    match arguments.registrar_rate_limit.parse::<u64>() {
+        Ok(value) => config.registrar_limits.rate_limit_per_minute = value,
+        Err(error) => errors.push(ConfigError::ParseError(
+            "registrar_rate_limit".to_owned(),
+            error,
+        )),
+    }
+    match arguments.registrar_burst.parse::<u64>() {
+        Ok(value) => config.registrar_limits.burst = value,
+        Err(error) => errors.push(ConfigError::ParseError("registrar_burst".to_owned(), error)),
+    }
+    match arguments.registrar_max_retries.parse::<u64>() {
+        Ok(value) => config.registrar_limits.max_retries = value,
+        Err(error) => errors.push(ConfigError::ParseError(
+            "registrar_max_retries".to_owned(),
+            error,
+        )),
+    }
+    if !arguments.notifiers.is_empty() {
+        match std::fs::read_to_string(arguments.notifiers.as_str()) {
+            Ok(value) => match serde_json::from_str(value.as_str()) {
+                Ok(value) => config.notifier_configs = value,
+                Err(error) => errors.push(ConfigError::JsonError("notifiers".to_owned(), error)),
             },
-        )
-        .map_err(|error| DdnsError::ResponseError(ResponseError::HyperError(error)))
-}
-
-fn make_service_future(address: &str) -> impl DdnsFuture<ServiceResponse> {
-    log::trace!("fn make_service_future(address={:?})", address);
-
-    log::debug!("Retrieving current IP address");
-
-    make_service_request_future(address).and_then(decode_service_response)
-}
-
-fn make_service_request_future(address: &str) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
-    make_request_future(
-        address,
-        "GET",
-        &[("Accept".to_owned(), "application/json".to_owned())],
-        hyper::Body::empty(),
-    )
-}
-
-fn decode_service_response(
-    response: hyper::Response<hyper::Body>,
-) -> impl DdnsFuture<ServiceResponse> {
-    log::trace!("fn decode_service_response(response={:?})", response);
-
-    decode_response(response).and_then(|response_bytes| {
-        serde_json::from_slice(response_bytes.as_ref())
-            .map_err(|error| DdnsError::ResponseError(ResponseError::SerdeJsonError(error)))
-    })
-}
-
-fn make_registrar_futures(registrar_requests: Vec<RegistrarRequest>) {
-    log::trace!(
-        "fn make_registrar_futures(registrar_requests={:?})",
-        registrar_requests,
-    );
-
-    registrar_requests.into_iter().for_each(|request| {
-        tokio::spawn(make_registrar_future(request).map_err(|error| log::error!("{:?}", error)));
-    });
-}
-
-fn make_registrar_future(request: RegistrarRequest) -> impl DdnsFuture<()> {
-    log::trace!("fn make_registrar_future(request={:?})", &request);
-
-    log::debug!("Updating registrar record '{}'...", request.name);
-
-    let name = request.name.to_owned();
-
-    make_request_future(
-        request.address.as_str(),
-        request.method.as_str(),
-        request.headers.as_slice(),
-        request.body,
-    )
-    .and_then(move |response| process_registrar_response(name.as_str(), response))
-}
-
-fn decode_registrar_response(
-    status_code: u16,
-    response: hyper::Response<hyper::Body>,
-) -> impl DdnsFuture<()> {
-    log::trace!("fn decode_registrar_response(response={:?})", response);
-
-    decode_response(response).and_then(move |response_bytes| {
-        future::err(DdnsError::ResponseError(ResponseError::StatusError(
-            StatusError::new(
-                status_code,
-                String::from_utf8_lossy(response_bytes.as_ref()).to_string(),
-            ),
-        )))
-    })
-}
-
-fn process_registrar_response(
-    name: &str,
-    response: hyper::Response<hyper::Body>,
-) -> impl DdnsFuture<()> {
-    log::trace!(
-        "fn process_registrar_response(name={:?}, response={:?})",
-        name,
-        response,
-    );
+            Err(error) => errors.push(ConfigError::ReadError("notifiers".to_owned(), error)),
+        }
+    }
 
-    let status_code = response.status().as_u16();
-    if status_code < 200 || status_code >= 300 {
-        log::warn!("Failed to update registrar record '{}'", name);
-        future::Either::A(decode_registrar_response(status_code, response))
+    if errors.is_empty() {
+        Ok(config)
     } else {
-        log::debug!("Successfully updated registrar record '{}'", name);
-        future::Either::B(future::ok(()))
+        Err(errors)
     }
 }
 
@@ -509,40 +756,8 @@ fn main() {
         .unwrap();
     log::info!("Initialized with {:?}", config);
 
-    let update_interval_secs = config.update_interval_secs;
-    let service_address = config.service_address;
-    let registrar_request_template = config.registrar_request_template;
-
-    let mut ip_address: Option<String> = None;
-    if !config.initial_address.is_empty() {
-        ip_address.replace(config.initial_address.clone());
-    }
-
-    tokio::run(
-        make_interval_timer_stream(update_interval_secs)
-            .and_then(move |_| make_service_future(service_address.as_str()))
-            .and_then(move |service_response| {
-                log::trace!("closure process_service_response({:?})", service_response);
-
-                if ip_address.as_ref() == Some(&service_response.ip) {
-                    log::debug!("IP Address unchanged from {:?}", ip_address);
-                    return future::ok(());
-                }
-
-                log::info!(
-                    "IP Address has changed from {:?} to {}",
-                    ip_address,
-                    service_response.ip,
-                );
-                ip_address.replace(service_response.ip.clone());
-
-                future::result(render_and_make_registrar_requests(
-                    registrar_request_template.as_str(),
-                    service_response.ip,
-                ))
-            })
-            .map_err(|error| log::error!("{:?}", error))
-            .then(|r| future::ok(stream::iter_ok::<_, ()>(r)))
-            .for_each(|_| Ok(())),
-    );
+    ddns_client::Ddns::from_config(config)
+        .map_err(|error| log::error!("{:?}", error))
+        .unwrap()
+        .run();
 }
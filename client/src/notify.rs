@@ -0,0 +1,630 @@
+use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+use tokio::prelude::{future, stream, Future, Stream};
+
+use crate::error::{DdnsError, DdnsFuture, SmtpError};
+use crate::hooks::make_command_future;
+use crate::request::{decode_registrar_response, make_request_future};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEventKind {
+    AddressChanged,
+    UpdateFailed,
+}
+
+impl NotificationEventKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            NotificationEventKind::AddressChanged => "address_changed",
+            NotificationEventKind::UpdateFailed => "update_failed",
+        }
+    }
+}
+
+#[derive(Debug, new)]
+pub(crate) struct NotificationEvent {
+    pub(crate) kind: NotificationEventKind,
+    pub(crate) old_address: Option<String>,
+    pub(crate) new_address: String,
+    pub(crate) record: Option<String>,
+    pub(crate) error: Option<String>,
+}
+
+impl NotificationEvent {
+    pub(crate) fn message(&self) -> String {
+        match self.kind {
+            NotificationEventKind::AddressChanged => format!(
+                "IP address has changed from {} to {}",
+                self.old_address.as_deref().unwrap_or("(none)"),
+                self.new_address,
+            ),
+            NotificationEventKind::UpdateFailed => format!(
+                "Failed to update registrar record '{}' to {}: {}",
+                self.record.as_deref().unwrap_or_default(),
+                self.new_address,
+                self.error.as_deref().unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub(crate) fn variables(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("event", self.kind.as_str().to_owned()),
+            ("old_address", self.old_address.clone().unwrap_or_default()),
+            ("new_address", self.new_address.clone()),
+            ("record", self.record.clone().unwrap_or_default()),
+            ("error", self.error.clone().unwrap_or_default()),
+            ("message", self.message()),
+        ]
+    }
+}
+
+fn default_notification_events() -> Vec<NotificationEventKind> {
+    vec![
+        NotificationEventKind::AddressChanged,
+        NotificationEventKind::UpdateFailed,
+    ]
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_owned()
+}
+
+fn default_command_timeout() -> u64 {
+    30
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_smtp_subject() -> String {
+    "ddns: ${message}".to_owned()
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierKind {
+    Command {
+        command: String,
+        #[serde(default = "default_command_timeout")]
+        timeout: u64,
+    },
+    Webhook {
+        #[serde(default = "default_webhook_method")]
+        method: String,
+        address: String,
+        #[serde(default)]
+        headers: std::collections::BTreeMap<String, String>,
+        body: serde_json::Value,
+    },
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        from: String,
+        to: Vec<String>,
+        #[serde(default = "default_smtp_subject")]
+        subject: String,
+    },
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NotifierConfig {
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<NotificationEventKind>,
+    pub min_interval: Option<u64>,
+}
+
+type NotificationKey = (NotificationEventKind, Option<String>);
+
+#[derive(Debug)]
+pub(crate) struct Notifier {
+    pub(crate) config: NotifierConfig,
+    pub(crate) min_interval: std::time::Duration,
+    pub(crate) last_sent:
+        std::sync::Mutex<std::collections::HashMap<NotificationKey, std::time::Instant>>,
+    /// Resolves SMTP hosts on a pool of its own, rather than blocking the
+    /// runtime.
+    resolver: GaiResolver,
+}
+
+impl Notifier {
+    pub(crate) fn new(config: NotifierConfig, default_min_interval_secs: u64) -> Self {
+        let min_interval_secs = config.min_interval.unwrap_or(default_min_interval_secs);
+        Notifier {
+            config,
+            min_interval: std::time::Duration::from_secs(min_interval_secs),
+            last_sent: std::sync::Mutex::new(std::collections::HashMap::new()),
+            resolver: GaiResolver::new(1),
+        }
+    }
+
+    /// Records an attempt to send a notification for the given event, returning
+    /// false if a notification for the same kind of event (and record) was
+    /// already sent too recently.
+    pub(crate) fn try_acquire(&self, event: &NotificationEvent) -> bool {
+        let now = std::time::Instant::now();
+        let key = (event.kind, event.record.clone());
+        let mut last_sent = self.last_sent.lock().unwrap();
+        match last_sent.get(&key) {
+            Some(instant) if now.duration_since(*instant) < self.min_interval => false,
+            _ => {
+                last_sent.insert(key, now);
+                true
+            }
+        }
+    }
+}
+
+pub(crate) type SharedNotifiers = std::sync::Arc<Vec<Notifier>>;
+
+fn substitute_variables(template: &str, variables: &[(&'static str, String)]) -> String {
+    variables
+        .iter()
+        .fold(template.to_owned(), |output, (key, value)| {
+            output.replace(format!("${{{}}}", key).as_str(), value.as_str())
+        })
+}
+
+fn substitute_json_variables(
+    template: &serde_json::Value,
+    variables: &[(&'static str, String)],
+) -> serde_json::Value {
+    match template {
+        serde_json::Value::String(value) => {
+            serde_json::Value::String(substitute_variables(value.as_str(), variables))
+        }
+        serde_json::Value::Array(values) => serde_json::Value::Array(
+            values
+                .iter()
+                .map(|value| substitute_json_variables(value, variables))
+                .collect(),
+        ),
+        serde_json::Value::Object(values) => serde_json::Value::Object(
+            values
+                .iter()
+                .map(|(key, value)| (key.to_owned(), substitute_json_variables(value, variables)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+pub(crate) fn notify(notifiers: &[Notifier], event: NotificationEvent) {
+    log::trace!("fn notify(event={:?})", &event);
+
+    notifiers
+        .iter()
+        .filter(|notifier| notifier.config.events.contains(&event.kind))
+        .for_each(|notifier| {
+            if !notifier.try_acquire(&event) {
+                log::info!(
+                    "Suppressing {} notification sent less than {:?} ago",
+                    event.kind.as_str(),
+                    notifier.min_interval,
+                );
+                return;
+            }
+            tokio::spawn(
+                make_notifier_future(&notifier.config.kind, &event, &notifier.resolver)
+                    .map_err(|error| log::error!("Failed to send notification: {:?}", error)),
+            );
+        });
+}
+
+fn make_notifier_future(
+    notifier: &NotifierKind,
+    event: &NotificationEvent,
+    resolver: &GaiResolver,
+) -> Box<dyn DdnsFuture<()> + Send> {
+    log::trace!(
+        "fn make_notifier_future(notifier={:?}, event={:?})",
+        notifier,
+        event,
+    );
+
+    let variables = event.variables();
+    match notifier {
+        NotifierKind::Command { command, timeout } => {
+            let environment = variables
+                .iter()
+                .map(|(key, value)| (format!("DDNS_{}", key.to_uppercase()), value.to_owned()))
+                .collect::<Vec<_>>();
+            Box::new(
+                make_command_future(
+                    command.as_str(),
+                    environment.as_slice(),
+                    std::time::Duration::from_secs(*timeout),
+                )
+                .map(|_| ()),
+            )
+        }
+        NotifierKind::Webhook {
+            method,
+            address,
+            headers,
+            body,
+        } => {
+            let headers = headers
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        key.to_owned(),
+                        substitute_variables(value.as_str(), variables.as_slice()),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let body = match substitute_json_variables(body, variables.as_slice()) {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            Box::new(
+                make_request_future(
+                    substitute_variables(address.as_str(), variables.as_slice()).as_str(),
+                    method.as_str(),
+                    headers.as_slice(),
+                    body,
+                    None,
+                    None,
+                )
+                .and_then(|response| {
+                    let status_code = response.status().as_u16();
+                    if !(200..300).contains(&status_code) {
+                        future::Either::A(decode_registrar_response(status_code, response))
+                    } else {
+                        future::Either::B(future::ok(()))
+                    }
+                }),
+            )
+        }
+        NotifierKind::Smtp {
+            host,
+            port,
+            from,
+            to,
+            subject,
+        } => {
+            let message = make_smtp_message(
+                from.as_str(),
+                to.as_slice(),
+                substitute_variables(subject.as_str(), variables.as_slice()).as_str(),
+                event.message().as_str(),
+                std::time::SystemTime::now(),
+            );
+            Box::new(
+                make_smtp_future(
+                    host.as_str(),
+                    *port,
+                    from.as_str(),
+                    to.as_slice(),
+                    message,
+                    resolver,
+                )
+                .map_err(DdnsError::SmtpError),
+            )
+        }
+    }
+}
+
+/// Makes a mail message. The subject is rendered from the event, whose error
+/// text comes from registrars, so line breaks in it are replaced to keep it
+/// from adding headers of its own.
+fn make_smtp_message(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    body: &str,
+    time: std::time::SystemTime,
+) -> String {
+    let subject = subject.replace(['\r', '\n'], " ");
+    // RFC 5322 prefers a numeric zone to the `GMT` of HTTP dates.
+    let date = httpdate::fmt_http_date(time).replace(" GMT", " +0000");
+    format!(
+        "Date: {}\r\nFrom: <{}>\r\nTo: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+        date,
+        from,
+        to.iter()
+            .map(|recipient| format!("<{}>", recipient))
+            .collect::<Vec<_>>()
+            .join(", "),
+        subject,
+        body,
+    )
+}
+
+/// Encodes a mail message as the data of an SMTP transaction, up to and
+/// including the lone period that terminates it.
+fn make_smtp_data(message: &str) -> String {
+    // Lines beginning with a period must be escaped with an additional period,
+    // and a lone period terminates the message. Lines are ended with CRLF alone,
+    // so bare carriage returns are replaced as well.
+    message
+        .lines()
+        .map(|line| line.replace('\r', " "))
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{}", line)
+            } else {
+                line
+            }
+        })
+        .chain(std::iter::once(".".to_owned()))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+type SmtpTransport = tokio::codec::Framed<tokio::net::TcpStream, tokio::codec::LinesCodec>;
+
+/// Reads a (possibly multi-line) SMTP reply, failing unless its code matches the
+/// expected code.
+fn read_smtp_reply(
+    transport: SmtpTransport,
+    command: String,
+    expected_code: &'static str,
+) -> impl Future<Item = SmtpTransport, Error = SmtpError> {
+    future::loop_fn(transport, move |transport| {
+        let command = command.to_owned();
+        transport
+            .into_future()
+            .map_err(|(error, _)| SmtpError::IoError(error))
+            .and_then(move |(line, transport)| {
+                let line = line.unwrap_or_default();
+                log::trace!("SMTP reply to {:?}: {:?}", command, line);
+                if !line.starts_with(expected_code) {
+                    Err(SmtpError::ReplyError(command, line))
+                } else if line.chars().nth(3) == Some('-') {
+                    Ok(future::Loop::Continue(transport))
+                } else {
+                    Ok(future::Loop::Break(transport))
+                }
+            })
+    })
+}
+
+fn send_smtp_command(
+    transport: SmtpTransport,
+    command: String,
+    expected_code: &'static str,
+) -> impl Future<Item = SmtpTransport, Error = SmtpError> {
+    use tokio::prelude::Sink;
+
+    // LinesCodec only terminates lines with a line feed, but SMTP requires a
+    // carriage return before it.
+    transport
+        .send(format!("{}\r", command))
+        .map_err(SmtpError::IoError)
+        .and_then(move |transport| read_smtp_reply(transport, command, expected_code))
+}
+
+/// Resolves an SMTP host to its addresses, off the runtime.
+fn make_smtp_resolve_future(
+    host: &str,
+    port: u16,
+    resolver: &GaiResolver,
+) -> impl Future<Item = Vec<std::net::SocketAddr>, Error = SmtpError> {
+    log::trace!(
+        "fn make_smtp_resolve_future(host={:?}, port={:?})",
+        host,
+        port
+    );
+
+    let addresses = match (host.parse::<std::net::IpAddr>(), host.parse::<Name>()) {
+        (Ok(address), _) => future::Either::A(future::ok(vec![address])),
+        (Err(_), Ok(name)) => future::Either::B(
+            resolver
+                .resolve(name)
+                .map(|addresses| addresses.collect())
+                .map_err(SmtpError::IoError),
+        ),
+        (Err(_), Err(_)) => {
+            future::Either::A(future::err(SmtpError::ResolveError(host.to_owned())))
+        }
+    };
+    let host = host.to_owned();
+    addresses.and_then(move |addresses: Vec<std::net::IpAddr>| {
+        if addresses.is_empty() {
+            Err(SmtpError::ResolveError(host))
+        } else {
+            Ok(addresses
+                .into_iter()
+                .map(|address| std::net::SocketAddr::new(address, port))
+                .collect())
+        }
+    })
+}
+
+/// Connects to the first address of an SMTP host that accepts the connection.
+fn make_smtp_connect_future(
+    host: &str,
+    port: u16,
+    resolver: &GaiResolver,
+) -> impl Future<Item = tokio::net::TcpStream, Error = SmtpError> {
+    let host = host.to_owned();
+    make_smtp_resolve_future(host.as_str(), port, resolver).and_then(move |socket_addresses| {
+        future::loop_fn(socket_addresses.into_iter(), move |mut socket_addresses| {
+            // Resolved hosts have at least one address.
+            let socket_address = socket_addresses.next().unwrap();
+            let host = host.clone();
+            tokio::net::TcpStream::connect(&socket_address).then(move |result| match result {
+                Ok(stream) => Ok(future::Loop::Break(stream)),
+                Err(error) if socket_addresses.len() == 0 => Err(SmtpError::IoError(error)),
+                Err(error) => {
+                    log::debug!(
+                        "SMTP host {} failed at {}: {:?}",
+                        host,
+                        socket_address,
+                        error,
+                    );
+                    Ok(future::Loop::Continue(socket_addresses))
+                }
+            })
+        })
+    })
+}
+
+fn make_smtp_future(
+    host: &str,
+    port: u16,
+    from: &str,
+    to: &[String],
+    message: String,
+    resolver: &GaiResolver,
+) -> impl Future<Item = (), Error = SmtpError> {
+    log::trace!(
+        "fn make_smtp_future(host={:?}, port={:?}, from={:?}, to={:?}, message={:?})",
+        host,
+        port,
+        from,
+        to,
+        message,
+    );
+
+    let data = make_smtp_data(message.as_str());
+
+    let commands = std::iter::once(("HELO localhost".to_owned(), "250"))
+        .chain(std::iter::once((format!("MAIL FROM:<{}>", from), "250")))
+        .chain(
+            to.iter()
+                .map(|recipient| (format!("RCPT TO:<{}>", recipient), "250")),
+        )
+        .chain(std::iter::once(("DATA".to_owned(), "354")))
+        .chain(std::iter::once((data, "250")))
+        .chain(std::iter::once(("QUIT".to_owned(), "221")))
+        .collect::<Vec<_>>();
+
+    make_smtp_connect_future(host, port, resolver)
+        .and_then(|stream| {
+            read_smtp_reply(
+                tokio::codec::Framed::new(stream, tokio::codec::LinesCodec::new()),
+                "CONNECT".to_owned(),
+                "220",
+            )
+        })
+        .and_then(|transport| {
+            stream::iter_ok(commands).fold(transport, |transport, (command, expected_code)| {
+                send_smtp_command(transport, command, expected_code)
+            })
+        })
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update_failed(record: &str, error: &str) -> NotificationEvent {
+        NotificationEvent::new(
+            NotificationEventKind::UpdateFailed,
+            Some("192.0.2.1".to_owned()),
+            "192.0.2.2".to_owned(),
+            Some(record.to_owned()),
+            Some(error.to_owned()),
+        )
+    }
+
+    fn make_notifier(min_interval: u64) -> Notifier {
+        let config = serde_json::from_value(serde_json::json!({
+            "type": "command",
+            "command": "true",
+            "min_interval": min_interval,
+        }))
+        .unwrap();
+        Notifier::new(config, 0)
+    }
+
+    #[test]
+    fn substitutes_event_variables() {
+        let event = update_failed("www", "internal error");
+        assert_eq!(
+            substitute_variables(
+                "${event} ${record} ${old_address}->${new_address}: ${error} ${unknown}",
+                event.variables().as_slice(),
+            ),
+            "update_failed www 192.0.2.1->192.0.2.2: internal error ${unknown}",
+        );
+
+        let event = NotificationEvent::new(
+            NotificationEventKind::AddressChanged,
+            None,
+            "192.0.2.2".to_owned(),
+            None,
+            None,
+        );
+        assert_eq!(
+            substitute_variables("${message}|${old_address}|${record}", &event.variables()),
+            "IP address has changed from (none) to 192.0.2.2||",
+        );
+    }
+
+    #[test]
+    fn substitutes_variables_in_json_strings_only() {
+        let event = update_failed("www", "internal \"error\"");
+        let body = substitute_json_variables(
+            &serde_json::json!({
+                "text": "${record}: ${error}",
+                "tags": ["${event}", 1, true, null],
+                "nested": { "${record}": "${new_address}" },
+            }),
+            event.variables().as_slice(),
+        );
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "text": "www: internal \"error\"",
+                "tags": ["update_failed", 1, true, null],
+                "nested": { "${record}": "192.0.2.2" },
+            }),
+        );
+    }
+
+    #[test]
+    fn rate_limits_each_event_kind_and_record() {
+        let notifier = make_notifier(3600);
+        assert!(notifier.try_acquire(&update_failed("www", "first")));
+        assert!(!notifier.try_acquire(&update_failed("www", "second")));
+        assert!(notifier.try_acquire(&update_failed("mail", "first")));
+        assert!(notifier.try_acquire(&NotificationEvent::new(
+            NotificationEventKind::AddressChanged,
+            None,
+            "192.0.2.2".to_owned(),
+            None,
+            None,
+        )));
+
+        let notifier = make_notifier(0);
+        assert!(notifier.try_acquire(&update_failed("www", "first")));
+        assert!(notifier.try_acquire(&update_failed("www", "second")));
+    }
+
+    #[test]
+    fn escapes_periods_at_the_start_of_smtp_lines() {
+        assert_eq!(
+            make_smtp_data("Subject: x\r\n\r\n.\r\n..leading\nmiddle.\r\n"),
+            "Subject: x\r\n\r\n..\r\n...leading\r\nmiddle.\r\n.",
+        );
+        assert_eq!(make_smtp_data("bare\rreturn"), "bare return\r\n.");
+    }
+
+    #[test]
+    fn keeps_event_text_out_of_mail_headers() {
+        let message = make_smtp_message(
+            "ddns@example.org",
+            &["admin@example.org".to_owned(), "ops@example.org".to_owned()],
+            "ddns: failed\r\nBcc: victim@example.org",
+            "body",
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(951_825_600),
+        );
+        assert_eq!(
+            message,
+            "Date: Tue, 29 Feb 2000 12:00:00 +0000\r\n\
+             From: <ddns@example.org>\r\n\
+             To: <admin@example.org>, <ops@example.org>\r\n\
+             Subject: ddns: failed  Bcc: victim@example.org\r\n\
+             \r\n\
+             body\r\n",
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use tokio::prelude::{future, Future};
+
+use crate::auth::TokenCache;
+use crate::error::{DdnsBoxFuture, DdnsError, DdnsResult, ProviderError};
+use crate::route53::Route53Provider;
+use crate::scheduler::{RegistrarLimits, RegistrarScheduler};
+use crate::template::GenericHttpProvider;
+use crate::update::{UpdateFuture, Updater};
+
+/// A registrar backend, managing a set of named records.
+pub trait Provider: Send + Sync {
+    /// Returns the names of the records this provider manages.
+    fn record_names(&self) -> DdnsResult<Vec<String>>;
+
+    /// Retrieves the IP address the named record currently points at, or `None`
+    /// if the record does not exist or the registrar cannot report it. Records
+    /// holding an address per family report the one of the same family as
+    /// `ip_address`, the address the record is about to be pointed at.
+    fn get_current(&self, record_name: &str, ip_address: &str) -> DdnsBoxFuture<Option<String>>;
+
+    /// Points the named record at the given IP address, creating it if needed.
+    fn upsert(&self, record_name: &str, ip_address: &str) -> DdnsBoxFuture<()>;
+
+    /// Removes the named record from the registrar.
+    fn delete(&self, record_name: &str) -> DdnsBoxFuture<()>;
+
+    /// Points the named record at the given IP address, unless the provider
+    /// reports that it already does. Providers whose reads and writes depend on
+    /// the same preliminary requests may override this to send them only once.
+    fn update(self: std::sync::Arc<Self>, record_name: &str, ip_address: &str) -> DdnsBoxFuture<()>
+    where
+        Self: 'static,
+    {
+        let provider = self.clone();
+        let (upsert_record_name, upsert_ip_address) =
+            (record_name.to_owned(), ip_address.to_owned());
+        make_read_before_write_future(
+            record_name.to_owned(),
+            ip_address.to_owned(),
+            self.get_current(record_name, ip_address),
+            move || provider.upsert(upsert_record_name.as_str(), upsert_ip_address.as_str()),
+        )
+    }
+}
+
+/// Configuration of a single provider. The `provider` property selects the
+/// provider from a `ProviderRegistry`, and all other properties are options
+/// specific to that provider.
+#[derive(Clone, Debug, new, serde::Deserialize)]
+pub struct ProviderConfig {
+    pub provider: String,
+    #[serde(flatten)]
+    pub options: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ProviderConfig {
+    /// Returns the named option, or an error if it is missing.
+    pub fn option(&self, name: &str) -> DdnsResult<&serde_json::Value> {
+        self.options.get(name).ok_or_else(|| {
+            DdnsError::ProviderError(ProviderError::OptionError(
+                self.provider.clone(),
+                name.to_owned(),
+            ))
+        })
+    }
+}
+
+/// State shared by all providers created from a registry, scheduling their
+/// requests according to per-host registrar limits and caching their OAuth2
+/// access tokens.
+#[derive(Clone, Debug)]
+pub struct ProviderContext {
+    pub(crate) scheduler: std::sync::Arc<RegistrarScheduler>,
+    pub(crate) tokens: std::sync::Arc<TokenCache>,
+}
+
+impl ProviderContext {
+    pub fn new(limits: RegistrarLimits) -> Self {
+        ProviderContext {
+            scheduler: std::sync::Arc::new(RegistrarScheduler::new(limits)),
+            tokens: std::sync::Arc::new(TokenCache::default()),
+        }
+    }
+}
+
+pub type ProviderFactory = fn(&ProviderConfig, &ProviderContext) -> DdnsResult<Box<dyn Provider>>;
+
+/// Creates providers by name. The default registry contains every provider
+/// built into the client.
+pub struct ProviderRegistry {
+    factories: std::collections::HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    /// Creates a registry with no providers.
+    pub fn empty() -> Self {
+        ProviderRegistry {
+            factories: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Registers a provider under the given name, replacing any provider
+    /// already registered under it.
+    pub fn register(&mut self, name: &str, factory: ProviderFactory) {
+        self.factories.insert(name.to_owned(), factory);
+    }
+
+    /// Creates the provider selected by the given configuration.
+    pub fn create(
+        &self,
+        config: &ProviderConfig,
+        context: &ProviderContext,
+    ) -> DdnsResult<Box<dyn Provider>> {
+        log::trace!("fn ProviderRegistry::create(config={:?})", config);
+
+        let factory = self
+            .factories
+            .get(config.provider.as_str())
+            .ok_or_else(|| {
+                DdnsError::ProviderError(ProviderError::UnknownProviderError(
+                    config.provider.clone(),
+                ))
+            })?;
+        factory(config, context)
+    }
+
+    /// Creates an updater over the providers selected by the given
+    /// configurations.
+    pub fn make_updater(
+        &self,
+        configs: &[ProviderConfig],
+        limits: RegistrarLimits,
+    ) -> DdnsResult<ProviderUpdater> {
+        let context = ProviderContext::new(limits);
+        let providers = configs
+            .iter()
+            .map(|config| self.create(config, &context))
+            .collect::<DdnsResult<Vec<_>>>()?;
+        ProviderUpdater::new(providers)
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = ProviderRegistry::empty();
+        registry.register("generic_http", GenericHttpProvider::from_config);
+        registry.register("route53", Route53Provider::from_config);
+        registry
+    }
+}
+
+/// Determines whether two addresses are the same, comparing IP addresses by value
+/// so that differently formatted IPv6 addresses match.
+fn is_same_address(current_address: &str, ip_address: &str) -> bool {
+    match (
+        current_address.trim().parse::<std::net::IpAddr>(),
+        ip_address.parse::<std::net::IpAddr>(),
+    ) {
+        (Ok(current_address), Ok(ip_address)) => current_address == ip_address,
+        _ => current_address.trim() == ip_address,
+    }
+}
+
+/// Writes the named record with the given IP address, unless its current address
+/// already matches. Records are still written when their current address cannot
+/// be retrieved.
+pub(crate) fn make_read_before_write_future<W>(
+    record_name: String,
+    ip_address: String,
+    current_address: DdnsBoxFuture<Option<String>>,
+    write: W,
+) -> DdnsBoxFuture<()>
+where
+    W: FnOnce() -> DdnsBoxFuture<()> + Send + 'static,
+{
+    log::trace!(
+        "fn make_read_before_write_future(record_name={:?}, ip_address={:?})",
+        record_name,
+        ip_address,
+    );
+
+    Box::new(
+        current_address.then(move |current_address| match current_address {
+            Ok(Some(ref current_address))
+                if is_same_address(current_address, ip_address.as_str()) =>
+            {
+                log::info!(
+                    "Registrar record '{}' already points at {}; skipping update",
+                    record_name,
+                    ip_address,
+                );
+                future::Either::A(future::ok(()))
+            }
+            current_address => {
+                if let Err(error) = current_address {
+                    log::warn!(
+                        "Failed to retrieve registrar record '{}': {:?}",
+                        record_name,
+                        error,
+                    );
+                }
+                future::Either::B(write())
+            }
+        }),
+    )
+}
+
+/// Updates records through the provider that manages each of them, skipping
+/// records that already point at the new IP address.
+pub struct ProviderUpdater {
+    providers: Vec<std::sync::Arc<dyn Provider>>,
+    records: Vec<(String, usize)>,
+}
+
+impl ProviderUpdater {
+    /// Creates an updater over the given providers. Each record may only be
+    /// managed by a single provider.
+    pub fn new(providers: Vec<Box<dyn Provider>>) -> DdnsResult<Self> {
+        let mut records: Vec<(String, usize)> = Vec::new();
+        for (index, provider) in providers.iter().enumerate() {
+            for name in provider.record_names()? {
+                if records.iter().any(|(record_name, _)| *record_name == name) {
+                    return Err(DdnsError::ProviderError(
+                        ProviderError::DuplicateRecordError(name),
+                    ));
+                }
+                records.push((name, index));
+            }
+        }
+        Ok(ProviderUpdater {
+            providers: providers.into_iter().map(std::sync::Arc::from).collect(),
+            records,
+        })
+    }
+
+    /// Returns the provider that manages the named record.
+    pub fn provider(&self, record_name: &str) -> Option<std::sync::Arc<dyn Provider>> {
+        self.records
+            .iter()
+            .find(|(name, _)| name == record_name)
+            .map(|(_, index)| self.providers[*index].clone())
+    }
+}
+
+impl Updater for ProviderUpdater {
+    fn record_names(&self) -> DdnsResult<Vec<String>> {
+        Ok(self.records.iter().map(|(name, _)| name.clone()).collect())
+    }
+
+    fn update(&self, record_names: &[String], ip_address: &str) -> DdnsResult<UpdateFuture> {
+        let update_futures: Vec<_> = record_names
+            .iter()
+            .filter_map(|name| {
+                self.provider(name.as_str()).map(|provider| {
+                    let name = name.to_owned();
+                    provider
+                        .update(name.as_str(), ip_address)
+                        .then(move |result| Ok((name, result)))
+                })
+            })
+            .collect();
+        Ok(Box::new(future::join_all(update_futures)))
+    }
+
+    fn delete(&self, record_name: &str) -> DdnsResult<DdnsBoxFuture<()>> {
+        self.provider(record_name)
+            .map(|provider| provider.delete(record_name))
+            .ok_or_else(|| {
+                DdnsError::ProviderError(ProviderError::UnknownRecordError(record_name.to_owned()))
+            })
+    }
+}
@@ -0,0 +1,162 @@
+use tokio::prelude::{future, Future, Stream};
+
+use crate::auth::{authorize_request, RequestAuth};
+use crate::error::{
+    DdnsError, DdnsFuture, RequestError, RequestResult, ResponseError, StatusError,
+};
+
+/// A certificate the client authenticates itself with to servers that require
+/// mutual TLS.
+#[derive(Clone, Debug, new)]
+pub struct ClientCertificate {
+    /// PEM-encoded certificate chain, starting with the client certificate.
+    pub certificate_path: std::path::PathBuf,
+    /// PEM-encoded PKCS #8 private key of the client certificate.
+    pub key_path: std::path::PathBuf,
+}
+
+impl ClientCertificate {
+    /// Reads the certificate files, so that rotated certificates are used for
+    /// new connections.
+    fn load_identity(&self) -> RequestResult<native_tls::Identity> {
+        let read = |path: &std::path::Path| {
+            std::fs::read(path)
+                .map_err(|error| RequestError::CertificateError(path.to_owned(), error))
+        };
+        let certificate = read(self.certificate_path.as_path())?;
+        let key = read(self.key_path.as_path())?;
+        native_tls::Identity::from_pkcs8(certificate.as_slice(), key.as_slice())
+            .map_err(RequestError::HyperTlsError)
+    }
+}
+
+fn make_https_connector(
+    client_certificate: Option<&ClientCertificate>,
+) -> RequestResult<hyper_tls::HttpsConnector<hyper::client::HttpConnector>> {
+    match client_certificate {
+        Some(client_certificate) => {
+            let tls_connector = native_tls::TlsConnector::builder()
+                .identity(client_certificate.load_identity()?)
+                .build()
+                .map_err(RequestError::HyperTlsError)?;
+            let mut http_connector = hyper::client::HttpConnector::new(4);
+            http_connector.enforce_http(false);
+            Ok(hyper_tls::HttpsConnector::from((
+                http_connector,
+                tls_connector,
+            )))
+        }
+        None => hyper_tls::HttpsConnector::new(4).map_err(RequestError::HyperTlsError),
+    }
+}
+
+enum RequestScheme {
+    Http,
+    Https,
+}
+
+fn get_request_scheme(scheme_option: Option<&http::uri::Scheme>) -> RequestScheme {
+    scheme_option.map_or(RequestScheme::Http, |scheme| {
+        if scheme.as_str() == "https" {
+            RequestScheme::Https
+        } else {
+            RequestScheme::Http
+        }
+    })
+}
+
+pub(crate) fn make_request_future(
+    address: &str,
+    method: &str,
+    headers: &[(String, String)],
+    body: String,
+    auth: Option<RequestAuth>,
+    client_certificate: Option<&ClientCertificate>,
+) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
+    log::trace!(
+        "fn make_request_future(address={:?}, method={:?}, headers={:?}, body={:?})",
+        address,
+        method,
+        headers,
+        body,
+    );
+
+    let https_connector = make_https_connector(client_certificate);
+    future::result(make_request(address, method, headers, body, auth.as_ref()))
+        .and_then(
+            move |request| match get_request_scheme(request.uri().scheme_part()) {
+                RequestScheme::Http => {
+                    let connector = hyper::client::HttpConnector::new(4);
+                    future::Either::A(
+                        hyper::Client::builder()
+                            .build::<_, hyper::Body>(connector)
+                            .request(request)
+                            .map_err(RequestError::HyperError),
+                    )
+                }
+                RequestScheme::Https => {
+                    future::Either::B(future::result(https_connector).and_then(|connector| {
+                        hyper::Client::builder()
+                            .build::<_, hyper::Body>(connector)
+                            .request(request)
+                            .map_err(RequestError::HyperError)
+                    }))
+                }
+            },
+        )
+        .map_err(DdnsError::RequestError)
+}
+
+fn make_request(
+    address: &str,
+    method: &str,
+    headers: &[(String, String)],
+    body: String,
+    auth: Option<&RequestAuth>,
+) -> RequestResult<hyper::Request<hyper::Body>> {
+    let mut builder = hyper::Request::builder();
+    builder.uri(address).method(method);
+    headers.into_iter().for_each(|(key, value)| {
+        builder.header(key.as_str(), value.as_str());
+    });
+    let body = body.into_bytes();
+    let mut request = builder
+        .body(hyper::Body::from(body.clone()))
+        .map_err(RequestError::HttpError)?;
+    if let Some(auth) = auth {
+        authorize_request(&mut request, body.as_slice(), auth)?;
+    }
+    Ok(request)
+}
+
+pub(crate) fn decode_response(
+    response: hyper::Response<hyper::Body>,
+) -> impl DdnsFuture<bytes::Bytes> {
+    response
+        .into_body()
+        .fold(
+            bytes::Bytes::new(),
+            |mut accumulator, chunk| -> hyper::Result<bytes::Bytes> {
+                accumulator.extend_from_slice(chunk.into_bytes().as_ref());
+                Ok(accumulator)
+            },
+        )
+        .map_err(|error| DdnsError::ResponseError(ResponseError::HyperError(error)))
+}
+
+pub(crate) fn decode_registrar_response(
+    status_code: u16,
+    response: hyper::Response<hyper::Body>,
+) -> impl DdnsFuture<()> {
+    log::trace!("fn decode_registrar_response(response={:?})", response);
+
+    decode_response(response).and_then(move |response_bytes| {
+        future::err(DdnsError::ResponseError(ResponseError::StatusError(
+            StatusError::new(
+                status_code,
+                String::from_utf8_lossy(response_bytes.as_ref()).to_string(),
+                None,
+            ),
+        )))
+    })
+}
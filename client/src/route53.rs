@@ -0,0 +1,475 @@
+use tokio::prelude::{future, Future};
+
+use crate::auth::{percent_encode, RegistrarAuth};
+use crate::error::{DdnsBoxFuture, DdnsError, DdnsFuture, DdnsResult, ProviderError};
+use crate::extract::ValueExtractor;
+use crate::provider::{Provider, ProviderConfig, ProviderContext};
+use crate::template::{make_registrar_future, validate_registrar_response, RegistrarRequest};
+use crate::validate::ResponseValidation;
+
+const PROVIDER_NAME: &str = "route53";
+const API_VERSION: &str = "2013-04-01";
+const XML_NAMESPACE: &str = "https://route53.amazonaws.com/doc/2013-04-01/";
+
+/// A resource record set, as listed by `ListResourceRecordSets`.
+#[derive(Clone, Debug)]
+struct ResourceRecordSet {
+    name: String,
+    record_type: String,
+    ttl: u64,
+    values: Vec<String>,
+}
+
+/// Manages A and AAAA records of an AWS Route 53 hosted zone through the
+/// `ChangeResourceRecordSets` API, waiting for each change to propagate to all
+/// Route 53 name servers.
+#[derive(Clone, Debug)]
+pub struct Route53Provider {
+    endpoint: String,
+    hosted_zone_id: String,
+    records: Vec<String>,
+    ttl: u64,
+    auth: RegistrarAuth,
+    sync_interval: Option<std::time::Duration>,
+    sync_timeout: std::time::Duration,
+    context: ProviderContext,
+}
+
+fn get_string_option(config: &ProviderConfig, name: &str) -> DdnsResult<Option<String>> {
+    match config.options.get(name) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(value)) => Ok(Some(value.to_owned())),
+        Some(_) => Err(option_error(config, name)),
+    }
+}
+
+fn get_u64_option(config: &ProviderConfig, name: &str, default: u64) -> DdnsResult<u64> {
+    match config.options.get(name) {
+        None | Some(serde_json::Value::Null) => Ok(default),
+        Some(value) => value.as_u64().ok_or_else(|| option_error(config, name)),
+    }
+}
+
+fn option_error(config: &ProviderConfig, name: &str) -> DdnsError {
+    DdnsError::ProviderError(ProviderError::OptionError(
+        config.provider.clone(),
+        name.to_owned(),
+    ))
+}
+
+/// Reads a credential from the given option, falling back to the environment
+/// variable the AWS CLI and SDKs read it from.
+fn get_credential(
+    config: &ProviderConfig,
+    name: &str,
+    variable: &str,
+) -> DdnsResult<Option<String>> {
+    Ok(get_string_option(config, name)?.or_else(|| std::env::var(variable).ok()))
+}
+
+/// Appends the trailing dot Route 53 uses in record names, if it is missing.
+fn get_fully_qualified_name(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_owned()
+    } else {
+        format!("{}.", name)
+    }
+}
+
+fn get_record_type(ip_address: &str) -> &'static str {
+    match ip_address.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V6(_)) => "AAAA",
+        _ => "A",
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Makes the body of a `ChangeResourceRecordSets` request applying the given
+/// action to each record set.
+fn make_change_batch(action: &str, record_sets: &[ResourceRecordSet]) -> String {
+    let changes: String = record_sets
+        .iter()
+        .map(|record_set| {
+            let values: String = record_set
+                .values
+                .iter()
+                .map(|value| {
+                    format!(
+                        "<ResourceRecord><Value>{}</Value></ResourceRecord>",
+                        xml_escape(value),
+                    )
+                })
+                .collect();
+            format!(
+                "<Change><Action>{}</Action><ResourceRecordSet><Name>{}</Name><Type>{}</Type>\
+                 <TTL>{}</TTL><ResourceRecords>{}</ResourceRecords></ResourceRecordSet></Change>",
+                action,
+                xml_escape(record_set.name.as_str()),
+                record_set.record_type,
+                record_set.ttl,
+                values,
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <ChangeResourceRecordSetsRequest xmlns=\"{}\"><ChangeBatch>\
+         <Comment>Updated by ddns</Comment><Changes>{}</Changes>\
+         </ChangeBatch></ChangeResourceRecordSetsRequest>",
+        XML_NAMESPACE, changes,
+    )
+}
+
+fn invalid_response_error(message: String) -> DdnsError {
+    DdnsError::ProviderError(ProviderError::InvalidResponseError(
+        PROVIDER_NAME.to_owned(),
+        message,
+    ))
+}
+
+fn parse_xml(body: &str) -> DdnsResult<roxmltree::Document<'_>> {
+    roxmltree::Document::parse(body).map_err(|error| invalid_response_error(error.to_string()))
+}
+
+fn get_child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+}
+
+/// Reads the ID and status of a change from a `ChangeResourceRecordSets` or
+/// `GetChange` response.
+fn parse_change_info(body: &str) -> DdnsResult<(String, String)> {
+    let document = parse_xml(body)?;
+    document
+        .descendants()
+        .find(|node| node.has_tag_name("ChangeInfo"))
+        .and_then(|change_info| {
+            let id = get_child_text(change_info, "Id")?;
+            let status = get_child_text(change_info, "Status")?;
+            Some((
+                id.trim_start_matches("/change/").to_owned(),
+                status.to_owned(),
+            ))
+        })
+        .ok_or_else(|| invalid_response_error("Missing ChangeInfo".to_owned()))
+}
+
+/// Reads the record sets of a `ListResourceRecordSets` response.
+fn parse_record_sets(body: &str) -> DdnsResult<Vec<ResourceRecordSet>> {
+    let document = parse_xml(body)?;
+    document
+        .descendants()
+        .filter(|node| node.has_tag_name("ResourceRecordSet"))
+        .map(|record_set| {
+            let values = record_set
+                .descendants()
+                .filter(|node| node.has_tag_name("Value"))
+                .filter_map(|value| value.text())
+                .map(|value| value.to_owned())
+                .collect();
+            Some(ResourceRecordSet {
+                name: get_child_text(record_set, "Name")?.to_owned(),
+                record_type: get_child_text(record_set, "Type")?.to_owned(),
+                ttl: get_child_text(record_set, "TTL")
+                    .and_then(|ttl| ttl.parse().ok())
+                    .unwrap_or_default(),
+                values,
+            })
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid_response_error("Invalid ResourceRecordSet".to_owned()))
+}
+
+impl Route53Provider {
+    /// Creates a provider from the `hosted_zone_id`, `records`, `ttl`,
+    /// credential, `endpoint` and synchronization options of the given
+    /// configuration.
+    pub fn from_config(
+        config: &ProviderConfig,
+        context: &ProviderContext,
+    ) -> DdnsResult<Box<dyn Provider>> {
+        log::trace!("fn Route53Provider::from_config(config={:?})", config);
+
+        let hosted_zone_id = get_string_option(config, "hosted_zone_id")?
+            .ok_or_else(|| option_error(config, "hosted_zone_id"))?;
+        let records = config
+            .option("records")?
+            .as_array()
+            .and_then(|records| {
+                records
+                    .iter()
+                    .map(|record| record.as_str().map(|record| record.to_owned()))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(|| option_error(config, "records"))?;
+        let auth = RegistrarAuth::Sigv4 {
+            access_key_id: get_credential(config, "access_key_id", "AWS_ACCESS_KEY_ID")?
+                .ok_or_else(|| option_error(config, "access_key_id"))?,
+            secret_access_key: get_credential(
+                config,
+                "secret_access_key",
+                "AWS_SECRET_ACCESS_KEY",
+            )?
+            .ok_or_else(|| option_error(config, "secret_access_key"))?,
+            session_token: get_credential(config, "session_token", "AWS_SESSION_TOKEN")?,
+            region: get_string_option(config, "region")?.unwrap_or_else(|| "us-east-1".to_owned()),
+            service: "route53".to_owned(),
+        };
+        let wait_for_sync = match config.options.get("wait_for_sync") {
+            None | Some(serde_json::Value::Null) => true,
+            Some(value) => value
+                .as_bool()
+                .ok_or_else(|| option_error(config, "wait_for_sync"))?,
+        };
+        let sync_interval =
+            std::time::Duration::from_secs(get_u64_option(config, "sync_interval", 5)?);
+
+        Ok(Box::new(Route53Provider {
+            endpoint: get_string_option(config, "endpoint")?
+                .unwrap_or_else(|| "https://route53.amazonaws.com".to_owned())
+                .trim_end_matches('/')
+                .to_owned(),
+            hosted_zone_id: hosted_zone_id.trim_start_matches("/hostedzone/").to_owned(),
+            records,
+            ttl: get_u64_option(config, "ttl", 300)?,
+            auth,
+            sync_interval: if wait_for_sync {
+                Some(sync_interval)
+            } else {
+                None
+            },
+            sync_timeout: std::time::Duration::from_secs(get_u64_option(
+                config,
+                "sync_timeout",
+                300,
+            )?),
+            context: context.clone(),
+        }))
+    }
+
+    fn get_record_name(&self, record_name: &str) -> DdnsResult<String> {
+        self.records
+            .iter()
+            .find(|name| *name == record_name)
+            .map(|name| get_fully_qualified_name(name))
+            .ok_or_else(|| {
+                DdnsError::ProviderError(ProviderError::UnknownRecordError(record_name.to_owned()))
+            })
+    }
+
+    fn make_request(
+        &self,
+        record_name: &str,
+        method: &str,
+        path: &str,
+        body: String,
+    ) -> RegistrarRequest {
+        let headers = if body.is_empty() {
+            Vec::new()
+        } else {
+            vec![("Content-Type".to_owned(), "text/xml".to_owned())]
+        };
+        RegistrarRequest {
+            name: record_name.to_owned(),
+            method: method.to_owned(),
+            address: format!("{}/{}{}", self.endpoint, API_VERSION, path),
+            headers,
+            body,
+            get: None,
+            delete: None,
+            extractor: None,
+            response: ResponseValidation {
+                error_message: regex::Regex::new("<Message>([^<]*)</Message>")
+                    .ok()
+                    .map(ValueExtractor::Regex),
+                ..ResponseValidation::default()
+            },
+            steps: Vec::new(),
+            auth: Some(self.auth.clone()),
+        }
+    }
+
+    /// Sends a request to the Route 53 API, returning the body of its
+    /// response if it succeeded.
+    fn send_request(&self, request: RegistrarRequest) -> impl DdnsFuture<String> {
+        let validation = request.response.clone();
+        make_registrar_future(request, self.context.clone())
+            .and_then(move |response| validate_registrar_response(validation, response))
+    }
+
+    /// Lists the A and AAAA record sets with the given name.
+    fn make_list_future(&self, record_name: &str) -> DdnsBoxFuture<Vec<ResourceRecordSet>> {
+        let name = match self.get_record_name(record_name) {
+            Ok(name) => name,
+            Err(error) => return Box::new(future::err(error)),
+        };
+        let request = self.make_request(
+            record_name,
+            "GET",
+            format!(
+                "/hostedzone/{}/rrset?name={}&maxitems=2",
+                self.hosted_zone_id,
+                percent_encode(name.as_str()),
+            )
+            .as_str(),
+            String::new(),
+        );
+        Box::new(self.send_request(request).and_then(move |body| {
+            parse_record_sets(body.as_str()).map(|record_sets| {
+                record_sets
+                    .into_iter()
+                    .filter(|record_set| {
+                        record_set.name.eq_ignore_ascii_case(name.as_str())
+                            && (record_set.record_type == "A" || record_set.record_type == "AAAA")
+                    })
+                    .collect()
+            })
+        }))
+    }
+
+    /// Submits a change batch, then waits for the change to be applied to all
+    /// Route 53 name servers, unless configured not to.
+    fn make_change_future(
+        &self,
+        record_name: &str,
+        action: &str,
+        record_sets: &[ResourceRecordSet],
+    ) -> DdnsBoxFuture<()> {
+        log::trace!(
+            "fn Route53Provider::make_change_future(record_name={:?}, action={:?}, record_sets={:?})",
+            record_name,
+            action,
+            record_sets,
+        );
+
+        let request = self.make_request(
+            record_name,
+            "POST",
+            format!("/hostedzone/{}/rrset", self.hosted_zone_id).as_str(),
+            make_change_batch(action, record_sets),
+        );
+        let provider = self.clone();
+        let record_name = record_name.to_owned();
+        Box::new(
+            self.send_request(request)
+                .and_then(|body| parse_change_info(body.as_str()))
+                .and_then(move |(change_id, status)| match provider.sync_interval {
+                    Some(sync_interval) => future::Either::A(provider.make_sync_future(
+                        record_name,
+                        change_id,
+                        status,
+                        sync_interval,
+                    )),
+                    None => future::Either::B(future::ok(())),
+                }),
+        )
+    }
+
+    /// Polls the status of a change until it is `INSYNC`.
+    fn make_sync_future(
+        &self,
+        record_name: String,
+        change_id: String,
+        status: String,
+        sync_interval: std::time::Duration,
+    ) -> impl DdnsFuture<()> {
+        let deadline = std::time::Instant::now() + self.sync_timeout;
+        let provider = self.clone();
+        future::loop_fn(status, move |status| {
+            if status == "INSYNC" {
+                log::debug!(
+                    "Route 53 change {} for registrar record '{}' is in sync",
+                    change_id,
+                    record_name,
+                );
+                return future::Either::A(future::ok(future::Loop::Break(())));
+            }
+            if std::time::Instant::now() + sync_interval > deadline {
+                return future::Either::A(future::err(DdnsError::ProviderError(
+                    ProviderError::SyncTimeoutError(PROVIDER_NAME.to_owned(), change_id.clone()),
+                )));
+            }
+
+            log::debug!(
+                "Route 53 change {} for registrar record '{}' is {}; checking again in {:?}",
+                change_id,
+                record_name,
+                status,
+                sync_interval,
+            );
+            let request = provider.make_request(
+                record_name.as_str(),
+                "GET",
+                format!("/change/{}", change_id).as_str(),
+                String::new(),
+            );
+            let send_future = provider.send_request(request);
+            future::Either::B(
+                tokio::timer::Delay::new(std::time::Instant::now() + sync_interval)
+                    .map_err(DdnsError::DelayError)
+                    .and_then(move |_| send_future)
+                    .and_then(|body| parse_change_info(body.as_str()))
+                    .map(|(_, status)| future::Loop::Continue(status)),
+            )
+        })
+    }
+}
+
+impl Provider for Route53Provider {
+    fn record_names(&self) -> DdnsResult<Vec<String>> {
+        Ok(self.records.clone())
+    }
+
+    fn get_current(&self, record_name: &str, ip_address: &str) -> DdnsBoxFuture<Option<String>> {
+        let record_type = get_record_type(ip_address);
+        Box::new(self.make_list_future(record_name).map(move |record_sets| {
+            record_sets
+                .into_iter()
+                .find(|record_set| record_set.record_type == record_type)
+                .and_then(|record_set| record_set.values.into_iter().next())
+        }))
+    }
+
+    fn upsert(&self, record_name: &str, ip_address: &str) -> DdnsBoxFuture<()> {
+        match self.get_record_name(record_name) {
+            Ok(name) => {
+                let record_set = ResourceRecordSet {
+                    name,
+                    record_type: get_record_type(ip_address).to_owned(),
+                    ttl: self.ttl,
+                    values: vec![ip_address.to_owned()],
+                };
+                self.make_change_future(record_name, "UPSERT", &[record_set])
+            }
+            Err(error) => Box::new(future::err(error)),
+        }
+    }
+
+    fn delete(&self, record_name: &str) -> DdnsBoxFuture<()> {
+        let provider = self.clone();
+        let record_name = record_name.to_owned();
+        Box::new(
+            self.make_list_future(record_name.as_str())
+                .and_then(move |record_sets| {
+                    if record_sets.is_empty() {
+                        future::Either::A(future::ok(()))
+                    } else {
+                        future::Either::B(provider.make_change_future(
+                            record_name.as_str(),
+                            "DELETE",
+                            record_sets.as_slice(),
+                        ))
+                    }
+                }),
+        )
+    }
+}
@@ -0,0 +1,356 @@
+use tokio::prelude::{task, Async, Future, Poll};
+
+/// Limits on the requests made to each registrar host.
+#[derive(Clone, Copy, Debug, new)]
+pub struct RegistrarLimits {
+    pub concurrency: usize,
+    pub rate_limit_per_minute: u64,
+    pub burst: u64,
+    pub max_retries: u64,
+}
+
+impl Default for RegistrarLimits {
+    fn default() -> Self {
+        RegistrarLimits::new(4, 0, 1, 3)
+    }
+}
+
+/// A token bucket that reserves send times for requests to a single registrar
+/// host.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: std::time::Instant,
+    paused_until: Option<std::time::Instant>,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, now: std::time::Instant) -> Self {
+        TokenBucket {
+            tokens: capacity,
+            last_refill: now,
+            paused_until: None,
+        }
+    }
+
+    /// Takes a token from the bucket, returning the instant at which the request
+    /// it was taken for may be sent. Tokens may be borrowed from the future, in
+    /// which case the request must wait until the bucket has refilled.
+    fn reserve(&mut self, limits: &RegistrarLimits, now: std::time::Instant) -> std::time::Instant {
+        let send_at = if limits.rate_limit_per_minute == 0 {
+            now
+        } else {
+            let tokens_per_sec = limits.rate_limit_per_minute as f64 / 60.0;
+            let capacity = limits.burst.max(1) as f64;
+            let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * tokens_per_sec).min(capacity);
+            self.last_refill = now;
+            self.tokens -= 1.0;
+            if self.tokens >= 0.0 {
+                now
+            } else {
+                now + std::time::Duration::from_secs_f64(-self.tokens / tokens_per_sec)
+            }
+        };
+        match self.paused_until {
+            Some(paused_until) if paused_until > send_at => paused_until,
+            _ => send_at,
+        }
+    }
+
+    fn pause(&mut self, until: std::time::Instant) {
+        if self
+            .paused_until
+            .is_none_or(|paused_until| paused_until < until)
+        {
+            self.paused_until.replace(until);
+        }
+    }
+}
+
+/// The requests in flight to a single registrar host, and the tasks waiting to
+/// send more, in the order they started waiting.
+#[derive(Debug, Default)]
+struct HostSlots {
+    in_flight: usize,
+    next_ticket: u64,
+    waiters: std::collections::VecDeque<(u64, task::Task)>,
+    /// Tickets of waiters a released slot was handed to, which they have not
+    /// yet taken.
+    granted: std::collections::HashSet<u64>,
+}
+
+impl HostSlots {
+    /// Hands a slot that is no longer needed to the longest waiting task, or
+    /// frees it if no task is waiting.
+    fn release(&mut self) {
+        match self.waiters.pop_front() {
+            Some((ticket, waiter)) => {
+                self.granted.insert(ticket);
+                waiter.notify();
+            }
+            None => self.in_flight -= 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RegistrarScheduler {
+    pub(crate) limits: RegistrarLimits,
+    buckets: std::sync::Mutex<std::collections::HashMap<String, TokenBucket>>,
+    slots: std::sync::Mutex<std::collections::HashMap<String, HostSlots>>,
+}
+
+/// A slot for a request in flight to a registrar host, released when dropped.
+#[derive(Debug)]
+pub(crate) struct RegistrarPermit {
+    scheduler: std::sync::Arc<RegistrarScheduler>,
+    host: String,
+}
+
+impl Drop for RegistrarPermit {
+    fn drop(&mut self) {
+        let mut slots = self.scheduler.slots.lock().unwrap();
+        if let Some(host_slots) = slots.get_mut(self.host.as_str()) {
+            host_slots.release();
+        }
+    }
+}
+
+/// Takes a slot for a request to a registrar host. Slots are handed to waiting
+/// requests in the order they started waiting.
+#[derive(Debug)]
+pub(crate) struct AcquirePermit {
+    scheduler: std::sync::Arc<RegistrarScheduler>,
+    host: String,
+    ticket: Option<u64>,
+}
+
+impl Future for AcquirePermit {
+    type Item = RegistrarPermit;
+    type Error = crate::error::DdnsError;
+
+    fn poll(&mut self) -> Poll<RegistrarPermit, crate::error::DdnsError> {
+        let mut slots = self.scheduler.slots.lock().unwrap();
+        let host_slots = slots.entry(self.host.clone()).or_default();
+        let acquired = match self.ticket {
+            Some(ticket) => host_slots.granted.remove(&ticket),
+            None if self.scheduler.limits.concurrency == 0 => true,
+            None => {
+                host_slots.waiters.is_empty()
+                    && host_slots.in_flight < self.scheduler.limits.concurrency
+            }
+        };
+        if acquired {
+            if self.ticket.take().is_none() {
+                host_slots.in_flight += 1;
+            }
+            return Ok(Async::Ready(RegistrarPermit {
+                scheduler: self.scheduler.clone(),
+                host: self.host.clone(),
+            }));
+        }
+
+        match self.ticket {
+            Some(ticket) => {
+                if let Some((_, waiter)) = host_slots
+                    .waiters
+                    .iter_mut()
+                    .find(|(waiting_ticket, _)| *waiting_ticket == ticket)
+                {
+                    *waiter = task::current();
+                }
+            }
+            None => {
+                let ticket = host_slots.next_ticket;
+                host_slots.next_ticket += 1;
+                host_slots.waiters.push_back((ticket, task::current()));
+                self.ticket.replace(ticket);
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for AcquirePermit {
+    fn drop(&mut self) {
+        let ticket = match self.ticket {
+            Some(ticket) => ticket,
+            None => return,
+        };
+        let mut slots = self.scheduler.slots.lock().unwrap();
+        if let Some(host_slots) = slots.get_mut(self.host.as_str()) {
+            if host_slots.granted.remove(&ticket) {
+                // The slot was handed over but never taken, so it passes on.
+                host_slots.release();
+            } else {
+                host_slots
+                    .waiters
+                    .retain(|(waiting_ticket, _)| *waiting_ticket != ticket);
+            }
+        }
+    }
+}
+
+impl RegistrarScheduler {
+    pub(crate) fn new(limits: RegistrarLimits) -> Self {
+        RegistrarScheduler {
+            limits,
+            buckets: std::sync::Mutex::new(std::collections::HashMap::new()),
+            slots: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    fn with_bucket<T>(
+        &self,
+        host: &str,
+        f: impl FnOnce(&mut TokenBucket, std::time::Instant) -> T,
+    ) -> T {
+        let now = std::time::Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(host.to_owned())
+            .or_insert_with(|| TokenBucket::new(self.limits.burst.max(1) as f64, now));
+        f(bucket, now)
+    }
+
+    pub(crate) fn reserve(&self, host: &str) -> std::time::Instant {
+        let limits = self.limits;
+        self.with_bucket(host, |bucket, now| bucket.reserve(&limits, now))
+    }
+
+    pub(crate) fn pause(&self, host: &str, duration: std::time::Duration) {
+        self.with_bucket(host, |bucket, now| bucket.pause(now + duration))
+    }
+
+    /// Takes a slot for a request to the given host, waiting until fewer than
+    /// the configured concurrency are in flight and every request that started
+    /// waiting earlier has been sent.
+    pub(crate) fn acquire(self: &std::sync::Arc<Self>, host: &str) -> AcquirePermit {
+        AcquirePermit {
+            scheduler: self.clone(),
+            host: host.to_owned(),
+            ticket: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::prelude::future;
+
+    fn secs(secs: f64) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(secs)
+    }
+
+    /// Runs the given function on a task, so that it can poll futures by hand.
+    fn on_task<T>(f: impl FnOnce() -> T) -> T {
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(future::lazy(|| Ok::<_, ()>(f())))
+            .unwrap()
+    }
+
+    fn is_ready(acquire: &mut AcquirePermit) -> Option<RegistrarPermit> {
+        match acquire.poll().unwrap() {
+            Async::Ready(permit) => Some(permit),
+            Async::NotReady => None,
+        }
+    }
+
+    #[test]
+    fn sends_immediately_without_a_rate_limit() {
+        let now = std::time::Instant::now();
+        let limits = RegistrarLimits::new(4, 0, 1, 3);
+        let mut bucket = TokenBucket::new(1.0, now);
+        assert_eq!(bucket.reserve(&limits, now), now);
+        assert_eq!(bucket.reserve(&limits, now), now);
+    }
+
+    #[test]
+    fn spaces_requests_past_the_burst_at_the_rate_limit() {
+        let now = std::time::Instant::now();
+        let limits = RegistrarLimits::new(4, 30, 2, 3);
+        let mut bucket = TokenBucket::new(2.0, now);
+        assert_eq!(bucket.reserve(&limits, now), now);
+        assert_eq!(bucket.reserve(&limits, now), now);
+        assert_eq!(bucket.reserve(&limits, now), now + secs(2.0));
+        assert_eq!(bucket.reserve(&limits, now), now + secs(4.0));
+
+        // Borrowed tokens are paid back before the bucket refills.
+        let later = now + secs(5.0);
+        assert_eq!(bucket.reserve(&limits, later), later + secs(1.0));
+        let later = now + secs(60.0);
+        assert_eq!(bucket.reserve(&limits, later), later);
+        assert_eq!(bucket.reserve(&limits, later), later);
+        assert_eq!(bucket.reserve(&limits, later), later + secs(2.0));
+    }
+
+    #[test]
+    fn holds_requests_while_paused() {
+        let now = std::time::Instant::now();
+        let limits = RegistrarLimits::new(4, 0, 1, 3);
+        let mut bucket = TokenBucket::new(1.0, now);
+        bucket.pause(now + secs(5.0));
+        bucket.pause(now + secs(3.0));
+        assert_eq!(bucket.reserve(&limits, now), now + secs(5.0));
+        assert_eq!(bucket.reserve(&limits, now + secs(6.0)), now + secs(6.0));
+    }
+
+    #[test]
+    fn caps_the_requests_in_flight_to_each_host() {
+        let scheduler =
+            std::sync::Arc::new(RegistrarScheduler::new(RegistrarLimits::new(2, 0, 1, 3)));
+        on_task(|| {
+            let first = is_ready(&mut scheduler.acquire("a.example")).unwrap();
+            let _second = is_ready(&mut scheduler.acquire("a.example")).unwrap();
+            let mut third = scheduler.acquire("a.example");
+            assert!(is_ready(&mut third).is_none());
+            assert!(is_ready(&mut scheduler.acquire("b.example")).is_some());
+
+            drop(first);
+            assert!(is_ready(&mut third).is_some());
+        });
+    }
+
+    #[test]
+    fn hands_slots_to_waiters_in_order() {
+        let scheduler =
+            std::sync::Arc::new(RegistrarScheduler::new(RegistrarLimits::new(1, 0, 1, 3)));
+        on_task(|| {
+            let permit = is_ready(&mut scheduler.acquire("a.example")).unwrap();
+            let mut second = scheduler.acquire("a.example");
+            let mut third = scheduler.acquire("a.example");
+            let mut fourth = scheduler.acquire("a.example");
+            assert!(is_ready(&mut second).is_none());
+            assert!(is_ready(&mut third).is_none());
+            assert!(is_ready(&mut fourth).is_none());
+
+            // A request that has not started waiting does not jump the queue.
+            drop(permit);
+            assert!(is_ready(&mut scheduler.acquire("a.example")).is_none());
+            assert!(is_ready(&mut third).is_none());
+            let permit = is_ready(&mut second).unwrap();
+
+            // A waiter that gives up passes its turn on.
+            drop(third);
+            drop(permit);
+            let permit = is_ready(&mut fourth).unwrap();
+            drop(permit);
+            assert!(is_ready(&mut scheduler.acquire("a.example")).is_some());
+        });
+    }
+
+    #[test]
+    fn does_not_cap_requests_without_a_concurrency() {
+        let scheduler =
+            std::sync::Arc::new(RegistrarScheduler::new(RegistrarLimits::new(0, 0, 1, 3)));
+        on_task(|| {
+            let permits = (0..8)
+                .map(|_| is_ready(&mut scheduler.acquire("a.example")).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(permits.len(), 8);
+        });
+    }
+}
@@ -0,0 +1,318 @@
+use ddns_common::protocol::{
+    advertised_versions, Capabilities, ErrorCode, ErrorResponse, ADDRESS_PATH, CAPABILITIES_PATH,
+    PROTOCOL_VERSION,
+};
+use ddns_common::signing::{make_nonce, ResponseVerifier, NONCE_HEADER};
+use tokio::prelude::{future, Future};
+
+use crate::auth::ServiceAuth;
+use crate::error::{
+    DdnsBoxFuture, DdnsError, DdnsFuture, RequestError, ResponseError, ServiceError, StatusError,
+};
+use crate::request::{decode_response, make_request_future, ClientCertificate};
+
+type ServiceResponse = ddns_common::AddressResponse;
+
+fn make_service_future(
+    address: &str,
+    auth: Option<&ServiceAuth>,
+    client_certificate: Option<&ClientCertificate>,
+    verifier: Option<&ResponseVerifier>,
+) -> impl DdnsFuture<(ServiceResponse, Vec<u32>)> {
+    log::trace!("fn make_service_future(address={:?})", address);
+
+    log::debug!("Retrieving current IP address");
+
+    let verifier = verifier.cloned();
+    let nonce = match verifier {
+        Some(_) => match make_nonce() {
+            Ok(nonce) => Some(nonce),
+            Err(error) => {
+                return future::Either::A(future::err(DdnsError::RequestError(
+                    RequestError::SigningError(error),
+                )))
+            }
+        },
+        None => None,
+    };
+    future::Either::B(
+        make_service_request_future(address, auth, client_certificate, nonce.as_deref())
+            .and_then(|response| {
+                let versions = advertised_versions(&response);
+                decode_service_response(response).map(|response| (response, versions))
+            })
+            .and_then(move |(response, versions)| match (verifier, nonce) {
+                (Some(verifier), Some(nonce)) => verifier
+                    .verify(&response, nonce.as_str())
+                    .map(|_| (response, versions))
+                    .map_err(|error| {
+                        DdnsError::ResponseError(ResponseError::SignatureError(error))
+                    }),
+                _ => Ok((response, versions)),
+            }),
+    )
+}
+
+fn make_service_request_future(
+    address: &str,
+    auth: Option<&ServiceAuth>,
+    client_certificate: Option<&ClientCertificate>,
+    nonce: Option<&str>,
+) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
+    let mut headers = vec![("Accept".to_owned(), "application/json".to_owned())];
+    headers.extend(nonce.map(|nonce| (NONCE_HEADER.to_owned(), nonce.to_owned())));
+    make_request_future(
+        address,
+        "GET",
+        headers.as_slice(),
+        String::new(),
+        auth.map(|auth| auth.to_request_auth()),
+        client_certificate,
+    )
+}
+
+fn decode_service_response(
+    response: hyper::Response<hyper::Body>,
+) -> impl DdnsFuture<ServiceResponse> {
+    log::trace!("fn decode_service_response(response={:?})", response);
+
+    decode_json_response(response)
+}
+
+/// Decodes the JSON body of a successful service response, or the error an
+/// unsuccessful one describes.
+fn decode_json_response<T>(response: hyper::Response<hyper::Body>) -> impl DdnsFuture<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(hyper::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    decode_response(response).and_then(move |response_bytes| {
+        if !status.is_success() {
+            return Err(DdnsError::ResponseError(decode_service_error(
+                status.as_u16(),
+                retry_after,
+                response_bytes.as_ref(),
+            )));
+        }
+        serde_json::from_slice(response_bytes.as_ref())
+            .map_err(|error| DdnsError::ResponseError(ResponseError::SerdeJsonError(error)))
+    })
+}
+
+/// Returns the error an error response describes, by its code or, for codes
+/// this client does not know, by its status. Bodies that are not error
+/// envelopes are reported as they are.
+fn decode_service_error(status: u16, retry_after: Option<u64>, body: &[u8]) -> ResponseError {
+    log::trace!(
+        "fn decode_service_error(status={:?}, retry_after={:?})",
+        status,
+        retry_after,
+    );
+
+    let error = match serde_json::from_slice::<ErrorResponse>(body) {
+        Ok(error) => error,
+        Err(_) => {
+            return ResponseError::StatusError(StatusError::new(
+                status,
+                String::from_utf8_lossy(body).to_string(),
+                None,
+            ))
+        }
+    };
+    let code = error.error_code();
+    let detail = |name: &str| {
+        error
+            .details
+            .as_ref()
+            .and_then(|details| details.get(name))
+            .cloned()
+    };
+    // The header is preferred, as proxies in front of the service may set it.
+    let retry_after =
+        retry_after.or_else(|| detail("retry_after").and_then(|value| value.as_u64()));
+    let addresses = detail("addresses")
+        .and_then(|value| serde_json::from_value::<Vec<String>>(value).ok())
+        .unwrap_or_default();
+    let service_error = ServiceError::new(status, error.code, error.message, error.details);
+    match (code, status) {
+        (Some(ErrorCode::Unauthorized), _) | (None, 401) => {
+            ResponseError::UnauthorizedError(service_error)
+        }
+        (Some(ErrorCode::Forbidden), _) | (None, 403) => {
+            ResponseError::ForbiddenError(service_error)
+        }
+        (Some(ErrorCode::RateLimited), _) | (None, 429) => {
+            ResponseError::RateLimitedError(service_error, retry_after)
+        }
+        (Some(ErrorCode::AddressUnavailable), _) | (Some(ErrorCode::NoAddresses), _) => {
+            ResponseError::AddressUnavailableError(service_error)
+        }
+        (Some(ErrorCode::MultipleAddresses), _) => {
+            ResponseError::AmbiguousAddressError(service_error, addresses)
+        }
+        _ => ResponseError::ServiceError(service_error),
+    }
+}
+
+/// Returns the origin of a service address without a path of its own, whose
+/// versioned endpoints can be used when the service speaks the protocol.
+fn get_service_origin(address: &str) -> Option<String> {
+    let uri = address.parse::<hyper::Uri>().ok()?;
+    if uri
+        .path_and_query()
+        .is_some_and(|path_and_query| path_and_query.as_str() != "/")
+    {
+        return None;
+    }
+    Some(format!(
+        "{}://{}",
+        uri.scheme_part()?,
+        uri.authority_part()?
+    ))
+}
+
+fn make_capabilities_future(
+    origin: &str,
+    auth: Option<&ServiceAuth>,
+    client_certificate: Option<&ClientCertificate>,
+) -> impl DdnsFuture<Capabilities> {
+    log::trace!("fn make_capabilities_future(origin={:?})", origin);
+
+    make_request_future(
+        format!("{}{}", origin, CAPABILITIES_PATH).as_str(),
+        "GET",
+        &[("Accept".to_owned(), "application/json".to_owned())],
+        String::new(),
+        auth.map(|auth| auth.to_request_auth()),
+        client_certificate,
+    )
+    .and_then(decode_json_response)
+}
+
+/// A source of the IP address that registrar records should point at.
+pub trait AddressSource: Send + Sync {
+    /// Retrieves the current IP address.
+    fn get_address(&self) -> DdnsBoxFuture<String>;
+}
+
+/// Retrieves the IP address from the JSON response of a DDNS service.
+#[derive(Debug, new)]
+pub struct HttpAddressSource {
+    address: String,
+    #[new(default)]
+    auth: Option<ServiceAuth>,
+    #[new(default)]
+    client_certificate: Option<ClientCertificate>,
+    #[new(default)]
+    verifier: Option<ResponseVerifier>,
+    /// The address to request, once the service has been found to speak the
+    /// protocol (or not). Unset until then, and again after a failed request,
+    /// in case the service changed.
+    #[new(default)]
+    resolved_address: std::sync::Arc<std::sync::Mutex<Option<Option<String>>>>,
+}
+
+impl HttpAddressSource {
+    /// Authenticates to the service with the given credentials, for services
+    /// that require authentication.
+    pub fn with_auth(mut self, auth: ServiceAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Authenticates to the service with the given client certificate, for
+    /// services that require mutual TLS.
+    pub fn with_client_certificate(mut self, client_certificate: ClientCertificate) -> Self {
+        self.client_certificate = Some(client_certificate);
+        self
+    }
+
+    /// Rejects responses that are not signed by the service, for the request
+    /// they answer, within the verifier's maximum age.
+    pub fn with_verifier(mut self, verifier: ResponseVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+}
+
+impl AddressSource for HttpAddressSource {
+    /// Retrieves the current IP address from the service address. When that
+    /// has no path of its own and the service advertises a protocol version
+    /// this client speaks, the service's capabilities are retrieved, and later
+    /// addresses are retrieved from its versioned address endpoint.
+    fn get_address(&self) -> DdnsBoxFuture<String> {
+        let resolved_address = self.resolved_address.lock().unwrap().clone();
+        let address = resolved_address
+            .clone()
+            .flatten()
+            .unwrap_or_else(|| self.address.clone());
+        let origin = match resolved_address {
+            Some(_) => None,
+            None => get_service_origin(self.address.as_str()),
+        };
+
+        let resolve = self.resolved_address.clone();
+        let reset = self.resolved_address.clone();
+        let auth = self.auth.clone();
+        let client_certificate = self.client_certificate.clone();
+        let signed = self.verifier.is_some();
+        Box::new(
+            make_service_future(
+                address.as_str(),
+                self.auth.as_ref(),
+                self.client_certificate.as_ref(),
+                self.verifier.as_ref(),
+            )
+            .and_then(move |(response, versions)| {
+                let origin = match origin {
+                    Some(origin) if versions.contains(&PROTOCOL_VERSION) => origin,
+                    _ => {
+                        resolve.lock().unwrap().get_or_insert(None);
+                        return future::Either::A(future::ok(response.ip));
+                    }
+                };
+                future::Either::B(
+                    make_capabilities_future(
+                        origin.as_str(),
+                        auth.as_ref(),
+                        client_certificate.as_ref(),
+                    )
+                    .then(move |capabilities| {
+                        let address = match capabilities {
+                            Ok(capabilities) if capabilities.supports_version(PROTOCOL_VERSION) => {
+                                log::info!("Service capabilities: {:?}", capabilities);
+                                if signed && !capabilities.signed {
+                                    log::warn!("Service does not advertise signed responses");
+                                }
+                                Some(format!("{}{}", origin, ADDRESS_PATH))
+                            }
+                            Ok(capabilities) => {
+                                log::info!(
+                                    "Service does not speak protocol version {}: {:?}",
+                                    PROTOCOL_VERSION,
+                                    capabilities,
+                                );
+                                None
+                            }
+                            Err(error) => {
+                                log::warn!("Failed to retrieve service capabilities: {:?}", error);
+                                None
+                            }
+                        };
+                        *resolve.lock().unwrap() = Some(address);
+                        Ok(response.ip)
+                    }),
+                )
+            })
+            .map_err(move |error| {
+                *reset.lock().unwrap() = None;
+                error
+            }),
+        )
+    }
+}
@@ -0,0 +1,321 @@
+use tokio::prelude::Future;
+
+use crate::update::{start_delete, start_update, ChangeDamping, UpdateContext};
+
+#[derive(Debug, serde::Serialize)]
+pub struct RecordStatus {
+    pub name: String,
+    pub applied_address: Option<String>,
+    pub last_update: Option<u64>,
+    pub last_error: Option<String>,
+    /// Whether the record was removed from its registrar, and is no longer
+    /// updated until it is synced again.
+    pub removed: bool,
+    /// Address the record is assumed to hold without this client having
+    /// applied it, such as the initial address.
+    #[serde(skip)]
+    pub(crate) assumed_address: Option<String>,
+    #[serde(skip)]
+    pub(crate) attempted_address: Option<String>,
+    #[serde(skip)]
+    pub(crate) last_attempt: Option<std::time::Instant>,
+}
+
+impl RecordStatus {
+    pub(crate) fn new(name: String, assumed_address: Option<String>) -> Self {
+        RecordStatus {
+            name,
+            applied_address: None,
+            last_update: None,
+            last_error: None,
+            removed: false,
+            assumed_address,
+            attempted_address: None,
+            last_attempt: None,
+        }
+    }
+
+    /// Returns the address the record holds, as applied by this client or else
+    /// as assumed.
+    pub(crate) fn current_address(&self) -> Option<&String> {
+        self.applied_address
+            .as_ref()
+            .or(self.assumed_address.as_ref())
+    }
+
+    pub(crate) fn mark_attempted(&mut self, ip_address: &str, now: std::time::Instant) {
+        self.attempted_address.replace(ip_address.to_owned());
+        self.last_attempt.replace(now);
+    }
+
+    /// Forgets the address of an attempt that failed or was abandoned, so that
+    /// the record is retried once the minimum update interval since the attempt
+    /// has elapsed.
+    pub(crate) fn mark_failed(&mut self) {
+        self.attempted_address.take();
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ClientStatus {
+    pub records: Vec<RecordStatus>,
+    pub desired_address: Option<String>,
+    pub pending_address: Option<String>,
+    pub pending_observations: u64,
+    pub suppressed_changes: u64,
+    pub deferred_updates: u64,
+    pub last_check: Option<u64>,
+    pub next_check: Option<u64>,
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    pub(crate) pending_since: Option<std::time::Instant>,
+}
+
+impl ClientStatus {
+    pub(crate) fn record_mut(&mut self, name: &str) -> Option<&mut RecordStatus> {
+        self.records.iter_mut().find(|record| record.name == name)
+    }
+
+    pub(crate) fn mark_failed(&mut self, record_names: &[String]) {
+        self.records
+            .iter_mut()
+            .filter(|record| record_names.contains(&record.name))
+            .for_each(|record| record.mark_failed());
+    }
+
+    pub(crate) fn suppress_pending_address(&mut self) {
+        if let Some(pending_address) = self.pending_address.take() {
+            log::info!(
+                "Suppressed IP Address change to {} after {} observation(s)",
+                pending_address,
+                self.pending_observations,
+            );
+            self.suppressed_changes += 1;
+        }
+        self.pending_observations = 0;
+        self.pending_since.take();
+    }
+
+    /// Records an observation of an IP address that differs from the desired
+    /// address, returning true once that address has been observed often enough
+    /// and for long enough to be published.
+    pub(crate) fn confirm_address(
+        &mut self,
+        ip_address: &str,
+        damping: &ChangeDamping,
+        now: std::time::Instant,
+    ) -> bool {
+        if self.pending_address.as_deref() != Some(ip_address) {
+            self.suppress_pending_address();
+            self.pending_address.replace(ip_address.to_owned());
+            self.pending_since.replace(now);
+        }
+        self.pending_observations += 1;
+
+        let pending_duration = now.duration_since(self.pending_since.unwrap_or(now));
+        if self.pending_observations >= damping.confirmation_count
+            && pending_duration >= damping.confirmation_period
+        {
+            self.pending_address.take();
+            self.pending_observations = 0;
+            self.pending_since.take();
+            true
+        } else {
+            log::info!(
+                "Awaiting confirmation of IP Address change from {:?} to {} ({} of {} observations over {:?} of {:?})",
+                self.desired_address,
+                ip_address,
+                self.pending_observations,
+                damping.confirmation_count,
+                pending_duration,
+                damping.confirmation_period,
+            );
+            false
+        }
+    }
+
+    /// Selects the records that have not yet been sent the desired IP address and
+    /// marks them as attempted. Records that were attempted too recently are
+    /// deferred until a later check.
+    pub(crate) fn take_pending_records(
+        &mut self,
+        damping: &ChangeDamping,
+        now: std::time::Instant,
+    ) -> Vec<String> {
+        let ip_address = match self.desired_address.clone() {
+            Some(ip_address) => ip_address,
+            None => return Vec::new(),
+        };
+
+        let mut deferred_updates = 0;
+        let record_names = self
+            .records
+            .iter_mut()
+            .filter(|record| {
+                !record.removed
+                    && record.current_address() != Some(&ip_address)
+                    && record.attempted_address.as_ref() != Some(&ip_address)
+            })
+            .filter_map(|record| {
+                let next_attempt = record
+                    .last_attempt
+                    .map(|last_attempt| last_attempt + damping.min_update_interval);
+                match next_attempt {
+                    Some(next_attempt) if next_attempt > now => {
+                        log::info!(
+                            "Deferring update of registrar record '{}' for {:?}",
+                            record.name,
+                            next_attempt.duration_since(now),
+                        );
+                        deferred_updates += 1;
+                        None
+                    }
+                    _ => {
+                        record.mark_attempted(ip_address.as_str(), now);
+                        Some(record.name.to_owned())
+                    }
+                }
+            })
+            .collect();
+        self.deferred_updates += deferred_updates;
+        record_names
+    }
+}
+
+pub type SharedStatus = std::sync::Arc<std::sync::Mutex<ClientStatus>>;
+
+pub(crate) fn unix_timestamp(time: std::time::SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn make_status_response(status: u16, body: String) -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .header("Content-Type", "application/json")
+        .status(status)
+        .body(hyper::Body::from(body))
+        .unwrap()
+}
+
+fn make_status_message_response(status: u16, message: &str) -> hyper::Response<hyper::Body> {
+    make_status_response(
+        status,
+        serde_json::json!({ "message": message }).to_string(),
+    )
+}
+
+fn respond_status(
+    request: hyper::Request<hyper::Body>,
+    context: &UpdateContext,
+    trigger_sender: &mut tokio::sync::mpsc::UnboundedSender<()>,
+) -> hyper::Response<hyper::Body> {
+    log::trace!("fn respond_status(request={:?})", &request);
+
+    let status = &context.status;
+
+    let path_segments = request
+        .uri()
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+
+    let response = match (request.method(), path_segments.as_slice()) {
+        (&hyper::Method::GET, ["status"]) => make_status_response(
+            200,
+            serde_json::to_string(&*status.lock().unwrap()).unwrap(),
+        ),
+        (&hyper::Method::POST, ["trigger"]) => match trigger_sender.try_send(()) {
+            Ok(()) => make_status_message_response(202, "Address check triggered"),
+            Err(error) => {
+                log::error!("Failed to trigger address check: {:?}", error);
+                make_status_message_response(503, "Failed to trigger address check")
+            }
+        },
+        (&hyper::Method::POST, ["records", name, "sync"]) => {
+            let (record_address, desired_address) = {
+                let mut status = status.lock().unwrap();
+                let desired_address = status.desired_address.clone();
+                let record_address = status.record_mut(name).map(|record| {
+                    record.removed = false;
+                    if let Some(ip_address) = desired_address.as_ref() {
+                        record.mark_attempted(ip_address.as_str(), std::time::Instant::now());
+                    }
+                    record.current_address().cloned()
+                });
+                (record_address, desired_address)
+            };
+            match (record_address, desired_address) {
+                (None, _) => make_status_message_response(404, "Unknown registrar record"),
+                (Some(_), None) => make_status_message_response(409, "No IP address known yet"),
+                (Some(current_address), Some(ip_address)) => {
+                    log::info!("Syncing registrar record '{}' to {}", name, ip_address);
+                    match start_update(context, current_address, ip_address, &[name.to_string()]) {
+                        Ok(()) => {
+                            make_status_message_response(202, "Registrar record sync started")
+                        }
+                        Err(error) => {
+                            log::error!("{:?}", error);
+                            make_status_message_response(500, format!("{:?}", error).as_str())
+                        }
+                    }
+                }
+            }
+        }
+        (&hyper::Method::DELETE, ["records", name]) => {
+            let is_known = status
+                .lock()
+                .unwrap()
+                .record_mut(name)
+                .map(|record| record.removed = true)
+                .is_some();
+            if !is_known {
+                make_status_message_response(404, "Unknown registrar record")
+            } else {
+                log::info!("Removing registrar record '{}'", name);
+                match start_delete(context, name) {
+                    Ok(()) => make_status_message_response(202, "Registrar record removal started"),
+                    Err(error) => {
+                        log::error!("{:?}", error);
+                        if let Some(record) = status.lock().unwrap().record_mut(name) {
+                            record.removed = false;
+                        }
+                        make_status_message_response(500, format!("{:?}", error).as_str())
+                    }
+                }
+            }
+        }
+        (_, ["status"]) | (_, ["trigger"]) | (_, ["records", _]) | (_, ["records", _, "sync"]) => {
+            make_status_message_response(405, "Method not allowed")
+        }
+        _ => make_status_message_response(404, "Not found"),
+    };
+
+    log::debug!("{:?}", response);
+    response
+}
+
+pub(crate) fn make_status_server_future(
+    socket_address: &std::net::SocketAddr,
+    context: UpdateContext,
+    trigger_sender: tokio::sync::mpsc::UnboundedSender<()>,
+) -> impl Future<Item = (), Error = ()> {
+    log::trace!(
+        "fn make_status_server_future(socket_address={:?})",
+        socket_address,
+    );
+
+    let new_service = move || {
+        let context_clone = context.clone();
+        let mut trigger_sender_clone = trigger_sender.clone();
+        hyper::service::service_fn_ok(move |request| {
+            respond_status(request, &context_clone, &mut trigger_sender_clone)
+        })
+    };
+
+    log::info!("Status API listening on http://{}", socket_address);
+    hyper::Server::bind(socket_address)
+        .serve(new_service)
+        .map_err(|error| log::error!("{:?}", error))
+}
@@ -0,0 +1,438 @@
+//! Discovery of the public address of the client from STUN (RFC 5389) servers,
+//! for networks without an address server of their own.
+
+use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+use tokio::prelude::{future, Async, Future, Poll};
+
+use crate::error::{DdnsBoxFuture, DdnsError, StunError};
+use crate::source::AddressSource;
+
+/// Port STUN servers listen on when a server does not name one.
+pub const DEFAULT_STUN_PORT: u16 = 3478;
+
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+const HEADER_SIZE: usize = 20;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS_RESPONSE: u16 = 0x0101;
+const BINDING_ERROR_RESPONSE: u16 = 0x0111;
+
+const ERROR_CODE: u16 = 0x0009;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+/// Time to wait for a response before retransmitting a request, doubled after
+/// each transmission, as recommended by RFC 5389.
+const INITIAL_RETRANSMISSION_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+/// Transmissions of a request before its server is given up on.
+const MAX_TRANSMISSIONS: u32 = 4;
+
+/// Address family STUN servers are asked over, which is also the family of the
+/// address they report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StunFamily {
+    /// Either family, trying each address a server resolves to in turn.
+    Any,
+    V4,
+    V6,
+}
+
+impl StunFamily {
+    fn includes(self, address: &std::net::IpAddr) -> bool {
+        match self {
+            StunFamily::Any => true,
+            StunFamily::V4 => address.is_ipv4(),
+            StunFamily::V6 => address.is_ipv6(),
+        }
+    }
+}
+
+/// Returns the host and port of a STUN server, given as `host`, `host:port`,
+/// or either with a `stun:` scheme as STUN servers are commonly listed.
+fn parse_server(server: &str) -> (String, u16) {
+    let server = server.strip_prefix("stun:").unwrap_or(server);
+    if let Ok(socket_address) = server.parse::<std::net::SocketAddr>() {
+        return (socket_address.ip().to_string(), socket_address.port());
+    }
+    if server.parse::<std::net::IpAddr>().is_ok() {
+        return (server.to_owned(), DEFAULT_STUN_PORT);
+    }
+    match server
+        .rsplit_once(':')
+        .and_then(|(host, port)| port.parse::<u16>().ok().map(|port| (host, port)))
+    {
+        Some((host, port)) => (host.to_owned(), port),
+        None => (server.to_owned(), DEFAULT_STUN_PORT),
+    }
+}
+
+fn make_binding_request(transaction_id: &[u8; 12]) -> [u8; HEADER_SIZE] {
+    let mut request = [0; HEADER_SIZE];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(transaction_id);
+    request
+}
+
+/// Returns the attributes of a STUN message, as types and values.
+fn read_attributes(message: &[u8]) -> Result<Vec<(u16, &[u8])>, String> {
+    let mut attributes = Vec::new();
+    let mut offset = HEADER_SIZE;
+    while offset < message.len() {
+        if offset + 4 > message.len() {
+            return Err("Truncated attribute header".to_owned());
+        }
+        let attribute_type = u16::from_be_bytes([message[offset], message[offset + 1]]);
+        let length = usize::from(u16::from_be_bytes([
+            message[offset + 2],
+            message[offset + 3],
+        ]));
+        let value = message
+            .get(offset + 4..offset + 4 + length)
+            .ok_or_else(|| format!("Truncated attribute {:#06x}", attribute_type))?;
+        attributes.push((attribute_type, value));
+        // Values are padded to a multiple of four bytes.
+        offset += 4 + length.div_ceil(4) * 4;
+    }
+    Ok(attributes)
+}
+
+fn read_xor_mapped_address(
+    value: &[u8],
+    transaction_id: &[u8; 12],
+) -> Result<std::net::IpAddr, String> {
+    let mut mask = [0; 16];
+    mask[0..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    mask[4..16].copy_from_slice(transaction_id);
+    let unmask = |address: &[u8]| {
+        address
+            .iter()
+            .zip(mask.iter())
+            .map(|(byte, mask)| byte ^ mask)
+            .collect::<Vec<_>>()
+    };
+    match (value.get(1), value.len()) {
+        (Some(0x01), 8) => {
+            let octets = unmask(&value[4..8]);
+            Ok(std::net::Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]).into())
+        }
+        (Some(0x02), 20) => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(unmask(&value[4..20]).as_slice());
+            Ok(std::net::Ipv6Addr::from(octets).into())
+        }
+        _ => Err("Malformed XOR-MAPPED-ADDRESS attribute".to_owned()),
+    }
+}
+
+/// Returns the address a binding response maps the client to, or `None` for
+/// datagrams that do not answer the given transaction.
+fn read_binding_response(
+    server: &str,
+    message: &[u8],
+    transaction_id: &[u8; 12],
+) -> Result<Option<std::net::IpAddr>, StunError> {
+    if message.len() < HEADER_SIZE
+        || message[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || message[8..20] != transaction_id[..]
+    {
+        return Ok(None);
+    }
+    let invalid = |reason: String| StunError::InvalidResponseError(server.to_owned(), reason);
+    let length = usize::from(u16::from_be_bytes([message[2], message[3]]));
+    let message = message
+        .get(..HEADER_SIZE + length)
+        .ok_or_else(|| invalid("Truncated message".to_owned()))?;
+    let attributes = read_attributes(message).map_err(invalid)?;
+    let find_attribute = |attribute_type: u16| {
+        attributes
+            .iter()
+            .find(|(other_type, _)| *other_type == attribute_type)
+            .map(|(_, value)| *value)
+    };
+
+    match u16::from_be_bytes([message[0], message[1]]) {
+        BINDING_SUCCESS_RESPONSE => find_attribute(XOR_MAPPED_ADDRESS)
+            .ok_or_else(|| "Missing XOR-MAPPED-ADDRESS attribute".to_owned())
+            .and_then(|value| read_xor_mapped_address(value, transaction_id))
+            .map(Some)
+            .map_err(invalid),
+        BINDING_ERROR_RESPONSE => match find_attribute(ERROR_CODE) {
+            Some(value) if value.len() >= 4 => Err(StunError::BindingError(
+                server.to_owned(),
+                u16::from(value[2] & 0x07) * 100 + u16::from(value[3]),
+                String::from_utf8_lossy(&value[4..]).to_string(),
+            )),
+            _ => Err(invalid("Missing ERROR-CODE attribute".to_owned())),
+        },
+        message_type => Err(invalid(format!(
+            "Unexpected message type {:#06x}",
+            message_type
+        ))),
+    }
+}
+
+/// A binding request in progress, retransmitted until it is answered,
+/// resolving to the address the server maps the client to.
+struct BindingFuture {
+    server: String,
+    server_address: std::net::SocketAddr,
+    socket: tokio::net::UdpSocket,
+    transaction_id: [u8; 12],
+    request: [u8; HEADER_SIZE],
+    transmissions: u32,
+    /// Whether the latest transmission is waiting for a response.
+    sent: bool,
+    timer: tokio::timer::Delay,
+    buffer: [u8; 1024],
+}
+
+impl BindingFuture {
+    fn new(server: &str, server_address: std::net::SocketAddr) -> Result<Self, StunError> {
+        let local_address: std::net::SocketAddr = if server_address.is_ipv6() {
+            (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+        } else {
+            (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = tokio::net::UdpSocket::bind(&local_address).map_err(StunError::IoError)?;
+        let mut transaction_id = [0; 12];
+        openssl::rand::rand_bytes(&mut transaction_id)
+            .map_err(|error| StunError::IoError(std::io::Error::other(error)))?;
+        Ok(BindingFuture {
+            server: server.to_owned(),
+            server_address,
+            socket,
+            transaction_id,
+            request: make_binding_request(&transaction_id),
+            transmissions: 0,
+            sent: false,
+            timer: tokio::timer::Delay::new(std::time::Instant::now()),
+            buffer: [0; 1024],
+        })
+    }
+}
+
+impl Future for BindingFuture {
+    type Item = std::net::IpAddr;
+    type Error = StunError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if !self.sent {
+                if let Async::NotReady = self
+                    .socket
+                    .poll_send_to(&self.request, &self.server_address)
+                    .map_err(StunError::IoError)?
+                {
+                    return Ok(Async::NotReady);
+                }
+                self.timer.reset(
+                    std::time::Instant::now()
+                        + INITIAL_RETRANSMISSION_TIMEOUT * 2u32.pow(self.transmissions),
+                );
+                self.transmissions += 1;
+                self.sent = true;
+            }
+
+            match self
+                .socket
+                .poll_recv_from(&mut self.buffer)
+                .map_err(StunError::IoError)?
+            {
+                Async::Ready((length, source_address)) => {
+                    if source_address != self.server_address {
+                        continue;
+                    }
+                    match read_binding_response(
+                        self.server.as_str(),
+                        &self.buffer[..length],
+                        &self.transaction_id,
+                    )? {
+                        Some(address) => return Ok(Async::Ready(address)),
+                        None => continue,
+                    }
+                }
+                Async::NotReady => {}
+            }
+
+            match self
+                .timer
+                .poll()
+                .map_err(|error| StunError::IoError(std::io::Error::other(error)))?
+            {
+                Async::Ready(()) if self.transmissions >= MAX_TRANSMISSIONS => {
+                    return Err(StunError::TimeoutError(self.server.clone()));
+                }
+                Async::Ready(()) => self.sent = false,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+/// Resolves a STUN server to the addresses of the given family, off the runtime
+/// thread as hyper's connector does.
+fn make_resolve_future(
+    server: &str,
+    family: StunFamily,
+    resolver: &GaiResolver,
+) -> impl Future<Item = Vec<std::net::SocketAddr>, Error = StunError> {
+    log::trace!(
+        "fn make_resolve_future(server={:?}, family={:?})",
+        server,
+        family,
+    );
+
+    let (host, port) = parse_server(server);
+    let addresses = match (host.parse::<std::net::IpAddr>(), host.parse::<Name>()) {
+        (Ok(address), _) => future::Either::A(future::ok(vec![address])),
+        (Err(_), Ok(name)) => future::Either::B(
+            resolver
+                .resolve(name)
+                .map(|addresses| addresses.collect())
+                .map_err(StunError::IoError),
+        ),
+        (Err(_), Err(_)) => {
+            future::Either::A(future::err(StunError::ResolveError(server.to_owned())))
+        }
+    };
+    let server = server.to_owned();
+    addresses.and_then(move |addresses: Vec<std::net::IpAddr>| {
+        let socket_addresses = addresses
+            .into_iter()
+            .filter(|address| family.includes(address))
+            .map(|address| std::net::SocketAddr::new(address, port))
+            .collect::<Vec<_>>();
+        if socket_addresses.is_empty() {
+            Err(StunError::ResolveError(server))
+        } else {
+            Ok(socket_addresses)
+        }
+    })
+}
+
+/// Sends a binding request to each address of a STUN server in turn, until one
+/// of them is answered.
+fn make_binding_future(
+    server: &str,
+    family: StunFamily,
+    resolver: &GaiResolver,
+) -> impl Future<Item = std::net::IpAddr, Error = StunError> {
+    log::trace!(
+        "fn make_binding_future(server={:?}, family={:?})",
+        server,
+        family,
+    );
+
+    let server = server.to_owned();
+    make_resolve_future(server.as_str(), family, resolver).and_then(move |socket_addresses| {
+        future::loop_fn(socket_addresses.into_iter(), move |mut socket_addresses| {
+            // Resolved servers have at least one address.
+            let server_address = socket_addresses.next().unwrap();
+            let server = server.clone();
+            future::result(BindingFuture::new(server.as_str(), server_address))
+                .flatten()
+                .then(move |result| match result {
+                    Ok(address) => Ok(future::Loop::Break(address)),
+                    Err(error) if socket_addresses.len() == 0 => Err(error),
+                    Err(error) => {
+                        log::debug!(
+                            "STUN server {} failed at {}: {:?}",
+                            server,
+                            server_address,
+                            error,
+                        );
+                        Ok(future::Loop::Continue(socket_addresses))
+                    }
+                })
+        })
+    })
+}
+
+/// Retrieves the IP address from the binding responses of STUN servers. Every
+/// server is asked at once, and the address is the one reported by the most
+/// servers, as long as at least a quorum of them agree on it.
+#[derive(Debug)]
+pub struct StunAddressSource {
+    servers: Vec<String>,
+    quorum: usize,
+    family: StunFamily,
+    resolver: GaiResolver,
+}
+
+impl StunAddressSource {
+    /// Creates a source asking the given servers over either family, a
+    /// majority of which must agree on the address.
+    pub fn new(servers: Vec<String>) -> Self {
+        let quorum = servers.len() / 2 + 1;
+        StunAddressSource {
+            servers,
+            quorum,
+            family: StunFamily::Any,
+            resolver: GaiResolver::new(4),
+        }
+    }
+
+    /// Asks servers only over the given address family.
+    pub fn with_family(mut self, family: StunFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Requires the given number of servers to agree on the address instead of
+    /// a majority.
+    pub fn with_quorum(mut self, quorum: usize) -> Self {
+        self.quorum = quorum;
+        self
+    }
+}
+
+impl AddressSource for StunAddressSource {
+    fn get_address(&self) -> DdnsBoxFuture<String> {
+        log::debug!("Retrieving current IP address from STUN servers");
+
+        let quorum = self.quorum;
+        let family = self.family;
+        let binding_futures = self
+            .servers
+            .iter()
+            .map(|server| {
+                let server = server.clone();
+                make_binding_future(server.as_str(), family, &self.resolver).then(move |result| {
+                    match result {
+                        Ok(address) => {
+                            log::debug!("STUN server {} reported {}", server, address);
+                            Ok(Some((server, address)))
+                        }
+                        Err(error) => {
+                            log::warn!("STUN server {} failed: {:?}", server, error);
+                            Ok(None)
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        Box::new(future::join_all(binding_futures).and_then(move |reports| {
+            let reports = reports.into_iter().flatten().collect::<Vec<_>>();
+            let mut votes = Vec::<(std::net::IpAddr, usize)>::new();
+            for (_, address) in reports.iter() {
+                match votes.iter_mut().find(|(other, _)| other == address) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((*address, 1)),
+                }
+            }
+            votes.sort_by(|(_, count), (_, other_count)| other_count.cmp(count));
+            match votes.as_slice() {
+                // A tie for the most votes is no consensus, whatever the
+                // quorum.
+                [(address, count), rest @ ..]
+                    if *count >= quorum && rest.first().is_none_or(|(_, other)| other < count) =>
+                {
+                    Ok(address.to_string())
+                }
+                _ => Err(DdnsError::StunError(StunError::ConsensusError(
+                    quorum, reports,
+                ))),
+            }
+        }))
+    }
+}
@@ -0,0 +1,718 @@
+use tokio::prelude::{future, stream, Future, Stream};
+
+use crate::auth::{make_request_auth_future, RegistrarAuth};
+use crate::error::{
+    DdnsBoxFuture, DdnsError, DdnsFuture, DdnsResult, ProviderError, RequestError, RequestResult,
+    ResponseError, TemplateError,
+};
+use crate::extract::ValueExtractor;
+use crate::provider::{make_read_before_write_future, Provider, ProviderConfig, ProviderContext};
+use crate::request::{decode_response, make_request_future};
+use crate::validate::ResponseValidation;
+use crate::workflow::{render_step_request, StepResponse};
+
+#[derive(Clone, Debug)]
+pub struct RegistrarRequest {
+    pub name: String,
+    pub method: String,
+    pub address: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Request that retrieves the record's current address, if the registrar
+    /// supports it.
+    pub get: Option<Box<RegistrarRequest>>,
+    /// Request that removes the record, if the registrar supports it.
+    pub delete: Option<Box<RegistrarRequest>>,
+    /// Extracts the record's address from the response of a `get` request.
+    pub extractor: Option<ValueExtractor>,
+    /// Criteria the registrar's response must meet.
+    pub response: ResponseValidation,
+    /// Requests sent in order before any of the record's requests, whose
+    /// responses may be referenced by later requests.
+    pub steps: Vec<RegistrarRequest>,
+    /// Credentials the request is authenticated with.
+    pub auth: Option<RegistrarAuth>,
+}
+
+/// Makes a registrar request from its JSON form. Sub-requests are made with their
+/// parent as `defaults`, from which any missing method, address or headers are
+/// taken.
+fn make_registrar_request(
+    request_json: &serde_json::Value,
+    defaults: Option<&RegistrarRequest>,
+) -> RequestResult<RegistrarRequest> {
+    let headers_vec = match (request_json["headers"].as_object(), defaults) {
+        (Some(headers), _) => headers
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value.as_str().unwrap().to_owned()))
+            .collect(),
+        (None, Some(defaults)) if request_json["headers"].is_null() => defaults.headers.clone(),
+        (None, _) => return Err(RequestError::FormatError),
+    };
+    let get_field = |key: &str, default: Option<&String>| {
+        request_json[key]
+            .as_str()
+            .or_else(|| default.map(|value| value.as_str()))
+            .ok_or(RequestError::FormatError)
+            .map(|value| value.to_owned())
+    };
+
+    let mut request = RegistrarRequest {
+        name: get_field("name", defaults.map(|defaults| &defaults.name))?,
+        method: get_field("method", defaults.map(|defaults| &defaults.method))?,
+        address: get_field("address", defaults.map(|defaults| &defaults.address))?,
+        headers: headers_vec,
+        body: match (&request_json["body"], defaults) {
+            (serde_json::Value::Null, Some(_)) => String::new(),
+            (body, _) => body.to_string(),
+        },
+        get: None,
+        delete: None,
+        extractor: None,
+        response: ResponseValidation::from_json(request_json)?,
+        steps: Vec::new(),
+        auth: match (request_json.get("auth"), defaults) {
+            (Some(serde_json::Value::Null), _) | (None, None) => None,
+            (Some(auth_json), _) => Some(
+                serde_json::from_value(auth_json.clone()).map_err(RequestError::SerdeJsonError)?,
+            ),
+            (None, Some(defaults)) => defaults.auth.clone(),
+        },
+    };
+    let get_defaults = RegistrarRequest {
+        method: "GET".to_owned(),
+        ..request.clone()
+    };
+    if let Some(steps_json) = request_json.get("steps") {
+        request.steps = steps_json
+            .as_array()
+            .ok_or(RequestError::FormatError)?
+            .iter()
+            .map(|step_json| make_registrar_request(step_json, Some(&get_defaults)))
+            .collect::<RequestResult<Vec<_>>>()?;
+    }
+    if let Some(get_json) = request_json.get("get") {
+        let mut get = make_registrar_request(get_json, Some(&get_defaults))?;
+        get.extractor = ValueExtractor::from_json(get_json)?;
+        request.get.replace(Box::new(get));
+    }
+    if let Some(delete_json) = request_json.get("delete") {
+        let delete = make_registrar_request(delete_json, Some(&request))?;
+        request.delete.replace(Box::new(delete));
+    }
+    Ok(request)
+}
+
+fn get_registrar_request_names(registrar_request_template: &str) -> RequestResult<Vec<String>> {
+    log::trace!(
+        "fn get_registrar_request_names(registrar_request_template={:?})",
+        registrar_request_template,
+    );
+
+    render_registrar_requests(registrar_request_template, String::new())
+        .and_then(|rendered_registrar_requests| {
+            make_registrar_requests(rendered_registrar_requests.as_str())
+        })
+        .map(|registrar_requests| {
+            registrar_requests
+                .into_iter()
+                .map(|request| request.name)
+                .collect()
+        })
+}
+
+pub fn render_registrar_requests(
+    request_template: &str,
+    ip_address: String,
+) -> RequestResult<String> {
+    log::trace!(
+        "fn render_registrar_requests(request_template={:?}, ip_address={:?})",
+        request_template,
+        ip_address.as_str(),
+    );
+
+    let mut template_variables = std::collections::HashMap::new();
+    template_variables.insert("ip_address".to_owned(), ip_address);
+
+    envsubst::substitute(request_template, &template_variables).map_err(|_| {
+        RequestError::TemplateError(TemplateError::new(
+            request_template.to_owned(),
+            template_variables,
+        ))
+    })
+}
+
+// TODO: document what this JSON format is supposed to be in a README
+pub fn make_registrar_requests(requests_str: &str) -> RequestResult<Vec<RegistrarRequest>> {
+    log::trace!(
+        "fn make_registrar_requests(requests_str={:?})",
+        requests_str,
+    );
+
+    let requests_result = serde_json::from_str::<serde_json::Value>(requests_str)
+        .map_err(RequestError::SerdeJsonError)?;
+    let requests = requests_result
+        .as_array()
+        .ok_or(RequestError::FormatError)
+        .map(|requests_array| {
+            requests_array
+                .iter()
+                .map(|request_json| make_registrar_request(request_json, None))
+                .fold(
+                    (Vec::new(), Vec::new()),
+                    |(mut values, mut errors), result| {
+                        match result {
+                            Ok(value) => values.push(value),
+                            Err(error) => errors.push(error),
+                        }
+                        (values, errors)
+                    },
+                )
+        });
+    match requests {
+        Ok((values, mut errors)) => {
+            // Only report the first error. RequestResult only allows a singular
+            // error to be returned. We could work around this by making a new
+            // plural error type, but there's not much benefit for that amount
+            // of boilerplate.
+            if let Some(error) = errors.pop() {
+                Err(error)
+            } else {
+                Ok(values)
+            }
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn get_registrar_host(address: &str) -> String {
+    address
+        .parse::<http::Uri>()
+        .ok()
+        .and_then(|uri| uri.authority_part().map(|authority| authority.to_string()))
+        .unwrap_or_else(|| address.to_owned())
+}
+
+/// Determines how long to wait before retrying a rate-limited request, from
+/// either form of the `Retry-After` header, falling back to an exponential
+/// backoff when the registrar does not specify.
+fn get_retry_delay(response: &hyper::Response<hyper::Body>, attempt: u64) -> std::time::Duration {
+    let retry_after = response
+        .headers()
+        .get(hyper::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| match value.trim().parse::<u64>() {
+            Ok(secs) => Some(std::time::Duration::from_secs(secs)),
+            Err(_) => httpdate::parse_http_date(value.trim()).ok().map(|time| {
+                time.duration_since(std::time::SystemTime::now())
+                    .unwrap_or_default()
+            }),
+        });
+    retry_after.unwrap_or_else(|| std::time::Duration::from_secs(1 << attempt.min(6)))
+}
+
+/// Makes the given registrar request once a slot for its host is available,
+/// retrying it while it is rate-limited.
+pub(crate) fn make_registrar_future(
+    request: RegistrarRequest,
+    context: ProviderContext,
+) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
+    log::trace!("fn make_registrar_future(request={:?})", &request);
+
+    let host = get_registrar_host(request.address.as_str());
+    context
+        .scheduler
+        .acquire(host.as_str())
+        .and_then(move |permit| {
+            make_registrar_attempts_future(request, host, context).then(move |result| {
+                drop(permit);
+                result
+            })
+        })
+}
+
+fn make_registrar_attempts_future(
+    request: RegistrarRequest,
+    host: String,
+    context: ProviderContext,
+) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
+    let max_retries = context.scheduler.limits.max_retries;
+    future::loop_fn(0, move |attempt| {
+        let send_at = context.scheduler.reserve(host.as_str());
+        let request = request.clone();
+        let host = host.to_owned();
+        let context = context.clone();
+        let auth_future = match request.auth.clone() {
+            Some(auth) => {
+                future::Either::A(make_request_auth_future(auth, context.tokens.clone()).map(Some))
+            }
+            None => future::Either::B(future::ok(None)),
+        };
+        tokio::timer::Delay::new(send_at)
+            .map_err(DdnsError::DelayError)
+            .and_then(move |_| auth_future)
+            .and_then(move |auth| {
+                log::debug!(
+                    "Sending {} request for registrar record '{}'...",
+                    request.method,
+                    request.name,
+                );
+                make_request_future(
+                    request.address.as_str(),
+                    request.method.as_str(),
+                    request.headers.as_slice(),
+                    request.body.clone(),
+                    auth,
+                    None,
+                )
+                .map(move |response| (request, response))
+            })
+            .and_then(move |(request, response)| {
+                let status_code = response.status().as_u16();
+                if status_code == 401 {
+                    // A rejected OAuth2 token may have been revoked before it
+                    // expired, so the next request obtains a new one.
+                    if let Some(auth) = request.auth.as_ref() {
+                        context.tokens.invalidate(auth);
+                    }
+                }
+                let is_rate_limited = status_code == 429
+                    || (status_code == 503
+                        && response.headers().contains_key(hyper::header::RETRY_AFTER));
+                if is_rate_limited && attempt < max_retries {
+                    let delay = get_retry_delay(&response, attempt);
+                    log::warn!(
+                        "Registrar host {} rate-limited record '{}'; retrying in {:?}",
+                        host,
+                        request.name,
+                        delay,
+                    );
+                    context.scheduler.pause(host.as_str(), delay);
+                    Ok(future::Loop::Continue(attempt + 1))
+                } else {
+                    Ok(future::Loop::Break(response))
+                }
+            })
+    })
+}
+
+/// Reads a registrar response and checks it against the request's validation
+/// criteria, returning its body if it succeeded.
+pub(crate) fn validate_registrar_response(
+    validation: ResponseValidation,
+    response: hyper::Response<hyper::Body>,
+) -> impl DdnsFuture<String> {
+    let status_code = response.status().as_u16();
+    decode_response(response).and_then(move |response_bytes| {
+        let body = String::from_utf8_lossy(response_bytes.as_ref()).to_string();
+        validation
+            .validate(status_code, body.as_str())
+            .map(|_| body)
+            .map_err(|error| DdnsError::ResponseError(ResponseError::StatusError(error)))
+    })
+}
+
+fn process_registrar_response(
+    name: &str,
+    validation: ResponseValidation,
+    response: hyper::Response<hyper::Body>,
+) -> impl DdnsFuture<()> {
+    log::trace!(
+        "fn process_registrar_response(name={:?}, response={:?})",
+        name,
+        response,
+    );
+
+    let name = name.to_owned();
+    validate_registrar_response(validation, response).then(move |result| match result {
+        Ok(_) => {
+            log::debug!("Successfully updated registrar record '{}'", name);
+            Ok(())
+        }
+        Err(error) => {
+            log::warn!("Failed to update registrar record '{}'", name);
+            Err(error)
+        }
+    })
+}
+
+/// Sends the steps of a record's workflow in order, capturing their responses
+/// for the requests that follow. The first step that fails aborts the workflow.
+fn make_steps_future(
+    steps: Vec<RegistrarRequest>,
+    context: ProviderContext,
+) -> impl DdnsFuture<Vec<StepResponse>> {
+    log::trace!("fn make_steps_future(steps={:?})", steps);
+
+    stream::iter_ok(steps).fold(Vec::new(), move |mut responses: Vec<StepResponse>, step| {
+        let context = context.clone();
+        let step_name = step.name.clone();
+        future::result(
+            render_step_request(&step, responses.as_slice()).map_err(DdnsError::RequestError),
+        )
+        .and_then(move |step| {
+            let name = step.name.clone();
+            let validation = step.response.clone();
+            make_registrar_future(step, context).and_then(move |response| {
+                let status = response.status().as_u16();
+                let headers = response
+                    .headers()
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        value
+                            .to_str()
+                            .ok()
+                            .map(|value| (key.as_str().to_owned(), value.to_owned()))
+                    })
+                    .collect();
+                validate_registrar_response(validation, response).map(move |body| {
+                    responses.push(StepResponse::new(name, status, headers, body));
+                    responses
+                })
+            })
+        })
+        .map_err(move |error| {
+            log::warn!("Workflow step '{}' failed", step_name);
+            error
+        })
+    })
+}
+
+/// Sends a request of a record's workflow with the values captured from the
+/// responses of its steps.
+fn make_step_request_future(
+    request: RegistrarRequest,
+    responses: &[StepResponse],
+    context: ProviderContext,
+) -> impl DdnsFuture<hyper::Response<hyper::Body>> {
+    log::trace!("fn make_step_request_future(request={:?})", request);
+
+    future::result(render_step_request(&request, responses).map_err(DdnsError::RequestError))
+        .and_then(move |request| make_registrar_future(request, context))
+}
+
+/// Extracts a record's current address from the response of its `get` request.
+/// A `404 Not Found` response means that the record does not exist, unless it
+/// is an accepted status.
+fn process_current_record_response(
+    name: &str,
+    extractor: Option<ValueExtractor>,
+    validation: ResponseValidation,
+    response: hyper::Response<hyper::Body>,
+) -> impl DdnsFuture<Option<String>> {
+    log::trace!(
+        "fn process_current_record_response(name={:?}, response={:?})",
+        name,
+        response,
+    );
+
+    let status_code = response.status().as_u16();
+    if status_code == 404 && !validation.statuses.contains(&status_code) {
+        log::debug!("Registrar record '{}' does not exist", name);
+        future::Either::A(future::ok(None))
+    } else {
+        let name = name.to_owned();
+        future::Either::B(
+            validate_registrar_response(validation, response).then(move |result| match result {
+                Ok(body) => {
+                    let current_address = match extractor {
+                        Some(extractor) => extractor.extract(body.as_str()),
+                        None => Some(body.trim().to_owned()),
+                    };
+                    log::debug!(
+                        "Registrar record '{}' currently points at {:?}",
+                        name,
+                        current_address,
+                    );
+                    Ok(current_address)
+                }
+                Err(error) => {
+                    log::warn!("Failed to retrieve registrar record '{}'", name);
+                    Err(error)
+                }
+            }),
+        )
+    }
+}
+
+/// Updates records by rendering a registrar request template and sending the
+/// resulting requests, scheduled according to per-host registrar limits.
+pub struct GenericHttpProvider {
+    registrar_request_template: String,
+    context: ProviderContext,
+}
+
+impl GenericHttpProvider {
+    pub fn new(registrar_request_template: String, context: &ProviderContext) -> Self {
+        GenericHttpProvider {
+            registrar_request_template,
+            context: context.clone(),
+        }
+    }
+
+    /// Creates a provider from the `requests` option, which is either a request
+    /// template string or the JSON request list itself.
+    pub fn from_config(
+        config: &ProviderConfig,
+        context: &ProviderContext,
+    ) -> DdnsResult<Box<dyn Provider>> {
+        let registrar_request_template = match config.option("requests")? {
+            serde_json::Value::String(template) => template.to_owned(),
+            requests => requests.to_string(),
+        };
+        Ok(Box::new(GenericHttpProvider::new(
+            registrar_request_template,
+            context,
+        )))
+    }
+
+    fn make_request(&self, record_name: &str, ip_address: &str) -> DdnsResult<RegistrarRequest> {
+        render_registrar_requests(
+            self.registrar_request_template.as_str(),
+            ip_address.to_owned(),
+        )
+        .and_then(|rendered_registrar_requests| {
+            make_registrar_requests(rendered_registrar_requests.as_str())
+        })
+        .map_err(DdnsError::RequestError)?
+        .into_iter()
+        .find(|request| request.name == record_name)
+        .ok_or_else(|| {
+            DdnsError::ProviderError(ProviderError::UnknownRecordError(record_name.to_owned()))
+        })
+    }
+
+    /// Retrieves a record's current address with its `get` request, given the
+    /// responses of its steps.
+    fn get_current_after_steps(
+        get: Option<Box<RegistrarRequest>>,
+        responses: &[StepResponse],
+        context: ProviderContext,
+    ) -> DdnsBoxFuture<Option<String>> {
+        match get {
+            Some(get) => {
+                let name = get.name.clone();
+                let extractor = get.extractor.clone();
+                let validation = get.response.clone();
+                Box::new(make_step_request_future(*get, responses, context).and_then(
+                    move |response| {
+                        process_current_record_response(
+                            name.as_str(),
+                            extractor,
+                            validation,
+                            response,
+                        )
+                    },
+                ))
+            }
+            None => Box::new(future::ok(None)),
+        }
+    }
+
+    /// Sends a request that writes a record, given the responses of its steps.
+    fn send_after_steps(
+        request: RegistrarRequest,
+        responses: &[StepResponse],
+        context: ProviderContext,
+    ) -> DdnsBoxFuture<()> {
+        let name = request.name.clone();
+        let validation = request.response.clone();
+        Box::new(
+            make_step_request_future(request, responses, context).and_then(move |response| {
+                process_registrar_response(name.as_str(), validation, response)
+            }),
+        )
+    }
+
+    fn send_request(
+        &self,
+        steps: Vec<RegistrarRequest>,
+        request: RegistrarRequest,
+    ) -> DdnsBoxFuture<()> {
+        let context = self.context.clone();
+        Box::new(
+            make_steps_future(steps, context.clone()).and_then(move |responses| {
+                GenericHttpProvider::send_after_steps(request, responses.as_slice(), context)
+            }),
+        )
+    }
+}
+
+impl Provider for GenericHttpProvider {
+    fn record_names(&self) -> DdnsResult<Vec<String>> {
+        get_registrar_request_names(self.registrar_request_template.as_str())
+            .map_err(DdnsError::RequestError)
+    }
+
+    fn get_current(&self, record_name: &str, _ip_address: &str) -> DdnsBoxFuture<Option<String>> {
+        match self.make_request(record_name, "") {
+            Ok(RegistrarRequest {
+                get: Some(get),
+                steps,
+                ..
+            }) => {
+                let context = self.context.clone();
+                Box::new(
+                    make_steps_future(steps, context.clone()).and_then(move |responses| {
+                        GenericHttpProvider::get_current_after_steps(
+                            Some(get),
+                            responses.as_slice(),
+                            context,
+                        )
+                    }),
+                )
+            }
+            Ok(_) => Box::new(future::ok(None)),
+            Err(error) => Box::new(future::err(error)),
+        }
+    }
+
+    fn upsert(&self, record_name: &str, ip_address: &str) -> DdnsBoxFuture<()> {
+        match self.make_request(record_name, ip_address) {
+            Ok(request) => self.send_request(request.steps.clone(), request),
+            Err(error) => Box::new(future::err(error)),
+        }
+    }
+
+    fn delete(&self, record_name: &str) -> DdnsBoxFuture<()> {
+        match self.make_request(record_name, "") {
+            Ok(RegistrarRequest {
+                delete: Some(delete),
+                steps,
+                ..
+            }) => self.send_request(steps, *delete),
+            Ok(_) => Box::new(future::err(DdnsError::ProviderError(
+                ProviderError::UnsupportedError("generic_http".to_owned(), "delete".to_owned()),
+            ))),
+            Err(error) => Box::new(future::err(error)),
+        }
+    }
+
+    /// Runs the steps of the record's workflow once, and sends both its `get`
+    /// request and the request that writes it with their responses.
+    fn update(
+        self: std::sync::Arc<Self>,
+        record_name: &str,
+        ip_address: &str,
+    ) -> DdnsBoxFuture<()> {
+        // The `get` request is rendered without the new address, as it is on its
+        // own.
+        let (mut request, get) = match self
+            .make_request(record_name, ip_address)
+            .and_then(|request| Ok((request, self.make_request(record_name, "")?.get)))
+        {
+            Ok(requests) => requests,
+            Err(error) => return Box::new(future::err(error)),
+        };
+        let context = self.context.clone();
+        let steps = std::mem::take(&mut request.steps);
+        let record_name = record_name.to_owned();
+        let ip_address = ip_address.to_owned();
+        Box::new(
+            make_steps_future(steps, context.clone()).and_then(move |responses| {
+                let current_address = GenericHttpProvider::get_current_after_steps(
+                    get,
+                    responses.as_slice(),
+                    context.clone(),
+                );
+                make_read_before_write_future(record_name, ip_address, current_address, move || {
+                    GenericHttpProvider::send_after_steps(request, responses.as_slice(), context)
+                })
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::RegistrarLimits;
+
+    fn response(retry_after: Option<&str>) -> hyper::Response<hyper::Body> {
+        let mut response = hyper::Response::builder();
+        response.status(429);
+        if let Some(retry_after) = retry_after {
+            response.header(hyper::header::RETRY_AFTER, retry_after);
+        }
+        response.body(hyper::Body::empty()).unwrap()
+    }
+
+    /// Serves the given statuses in turn, repeating the last, and resolves to
+    /// the status of the registrar's final response along with the number of
+    /// requests it received.
+    fn send_to_registrar(statuses: Vec<u16>, max_retries: u64) -> (u16, usize) {
+        let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let server_requests = requests.clone();
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(move || {
+            let requests = server_requests.clone();
+            let statuses = statuses.clone();
+            hyper::service::service_fn_ok(move |_| {
+                let request = requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                hyper::Response::builder()
+                    .status(statuses[request.min(statuses.len() - 1)])
+                    .header(hyper::header::RETRY_AFTER, "0")
+                    .body(hyper::Body::empty())
+                    .unwrap()
+            })
+        });
+        let request = RegistrarRequest {
+            name: "www".to_owned(),
+            method: "PUT".to_owned(),
+            address: format!("http://{}/records/www", server.local_addr()),
+            headers: Vec::new(),
+            body: String::new(),
+            get: None,
+            delete: None,
+            extractor: None,
+            response: ResponseValidation::default(),
+            steps: Vec::new(),
+            auth: None,
+        };
+        let context = ProviderContext::new(RegistrarLimits::new(1, 0, 1, max_retries));
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.spawn(server.map_err(|error| panic!("{:?}", error)));
+        let response = runtime
+            .block_on(make_registrar_future(request, context))
+            .unwrap();
+        (
+            response.status().as_u16(),
+            requests.load(std::sync::atomic::Ordering::SeqCst),
+        )
+    }
+
+    #[test]
+    fn waits_for_the_retry_after_header() {
+        assert_eq!(
+            get_retry_delay(&response(Some("7")), 0),
+            std::time::Duration::from_secs(7),
+        );
+        let time = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        let delay = get_retry_delay(&response(Some(&httpdate::fmt_http_date(time))), 0);
+        assert!(delay > std::time::Duration::from_secs(55));
+        assert!(delay <= std::time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn backs_off_exponentially_without_a_retry_after_header() {
+        assert_eq!(
+            get_retry_delay(&response(None), 0),
+            std::time::Duration::from_secs(1),
+        );
+        assert_eq!(
+            get_retry_delay(&response(Some("soon")), 3),
+            std::time::Duration::from_secs(8),
+        );
+        assert_eq!(
+            get_retry_delay(&response(None), 20),
+            std::time::Duration::from_secs(64),
+        );
+    }
+
+    #[test]
+    fn retries_rate_limited_requests() {
+        assert_eq!(send_to_registrar(vec![429, 503, 200], 3), (200, 3));
+        assert_eq!(send_to_registrar(vec![429], 2), (429, 3));
+        assert_eq!(send_to_registrar(vec![500, 200], 3), (500, 1));
+    }
+}
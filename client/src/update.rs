@@ -0,0 +1,307 @@
+use tokio::prelude::{future, Future, Stream};
+
+use crate::error::{DdnsBoxFuture, DdnsError, DdnsResult, DdnsStream, ProviderError};
+use crate::hooks::{
+    make_hook_environment, make_hook_future, make_hook_result_environment, HookFailurePolicy, Hooks,
+};
+use crate::notify::{notify, NotificationEvent, NotificationEventKind, SharedNotifiers};
+use crate::status::{unix_timestamp, SharedStatus};
+
+/// How observed IP address changes are damped before being published.
+#[derive(Clone, Copy, Debug, new)]
+pub struct ChangeDamping {
+    pub confirmation_count: u64,
+    pub confirmation_period: std::time::Duration,
+    pub min_update_interval: std::time::Duration,
+}
+
+impl Default for ChangeDamping {
+    fn default() -> Self {
+        ChangeDamping::new(
+            1,
+            std::time::Duration::default(),
+            std::time::Duration::default(),
+        )
+    }
+}
+
+/// The name of each updated record, with the result of updating it.
+pub type UpdateResults = Vec<(String, DdnsResult<()>)>;
+
+pub type UpdateFuture = Box<dyn Future<Item = UpdateResults, Error = ()> + Send>;
+
+/// Applies IP address changes to registrar records.
+pub trait Updater: Send + Sync {
+    /// Returns the names of the records this updater manages.
+    fn record_names(&self) -> DdnsResult<Vec<String>>;
+
+    /// Prepares an update of the named records to the given IP address. Nothing
+    /// is sent to the registrar until the returned future is polled.
+    fn update(&self, record_names: &[String], ip_address: &str) -> DdnsResult<UpdateFuture>;
+
+    /// Prepares the removal of the named record from its registrar. Nothing is
+    /// sent to the registrar until the returned future is polled.
+    fn delete(&self, record_name: &str) -> DdnsResult<DdnsBoxFuture<()>> {
+        Err(DdnsError::ProviderError(ProviderError::UnsupportedError(
+            record_name.to_owned(),
+            "delete".to_owned(),
+        )))
+    }
+}
+
+#[derive(Clone, new)]
+pub(crate) struct UpdateContext {
+    pub(crate) updater: std::sync::Arc<dyn Updater>,
+    pub(crate) status: SharedStatus,
+    pub(crate) notifiers: SharedNotifiers,
+    pub(crate) hooks: std::sync::Arc<Hooks>,
+    pub(crate) damping: ChangeDamping,
+}
+
+pub(crate) fn process_address(context: &UpdateContext, ip_address: String) -> DdnsResult<()> {
+    log::trace!("fn process_address(ip_address={:?})", ip_address);
+
+    let now = std::time::Instant::now();
+
+    let mut status = context.status.lock().unwrap();
+    status
+        .last_check
+        .replace(unix_timestamp(std::time::SystemTime::now()));
+    status.last_error.take();
+
+    let desired_address = status.desired_address.clone();
+    if desired_address.as_ref() == Some(&ip_address) {
+        log::debug!("IP Address unchanged from {:?}", desired_address);
+        status.suppress_pending_address();
+    } else if desired_address.is_none()
+        || status.confirm_address(&ip_address, &context.damping, now)
+    {
+        log::info!(
+            "IP Address has changed from {:?} to {}",
+            desired_address,
+            ip_address,
+        );
+        status.desired_address.replace(ip_address.clone());
+
+        notify(
+            context.notifiers.as_slice(),
+            NotificationEvent::new(
+                NotificationEventKind::AddressChanged,
+                desired_address,
+                ip_address,
+                None,
+                None,
+            ),
+        );
+    }
+
+    let record_names = status.take_pending_records(&context.damping, now);
+    if record_names.is_empty() {
+        return Ok(());
+    }
+
+    let ip_address = status.desired_address.clone().unwrap_or_default();
+    let old_address = status
+        .record_mut(record_names[0].as_str())
+        .and_then(|record| record.current_address().cloned());
+    drop(status);
+
+    start_update(context, old_address, ip_address, record_names.as_slice())
+}
+
+pub(crate) fn start_update(
+    context: &UpdateContext,
+    old_address: Option<String>,
+    ip_address: String,
+    record_names: &[String],
+) -> DdnsResult<()> {
+    log::trace!(
+        "fn start_update(old_address={:?}, ip_address={:?}, record_names={:?})",
+        old_address,
+        ip_address.as_str(),
+        record_names,
+    );
+
+    context
+        .updater
+        .update(record_names, ip_address.as_str())
+        .map(|update_future| {
+            tokio::spawn(make_update_future(
+                update_future,
+                old_address,
+                ip_address,
+                record_names.to_vec(),
+                context.clone(),
+            ));
+        })
+        .inspect_err(|_| context.status.lock().unwrap().mark_failed(record_names))
+}
+
+pub(crate) fn start_delete(context: &UpdateContext, record_name: &str) -> DdnsResult<()> {
+    log::trace!("fn start_delete(record_name={:?})", record_name);
+
+    let status = context.status.clone();
+    let record_name = record_name.to_owned();
+    context
+        .updater
+        .delete(record_name.as_str())
+        .map(|delete_future| {
+            tokio::spawn(delete_future.then(move |result| {
+                let mut status = status.lock().unwrap();
+                if let Some(record) = status.record_mut(record_name.as_str()) {
+                    match result {
+                        Ok(()) => {
+                            log::info!("Removed registrar record '{}'", record_name);
+                            record.applied_address.take();
+                            record.assumed_address.take();
+                            record
+                                .last_update
+                                .replace(unix_timestamp(std::time::SystemTime::now()));
+                            record.last_error.take();
+                        }
+                        Err(error) => {
+                            log::error!("{:?}", error);
+                            record.last_error.replace(format!("{:?}", error));
+                            record.removed = false;
+                        }
+                    }
+                }
+                Ok(())
+            }));
+        })
+}
+
+fn make_update_future(
+    update_future: UpdateFuture,
+    old_address: Option<String>,
+    ip_address: String,
+    record_names: Vec<String>,
+    context: UpdateContext,
+) -> impl Future<Item = (), Error = ()> {
+    log::trace!(
+        "fn make_update_future(old_address={:?}, ip_address={:?})",
+        old_address,
+        ip_address.as_str(),
+    );
+
+    let environment = make_hook_environment(old_address.as_ref(), ip_address.as_str());
+    let failure_policy = context.hooks.failure_policy;
+    let post_update_context = context.clone();
+    let post_update_environment = environment.clone();
+
+    make_hook_future(
+        "pre-update",
+        &context.hooks,
+        &context.hooks.pre_update_hook,
+        environment,
+    )
+    .then(move |result| match result {
+        Ok(()) => Ok(true),
+        Err(error) => {
+            log::error!("Pre-update hook failed: {:?}", error);
+            context
+                .status
+                .lock()
+                .unwrap()
+                .last_error
+                .replace(format!("{:?}", error));
+            Ok(failure_policy == HookFailurePolicy::Continue)
+        }
+    })
+    .and_then(move |proceed| {
+        if !proceed {
+            log::warn!("Aborting registrar update after pre-update hook failure");
+            post_update_context
+                .status
+                .lock()
+                .unwrap()
+                .mark_failed(record_names.as_slice());
+            return future::Either::A(future::ok(()));
+        }
+
+        let context = post_update_context;
+        let mut environment = post_update_environment;
+        future::Either::B(update_future.and_then(move |results| {
+            record_update_results(&context, ip_address.as_str(), results.as_slice());
+            environment.extend(make_hook_result_environment(results.as_slice()));
+            make_hook_future(
+                "post-update",
+                &context.hooks,
+                &context.hooks.post_update_hook,
+                environment,
+            )
+            .map_err(|error| log::error!("Post-update hook failed: {:?}", error))
+        }))
+    })
+}
+
+fn record_update_results(
+    context: &UpdateContext,
+    ip_address: &str,
+    results: &[(String, DdnsResult<()>)],
+) {
+    results.iter().for_each(|(name, result)| {
+        let old_address = update_record_status(&context.status, name.as_str(), ip_address, result);
+        if let Err(error) = result {
+            log::error!("{:?}", error);
+            notify(
+                context.notifiers.as_slice(),
+                NotificationEvent::new(
+                    NotificationEventKind::UpdateFailed,
+                    old_address,
+                    ip_address.to_owned(),
+                    Some(name.to_owned()),
+                    Some(format!("{:?}", error)),
+                ),
+            );
+        }
+    });
+}
+
+/// Records the result of a registrar update in the shared status, returning the
+/// address the record held before the update.
+fn update_record_status(
+    status: &SharedStatus,
+    name: &str,
+    ip_address: &str,
+    result: &DdnsResult<()>,
+) -> Option<String> {
+    log::trace!(
+        "fn update_record_status(name={:?}, ip_address={:?}, result={:?})",
+        name,
+        ip_address,
+        result,
+    );
+
+    let mut status = status.lock().unwrap();
+    let record = status.record_mut(name)?;
+    let old_address = record.current_address().cloned();
+    match result {
+        Ok(()) => {
+            record.applied_address.replace(ip_address.to_owned());
+            record
+                .last_update
+                .replace(unix_timestamp(std::time::SystemTime::now()));
+            record.last_error.take();
+        }
+        Err(error) => {
+            record.last_error.replace(format!("{:?}", error));
+            record.mark_failed();
+        }
+    }
+    old_address
+}
+
+pub(crate) fn make_interval_timer_stream(update_interval: u64) -> impl DdnsStream<()> {
+    log::trace!(
+        "fn make_interval_timer_stream(update_interval={:?})",
+        update_interval
+    );
+
+    tokio::timer::Interval::new(
+        std::time::Instant::now(),
+        std::time::Duration::from_secs(update_interval),
+    )
+    .map_err(DdnsError::IntervalError)
+    .map(|_| ())
+}
@@ -0,0 +1,233 @@
+use crate::error::{RequestError, RequestResult, StatusError};
+use crate::extract::{JsonPath, ValueExtractor};
+
+/// Criteria a registrar response must meet for its request to succeed.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseValidation {
+    /// Accepted status codes. Any `2xx` status is accepted when empty.
+    pub statuses: Vec<u16>,
+    /// JSONPath expressions, each of which must select the given value from a
+    /// JSON response body.
+    pub assertions: Vec<(JsonPath, serde_json::Value)>,
+    /// Regular expression the response body must match.
+    pub regex: Option<regex::Regex>,
+    /// Extracts the registrar's error message from the body of a failed
+    /// response.
+    pub error_message: Option<ValueExtractor>,
+}
+
+impl ResponseValidation {
+    /// Makes validation criteria from the `response` property of the given
+    /// request object, if it is present.
+    pub(crate) fn from_json(request_json: &serde_json::Value) -> RequestResult<Self> {
+        let response_json = match request_json.get("response") {
+            Some(response_json) => response_json,
+            None => return Ok(ResponseValidation::default()),
+        };
+
+        let statuses = match response_json.get("status") {
+            Some(serde_json::Value::Array(statuses)) => statuses
+                .iter()
+                .map(|status| status.as_u64().map(|status| status as u16))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(RequestError::FormatError)?,
+            Some(status) => vec![status.as_u64().ok_or(RequestError::FormatError)? as u16],
+            None => Vec::new(),
+        };
+        let assertions = match response_json.get("json_path") {
+            Some(assertions) => assertions
+                .as_object()
+                .ok_or(RequestError::FormatError)?
+                .iter()
+                .map(|(expression, expected)| {
+                    JsonPath::parse(expression)
+                        .map(|json_path| (json_path, expected.clone()))
+                        .ok_or_else(|| RequestError::JsonPathError(expression.to_owned()))
+                })
+                .collect::<RequestResult<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        let regex = match response_json.get("regex") {
+            Some(expression) => Some(
+                regex::Regex::new(expression.as_str().ok_or(RequestError::FormatError)?)
+                    .map_err(RequestError::RegexError)?,
+            ),
+            None => None,
+        };
+        let error_message = match response_json.get("error_message") {
+            Some(error_message_json) => ValueExtractor::from_json(error_message_json)?,
+            None => None,
+        };
+
+        Ok(ResponseValidation {
+            statuses,
+            assertions,
+            regex,
+            error_message,
+        })
+    }
+
+    /// Returns whether a response with the given status code may succeed.
+    pub fn is_accepted_status(&self, status: u16) -> bool {
+        if self.statuses.is_empty() {
+            (200..300).contains(&status)
+        } else {
+            self.statuses.contains(&status)
+        }
+    }
+
+    /// Checks a response against the criteria, describing the first unmet
+    /// criterion and the registrar's own error message if it fails.
+    pub fn validate(&self, status: u16, body: &str) -> Result<(), StatusError> {
+        log::trace!(
+            "fn ResponseValidation::validate(status={:?}, body={:?})",
+            status,
+            body,
+        );
+
+        let failure = if !self.is_accepted_status(status) {
+            Some(format!("Unexpected status {}", status))
+        } else if !self.assertions.is_empty() {
+            match serde_json::from_str::<serde_json::Value>(body) {
+                Ok(document) => self.assertions.iter().find_map(|(json_path, expected)| {
+                    let actual = json_path.find(&document).first().cloned();
+                    if actual == Some(expected) {
+                        None
+                    } else {
+                        Some(format!(
+                            "Expected {} to be {}, found {}",
+                            json_path,
+                            expected,
+                            actual.map_or("nothing".to_owned(), |actual| actual.to_string()),
+                        ))
+                    }
+                }),
+                Err(error) => Some(format!("Invalid JSON response: {}", error)),
+            }
+        } else {
+            None
+        };
+        let failure = failure.or_else(|| {
+            self.regex
+                .as_ref()
+                .filter(|regex| !regex.is_match(body))
+                .map(|regex| format!("Expected response to match /{}/", regex))
+        });
+
+        match failure {
+            None => Ok(()),
+            Some(failure) => {
+                let message = self
+                    .error_message
+                    .as_ref()
+                    .and_then(|error_message| error_message.extract(body))
+                    .unwrap_or(failure);
+                Err(StatusError::new(status, body.to_owned(), Some(message)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validation(response: serde_json::Value) -> ResponseValidation {
+        ResponseValidation::from_json(&serde_json::json!({ "response": response })).unwrap()
+    }
+
+    #[test]
+    fn accepts_any_success_status_by_default() {
+        let validation = ResponseValidation::from_json(&serde_json::json!({})).unwrap();
+        assert!(validation.validate(200, "").is_ok());
+        assert!(validation.validate(204, "").is_ok());
+        let error = validation.validate(500, "internal error").unwrap_err();
+        assert_eq!(error.status, 500);
+        assert_eq!(error.body, "internal error");
+        assert_eq!(error.message.as_deref(), Some("Unexpected status 500"));
+    }
+
+    #[test]
+    fn accepts_listed_statuses() {
+        let statuses = validation(serde_json::json!({ "status": [200, 409] }));
+        assert!(statuses.validate(409, "").is_ok());
+        assert!(statuses.validate(201, "").is_err());
+        assert!(statuses.is_accepted_status(200));
+
+        let status = validation(serde_json::json!({ "status": 201 }));
+        assert!(status.validate(201, "").is_ok());
+        assert!(status.validate(200, "").is_err());
+    }
+
+    #[test]
+    fn checks_json_path_assertions() {
+        let validation = validation(serde_json::json!({
+            "json_path": { "$.success": true, "$.result[?(@.name == 'www')].ttl": 300 },
+        }));
+        let body = serde_json::json!({
+            "success": true,
+            "result": [{ "name": "www", "ttl": 300 }],
+        });
+        assert!(validation.validate(200, body.to_string().as_str()).is_ok());
+
+        let error = validation
+            .validate(200, "{\"success\":false,\"result\":[]}")
+            .unwrap_err();
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Expected $.result[?(@.name == 'www')].ttl to be 300, found nothing")
+        );
+
+        let error = validation.validate(200, "not json").unwrap_err();
+        assert!(error
+            .message
+            .unwrap()
+            .starts_with("Invalid JSON response: "));
+    }
+
+    #[test]
+    fn checks_regex() {
+        let validation = validation(serde_json::json!({ "regex": "^(good|nochg) " }));
+        assert!(validation.validate(200, "nochg 192.0.2.1").is_ok());
+        let error = validation.validate(200, "badauth").unwrap_err();
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Expected response to match /^(good|nochg) /")
+        );
+    }
+
+    #[test]
+    fn prefers_registrar_error_messages() {
+        let validation = validation(serde_json::json!({
+            "json_path": { "$.success": true },
+            "error_message": { "json_path": "$.errors[0].message" },
+        }));
+        let error = validation
+            .validate(
+                400,
+                "{\"success\":false,\"errors\":[{\"message\":\"Invalid zone\"}]}",
+            )
+            .unwrap_err();
+        assert_eq!(error.message.as_deref(), Some("Invalid zone"));
+
+        let error = validation.validate(400, "{}").unwrap_err();
+        assert_eq!(error.message.as_deref(), Some("Unexpected status 400"));
+    }
+
+    #[test]
+    fn rejects_invalid_criteria() {
+        for response in &[
+            serde_json::json!({ "status": "200" }),
+            serde_json::json!({ "json_path": ["$.success"] }),
+            serde_json::json!({ "json_path": { "success": true } }),
+            serde_json::json!({ "regex": "(" }),
+        ] {
+            assert!(
+                ResponseValidation::from_json(&serde_json::json!({ "response": response }))
+                    .is_err(),
+                "{}",
+                response
+            );
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::auth::percent_encode;
+use crate::error::{RequestError, RequestResult};
+use crate::extract::JsonPath;
+use crate::template::RegistrarRequest;
+
+/// The response to a workflow step, from which later requests capture values.
+#[derive(Clone, Debug, new)]
+pub(crate) struct StepResponse {
+    pub(crate) name: String,
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: String,
+}
+
+const STEP_REFERENCE_PREFIX: &str = "${steps.";
+
+/// Resolves a reference of the form `<step>.status`, `<step>.headers.<name>`,
+/// `<step>.body` or `<step>.body.<path>`, where `<path>` is a dot-separated
+/// list of member names and array indices into a JSON body.
+fn resolve_step_reference(reference: &str, responses: &[StepResponse]) -> Option<String> {
+    let mut parts = reference.splitn(3, '.');
+    let step_name = parts.next()?;
+    let response = responses
+        .iter()
+        .rev()
+        .find(|response| response.name == step_name)?;
+    match (parts.next()?, parts.next()) {
+        ("status", None) => Some(response.status.to_string()),
+        ("headers", Some(header_name)) => response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(header_name))
+            .map(|(_, value)| value.to_owned()),
+        ("body", None) => Some(response.body.to_owned()),
+        ("body", Some(path)) => {
+            let expression = path.split('.').fold("$".to_owned(), |expression, key| {
+                if key.parse::<usize>().is_ok() {
+                    format!("{}[{}]", expression, key)
+                } else {
+                    format!("{}['{}']", expression, key)
+                }
+            });
+            let document = serde_json::from_str::<serde_json::Value>(&response.body).ok()?;
+            JsonPath::parse(expression.as_str())?
+                .find(&document)
+                .first()
+                .map(|value| match value {
+                    serde_json::Value::String(string) => string.to_owned(),
+                    value => value.to_string(),
+                })
+        }
+        _ => None,
+    }
+}
+
+/// How captured values are escaped for the part of a request they are rendered
+/// into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StepValueEscape {
+    /// Headers take values as they are.
+    None,
+    /// Values in the address are percent-encoded, so that characters such as
+    /// `/`, `?`, `&` and `#` cannot change which resource is addressed.
+    Url,
+    /// Values in the body are escaped for inclusion in a JSON string.
+    Json,
+}
+
+/// Replaces every `${steps.…}` reference in `text` with the value it captures
+/// from the given step responses, escaped as given.
+fn render_step_references(
+    text: &str,
+    responses: &[StepResponse],
+    escape: StepValueEscape,
+) -> RequestResult<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(STEP_REFERENCE_PREFIX) {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| RequestError::StepReferenceError(rest[start..].to_owned()))?;
+        let reference = &rest[start + STEP_REFERENCE_PREFIX.len()..end];
+        let value = resolve_step_reference(reference, responses)
+            .ok_or_else(|| RequestError::StepReferenceError(rest[start..=end].to_owned()))?;
+
+        output.push_str(&rest[..start]);
+        match escape {
+            StepValueEscape::None => output.push_str(value.as_str()),
+            StepValueEscape::Url => output.push_str(percent_encode(value.as_str()).as_str()),
+            StepValueEscape::Json => {
+                let escaped = serde_json::Value::String(value).to_string();
+                output.push_str(&escaped[1..escaped.len() - 1]);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Renders the values captured from earlier workflow steps into a request's
+/// address, headers and body.
+pub(crate) fn render_step_request(
+    request: &RegistrarRequest,
+    responses: &[StepResponse],
+) -> RequestResult<RegistrarRequest> {
+    log::trace!(
+        "fn render_step_request(request={:?}, responses={:?})",
+        request,
+        responses,
+    );
+
+    let headers = request
+        .headers
+        .iter()
+        .map(|(key, value)| {
+            render_step_references(value.as_str(), responses, StepValueEscape::None)
+                .map(|value| (key.to_owned(), value))
+        })
+        .collect::<RequestResult<Vec<_>>>()?;
+    Ok(RegistrarRequest {
+        address: render_step_references(request.address.as_str(), responses, StepValueEscape::Url)?,
+        headers,
+        body: render_step_references(request.body.as_str(), responses, StepValueEscape::Json)?,
+        ..request.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(request_json: serde_json::Value, body: &str) -> RegistrarRequest {
+        let requests = serde_json::json!([request_json]).to_string();
+        let request = crate::template::make_registrar_requests(requests.as_str())
+            .unwrap()
+            .remove(0);
+        let responses = vec![StepResponse::new(
+            "lookup".to_owned(),
+            200,
+            vec![("X-Zone".to_owned(), "a/b?c".to_owned())],
+            body.to_owned(),
+        )];
+        render_step_request(&request, responses.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn percent_encodes_values_in_addresses() {
+        let request = render(
+            serde_json::json!({
+                "name": "www",
+                "method": "PUT",
+                "address": "https://registrar.example.com/records/${steps.lookup.body.id}?zone=${steps.lookup.headers.x-zone}",
+                "headers": {},
+            }),
+            r#"{"id": "../admin?delete=all&x=#"}"#,
+        );
+        assert_eq!(
+            request.address,
+            "https://registrar.example.com/records/..%2Fadmin%3Fdelete%3Dall%26x%3D%23?zone=a%2Fb%3Fc"
+        );
+    }
+
+    #[test]
+    fn escapes_values_in_bodies_and_keeps_headers() {
+        let request = render(
+            serde_json::json!({
+                "name": "www",
+                "method": "PUT",
+                "address": "https://registrar.example.com/records/${steps.lookup.body.id}",
+                "headers": { "X-Zone": "${steps.lookup.headers.x-zone}" },
+                "body": { "id": "${steps.lookup.body.id}" },
+            }),
+            r#"{"id": "a\"b/c"}"#,
+        );
+        assert_eq!(
+            request.address,
+            "https://registrar.example.com/records/a%22b%2Fc"
+        );
+        assert_eq!(
+            request.headers,
+            vec![("X-Zone".to_owned(), "a/b?c".to_owned())]
+        );
+        assert_eq!(request.body, r#"{"id":"a\"b/c"}"#);
+    }
+
+    #[test]
+    fn fails_on_unresolved_references() {
+        let requests = serde_json::json!([{
+            "name": "www",
+            "method": "PUT",
+            "address": "https://registrar.example.com/records/${steps.missing.body}",
+            "headers": {},
+        }])
+        .to_string();
+        let request = crate::template::make_registrar_requests(requests.as_str())
+            .unwrap()
+            .remove(0);
+        assert!(render_step_request(&request, &[]).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+use crate::protocol::{make_error_response, ErrorCode, ErrorResponse};
+
+/// Authorization scheme of HMAC-signed requests.
+pub const HMAC_SCHEME: &str = "DDNS-HMAC-SHA256";
+
+/// Header carrying a per-client API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Credentials a server accepts requests with. Requests must be authenticated
+/// when any are set.
+#[derive(Clone, new)]
+pub struct AuthConfig {
+    /// Static tokens accepted as `Authorization: Bearer <token>`.
+    pub tokens: Vec<String>,
+    /// Shared secret of HMAC-signed requests without a key ID.
+    pub hmac_secret: Option<String>,
+    /// JSON object mapping client names to their API keys.
+    pub api_keys_path: Option<std::path::PathBuf>,
+    /// How far the timestamps of signed requests may be from the current time.
+    pub max_skew: std::time::Duration,
+}
+
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("tokens", &format!("<{} redacted>", self.tokens.len()))
+            .field(
+                "hmac_secret",
+                &self.hmac_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("api_keys_path", &self.api_keys_path)
+            .field("max_skew", &self.max_skew)
+            .finish()
+    }
+}
+
+impl AuthConfig {
+    /// Returns whether requests must be authenticated.
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty() || self.hmac_secret.is_some() || self.api_keys_path.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    IoError(std::path::PathBuf, std::io::Error),
+    ParseError(std::path::PathBuf, serde_json::Error),
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac_sha256(secret: &str, data: &str) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let key = openssl::pkey::PKey::hmac(secret.as_bytes())?;
+    let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)?;
+    signer.update(data.as_bytes())?;
+    signer.sign_to_vec()
+}
+
+/// Computes the signature of a request made at the given time (in seconds since
+/// the Unix epoch) with the given nonce.
+fn sign(
+    secret: &str,
+    method: &str,
+    path_and_query: &str,
+    timestamp: u64,
+    nonce: &str,
+) -> Result<String, openssl::error::ErrorStack> {
+    let string_to_sign = format!("{}\n{}\n{}\n{}", method, path_and_query, timestamp, nonce);
+    hmac_sha256(secret, string_to_sign.as_str()).map(|signature| to_hex(signature.as_slice()))
+}
+
+/// Compares secrets in constant time, so that their contents cannot be
+/// discovered by timing failed comparisons.
+fn secrets_equal(lhs: &str, rhs: &str) -> bool {
+    lhs.len() == rhs.len() && openssl::memcmp::eq(lhs.as_bytes(), rhs.as_bytes())
+}
+
+fn unix_timestamp(time: std::time::SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Makes the `Authorization` header value of an HMAC-signed request, signed
+/// with a per-client key when `key_id` is given, or with the shared secret
+/// otherwise.
+pub fn make_hmac_authorization(
+    key_id: Option<&str>,
+    secret: &str,
+    method: &str,
+    path_and_query: &str,
+) -> Result<String, openssl::error::ErrorStack> {
+    let timestamp = unix_timestamp(std::time::SystemTime::now());
+    let mut nonce = [0; 16];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let nonce = to_hex(&nonce);
+    let signature = sign(secret, method, path_and_query, timestamp, nonce.as_str())?;
+    let key_id = key_id
+        .map(|key_id| format!("KeyId={}, ", key_id))
+        .unwrap_or_default();
+    Ok(format!(
+        "{} {}Timestamp={}, Nonce={}, Signature={}",
+        HMAC_SCHEME, key_id, timestamp, nonce, signature,
+    ))
+}
+
+/// Per-client API keys, read from a JSON object mapping client names to keys.
+/// The file is reloaded whenever it changes, so that keys can be added and
+/// revoked without a restart.
+struct ApiKeyFile {
+    path: std::path::PathBuf,
+    state: std::sync::Mutex<(
+        Option<std::time::SystemTime>,
+        std::collections::HashMap<String, String>,
+    )>,
+}
+
+impl ApiKeyFile {
+    fn read(
+        path: &std::path::Path,
+    ) -> Result<std::collections::HashMap<String, String>, AuthError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| AuthError::IoError(path.to_owned(), error))?;
+        serde_json::from_str(contents.as_str())
+            .map_err(|error| AuthError::ParseError(path.to_owned(), error))
+    }
+
+    /// Returns the keys of every client, by client name.
+    fn keys(&self) -> std::collections::HashMap<String, String> {
+        let mut state = self.state.lock().unwrap();
+        let modified = std::fs::metadata(self.path.as_path())
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified != state.0 {
+            match Self::read(self.path.as_path()) {
+                Ok(keys) => {
+                    log::info!("Loaded {} API keys", keys.len());
+                    *state = (modified, keys);
+                }
+                Err(error) => log::warn!("Failed to reload API key file: {:?}", error),
+            }
+        }
+        state.1.clone()
+    }
+}
+
+/// Authenticates requests with the credentials of an `AuthConfig`, by any of:
+/// * `Authorization: Bearer <token>`, with one of the static tokens.
+/// * `X-API-Key: <key>`, with the key of a client in the API key file.
+/// * `Authorization: DDNS-HMAC-SHA256 [KeyId=<client>, ]Timestamp=<seconds>,
+///   Nonce=<nonce>, Signature=<hex>`, signed with the shared HMAC secret, or the
+///   key of the named client. Each nonce is only accepted once, and timestamps
+///   must be within the maximum clock skew.
+pub struct ServerAuth {
+    tokens: Vec<String>,
+    hmac_secret: Option<String>,
+    api_keys: Option<ApiKeyFile>,
+    max_skew: std::time::Duration,
+    nonces: std::sync::Mutex<std::collections::HashMap<String, u64>>,
+}
+
+impl ServerAuth {
+    /// Creates an authenticator, failing if the API key file cannot be loaded.
+    pub fn new(config: AuthConfig) -> Result<Self, AuthError> {
+        let api_keys = match config.api_keys_path {
+            Some(path) => {
+                let modified = std::fs::metadata(path.as_path())
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                let keys = ApiKeyFile::read(path.as_path())?;
+                Some(ApiKeyFile {
+                    path,
+                    state: std::sync::Mutex::new((modified, keys)),
+                })
+            }
+            None => None,
+        };
+        Ok(ServerAuth {
+            tokens: config.tokens,
+            hmac_secret: config.hmac_secret,
+            api_keys,
+            max_skew: config.max_skew,
+            nonces: std::sync::Mutex::new(std::collections::HashMap::new()),
+        })
+    }
+
+    /// Authenticates a request, returning the name of the client that made it,
+    /// or why it was rejected.
+    pub fn authenticate<B>(&self, request: &hyper::Request<B>) -> Result<String, String> {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+
+        if let Some(api_key) = header(API_KEY_HEADER) {
+            let keys = self.api_keys.as_ref().map(|api_keys| api_keys.keys());
+            return keys
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(_, key)| secrets_equal(key, api_key))
+                .map(|(client, _)| client)
+                .ok_or_else(|| "Invalid API key".to_owned());
+        }
+
+        match header(hyper::header::AUTHORIZATION.as_str()).and_then(|value| value.split_once(' '))
+        {
+            Some(("Bearer", token)) => self
+                .tokens
+                .iter()
+                .any(|expected| secrets_equal(expected, token.trim()))
+                .then(|| "bearer".to_owned())
+                .ok_or_else(|| "Invalid bearer token".to_owned()),
+            Some((HMAC_SCHEME, parameters)) => self.authenticate_hmac(request, parameters),
+            Some((scheme, _)) => Err(format!("Unsupported authorization scheme '{}'", scheme)),
+            None => Err("Missing credentials".to_owned()),
+        }
+    }
+
+    /// Authenticates a request, returning the response to send instead of
+    /// handling it when it is rejected.
+    pub fn check<B>(&self, request: &hyper::Request<B>) -> Option<hyper::Response<hyper::Body>> {
+        match self.authenticate(request) {
+            Ok(client) => {
+                log::debug!("Authenticated request from client '{}'", client);
+                None
+            }
+            Err(reason) => {
+                log::info!("Rejected request: {}", reason);
+                Some(make_unauthorized_response(reason.as_str()))
+            }
+        }
+    }
+
+    fn authenticate_hmac<B>(
+        &self,
+        request: &hyper::Request<B>,
+        parameters: &str,
+    ) -> Result<String, String> {
+        let parameters = parameters
+            .split(',')
+            .filter_map(|parameter| parameter.trim().split_once('='))
+            .collect::<std::collections::HashMap<_, _>>();
+        let parameter = |name| {
+            parameters
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("Missing signature parameter '{}'", name))
+        };
+
+        let (client, secret) = match parameters.get("KeyId") {
+            Some(key_id) => {
+                let keys = self.api_keys.as_ref().map(|api_keys| api_keys.keys());
+                let secret = keys
+                    .unwrap_or_default()
+                    .remove(*key_id)
+                    .ok_or_else(|| format!("Unknown key ID '{}'", key_id))?;
+                ((*key_id).to_owned(), secret)
+            }
+            None => (
+                "hmac".to_owned(),
+                self.hmac_secret
+                    .clone()
+                    .ok_or_else(|| "Missing signature parameter 'KeyId'".to_owned())?,
+            ),
+        };
+
+        let timestamp = parameter("Timestamp")?
+            .parse::<u64>()
+            .map_err(|_| "Invalid signature timestamp".to_owned())?;
+        let now = unix_timestamp(std::time::SystemTime::now());
+        if now.max(timestamp) - now.min(timestamp) > self.max_skew.as_secs() {
+            return Err("Signature timestamp is outside the allowed clock skew".to_owned());
+        }
+
+        let nonce = parameter("Nonce")?;
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+        let expected = sign(
+            secret.as_str(),
+            request.method().as_str(),
+            path_and_query,
+            timestamp,
+            nonce,
+        )
+        .map_err(|error| error.to_string())?;
+        if !secrets_equal(expected.as_str(), parameter("Signature")?) {
+            return Err("Invalid signature".to_owned());
+        }
+
+        // Nonces only need to be remembered for as long as their timestamps
+        // would be accepted.
+        let mut nonces = self.nonces.lock().unwrap();
+        let max_skew = self.max_skew.as_secs();
+        nonces.retain(|_, seen_at| *seen_at + 2 * max_skew >= now);
+        if nonces
+            .insert(format!("{}:{}", client, nonce), now)
+            .is_some()
+        {
+            return Err("Replayed signature nonce".to_owned());
+        }
+        Ok(client)
+    }
+}
+
+/// Makes the response to a request that failed authentication.
+pub fn make_unauthorized_response(reason: &str) -> hyper::Response<hyper::Body> {
+    let mut response = make_error_response(
+        hyper::StatusCode::UNAUTHORIZED,
+        &ErrorResponse::new(ErrorCode::Unauthorized, reason),
+    );
+    response.headers_mut().insert(
+        hyper::header::WWW_AUTHENTICATE,
+        hyper::header::HeaderValue::from_str(format!("Bearer, {}", HMAC_SCHEME).as_str()).unwrap(),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    fn make_auth() -> ServerAuth {
+        ServerAuth::new(AuthConfig::new(
+            vec!["token".to_owned()],
+            Some(SECRET.to_owned()),
+            None,
+            std::time::Duration::from_secs(60),
+        ))
+        .unwrap()
+    }
+
+    fn make_request(path: &str, authorization: &str) -> hyper::Request<()> {
+        hyper::Request::get(path)
+            .header(hyper::header::AUTHORIZATION, authorization)
+            .body(())
+            .unwrap()
+    }
+
+    fn make_signed_authorization(secret: &str, path: &str, timestamp: u64, nonce: &str) -> String {
+        let signature = sign(secret, "GET", path, timestamp, nonce).unwrap();
+        format!(
+            "{} Timestamp={}, Nonce={}, Signature={}",
+            HMAC_SCHEME, timestamp, nonce, signature
+        )
+    }
+
+    #[test]
+    fn accepts_signed_requests() {
+        let authorization = make_hmac_authorization(None, SECRET, "GET", "/?format=json").unwrap();
+        let request = make_request("/?format=json", authorization.as_str());
+        assert_eq!(make_auth().authenticate(&request), Ok("hmac".to_owned()));
+    }
+
+    #[test]
+    fn rejects_replayed_nonces() {
+        let auth = make_auth();
+        let authorization = make_hmac_authorization(None, SECRET, "GET", "/").unwrap();
+        let request = make_request("/", authorization.as_str());
+        assert!(auth.authenticate(&request).is_ok());
+        assert_eq!(
+            auth.authenticate(&request),
+            Err("Replayed signature nonce".to_owned())
+        );
+
+        // The same nonce from another client is not a replay.
+        let now = unix_timestamp(std::time::SystemTime::now());
+        let authorization = make_signed_authorization(SECRET, "/", now, "nonce");
+        let request = make_request("/", authorization.as_str());
+        assert!(auth.authenticate(&request).is_ok());
+    }
+
+    #[test]
+    fn rejects_requests_signed_for_other_requests() {
+        let auth = make_auth();
+        let now = unix_timestamp(std::time::SystemTime::now());
+
+        let authorization = make_signed_authorization("other secret", "/", now, "a");
+        let request = make_request("/", authorization.as_str());
+        assert_eq!(
+            auth.authenticate(&request),
+            Err("Invalid signature".to_owned())
+        );
+
+        let authorization = make_signed_authorization(SECRET, "/", now, "b");
+        let request = make_request("/?format=text", authorization.as_str());
+        assert_eq!(
+            auth.authenticate(&request),
+            Err("Invalid signature".to_owned())
+        );
+
+        let authorization = make_signed_authorization(SECRET, "/", now, "c");
+        let request = make_request("/", authorization.replace("Nonce=c", "Nonce=d").as_str());
+        assert_eq!(
+            auth.authenticate(&request),
+            Err("Invalid signature".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_timestamps_outside_the_clock_skew() {
+        let auth = make_auth();
+        let now = unix_timestamp(std::time::SystemTime::now());
+        for timestamp in [now - 61, now + 61] {
+            let authorization = make_signed_authorization(SECRET, "/", timestamp, "nonce");
+            let request = make_request("/", authorization.as_str());
+            assert_eq!(
+                auth.authenticate(&request),
+                Err("Signature timestamp is outside the allowed clock skew".to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_signatures_missing_parameters() {
+        let authorization = format!("{} Timestamp=1, Signature=00", HMAC_SCHEME);
+        let request = make_request("/", authorization.as_str());
+        assert!(make_auth().authenticate(&request).is_err());
+
+        let auth = ServerAuth::new(AuthConfig::new(
+            vec![],
+            None,
+            None,
+            std::time::Duration::from_secs(60),
+        ))
+        .unwrap();
+        let authorization = make_hmac_authorization(None, SECRET, "GET", "/").unwrap();
+        let request = make_request("/", authorization.as_str());
+        assert_eq!(
+            auth.authenticate(&request),
+            Err("Missing signature parameter 'KeyId'".to_owned())
+        );
+    }
+
+    #[test]
+    fn checks_bearer_tokens() {
+        let auth = make_auth();
+        assert_eq!(
+            auth.authenticate(&make_request("/", "Bearer token")),
+            Ok("bearer".to_owned())
+        );
+        assert_eq!(
+            auth.authenticate(&make_request("/", "Bearer tokens")),
+            Err("Invalid bearer token".to_owned())
+        );
+        assert_eq!(
+            auth.authenticate(&make_request("/", "Basic dXNlcjpwYXNz")),
+            Err("Unsupported authorization scheme 'Basic'".to_owned())
+        );
+        assert_eq!(
+            auth.authenticate(&hyper::Request::get("/").body(()).unwrap()),
+            Err("Missing credentials".to_owned())
+        );
+    }
+}
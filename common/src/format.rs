@@ -0,0 +1,235 @@
+use crate::protocol::{make_error_response, ErrorCode, ErrorResponse};
+use crate::AddressResponse;
+
+/// Media type of detailed address responses, which are JSON with every field
+/// the server knows.
+pub const DETAILED_JSON_MEDIA_TYPE: &str = "application/vnd.ddns.address+json";
+
+/// Representation of an address response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResponseFormat {
+    /// The bare address, as `text/plain`.
+    Text,
+    /// `{"ip": "..."}`, with a signature when the server signs responses.
+    Json,
+    /// JSON with the address family, port, timestamp, server ID and interface
+    /// name as well, where the server knows them.
+    Detailed,
+}
+
+impl std::str::FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" | "plain" => Ok(ResponseFormat::Text),
+            "json" => Ok(ResponseFormat::Json),
+            "detailed" => Ok(ResponseFormat::Detailed),
+            _ => Err(format!("Unsupported response format '{}'", value)),
+        }
+    }
+}
+
+impl ResponseFormat {
+    /// Every supported format.
+    pub const ALL: [ResponseFormat; 3] = [
+        ResponseFormat::Text,
+        ResponseFormat::Json,
+        ResponseFormat::Detailed,
+    ];
+
+    /// Returns the name of the format, as in the `format` query parameter.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResponseFormat::Text => "text",
+            ResponseFormat::Json => "json",
+            ResponseFormat::Detailed => "detailed",
+        }
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ResponseFormat::Text => "text/plain; charset=utf-8",
+            ResponseFormat::Json => "application/json",
+            ResponseFormat::Detailed => DETAILED_JSON_MEDIA_TYPE,
+        }
+    }
+
+    /// Returns the format of a media range of an `Accept` header, if it is one
+    /// of the supported formats.
+    fn from_media_range(media_range: &str) -> Option<Self> {
+        match media_range.to_ascii_lowercase().as_str() {
+            "text/plain" | "text/*" => Some(ResponseFormat::Text),
+            "application/json" | "application/*" | "*/*" => Some(ResponseFormat::Json),
+            DETAILED_JSON_MEDIA_TYPE => Some(ResponseFormat::Detailed),
+            _ => None,
+        }
+    }
+
+    /// Returns the format a request asks for: the `format` query parameter if
+    /// it has one, or else the most preferred supported media range of its
+    /// `Accept` header. Requests that state no supported preference are
+    /// answered with JSON, as they were before other formats were supported.
+    pub fn negotiate<B>(request: &hyper::Request<B>) -> Result<Self, String> {
+        let format = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .filter_map(|parameter| parameter.split_once('='))
+                .find(|(name, _)| *name == "format")
+                .map(|(_, value)| value)
+        });
+        if let Some(format) = format {
+            return format.parse();
+        }
+
+        let mut preferred = None;
+        for media_range in request
+            .headers()
+            .get_all(hyper::header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let mut parameters = media_range.split(';');
+            let format = match Self::from_media_range(parameters.next().unwrap_or_default().trim())
+            {
+                Some(format) => format,
+                None => continue,
+            };
+            let quality = parameters
+                .filter_map(|parameter| parameter.trim().split_once('='))
+                .find(|(name, _)| name.eq_ignore_ascii_case("q"))
+                .and_then(|(_, quality)| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            // Earlier media ranges win ties, as clients list them in order of
+            // preference.
+            if quality > 0.0 && preferred.is_none_or(|(_, preferred)| quality > preferred) {
+                preferred = Some((format, quality));
+            }
+        }
+        Ok(preferred
+            .map(|(format, _)| format)
+            .unwrap_or(ResponseFormat::Json))
+    }
+}
+
+/// Makes the response carrying an address in the given format. Detailed
+/// responses are timestamped with the current time unless they are signed.
+pub fn make_address_response(
+    format: ResponseFormat,
+    mut address_response: AddressResponse,
+) -> hyper::Response<hyper::Body> {
+    let body = match format {
+        ResponseFormat::Text => format!("{}\n", address_response.ip),
+        ResponseFormat::Json => {
+            address_response.family = None;
+            address_response.port = None;
+            address_response.server_id = None;
+            address_response.interface = None;
+            serde_json::to_string(&address_response).unwrap()
+        }
+        ResponseFormat::Detailed => {
+            if address_response.timestamp.is_none() {
+                address_response.timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .ok();
+            }
+            serde_json::to_string(&address_response).unwrap()
+        }
+    };
+    hyper::Response::builder()
+        .header("Content-Type", format.media_type())
+        .header(hyper::header::VARY, "Accept")
+        .body(hyper::Body::from(body))
+        .unwrap()
+}
+
+/// Makes the response to a request for an unsupported format.
+pub fn make_unsupported_format_response(message: &str) -> hyper::Response<hyper::Body> {
+    let formats = ResponseFormat::ALL
+        .iter()
+        .map(|format| format.name())
+        .collect::<Vec<_>>();
+    make_error_response(
+        hyper::StatusCode::BAD_REQUEST,
+        &ErrorResponse::new(ErrorCode::UnsupportedFormat, message)
+            .with_details(serde_json::json!({ "formats": formats })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn negotiate(uri: &str, accept: &[&str]) -> Result<ResponseFormat, String> {
+        let mut request = hyper::Request::get(uri);
+        for value in accept {
+            request.header(hyper::header::ACCEPT, *value);
+        }
+        ResponseFormat::negotiate(&request.body(()).unwrap())
+    }
+
+    #[test]
+    fn prefers_the_format_query_parameter() {
+        assert_eq!(
+            negotiate("/?format=TEXT", &["application/json"]),
+            Ok(ResponseFormat::Text)
+        );
+        assert_eq!(
+            negotiate("/?nonce=1&format=detailed", &[]),
+            Ok(ResponseFormat::Detailed)
+        );
+        assert!(negotiate("/?format=xml", &["text/plain"]).is_err());
+    }
+
+    #[test]
+    fn negotiates_the_most_preferred_media_range() {
+        assert_eq!(negotiate("/", &["text/plain"]), Ok(ResponseFormat::Text));
+        // Unparseable qualities are taken as the default of 1.
+        assert_eq!(
+            negotiate("/", &["text/plain;q=invalid"]),
+            Ok(ResponseFormat::Text)
+        );
+        assert_eq!(
+            negotiate("/", &["text/plain;q=0.5, application/json"]),
+            Ok(ResponseFormat::Json)
+        );
+        assert_eq!(
+            negotiate("/", &["application/json; q=0.2", "text/*; Q=0.8"]),
+            Ok(ResponseFormat::Text)
+        );
+        assert_eq!(
+            negotiate(
+                "/",
+                &["application/xml, APPLICATION/VND.DDNS.ADDRESS+JSON;q=0.9, */*;q=0.1"]
+            ),
+            Ok(ResponseFormat::Detailed)
+        );
+    }
+
+    #[test]
+    fn breaks_ties_in_favor_of_earlier_media_ranges() {
+        assert_eq!(
+            negotiate("/", &["text/plain, application/json"]),
+            Ok(ResponseFormat::Text)
+        );
+        assert_eq!(
+            negotiate("/", &["application/json, text/plain"]),
+            Ok(ResponseFormat::Json)
+        );
+    }
+
+    #[test]
+    fn defaults_to_json() {
+        assert_eq!(negotiate("/", &[]), Ok(ResponseFormat::Json));
+        assert_eq!(
+            negotiate("/", &["application/xml"]),
+            Ok(ResponseFormat::Json)
+        );
+        assert_eq!(
+            negotiate("/", &["text/plain;q=0, application/xml"]),
+            Ok(ResponseFormat::Json)
+        );
+    }
+}
@@ -1,6 +1,69 @@
+#[macro_use]
+extern crate derive_new;
+
+extern crate hyper;
+extern crate log;
+extern crate openssl;
 extern crate serde;
+extern crate serde_json;
+extern crate socket2;
+extern crate tokio;
+
+pub mod auth;
+pub mod format;
+pub mod limit;
+pub mod protocol;
+pub mod proxy;
+pub mod server;
+pub mod signing;
+pub mod tls;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct AddressResponse {
     pub ip: String,
+    /// Time the response was signed (or, unsigned, made) at, in seconds since
+    /// the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Nonce of the request the response was signed for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// Base64-encoded Ed25519 signature of the address, timestamp and nonce.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Family of the address, `ipv4` or `ipv6`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    /// Source port of the connection the address was seen on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Identifier of the server that made the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// Name of the network interface the address was found on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+}
+
+impl AddressResponse {
+    /// Creates an unsigned response.
+    pub fn new(ip: String) -> Self {
+        AddressResponse {
+            ip,
+            timestamp: None,
+            nonce: None,
+            signature: None,
+            family: None,
+            port: None,
+            server_id: None,
+            interface: None,
+        }
+    }
+
+    /// Creates an unsigned response for an address, with its family.
+    pub fn for_address(address: &std::net::IpAddr) -> Self {
+        let mut response = Self::new(address.to_string());
+        response.family = Some(protocol::AddressFamily::of(address).name().to_owned());
+        response
+    }
 }
@@ -0,0 +1,279 @@
+use crate::protocol::{make_error_response, ErrorCode, ErrorResponse};
+use crate::proxy::Cidr;
+
+/// Number of buckets above which full buckets are dropped, as a client whose
+/// bucket is full is indistinguishable from one that was never seen.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// Limits the rate of requests from each source address with a token bucket,
+/// refilled at a constant rate up to a maximum burst.
+pub struct RateLimiter {
+    /// Tokens added per second.
+    rate: f64,
+    /// Maximum number of tokens, and so of requests made at once.
+    burst: f64,
+    buckets:
+        std::sync::Mutex<std::collections::HashMap<std::net::IpAddr, (f64, std::time::Instant)>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `requests_per_minute` requests from each
+    /// source address, in bursts of up to `burst` requests.
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        RateLimiter {
+            rate: f64::from(requests_per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+            buckets: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    /// Takes a token for a request from the given address, or returns how long
+    /// to wait until one is available.
+    pub fn check(&self, address: &std::net::IpAddr) -> Result<(), std::time::Duration> {
+        let now = std::time::Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            let (rate, burst) = (self.rate, self.burst);
+            buckets.retain(|_, (tokens, updated_at)| {
+                *tokens + now.duration_since(*updated_at).as_secs_f64() * rate < burst
+            });
+        }
+
+        let (tokens, updated_at) = buckets.entry(*address).or_insert((self.burst, now));
+        *tokens =
+            (*tokens + now.duration_since(*updated_at).as_secs_f64() * self.rate).min(self.burst);
+        *updated_at = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(std::time::Duration::from_secs_f64(
+                (1.0 - *tokens) / self.rate,
+            ))
+        }
+    }
+}
+
+/// Source addresses a server answers. Denied blocks take precedence over
+/// allowed ones, and every address is allowed when no blocks are.
+#[derive(Clone, Debug, Default, new)]
+pub struct AccessList {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl AccessList {
+    pub fn is_allowed(&self, address: &std::net::IpAddr) -> bool {
+        !self.deny.iter().any(|cidr| cidr.contains(address))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(address)))
+    }
+}
+
+/// Caps the number of connections a server holds open at once.
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    max: usize,
+    active: std::sync::atomic::AtomicUsize,
+}
+
+/// An open connection, counted against its limit until dropped.
+pub struct ConnectionGuard(std::sync::Arc<ConnectionLimit>);
+
+impl ConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        ConnectionLimit {
+            max,
+            active: std::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    /// Counts a new connection, unless the limit has been reached.
+    pub fn acquire(self: &std::sync::Arc<Self>) -> Option<ConnectionGuard> {
+        self.active
+            .fetch_update(
+                std::sync::atomic::Ordering::SeqCst,
+                std::sync::atomic::Ordering::SeqCst,
+                |active| Some(active + 1).filter(|active| *active <= self.max),
+            )
+            .ok()
+            .map(|_| ConnectionGuard(self.clone()))
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0
+            .active
+            .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Caps the number of connections a server holds open at once from each source
+/// address.
+#[derive(Debug)]
+pub struct AddressConnectionLimit {
+    max: usize,
+    active: std::sync::Mutex<std::collections::HashMap<std::net::IpAddr, usize>>,
+}
+
+/// An open connection, counted against the limit of its source address until
+/// dropped.
+pub struct AddressConnectionGuard(std::sync::Arc<AddressConnectionLimit>, std::net::IpAddr);
+
+impl AddressConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        AddressConnectionLimit {
+            max,
+            active: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    /// Counts a new connection from the given address, unless the address has
+    /// reached the limit.
+    pub fn acquire(
+        self: &std::sync::Arc<Self>,
+        address: std::net::IpAddr,
+    ) -> Option<AddressConnectionGuard> {
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(address).or_insert(0);
+        if *count >= self.max {
+            return None;
+        }
+        *count += 1;
+        Some(AddressConnectionGuard(self.clone(), address))
+    }
+}
+
+impl Drop for AddressConnectionGuard {
+    fn drop(&mut self) {
+        let mut active = self.0.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.1) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.1);
+            }
+        }
+    }
+}
+
+/// Makes the response to a request over its client's rate limit, asking it to
+/// retry after the given delay.
+pub fn make_too_many_requests_response(
+    retry_after: std::time::Duration,
+) -> hyper::Response<hyper::Body> {
+    // Retry-After is in whole seconds, so round up to not invite an early retry.
+    let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = make_error_response(
+        hyper::StatusCode::TOO_MANY_REQUESTS,
+        &ErrorResponse::new(ErrorCode::RateLimited, "Too many requests")
+            .with_details(serde_json::json!({ "retry_after": retry_after })),
+    );
+    response.headers_mut().insert(
+        hyper::header::RETRY_AFTER,
+        hyper::header::HeaderValue::from(retry_after),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(address: &str) -> std::net::IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn limits_each_address_to_its_burst() {
+        let limiter = RateLimiter::new(60, 2);
+        let client = address("192.0.2.1");
+        assert!(limiter.check(&client).is_ok());
+        assert!(limiter.check(&client).is_ok());
+        let retry_after = limiter.check(&client).unwrap_err();
+        assert!(retry_after > std::time::Duration::from_millis(900));
+        assert!(retry_after <= std::time::Duration::from_secs(1));
+
+        assert!(limiter.check(&address("192.0.2.2")).is_ok());
+    }
+
+    #[test]
+    fn refills_buckets_over_time() {
+        let limiter = RateLimiter::new(60, 2);
+        let client = address("192.0.2.1");
+        assert!(limiter.check(&client).is_ok());
+        assert!(limiter.check(&client).is_ok());
+        assert!(limiter.check(&client).is_err());
+
+        // Moves the bucket's last update into the past, as if time had passed.
+        let rewind = |seconds: f64| {
+            let mut buckets = limiter.buckets.lock().unwrap();
+            let (_, updated_at) = buckets.get_mut(&client).unwrap();
+            *updated_at -= std::time::Duration::from_secs_f64(seconds);
+        };
+        rewind(1.5);
+        assert!(limiter.check(&client).is_ok());
+        let retry_after = limiter.check(&client).unwrap_err();
+        assert!(retry_after <= std::time::Duration::from_millis(500));
+
+        // Buckets never hold more than the burst.
+        rewind(60.0);
+        assert!(limiter.check(&client).is_ok());
+        assert!(limiter.check(&client).is_ok());
+        assert!(limiter.check(&client).is_err());
+    }
+
+    #[test]
+    fn denies_addresses_in_denied_blocks() {
+        let everyone = AccessList::default();
+        assert!(everyone.is_allowed(&address("192.0.2.1")));
+
+        let access_list = AccessList::new(
+            crate::proxy::parse_cidrs("192.0.2.0/24, 2001:db8::/32").unwrap(),
+            crate::proxy::parse_cidrs("192.0.2.128/25").unwrap(),
+        );
+        assert!(access_list.is_allowed(&address("192.0.2.1")));
+        assert!(access_list.is_allowed(&address("::ffff:192.0.2.1")));
+        assert!(access_list.is_allowed(&address("2001:db8::1")));
+        assert!(!access_list.is_allowed(&address("192.0.2.200")));
+        assert!(!access_list.is_allowed(&address("198.51.100.1")));
+
+        let access_list = AccessList::new(
+            vec![],
+            crate::proxy::parse_cidrs("198.51.100.0/24").unwrap(),
+        );
+        assert!(access_list.is_allowed(&address("192.0.2.1")));
+        assert!(!access_list.is_allowed(&address("198.51.100.1")));
+    }
+
+    #[test]
+    fn caps_connections() {
+        let limit = std::sync::Arc::new(ConnectionLimit::new(2));
+        let first = limit.acquire().unwrap();
+        let _second = limit.acquire().unwrap();
+        assert!(limit.acquire().is_none());
+        drop(first);
+        assert!(limit.acquire().is_some());
+    }
+
+    #[test]
+    fn caps_connections_from_each_address() {
+        let limit = std::sync::Arc::new(AddressConnectionLimit::new(1));
+        let client = address("192.0.2.1");
+        let guard = limit.acquire(client).unwrap();
+        assert!(limit.acquire(client).is_none());
+        assert!(limit.acquire(address("192.0.2.2")).is_some());
+        drop(guard);
+        assert!(limit.acquire(client).is_some());
+        assert!(limit.active.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rounds_retry_after_up_to_whole_seconds() {
+        let response = make_too_many_requests_response(std::time::Duration::from_millis(1500));
+        assert_eq!(response.status(), hyper::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[hyper::header::RETRY_AFTER], "2");
+
+        let response = make_too_many_requests_response(std::time::Duration::from_secs(3));
+        assert_eq!(response.headers()[hyper::header::RETRY_AFTER], "3");
+    }
+}
@@ -0,0 +1,360 @@
+//! Versioned wire protocol between address servers and clients.
+//!
+//! Servers answer `/v1/address` (and `/v1/address/v4` or `/v1/address/v6` for
+//! one address family) and describe themselves at `/v1/capabilities`. Every
+//! response advertises the protocol versions the server speaks in the
+//! `X-DDNS-Protocol-Versions` header, so that clients of older servers, which
+//! answer any path with the address, never need to probe for them.
+
+/// Protocol version of this module.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Header listing the comma-separated protocol versions a server speaks.
+pub const VERSIONS_HEADER: &str = "x-ddns-protocol-versions";
+
+/// Path of the address of the client, in any family.
+pub const ADDRESS_PATH: &str = "/v1/address";
+
+/// Path of the capabilities of the server.
+pub const CAPABILITIES_PATH: &str = "/v1/capabilities";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressFamily {
+    V4,
+    V6,
+}
+
+impl AddressFamily {
+    pub fn of(address: &std::net::IpAddr) -> Self {
+        match address {
+            std::net::IpAddr::V4(_) => AddressFamily::V4,
+            std::net::IpAddr::V6(_) => AddressFamily::V6,
+        }
+    }
+
+    /// Returns the name of the family on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddressFamily::V4 => "ipv4",
+            AddressFamily::V6 => "ipv6",
+        }
+    }
+}
+
+impl std::fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddressFamily::V4 => write!(f, "IPv4"),
+            AddressFamily::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// A resource of an address server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endpoint {
+    /// The address of the client, in the given family if any.
+    Address(Option<AddressFamily>),
+    Capabilities,
+}
+
+impl Endpoint {
+    /// Returns the endpoint of a request path, or `None` for unknown versioned
+    /// paths. Unversioned paths are answered with the address as they were
+    /// before the protocol was versioned, with `/v4` and `/v6` asking for one
+    /// address family.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let is_version = |segment: &str| {
+            segment
+                .strip_prefix('v')
+                .is_some_and(|version| version.parse::<u32>().is_ok())
+        };
+        match segments.as_slice() {
+            ["v4"] => Some(Endpoint::Address(Some(AddressFamily::V4))),
+            ["v6"] => Some(Endpoint::Address(Some(AddressFamily::V6))),
+            ["v1", "address"] => Some(Endpoint::Address(None)),
+            ["v1", "address", "v4"] => Some(Endpoint::Address(Some(AddressFamily::V4))),
+            ["v1", "address", "v6"] => Some(Endpoint::Address(Some(AddressFamily::V6))),
+            ["v1", "capabilities"] => Some(Endpoint::Capabilities),
+            [version, ..] if is_version(version) => None,
+            _ => Some(Endpoint::Address(None)),
+        }
+    }
+
+    /// Returns the versioned path of the endpoint.
+    pub fn path(&self) -> String {
+        match self {
+            Endpoint::Address(None) => ADDRESS_PATH.to_owned(),
+            Endpoint::Address(Some(AddressFamily::V4)) => format!("{}/v4", ADDRESS_PATH),
+            Endpoint::Address(Some(AddressFamily::V6)) => format!("{}/v6", ADDRESS_PATH),
+            Endpoint::Capabilities => CAPABILITIES_PATH.to_owned(),
+        }
+    }
+}
+
+/// What a server supports, as served at `/v1/capabilities`. Families and
+/// formats are named as on the wire, so that clients can read the capabilities
+/// of newer servers that support more of them.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Capabilities {
+    /// Protocol versions the server speaks.
+    #[serde(default)]
+    pub versions: Vec<u32>,
+    /// Address families the server can report, e.g. `ipv4`.
+    #[serde(default)]
+    pub families: Vec<String>,
+    /// Response formats the server can answer in, e.g. `json`.
+    #[serde(default)]
+    pub formats: Vec<String>,
+    /// Whether the server can push address changes to clients.
+    #[serde(default)]
+    pub push: bool,
+    /// Whether the server signs address responses.
+    #[serde(default)]
+    pub signed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+impl Capabilities {
+    pub fn supports_version(&self, version: u32) -> bool {
+        self.versions.contains(&version)
+    }
+
+    pub fn supports_family(&self, family: AddressFamily) -> bool {
+        self.families.iter().any(|name| name == family.name())
+    }
+}
+
+/// Why a server refused a request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorCode {
+    /// The request was not authenticated.
+    Unauthorized,
+    /// The client address is not allowed.
+    Forbidden,
+    /// The client made too many requests or connections. Details hold the
+    /// `retry_after` seconds.
+    RateLimited,
+    /// The request body is too large. Details hold the `max_body_size`.
+    PayloadTooLarge,
+    /// The requested format is not supported. Details hold the supported
+    /// `formats`.
+    UnsupportedFormat,
+    /// The requested endpoint does not exist. Details hold the `path`.
+    NotFound,
+    /// No address is known for the client in the requested family. Details
+    /// hold the `requested_family`, and the `family` the client connected
+    /// over.
+    AddressUnavailable,
+    /// The interface has no addresses. Details hold the `interface`.
+    NoAddresses,
+    /// The interface has several addresses. Details hold the `interface` and
+    /// its `addresses`.
+    MultipleAddresses,
+}
+
+impl ErrorCode {
+    /// Every error code.
+    pub const ALL: [ErrorCode; 9] = [
+        ErrorCode::Unauthorized,
+        ErrorCode::Forbidden,
+        ErrorCode::RateLimited,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::UnsupportedFormat,
+        ErrorCode::NotFound,
+        ErrorCode::AddressUnavailable,
+        ErrorCode::NoAddresses,
+        ErrorCode::MultipleAddresses,
+    ];
+
+    /// Returns the name of the code on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::UnsupportedFormat => "unsupported_format",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::AddressUnavailable => "address_unavailable",
+            ErrorCode::NoAddresses => "no_addresses",
+            ErrorCode::MultipleAddresses => "multiple_addresses",
+        }
+    }
+
+    /// Returns the code with the given name, if it is one this module knows.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|code| code.name() == name)
+    }
+}
+
+/// Body of error responses. The code is named as on the wire, so that clients
+/// can read the errors of newer servers with codes they do not know, and is
+/// empty in those of servers that predate it, which only had a message.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ErrorResponse {
+    #[serde(default)]
+    pub code: String,
+    pub message: String,
+    /// Further information about the error, as described by its code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: &str) -> Self {
+        ErrorResponse {
+            code: code.name().to_owned(),
+            message: message.to_owned(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Returns the error code, if it is one this module knows.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        ErrorCode::from_name(self.code.as_str())
+    }
+}
+
+/// Returns the protocol versions a response advertises, or none for servers
+/// that predate versioning.
+pub fn advertised_versions<B>(response: &hyper::Response<B>) -> Vec<u32> {
+    response
+        .headers()
+        .get_all(VERSIONS_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|version| version.trim().parse().ok())
+        .collect()
+}
+
+/// Adds the header advertising the protocol versions of this module to a
+/// response.
+pub fn advertise_versions<B>(response: &mut hyper::Response<B>) {
+    response.headers_mut().insert(
+        VERSIONS_HEADER,
+        hyper::header::HeaderValue::from_str(PROTOCOL_VERSION.to_string().as_str()).unwrap(),
+    );
+}
+
+/// Makes the response describing the capabilities of a server.
+pub fn make_capabilities_response(capabilities: &Capabilities) -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(
+            serde_json::to_string(capabilities).unwrap(),
+        ))
+        .unwrap()
+}
+
+/// Makes an error response with the given status.
+pub fn make_error_response(
+    status: hyper::StatusCode,
+    error: &ErrorResponse,
+) -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .header("Content-Type", "application/json")
+        .status(status)
+        .body(hyper::Body::from(serde_json::to_string(error).unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_request_paths_to_endpoints() {
+        for endpoint in [
+            Endpoint::Address(None),
+            Endpoint::Address(Some(AddressFamily::V4)),
+            Endpoint::Address(Some(AddressFamily::V6)),
+            Endpoint::Capabilities,
+        ] {
+            assert_eq!(
+                Endpoint::from_path(endpoint.path().as_str()),
+                Some(endpoint)
+            );
+        }
+        assert_eq!(Endpoint::from_path("/"), Some(Endpoint::Address(None)));
+        assert_eq!(
+            Endpoint::from_path("/anything"),
+            Some(Endpoint::Address(None))
+        );
+        assert_eq!(
+            Endpoint::from_path("/v6/"),
+            Some(Endpoint::Address(Some(AddressFamily::V6)))
+        );
+        assert_eq!(Endpoint::from_path("/v1/unknown"), None);
+        assert_eq!(Endpoint::from_path("/v2/address"), None);
+    }
+
+    #[test]
+    fn reads_advertised_versions() {
+        let mut response = hyper::Response::new(());
+        assert!(advertised_versions(&response).is_empty());
+
+        advertise_versions(&mut response);
+        assert_eq!(advertised_versions(&response), vec![PROTOCOL_VERSION]);
+
+        response.headers_mut().insert(
+            VERSIONS_HEADER,
+            hyper::header::HeaderValue::from_static("1, 2,x"),
+        );
+        assert_eq!(advertised_versions(&response), vec![1, 2]);
+    }
+
+    #[test]
+    fn reads_capabilities_of_newer_servers() {
+        let capabilities = serde_json::from_str::<Capabilities>(
+            r#"{"versions": [1, 2], "families": ["ipv4", "ipx"], "future": true}"#,
+        )
+        .unwrap();
+        assert!(capabilities.supports_version(2));
+        assert!(!capabilities.supports_version(3));
+        assert!(capabilities.supports_family(AddressFamily::V4));
+        assert!(!capabilities.supports_family(AddressFamily::V6));
+        assert!(!capabilities.signed);
+    }
+
+    #[test]
+    fn names_error_codes_on_the_wire() {
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_name(code.name()), Some(code));
+        }
+        assert_eq!(ErrorCode::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn reads_errors_of_other_servers() {
+        // Servers that predate error codes only sent a message.
+        let error = serde_json::from_str::<ErrorResponse>(r#"{"message": "Not found"}"#).unwrap();
+        assert_eq!(error.error_code(), None);
+
+        let error = serde_json::from_str::<ErrorResponse>(
+            r#"{"code": "quota_exceeded", "message": "Quota exceeded"}"#,
+        )
+        .unwrap();
+        assert_eq!(error.code, "quota_exceeded");
+        assert_eq!(error.error_code(), None);
+
+        let error = ErrorResponse::new(ErrorCode::RateLimited, "Too many requests")
+            .with_details(serde_json::json!({ "retry_after": 1 }));
+        let error =
+            serde_json::from_str::<ErrorResponse>(serde_json::to_string(&error).unwrap().as_str())
+                .unwrap();
+        assert_eq!(error.error_code(), Some(ErrorCode::RateLimited));
+        assert_eq!(error.details, Some(serde_json::json!({ "retry_after": 1 })));
+    }
+}
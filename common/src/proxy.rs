@@ -0,0 +1,511 @@
+use tokio::prelude::{future, Future};
+
+/// A block of IP addresses, e.g. `10.0.0.0/8` or `2001:db8::/32`. A single
+/// address is a block of one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cidr {
+    address: std::net::IpAddr,
+    prefix_len: u8,
+}
+
+impl std::str::FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+        let address = address
+            .trim()
+            .parse::<std::net::IpAddr>()
+            .map_err(|error| format!("{}: {}", value, error))?
+            .to_canonical();
+        let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| format!("{}: invalid prefix length", value))?,
+            None => max_prefix_len,
+        };
+        Ok(Cidr {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl Cidr {
+    /// Returns whether the block contains an address. IPv4-mapped IPv6
+    /// addresses are matched as the IPv4 addresses they map.
+    pub fn contains(&self, address: &std::net::IpAddr) -> bool {
+        fn mask(bits: u128, prefix_len: u8, width: u8) -> u128 {
+            match prefix_len {
+                0 => 0,
+                _ => bits >> (width - prefix_len),
+            }
+        }
+
+        match (self.address, address.to_canonical()) {
+            (std::net::IpAddr::V4(block), std::net::IpAddr::V4(address)) => {
+                mask(u32::from(block).into(), self.prefix_len, 32)
+                    == mask(u32::from(address).into(), self.prefix_len, 32)
+            }
+            (std::net::IpAddr::V6(block), std::net::IpAddr::V6(address)) => {
+                mask(block.into(), self.prefix_len, 128)
+                    == mask(address.into(), self.prefix_len, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses a comma-separated list of CIDR blocks.
+pub fn parse_cidrs(value: &str) -> Result<Vec<Cidr>, String> {
+    value
+        .split(',')
+        .map(|cidr| cidr.trim())
+        .filter(|cidr| !cidr.is_empty())
+        .map(|cidr| cidr.parse())
+        .collect()
+}
+
+/// Header a trusted proxy declares the address of its client in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ForwardedHeader {
+    /// RFC 7239 `Forwarded`, e.g. `Forwarded: for=192.0.2.60`.
+    Forwarded,
+    /// `X-Forwarded-For`, e.g. `X-Forwarded-For: 192.0.2.60, 10.0.0.5`.
+    #[default]
+    XForwardedFor,
+    /// `X-Real-IP`, e.g. `X-Real-IP: 192.0.2.60`.
+    XRealIp,
+}
+
+impl std::str::FromStr for ForwardedHeader {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "forwarded" => Ok(ForwardedHeader::Forwarded),
+            "x-forwarded-for" => Ok(ForwardedHeader::XForwardedFor),
+            "x-real-ip" => Ok(ForwardedHeader::XRealIp),
+            _ => Err(format!("Unsupported forwarding header '{}'", value)),
+        }
+    }
+}
+
+impl ForwardedHeader {
+    /// Returns the name of the header on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForwardedHeader::Forwarded => "forwarded",
+            ForwardedHeader::XForwardedFor => "x-forwarded-for",
+            ForwardedHeader::XRealIp => "x-real-ip",
+        }
+    }
+}
+
+/// Proxies whose forwarding headers and PROXY protocol headers are honored.
+#[derive(Clone, Debug, Default, new)]
+pub struct TrustedProxies {
+    cidrs: Vec<Cidr>,
+    /// The only header the proxies declare client addresses in. Proxies pass
+    /// the other headers on as the client sent them, so those are ignored.
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    pub fn is_trusted(&self, address: &std::net::IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(address))
+    }
+
+    /// Returns the address of the client a request was made for. When the peer
+    /// is a trusted proxy, the addresses it forwarded in its forwarding header
+    /// are walked from the nearest hop, and the first that is not itself a
+    /// trusted proxy is the client.
+    pub fn client_address<B>(
+        &self,
+        request: &hyper::Request<B>,
+        peer_address: std::net::IpAddr,
+    ) -> std::net::IpAddr {
+        let peer_address = peer_address.to_canonical();
+        if !self.is_trusted(&peer_address) {
+            return peer_address;
+        }
+
+        let hops = request
+            .headers()
+            .get_all(self.header.name())
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|value| match self.header {
+                ForwardedHeader::Forwarded => parse_forwarded_element(value),
+                ForwardedHeader::XForwardedFor | ForwardedHeader::XRealIp => parse_node(value),
+            })
+            .collect::<Vec<_>>();
+
+        let mut client_address = peer_address;
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(address) => {
+                    client_address = address;
+                    if !self.is_trusted(&address) {
+                        break;
+                    }
+                }
+                // Obfuscated and unknown hops cannot be attributed, so the
+                // nearest identified hop is reported instead.
+                None => break,
+            }
+        }
+        client_address
+    }
+}
+
+/// Parses the address of a node, as in `192.0.2.1`, `192.0.2.1:4711`,
+/// `2001:db8::1` or `[2001:db8::1]:4711`.
+fn parse_node(node: &str) -> Option<std::net::IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(address) = node.parse::<std::net::IpAddr>() {
+        return Some(address.to_canonical());
+    }
+    if let Ok(address) = node.parse::<std::net::SocketAddr>() {
+        return Some(address.ip().to_canonical());
+    }
+    node.strip_prefix('[')
+        .and_then(|node| node.split_once(']'))
+        .and_then(|(address, _)| address.parse::<std::net::IpAddr>().ok())
+        .map(|address| address.to_canonical())
+}
+
+/// Parses the `for` parameter of an RFC 7239 `Forwarded` element, e.g.
+/// `for=192.0.2.60;proto=http;by=203.0.113.43`.
+fn parse_forwarded_element(element: &str) -> Option<std::net::IpAddr> {
+    element
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+        .and_then(|(_, node)| parse_node(node))
+}
+
+const PROXY_V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest PROXY protocol v1 header, including its line ending.
+const PROXY_V1_MAX_LEN: usize = 107;
+
+fn invalid_header(message: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid PROXY protocol header: {}", message),
+    )
+}
+
+/// Parses a PROXY protocol v1 header line, e.g.
+/// `PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n`.
+fn parse_proxy_v1(line: &[u8]) -> std::io::Result<Option<std::net::SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid_header("not UTF-8"))?;
+    let fields = line.trim_end().split(' ').collect::<Vec<_>>();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4", source, _, source_port, _]
+        | ["PROXY", "TCP6", source, _, source_port, _] => {
+            let source = source
+                .parse::<std::net::IpAddr>()
+                .map_err(|_| invalid_header("invalid source address"))?;
+            let source_port = source_port
+                .parse::<u16>()
+                .map_err(|_| invalid_header("invalid source port"))?;
+            Ok(Some(std::net::SocketAddr::new(source, source_port)))
+        }
+        _ => Err(invalid_header(line.trim_end())),
+    }
+}
+
+/// Parses the body of a PROXY protocol v2 header, following its 16-byte
+/// preamble.
+fn parse_proxy_v2(preamble: &[u8], body: &[u8]) -> std::io::Result<Option<std::net::SocketAddr>> {
+    let version_command = preamble[12];
+    if version_command >> 4 != 2 {
+        return Err(invalid_header("unsupported version"));
+    }
+    match version_command & 0x0F {
+        // LOCAL connections are made by the proxy itself, e.g. health checks.
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid_header("unsupported command")),
+    }
+
+    let port = |offset: usize| u16::from_be_bytes([body[offset], body[offset + 1]]);
+    match preamble[13] >> 4 {
+        0x1 if body.len() >= 12 => {
+            let source = std::net::Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            Ok(Some(std::net::SocketAddr::new(source.into(), port(8))))
+        }
+        0x2 if body.len() >= 36 => {
+            let mut source = [0; 16];
+            source.copy_from_slice(&body[0..16]);
+            let source = std::net::Ipv6Addr::from(source);
+            Ok(Some(std::net::SocketAddr::new(source.into(), port(32))))
+        }
+        0x0 => Ok(None),
+        _ => Err(invalid_header("unsupported address family")),
+    }
+}
+
+type ProxyHeaderFuture<S> =
+    Box<dyn Future<Item = (S, Option<std::net::SocketAddr>), Error = std::io::Error> + Send>;
+
+/// Reads a PROXY protocol v1 or v2 header from the start of a stream, resolving
+/// to the stream and the source address the header declares, if any. Exactly
+/// the header is consumed, so the stream can be handed on as it is.
+pub fn read_proxy_header<S>(stream: S) -> ProxyHeaderFuture<S>
+where
+    S: tokio::io::AsyncRead + Send + 'static,
+{
+    // Both versions are at least 12 bytes long; a v1 header is at least
+    // `PROXY UNKNOWN\r\n`.
+    Box::new(
+        tokio::io::read_exact(stream, [0; 12]).and_then(|(stream, start)| {
+            if &start == PROXY_V2_SIGNATURE {
+                future::Either::A(
+                    tokio::io::read_exact(stream, [0; 4])
+                        .and_then(move |(stream, rest)| {
+                            let mut preamble = start.to_vec();
+                            preamble.extend_from_slice(&rest);
+                            let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+                            tokio::io::read_exact(stream, vec![0; len])
+                                .map(move |(stream, body)| (stream, preamble, body))
+                        })
+                        .and_then(|(stream, preamble, body)| {
+                            parse_proxy_v2(preamble.as_slice(), body.as_slice())
+                                .map(|address| (stream, address))
+                        }),
+                )
+            } else if start.starts_with(b"PROXY ") {
+                future::Either::B(future::Either::A(
+                    future::loop_fn((stream, start.to_vec()), |(stream, line)| {
+                        if line.ends_with(b"\r\n") {
+                            return future::Either::A(future::ok(future::Loop::Break((
+                                stream, line,
+                            ))));
+                        }
+                        if line.len() >= PROXY_V1_MAX_LEN {
+                            return future::Either::A(future::err(invalid_header("too long")));
+                        }
+                        // Read a byte at a time, so that nothing after the
+                        // header is consumed.
+                        future::Either::B(tokio::io::read_exact(stream, [0; 1]).map(
+                            |(stream, byte)| {
+                                let mut line = line;
+                                line.push(byte[0]);
+                                future::Loop::Continue((stream, line))
+                            },
+                        ))
+                    })
+                    .and_then(|(stream, line)| {
+                        parse_proxy_v1(line.as_slice()).map(|address| (stream, address))
+                    }),
+                ))
+            } else {
+                future::Either::B(future::Either::B(future::err(invalid_header(
+                    "missing header",
+                ))))
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(address: &str) -> std::net::IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn read_header(bytes: &[u8]) -> std::io::Result<(Option<std::net::SocketAddr>, Vec<u8>)> {
+        read_proxy_header(std::io::Cursor::new(bytes.to_vec()))
+            .wait()
+            .map(|(stream, source)| {
+                let rest = stream.get_ref()[stream.position() as usize..].to_vec();
+                (source, rest)
+            })
+    }
+
+    fn make_proxy_v2(command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = PROXY_V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20 | command, family]);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    #[test]
+    fn matches_addresses_in_blocks() {
+        let cidr = "10.0.0.0/8".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&address("10.255.0.1")));
+        assert!(cidr.contains(&address("::ffff:10.0.0.1")));
+        assert!(!cidr.contains(&address("11.0.0.1")));
+        assert!(!cidr.contains(&address("::a00:1")));
+
+        let cidr = "2001:db8::/32".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&address("2001:db8:ffff::1")));
+        assert!(!cidr.contains(&address("2001:db9::1")));
+
+        let cidr = "192.0.2.1".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&address("192.0.2.1")));
+        assert!(!cidr.contains(&address("192.0.2.2")));
+
+        let cidr = "0.0.0.0/0".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&address("203.0.113.1")));
+        assert!(!cidr.contains(&address("2001:db8::1")));
+    }
+
+    #[test]
+    fn parses_cidr_lists() {
+        assert_eq!(
+            parse_cidrs(" 10.0.0.0/8, ,::1 ").unwrap(),
+            vec![
+                "10.0.0.0/8".parse::<Cidr>().unwrap(),
+                "::1/128".parse::<Cidr>().unwrap(),
+            ]
+        );
+        assert!(parse_cidrs("10.0.0.0/33").is_err());
+        assert!(parse_cidrs("2001:db8::/129").is_err());
+        assert!(parse_cidrs("10.0.0.0/x").is_err());
+        assert!(parse_cidrs("example.com").is_err());
+    }
+
+    #[test]
+    fn parses_forwarding_header_names() {
+        assert_eq!(
+            "Forwarded".parse::<ForwardedHeader>(),
+            Ok(ForwardedHeader::Forwarded)
+        );
+        assert_eq!(
+            "X-Real-IP".parse::<ForwardedHeader>(),
+            Ok(ForwardedHeader::XRealIp)
+        );
+        assert!("via".parse::<ForwardedHeader>().is_err());
+    }
+
+    #[test]
+    fn parses_forwarded_nodes() {
+        assert_eq!(parse_node(" 192.0.2.1:4711"), Some(address("192.0.2.1")));
+        assert_eq!(
+            parse_node("\"[2001:db8::1]:4711\""),
+            Some(address("2001:db8::1"))
+        );
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(
+            parse_forwarded_element("proto=http;For=\"[2001:db8::1]\";by=10.0.0.1"),
+            Some(address("2001:db8::1"))
+        );
+        assert_eq!(parse_forwarded_element("for=_hidden"), None);
+    }
+
+    #[test]
+    fn reads_proxy_v1_headers() {
+        let (source, rest) =
+            read_header(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\nGET / HTTP/1.1\r\n").unwrap();
+        assert_eq!(source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let (source, _) = read_header(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").unwrap();
+        assert_eq!(source, Some("[2001:db8::1]:56324".parse().unwrap()));
+
+        let (source, rest) = read_header(b"PROXY UNKNOWN\r\nrest").unwrap();
+        assert_eq!(source, None);
+        assert_eq!(rest, b"rest");
+    }
+
+    #[test]
+    fn rejects_invalid_proxy_v1_headers() {
+        assert!(read_header(b"PROXY TCP4 192.0.2.1 192.0.2.2 99999 443\r\n").is_err());
+        assert!(read_header(b"PROXY TCP4 example.com 192.0.2.2 56324 443\r\n").is_err());
+        assert!(read_header(b"PROXY UDP4 192.0.2.1 192.0.2.2 56324 443\r\n").is_err());
+        assert!(
+            read_header(format!("PROXY {}\r\n", "A".repeat(PROXY_V1_MAX_LEN)).as_bytes()).is_err()
+        );
+        assert!(read_header(b"GET / HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn reads_proxy_v2_headers() {
+        let mut body = vec![192, 0, 2, 1, 192, 0, 2, 2];
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&443u16.to_be_bytes());
+        // Trailing TLVs are skipped along with the addresses.
+        body.extend_from_slice(&[0x04, 0x00, 0x01, 0xFF]);
+        let mut header = make_proxy_v2(0x1, 0x11, body.as_slice());
+        header.extend_from_slice(b"rest");
+        let (source, rest) = read_header(header.as_slice()).unwrap();
+        assert_eq!(source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"rest");
+
+        let mut body = "2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+            .to_vec();
+        body.extend_from_slice(&[0; 16]);
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&443u16.to_be_bytes());
+        let (source, _) =
+            read_header(make_proxy_v2(0x1, 0x21, body.as_slice()).as_slice()).unwrap();
+        assert_eq!(source, Some("[2001:db8::1]:56324".parse().unwrap()));
+
+        let (source, rest) = read_header(make_proxy_v2(0x0, 0x00, &[]).as_slice()).unwrap();
+        assert_eq!(source, None);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_proxy_v2_headers() {
+        let mut header = make_proxy_v2(0x1, 0x11, &[0; 12]);
+        header[12] = 0x11;
+        assert!(read_header(header.as_slice()).is_err());
+        assert!(read_header(make_proxy_v2(0x2, 0x11, &[0; 12]).as_slice()).is_err());
+        assert!(read_header(make_proxy_v2(0x1, 0x31, &[0; 216]).as_slice()).is_err());
+        // Address blocks shorter than their family requires.
+        assert!(read_header(make_proxy_v2(0x1, 0x11, &[0; 4]).as_slice()).is_err());
+        // Headers cut off before the declared length.
+        let header = make_proxy_v2(0x1, 0x11, &[0; 12]);
+        assert!(read_header(&header[..20]).is_err());
+    }
+
+    #[test]
+    fn reports_clients_of_trusted_proxies() {
+        let proxies = TrustedProxies::new(
+            parse_cidrs("10.0.0.0/8").unwrap(),
+            ForwardedHeader::XForwardedFor,
+        );
+        let request = hyper::Request::get("/")
+            .header("x-forwarded-for", "203.0.113.1, 192.0.2.1, 10.0.0.2")
+            .header("forwarded", "for=198.51.100.1")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            proxies.client_address(&request, address("10.0.0.1")),
+            address("192.0.2.1")
+        );
+        assert_eq!(
+            proxies.client_address(&request, address("192.0.2.9")),
+            address("192.0.2.9")
+        );
+
+        let proxies = TrustedProxies::new(
+            parse_cidrs("10.0.0.0/8").unwrap(),
+            ForwardedHeader::Forwarded,
+        );
+        assert_eq!(
+            proxies.client_address(&request, address("10.0.0.1")),
+            address("198.51.100.1")
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use tokio::prelude::{future, Async, Future, Poll, Stream};
+
+use crate::limit::{AddressConnectionLimit, ConnectionLimit};
+use crate::proxy::{read_proxy_header, TrustedProxies};
+use crate::tls::TlsAcceptor;
+
+/// How a server accepts connections.
+#[derive(Clone, Default)]
+pub struct ServeOptions {
+    /// Acceptor of TLS connections, if connections must use TLS.
+    pub tls_acceptor: Option<std::sync::Arc<TlsAcceptor>>,
+    /// Proxies that begin their connections with a PROXY protocol header, if
+    /// any. Connections from other peers are served as they are.
+    pub proxy_protocol: Option<std::sync::Arc<TrustedProxies>>,
+    /// Cap on the connections held open at once, shared by every listener it
+    /// is given to. Connections over the cap are refused.
+    pub connection_limit: Option<std::sync::Arc<ConnectionLimit>>,
+    /// Cap on the connections held open at once from each source address, as
+    /// declared by a PROXY protocol header if the connection has one.
+    /// Connections over the cap are refused.
+    pub address_connection_limit: Option<std::sync::Arc<AddressConnectionLimit>>,
+    /// How long a connection may take to send the head of its first request,
+    /// including its PROXY protocol header and TLS handshake. This closes
+    /// clients that trickle their headers in, but not connections kept alive
+    /// after a request.
+    pub header_timeout: Option<std::time::Duration>,
+    /// How long a connection may stay open in all, however many requests are
+    /// made over it.
+    pub connection_timeout: Option<std::time::Duration>,
+    /// Largest request head (request line and headers) accepted, in bytes.
+    /// Larger heads are answered with `431 Request Header Fields Too Large`.
+    /// Sizes below `MIN_MAX_HEADER_SIZE` are raised to it.
+    pub max_header_size: Option<usize>,
+}
+
+/// Smallest maximum request head size a server can be given.
+pub const MIN_MAX_HEADER_SIZE: usize = 8192;
+
+/// Response to connections over the connection limit, which are refused before
+/// any of their request is read.
+const TOO_MANY_CONNECTIONS_RESPONSE: &[u8] = b"HTTP/1.1 429 Too Many Requests\r\n\
+    Retry-After: 1\r\n\
+    Connection: close\r\n\
+    Content-Type: application/json\r\n\
+    Content-Length: 84\r\n\
+    \r\n\
+    {\"code\":\"rate_limited\",\"message\":\"Too many connections\",\"details\":{\"retry_after\":1}}";
+
+/// Wraps a service to flag when the first request over its connection has been
+/// parsed.
+struct FlagRequestService<S> {
+    service: S,
+    requested: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl<S> hyper::service::Service for FlagRequestService<S>
+where
+    S: hyper::service::Service,
+{
+    type ReqBody = S::ReqBody;
+    type ResBody = S::ResBody;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&mut self, request: hyper::Request<Self::ReqBody>) -> Self::Future {
+        self.requested
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.service.call(request)
+    }
+}
+
+/// A connection that is closed if its first request has not been parsed when
+/// its delay elapses.
+struct HeaderTimeout<F> {
+    connection: F,
+    delay: Option<tokio::timer::Delay>,
+    requested: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    peer_address: std::net::SocketAddr,
+}
+
+impl<F> Future for HeaderTimeout<F>
+where
+    F: Future<Item = (), Error = ()>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        if let Async::Ready(()) = self.connection.poll()? {
+            return Ok(Async::Ready(()));
+        }
+        if self.requested.load(std::sync::atomic::Ordering::SeqCst) {
+            self.delay = None;
+        }
+        match self.delay.as_mut().map(|delay| delay.poll()) {
+            Some(Ok(Async::NotReady)) | None => Ok(Async::NotReady),
+            Some(_) => {
+                log::debug!(
+                    "Connection from {} timed out before its first request",
+                    self.peer_address,
+                );
+                Err(())
+            }
+        }
+    }
+}
+
+/// Binds a listener to the given socket address. IPv6 listeners only accept
+/// IPv6 connections, so that an IPv4 listener can share their port.
+fn bind(socket_address: &std::net::SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(*socket_address),
+        socket2::Type::STREAM,
+        None,
+    )?;
+    if socket_address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&(*socket_address).into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// Serves HTTP connections accepted on the given socket address.
+/// `make_service` is called with the remote address of each connection, as
+/// declared by its PROXY protocol header if it has one.
+pub fn serve<F, S>(
+    socket_address: &std::net::SocketAddr,
+    options: ServeOptions,
+    make_service: F,
+) -> std::io::Result<impl Future<Item = (), Error = ()>>
+where
+    F: Fn(std::net::SocketAddr) -> S + Send + Sync + 'static,
+    S: hyper::service::Service<ReqBody = hyper::Body, ResBody = hyper::Body> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    log::trace!("fn serve(socket_address={:?})", socket_address);
+
+    let listener = tokio::net::TcpListener::from_std(
+        bind(socket_address)?,
+        &tokio::reactor::Handle::default(),
+    )?;
+    let mut http = hyper::server::conn::Http::new();
+    if let Some(max_header_size) = options.max_header_size {
+        http.max_buf_size(max_header_size.max(MIN_MAX_HEADER_SIZE));
+    }
+    let make_service = std::sync::Arc::new(make_service);
+    Ok(listener
+        .incoming()
+        .then(Ok::<_, ()>)
+        .for_each(move |stream| {
+            let (stream, peer_address) = match stream
+                .and_then(|stream| stream.peer_addr().map(|address| (stream, address)))
+            {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    // Accept errors such as running out of file descriptors
+                    // tend to persist, so back off before trying again.
+                    log::warn!("Failed to accept connection: {:?}", error);
+                    return future::Either::A(
+                        tokio::timer::Delay::new(
+                            std::time::Instant::now() + std::time::Duration::from_secs(1),
+                        )
+                        .then(|_| Ok(())),
+                    );
+                }
+            };
+            if let Err(error) = stream.set_nodelay(true) {
+                log::debug!("Failed to set TCP_NODELAY: {:?}", error);
+            }
+
+            let connection_guard = match options.connection_limit.as_ref() {
+                Some(connection_limit) => match connection_limit.acquire() {
+                    Some(connection_guard) => Some(connection_guard),
+                    None => {
+                        log::info!(
+                            "Refused connection from {}: too many connections",
+                            peer_address,
+                        );
+                        // A TLS client could not read a plaintext response, so
+                        // its connection is only closed.
+                        if options.tls_acceptor.is_none() {
+                            tokio::spawn(
+                                tokio::io::write_all(stream, TOO_MANY_CONNECTIONS_RESPONSE)
+                                    .then(|_| Ok(())),
+                            );
+                        }
+                        return future::Either::B(future::ok(()));
+                    }
+                },
+                None => None,
+            };
+
+            let remote_address = match options.proxy_protocol.as_ref() {
+                Some(proxies) if proxies.is_trusted(&peer_address.ip()) => future::Either::A(
+                    read_proxy_header(stream).map(move |(stream, source_address)| {
+                        (stream, source_address.unwrap_or(peer_address))
+                    }),
+                ),
+                _ => future::Either::B(future::ok((stream, peer_address))),
+            };
+
+            let http = http.clone();
+            let make_service = make_service.clone();
+            let tls_acceptor = options.tls_acceptor.clone();
+            let address_connection_limit = options.address_connection_limit.clone();
+            let requested = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+            let service_requested = requested.clone();
+            let connection = remote_address
+                .map_err(move |error| {
+                    log::debug!(
+                        "PROXY protocol header from {} failed: {:?}",
+                        peer_address,
+                        error,
+                    )
+                })
+                .and_then(move |(stream, remote_address)| {
+                    let address_guard = match address_connection_limit {
+                        Some(address_connection_limit) => {
+                            match address_connection_limit.acquire(remote_address.ip()) {
+                                Some(address_guard) => Some(address_guard),
+                                None => {
+                                    log::info!(
+                                        "Refused connection from {}: too many connections from \
+                                         its address",
+                                        remote_address,
+                                    );
+                                    return future::Either::A(match tls_acceptor {
+                                        Some(_) => future::Either::A(future::ok(())),
+                                        None => future::Either::B(
+                                            tokio::io::write_all(
+                                                stream,
+                                                TOO_MANY_CONNECTIONS_RESPONSE,
+                                            )
+                                            .then(|_| Ok(())),
+                                        ),
+                                    });
+                                }
+                            }
+                        }
+                        None => None,
+                    };
+
+                    let service = FlagRequestService {
+                        service: make_service(remote_address),
+                        requested: service_requested,
+                    };
+                    let log_error = move |error: hyper::Error| {
+                        log::debug!("Connection from {} failed: {:?}", remote_address, error)
+                    };
+                    let connection = match tls_acceptor {
+                        Some(tls_acceptor) => future::Either::A(
+                            tls_acceptor
+                                .accept(stream)
+                                .map_err(move |error| {
+                                    log::debug!(
+                                        "TLS handshake with {} failed: {:?}",
+                                        remote_address,
+                                        error,
+                                    )
+                                })
+                                .and_then(move |stream| {
+                                    http.serve_connection(stream, service).map_err(log_error)
+                                }),
+                        ),
+                        None => future::Either::B(
+                            http.serve_connection(stream, service).map_err(log_error),
+                        ),
+                    };
+                    future::Either::B(connection.then(move |result| {
+                        drop(address_guard);
+                        result
+                    }))
+                });
+            let connection = match options.header_timeout {
+                Some(header_timeout) => future::Either::A(HeaderTimeout {
+                    connection,
+                    delay: Some(tokio::timer::Delay::new(
+                        std::time::Instant::now() + header_timeout,
+                    )),
+                    requested,
+                    peer_address,
+                }),
+                None => future::Either::B(connection),
+            };
+            let connection = match options.connection_timeout {
+                Some(connection_timeout) => future::Either::A(
+                    tokio::timer::Timeout::new(connection, connection_timeout).map_err(
+                        move |error| {
+                            if error.is_elapsed() {
+                                log::debug!("Connection from {} timed out", peer_address);
+                            }
+                        },
+                    ),
+                ),
+                None => future::Either::B(connection),
+            };
+            tokio::spawn(connection.then(move |result| {
+                drop(connection_guard);
+                result
+            }));
+            future::Either::B(future::ok(()))
+        }))
+}
@@ -0,0 +1,240 @@
+use crate::AddressResponse;
+
+/// Header a client sends a nonce in, for the server to include in the signed
+/// response. Responses signed for another nonce cannot be replayed to it.
+pub const NONCE_HEADER: &str = "x-ddns-nonce";
+
+#[derive(Debug)]
+pub enum SigningError {
+    IoError(std::path::PathBuf, std::io::Error),
+    SslError(openssl::error::ErrorStack),
+}
+
+/// Returns the message signed for an address response.
+fn signed_message(ip: &str, timestamp: u64, nonce: &str) -> String {
+    format!("{}\n{}\n{}", ip, timestamp, nonce)
+}
+
+fn read_pem(path: &std::path::Path) -> Result<Vec<u8>, SigningError> {
+    std::fs::read(path).map_err(|error| SigningError::IoError(path.to_owned(), error))
+}
+
+/// Signs address responses with an Ed25519 private key.
+pub struct ResponseSigner {
+    key: openssl::pkey::PKey<openssl::pkey::Private>,
+}
+
+impl ResponseSigner {
+    /// Creates a signer with the PEM-encoded private key in the given file.
+    pub fn from_file(path: &std::path::Path) -> Result<Self, SigningError> {
+        let key = openssl::pkey::PKey::private_key_from_pem(read_pem(path)?.as_slice())
+            .map_err(SigningError::SslError)?;
+        Ok(ResponseSigner { key })
+    }
+
+    /// Signs a response with the current time, and the nonce of the request it
+    /// answers, or a random one if the request had none.
+    pub fn sign(
+        &self,
+        response: &mut AddressResponse,
+        nonce: Option<&str>,
+    ) -> Result<(), openssl::error::ErrorStack> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let nonce = match nonce {
+            Some(nonce) => nonce.to_owned(),
+            None => make_nonce()?,
+        };
+        let message = signed_message(response.ip.as_str(), timestamp, nonce.as_str());
+        let mut signer = openssl::sign::Signer::new_without_digest(&self.key)?;
+        let signature = signer.sign_oneshot_to_vec(message.as_bytes())?;
+
+        response.timestamp = Some(timestamp);
+        response.nonce = Some(nonce);
+        response.signature = Some(openssl::base64::encode_block(signature.as_slice()));
+        Ok(())
+    }
+}
+
+/// Makes a random nonce.
+pub fn make_nonce() -> Result<String, openssl::error::ErrorStack> {
+    let mut nonce = [0; 16];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    Ok(nonce.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Verifies the signatures of address responses with an Ed25519 public key.
+#[derive(Clone)]
+pub struct ResponseVerifier {
+    key: openssl::pkey::PKey<openssl::pkey::Public>,
+    max_age: std::time::Duration,
+}
+
+impl std::fmt::Debug for ResponseVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseVerifier")
+            .field("max_age", &self.max_age)
+            .finish()
+    }
+}
+
+impl ResponseVerifier {
+    /// Creates a verifier with the PEM-encoded public key in the given file,
+    /// accepting responses signed at most `max_age` ago.
+    pub fn from_file(
+        path: &std::path::Path,
+        max_age: std::time::Duration,
+    ) -> Result<Self, SigningError> {
+        let key = openssl::pkey::PKey::public_key_from_pem(read_pem(path)?.as_slice())
+            .map_err(SigningError::SslError)?;
+        Ok(ResponseVerifier { key, max_age })
+    }
+
+    /// Verifies that a response was signed for the given nonce within the
+    /// maximum age, returning why it was rejected otherwise.
+    pub fn verify(&self, response: &AddressResponse, nonce: &str) -> Result<(), String> {
+        let (timestamp, response_nonce, signature) =
+            match (response.timestamp, &response.nonce, &response.signature) {
+                (Some(timestamp), Some(response_nonce), Some(signature)) => {
+                    (timestamp, response_nonce, signature)
+                }
+                _ => return Err("Response is not signed".to_owned()),
+            };
+
+        let signature = openssl::base64::decode_block(signature.as_str())
+            .map_err(|_| "Invalid signature encoding".to_owned())?;
+        let message = signed_message(response.ip.as_str(), timestamp, response_nonce.as_str());
+        let verified = openssl::sign::Verifier::new_without_digest(&self.key)
+            .and_then(|mut verifier| {
+                verifier.verify_oneshot(signature.as_slice(), message.as_bytes())
+            })
+            .unwrap_or(false);
+        if !verified {
+            return Err("Invalid signature".to_owned());
+        }
+
+        if response_nonce != nonce {
+            return Err("Response was signed for another request".to_owned());
+        }
+        let signed_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp);
+        let age = match std::time::SystemTime::now().duration_since(signed_at) {
+            Ok(age) => age,
+            // Tolerate clocks that are ahead of ours by up to the maximum age.
+            Err(error) => error.duration(),
+        };
+        if age > self.max_age {
+            return Err(format!("Response was signed {} seconds ago", age.as_secs()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_keys(max_age: std::time::Duration) -> (ResponseSigner, ResponseVerifier) {
+        let key = openssl::pkey::PKey::generate_ed25519().unwrap();
+        let public_key =
+            openssl::pkey::PKey::public_key_from_pem(key.public_key_to_pem().unwrap().as_slice())
+                .unwrap();
+        (
+            ResponseSigner { key },
+            ResponseVerifier {
+                key: public_key,
+                max_age,
+            },
+        )
+    }
+
+    fn make_signed_response(signer: &ResponseSigner, nonce: &str) -> AddressResponse {
+        let mut response = AddressResponse::new("192.0.2.1".to_owned());
+        signer.sign(&mut response, Some(nonce)).unwrap();
+        response
+    }
+
+    #[test]
+    fn verifies_signed_responses() {
+        let (signer, verifier) = make_keys(std::time::Duration::from_secs(60));
+        let response = make_signed_response(&signer, "nonce");
+        assert_eq!(response.nonce.as_deref(), Some("nonce"));
+        assert_eq!(verifier.verify(&response, "nonce"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_altered_responses() {
+        let (signer, verifier) = make_keys(std::time::Duration::from_secs(60));
+        let response = make_signed_response(&signer, "nonce");
+
+        let mut altered = response.clone();
+        altered.ip = "192.0.2.2".to_owned();
+        assert_eq!(
+            verifier.verify(&altered, "nonce"),
+            Err("Invalid signature".to_owned())
+        );
+
+        let mut altered = response.clone();
+        altered.timestamp = altered.timestamp.map(|timestamp| timestamp + 1);
+        assert_eq!(
+            verifier.verify(&altered, "nonce"),
+            Err("Invalid signature".to_owned())
+        );
+
+        let mut altered = response;
+        altered.signature = Some("not base64!".to_owned());
+        assert_eq!(
+            verifier.verify(&altered, "nonce"),
+            Err("Invalid signature encoding".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_responses_signed_by_other_keys() {
+        let (signer, _) = make_keys(std::time::Duration::from_secs(60));
+        let (_, verifier) = make_keys(std::time::Duration::from_secs(60));
+        let response = make_signed_response(&signer, "nonce");
+        assert_eq!(
+            verifier.verify(&response, "nonce"),
+            Err("Invalid signature".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_responses_for_other_requests() {
+        let (signer, verifier) = make_keys(std::time::Duration::from_secs(60));
+        let response = make_signed_response(&signer, "nonce");
+        assert_eq!(
+            verifier.verify(&response, "other nonce"),
+            Err("Response was signed for another request".to_owned())
+        );
+
+        let unsigned = AddressResponse::new("192.0.2.1".to_owned());
+        assert_eq!(
+            verifier.verify(&unsigned, "nonce"),
+            Err("Response is not signed".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_stale_responses() {
+        let (signer, verifier) = make_keys(std::time::Duration::from_secs(0));
+        let mut response = AddressResponse::new("192.0.2.1".to_owned());
+        signer.sign(&mut response, Some("nonce")).unwrap();
+        // Re-sign the response as if it were made a minute ago.
+        let timestamp = response.timestamp.unwrap() - 60;
+        let message = signed_message("192.0.2.1", timestamp, "nonce");
+        let mut openssl_signer = openssl::sign::Signer::new_without_digest(&signer.key).unwrap();
+        let signature = openssl_signer
+            .sign_oneshot_to_vec(message.as_bytes())
+            .unwrap();
+        response.timestamp = Some(timestamp);
+        response.signature = Some(openssl::base64::encode_block(signature.as_slice()));
+
+        assert!(verifier
+            .verify(&response, "nonce")
+            .unwrap_err()
+            .starts_with("Response was signed"));
+    }
+}
@@ -0,0 +1,205 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::prelude::{Async, Future, Poll};
+
+/// Certificate files a server accepts TLS connections with.
+#[derive(Clone, Debug, new)]
+pub struct TlsConfig {
+    /// PEM-encoded certificate chain, starting with the server certificate.
+    pub certificate_path: std::path::PathBuf,
+    /// PEM-encoded private key of the server certificate.
+    pub key_path: std::path::PathBuf,
+    /// PEM-encoded CA certificates that client certificates must be issued by.
+    /// Clients need not present a certificate when this is not set.
+    pub client_ca_path: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    IoError(std::path::PathBuf, std::io::Error),
+    SslError(openssl::error::ErrorStack),
+}
+
+impl TlsConfig {
+    fn paths(&self) -> Vec<&std::path::Path> {
+        let mut paths = vec![self.certificate_path.as_path(), self.key_path.as_path()];
+        paths.extend(self.client_ca_path.as_deref());
+        paths
+    }
+
+    /// Returns the modification time of each certificate file.
+    fn modified_times(&self) -> Result<Vec<std::time::SystemTime>, TlsError> {
+        self.paths()
+            .into_iter()
+            .map(|path| {
+                std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|error| TlsError::IoError(path.to_owned(), error))
+            })
+            .collect()
+    }
+
+    fn make_acceptor(&self) -> Result<openssl::ssl::SslAcceptor, TlsError> {
+        log::trace!("fn TlsConfig::make_acceptor()");
+
+        let mut builder =
+            openssl::ssl::SslAcceptor::mozilla_intermediate_v5(openssl::ssl::SslMethod::tls())
+                .map_err(TlsError::SslError)?;
+        builder
+            .set_certificate_chain_file(self.certificate_path.as_path())
+            .map_err(TlsError::SslError)?;
+        builder
+            .set_private_key_file(self.key_path.as_path(), openssl::ssl::SslFiletype::PEM)
+            .map_err(TlsError::SslError)?;
+        builder.check_private_key().map_err(TlsError::SslError)?;
+        if let Some(client_ca_path) = self.client_ca_path.as_ref() {
+            builder
+                .set_ca_file(client_ca_path.as_path())
+                .map_err(TlsError::SslError)?;
+            builder.set_client_ca_list(
+                openssl::x509::X509Name::load_client_ca_file(client_ca_path.as_path())
+                    .map_err(TlsError::SslError)?,
+            );
+            builder.set_verify(
+                openssl::ssl::SslVerifyMode::PEER
+                    | openssl::ssl::SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+            );
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Accepts TLS connections with the certificate files of a `TlsConfig`. The
+/// files are reloaded whenever they change, so that rotated certificates are
+/// used for new connections without a restart.
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    state: std::sync::Mutex<(openssl::ssl::SslAcceptor, Vec<std::time::SystemTime>)>,
+}
+
+impl TlsAcceptor {
+    /// Creates an acceptor, failing if the certificate files cannot be loaded.
+    pub fn new(config: TlsConfig) -> Result<Self, TlsError> {
+        let modified_times = config.modified_times()?;
+        let acceptor = config.make_acceptor()?;
+        Ok(TlsAcceptor {
+            config,
+            state: std::sync::Mutex::new((acceptor, modified_times)),
+        })
+    }
+
+    /// Returns the acceptor for the current certificate files. If they changed
+    /// but cannot be loaded, as when only some of them have been replaced so
+    /// far, the previous acceptor is used until they can.
+    fn get_acceptor(&self) -> openssl::ssl::SslAcceptor {
+        let mut state = self.state.lock().unwrap();
+        match self.config.modified_times() {
+            Ok(modified_times) if modified_times != state.1 => match self.config.make_acceptor() {
+                Ok(acceptor) => {
+                    log::info!("Reloaded TLS certificate files");
+                    *state = (acceptor, modified_times);
+                }
+                Err(error) => log::warn!("Failed to reload TLS certificate files: {:?}", error),
+            },
+            Ok(_) => {}
+            Err(error) => log::warn!("Failed to check TLS certificate files: {:?}", error),
+        }
+        state.0.clone()
+    }
+
+    /// Performs the server side of a TLS handshake over the given stream.
+    pub fn accept<S>(&self, stream: S) -> AcceptFuture<S>
+    where
+        S: std::io::Read + std::io::Write,
+    {
+        AcceptFuture {
+            state: AcceptState::Start(self.get_acceptor(), stream),
+        }
+    }
+}
+
+enum AcceptState<S> {
+    Start(openssl::ssl::SslAcceptor, S),
+    Handshaking(openssl::ssl::MidHandshakeSslStream<S>),
+    Done,
+}
+
+/// A TLS handshake in progress, resolving to the established stream.
+pub struct AcceptFuture<S> {
+    state: AcceptState<S>,
+}
+
+impl<S> Future for AcceptFuture<S>
+where
+    S: std::io::Read + std::io::Write,
+{
+    type Item = TlsStream<S>;
+    type Error = std::io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match std::mem::replace(&mut self.state, AcceptState::Done) {
+            AcceptState::Start(acceptor, stream) => acceptor.accept(stream),
+            AcceptState::Handshaking(stream) => stream.handshake(),
+            AcceptState::Done => panic!("AcceptFuture polled after completion"),
+        };
+        match result {
+            Ok(stream) => Ok(Async::Ready(TlsStream(stream))),
+            Err(openssl::ssl::HandshakeError::WouldBlock(stream)) => {
+                self.state = AcceptState::Handshaking(stream);
+                Ok(Async::NotReady)
+            }
+            Err(openssl::ssl::HandshakeError::SetupFailure(error)) => {
+                Err(std::io::Error::other(error))
+            }
+            Err(openssl::ssl::HandshakeError::Failure(stream)) => {
+                Err(std::io::Error::other(stream.into_error()))
+            }
+        }
+    }
+}
+
+/// A server-side TLS stream over a non-blocking stream.
+#[derive(Debug)]
+pub struct TlsStream<S>(openssl::ssl::SslStream<S>);
+
+impl<S> TlsStream<S> {
+    /// Returns the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        self.0.get_ref()
+    }
+}
+
+impl<S: std::io::Read + std::io::Write> std::io::Read for TlsStream<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buffer)
+    }
+}
+
+impl<S: std::io::Read + std::io::Write> std::io::Write for TlsStream<S> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buffer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncRead for TlsStream<S> {}
+
+impl<S: AsyncRead + AsyncWrite> AsyncWrite for TlsStream<S> {
+    fn shutdown(&mut self) -> Poll<(), std::io::Error> {
+        match self.0.shutdown() {
+            Ok(_) => Ok(Async::Ready(())),
+            Err(ref error) if error.code() == openssl::ssl::ErrorCode::ZERO_RETURN => {
+                Ok(Async::Ready(()))
+            }
+            Err(error) => match error.into_io_error() {
+                Ok(ref error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                    Ok(Async::NotReady)
+                }
+                Ok(error) => Err(error),
+                Err(error) => Err(std::io::Error::other(error)),
+            },
+        }
+    }
+}
@@ -9,16 +9,55 @@ extern crate serde_json;
 
 extern crate ddns_common;
 
-#[derive(Debug, new)]
+#[derive(Debug)]
 struct Config {
     host: String,
     port: String,
-    socket_address: std::net::SocketAddr,
+    listen: ListenConfig,
+    tls: Option<TlsConfig>,
+    auth: AuthConfig,
+    signing_key: Option<std::path::PathBuf>,
+    proxy: ProxyConfig,
+    limits: LimitsConfig,
+    access_list: AccessList,
+    server_id: Option<String>,
+}
+
+#[derive(Debug, new)]
+struct ListenConfig {
+    socket_addresses: Vec<std::net::SocketAddr>,
+    /// Hostnames that only resolve to IPv4 and IPv6 addresses of the server,
+    /// respectively.
+    v4_hostname: Option<String>,
+    v6_hostname: Option<String>,
+}
+
+#[derive(Debug, new)]
+struct ProxyConfig {
+    trusted_proxies: Vec<Cidr>,
+    forwarded_header: ForwardedHeader,
+    proxy_protocol: bool,
+}
+
+#[derive(Debug, new)]
+#[allow(clippy::too_many_arguments)]
+struct LimitsConfig {
+    /// Requests per minute allowed from each client address, if limited.
+    rate_limit: Option<u32>,
+    rate_limit_burst: u32,
+    max_connections: Option<usize>,
+    max_connections_per_address: Option<usize>,
+    header_timeout: Option<std::time::Duration>,
+    connection_timeout: Option<std::time::Duration>,
+    max_header_size: usize,
+    max_body_size: u64,
 }
 
 enum ConfigError {
     ArgumentError(String),
     ParseError(String, String, std::net::AddrParseError),
+    ParseIntError(String, String, std::num::ParseIntError),
+    ValueError(String, String),
 }
 
 impl std::fmt::Debug for ConfigError {
@@ -34,11 +73,38 @@ impl std::fmt::Debug for ConfigError {
                 "ConfigError(ParseError(Failed to parse socket address '{}:{}': {:?}))",
                 host, port, inner_error,
             ),
+            ConfigError::ParseIntError(argument, value, inner_error) => write!(
+                f,
+                "ConfigError(ParseIntError(Failed to parse argument '{}' value '{}': {:?}))",
+                argument, value, inner_error,
+            ),
+            ConfigError::ValueError(argument, value) => write!(
+                f,
+                "ConfigError(ValueError(Invalid value for argument '{}': '{}'))",
+                argument, value,
+            ),
         }
     }
 }
 
+use ddns_common::auth::{AuthConfig, ServerAuth};
+use ddns_common::format::{
+    make_address_response, make_unsupported_format_response, ResponseFormat,
+};
+use ddns_common::limit::{
+    make_too_many_requests_response, AccessList, AddressConnectionLimit, ConnectionLimit,
+    RateLimiter,
+};
+use ddns_common::protocol::{
+    advertise_versions, make_capabilities_response, make_error_response, AddressFamily,
+    Capabilities, Endpoint, ErrorCode, ErrorResponse, PROTOCOL_VERSION,
+};
+use ddns_common::proxy::{parse_cidrs, Cidr, ForwardedHeader, TrustedProxies};
+use ddns_common::server::{ServeOptions, MIN_MAX_HEADER_SIZE};
+use ddns_common::signing::{ResponseSigner, NONCE_HEADER};
+use ddns_common::tls::{TlsAcceptor, TlsConfig};
 use ddns_common::AddressResponse;
+use hyper::rt::{Future, Stream};
 
 fn get_args() -> clap::ArgMatches<'static> {
     log::trace!("fn get_args()");
@@ -65,9 +131,257 @@ fn get_args() -> clap::ArgMatches<'static> {
                 .default_value("3000")
                 .help("Host port this server should listen on"),
         )
+        .arg(
+            clap::Arg::with_name("listen")
+                .long("listen")
+                .env("DDNS_EXTERNAL_SERVER__LISTEN")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help(
+                    "Comma-separated socket addresses to listen on, instead of the host and port",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("v4_hostname")
+                .long("v4_hostname")
+                .env("DDNS_EXTERNAL_SERVER__V4_HOSTNAME")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Hostname of this server that only resolves to IPv4 addresses"),
+        )
+        .arg(
+            clap::Arg::with_name("v6_hostname")
+                .long("v6_hostname")
+                .env("DDNS_EXTERNAL_SERVER__V6_HOSTNAME")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Hostname of this server that only resolves to IPv6 addresses"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_certificate")
+                .long("tls_certificate")
+                .env("DDNS_EXTERNAL_SERVER__TLS_CERTIFICATE")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded certificate chain to serve HTTPS with"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_key")
+                .long("tls_key")
+                .env("DDNS_EXTERNAL_SERVER__TLS_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded private key of the TLS certificate"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_client_ca")
+                .long("tls_client_ca")
+                .env("DDNS_EXTERNAL_SERVER__TLS_CLIENT_CA")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help(
+                    "Filepath of PEM-encoded CA certificates client certificates must be issued by",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("auth_tokens")
+                .long("auth_tokens")
+                .env("DDNS_EXTERNAL_SERVER__AUTH_TOKENS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Comma-separated bearer tokens clients may authenticate with"),
+        )
+        .arg(
+            clap::Arg::with_name("auth_hmac_secret")
+                .long("auth_hmac_secret")
+                .env("DDNS_EXTERNAL_SERVER__AUTH_HMAC_SECRET")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Shared secret clients may sign requests with"),
+        )
+        .arg(
+            clap::Arg::with_name("auth_api_keys")
+                .long("auth_api_keys")
+                .env("DDNS_EXTERNAL_SERVER__AUTH_API_KEYS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of JSON object mapping client names to their API keys"),
+        )
+        .arg(
+            clap::Arg::with_name("auth_max_skew")
+                .long("auth_max_skew")
+                .env("DDNS_EXTERNAL_SERVER__AUTH_MAX_SKEW")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("300")
+                .help("Seconds the timestamps of signed requests may differ from the current time"),
+        )
+        .arg(
+            clap::Arg::with_name("signing_key")
+                .long("signing_key")
+                .env("DDNS_EXTERNAL_SERVER__SIGNING_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded Ed25519 private key to sign responses with"),
+        )
+        .arg(
+            clap::Arg::with_name("trusted_proxies")
+                .long("trusted_proxies")
+                .env("DDNS_EXTERNAL_SERVER__TRUSTED_PROXIES")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help(
+                    "Comma-separated CIDR blocks of proxies whose forwarded addresses are trusted",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("forwarded_header")
+                .long("forwarded_header")
+                .env("DDNS_EXTERNAL_SERVER__FORWARDED_HEADER")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("x-forwarded-for")
+                .help("Header trusted proxies set to the client address: forwarded, x-forwarded-for or x-real-ip"),
+        )
+        .arg(
+            clap::Arg::with_name("proxy_protocol")
+                .long("proxy_protocol")
+                .env("DDNS_EXTERNAL_SERVER__PROXY_PROTOCOL")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("false")
+                .help("Whether trusted proxies begin connections with a PROXY protocol header"),
+        )
+        .arg(
+            clap::Arg::with_name("server_id")
+                .long("server_id")
+                .env("DDNS_EXTERNAL_SERVER__SERVER_ID")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Identifier of this server to include in detailed responses"),
+        )
+        .arg(
+            clap::Arg::with_name("rate_limit")
+                .long("rate_limit")
+                .env("DDNS_EXTERNAL_SERVER__RATE_LIMIT")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("0")
+                .help("Requests per minute allowed from each client address, or 0 for no limit"),
+        )
+        .arg(
+            clap::Arg::with_name("rate_limit_burst")
+                .long("rate_limit_burst")
+                .env("DDNS_EXTERNAL_SERVER__RATE_LIMIT_BURST")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("10")
+                .help("Requests a client address may make at once before being rate limited"),
+        )
+        .arg(
+            clap::Arg::with_name("max_connections")
+                .long("max_connections")
+                .env("DDNS_EXTERNAL_SERVER__MAX_CONNECTIONS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("1024")
+                .help("Connections held open at once, or 0 for no limit"),
+        )
+        .arg(
+            clap::Arg::with_name("max_connections_per_address")
+                .long("max_connections_per_address")
+                .env("DDNS_EXTERNAL_SERVER__MAX_CONNECTIONS_PER_ADDRESS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("16")
+                .help("Connections held open at once from each client address, or 0 for no limit"),
+        )
+        .arg(
+            clap::Arg::with_name("header_timeout")
+                .long("header_timeout")
+                .env("DDNS_EXTERNAL_SERVER__HEADER_TIMEOUT")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("10")
+                .help("Seconds a connection may take to send its first request's headers, or 0 for no limit"),
+        )
+        .arg(
+            clap::Arg::with_name("connection_timeout")
+                .long("connection_timeout")
+                .env("DDNS_EXTERNAL_SERVER__CONNECTION_TIMEOUT")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("0")
+                .help("Seconds a connection may stay open in all, or 0 for no limit"),
+        )
+        .arg(
+            clap::Arg::with_name("max_header_size")
+                .long("max_header_size")
+                .env("DDNS_EXTERNAL_SERVER__MAX_HEADER_SIZE")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("8192")
+                .help("Largest request line and headers accepted, in bytes"),
+        )
+        .arg(
+            clap::Arg::with_name("max_body_size")
+                .long("max_body_size")
+                .env("DDNS_EXTERNAL_SERVER__MAX_BODY_SIZE")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("1024")
+                .help("Largest request body accepted, in bytes"),
+        )
+        .arg(
+            clap::Arg::with_name("allow")
+                .long("allow")
+                .env("DDNS_EXTERNAL_SERVER__ALLOW")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Comma-separated CIDR blocks of the only client addresses to answer"),
+        )
+        .arg(
+            clap::Arg::with_name("deny")
+                .long("deny")
+                .env("DDNS_EXTERNAL_SERVER__DENY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Comma-separated CIDR blocks of client addresses to refuse"),
+        )
         .get_matches()
 }
 
+/// Parses an integer argument, recording an error and returning zero if it is
+/// invalid.
+fn parse_int_arg<T>(args: &clap::ArgMatches, name: &str, errors: &mut Vec<ConfigError>) -> T
+where
+    T: std::str::FromStr<Err = std::num::ParseIntError> + Default,
+{
+    let value = args.value_of(name).unwrap_or_default();
+    value.parse().unwrap_or_else(|error| {
+        errors.push(ConfigError::ParseIntError(
+            name.to_owned(),
+            value.to_owned(),
+            error,
+        ));
+        T::default()
+    })
+}
+
 fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
     log::trace!("fn make_config_from_args()");
 
@@ -87,35 +401,399 @@ fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
         None => errors.push(ConfigError::ArgumentError("port".to_owned())),
     }
 
+    let tls_certificate = args.value_of("tls_certificate").unwrap_or_default();
+    let tls_key = args.value_of("tls_key").unwrap_or_default();
+    let tls_client_ca = args.value_of("tls_client_ca").unwrap_or_default();
+    let tls = if tls_certificate.is_empty() && tls_key.is_empty() {
+        if !tls_client_ca.is_empty() {
+            errors.push(ConfigError::ArgumentError("tls_certificate".to_owned()));
+        }
+        None
+    } else if tls_certificate.is_empty() {
+        errors.push(ConfigError::ArgumentError("tls_certificate".to_owned()));
+        None
+    } else if tls_key.is_empty() {
+        errors.push(ConfigError::ArgumentError("tls_key".to_owned()));
+        None
+    } else {
+        Some(TlsConfig::new(
+            tls_certificate.into(),
+            tls_key.into(),
+            Some(tls_client_ca)
+                .filter(|tls_client_ca| !tls_client_ca.is_empty())
+                .map(|tls_client_ca| tls_client_ca.into()),
+        ))
+    };
+
+    let auth_max_skew = args.value_of("auth_max_skew").unwrap_or_default();
+    let auth = AuthConfig::new(
+        args.value_of("auth_tokens")
+            .unwrap_or_default()
+            .split(',')
+            .map(|token| token.trim())
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_owned())
+            .collect(),
+        args.value_of("auth_hmac_secret")
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| secret.to_owned()),
+        args.value_of("auth_api_keys")
+            .filter(|path| !path.is_empty())
+            .map(|path| path.into()),
+        match auth_max_skew.parse() {
+            Ok(seconds) => std::time::Duration::from_secs(seconds),
+            Err(error) => {
+                errors.push(ConfigError::ParseIntError(
+                    "auth_max_skew".to_owned(),
+                    auth_max_skew.to_owned(),
+                    error,
+                ));
+                std::time::Duration::default()
+            }
+        },
+    );
+
+    let signing_key = args
+        .value_of("signing_key")
+        .filter(|path| !path.is_empty())
+        .map(|path| path.into());
+
+    let trusted_proxies = args.value_of("trusted_proxies").unwrap_or_default();
+    let trusted_proxies = parse_cidrs(trusted_proxies).unwrap_or_else(|error| {
+        errors.push(ConfigError::ValueError("trusted_proxies".to_owned(), error));
+        Vec::new()
+    });
+
+    let forwarded_header = args.value_of("forwarded_header").unwrap_or_default();
+    let forwarded_header = forwarded_header.parse().unwrap_or_else(|_| {
+        errors.push(ConfigError::ValueError(
+            "forwarded_header".to_owned(),
+            forwarded_header.to_owned(),
+        ));
+        ForwardedHeader::default()
+    });
+
+    let proxy_protocol = args.value_of("proxy_protocol").unwrap_or_default();
+    let proxy_protocol = proxy_protocol.parse::<bool>().unwrap_or_else(|_| {
+        errors.push(ConfigError::ValueError(
+            "proxy_protocol".to_owned(),
+            proxy_protocol.to_owned(),
+        ));
+        false
+    });
+    if proxy_protocol && trusted_proxies.is_empty() {
+        errors.push(ConfigError::ArgumentError("trusted_proxies".to_owned()));
+    }
+
+    let rate_limit = parse_int_arg::<u32>(&args, "rate_limit", &mut errors);
+    let rate_limit_burst = parse_int_arg::<u32>(&args, "rate_limit_burst", &mut errors);
+    if rate_limit != 0 && rate_limit_burst == 0 {
+        errors.push(ConfigError::ValueError(
+            "rate_limit_burst".to_owned(),
+            rate_limit_burst.to_string(),
+        ));
+    }
+    let max_connections = parse_int_arg::<usize>(&args, "max_connections", &mut errors);
+    let max_connections_per_address =
+        parse_int_arg::<usize>(&args, "max_connections_per_address", &mut errors);
+    let header_timeout = parse_int_arg::<u64>(&args, "header_timeout", &mut errors);
+    let connection_timeout = parse_int_arg::<u64>(&args, "connection_timeout", &mut errors);
+    let max_header_size = parse_int_arg::<usize>(&args, "max_header_size", &mut errors);
+    if max_header_size < MIN_MAX_HEADER_SIZE {
+        errors.push(ConfigError::ValueError(
+            "max_header_size".to_owned(),
+            max_header_size.to_string(),
+        ));
+    }
+    let limits = LimitsConfig::new(
+        Some(rate_limit).filter(|rate_limit| *rate_limit != 0),
+        rate_limit_burst,
+        Some(max_connections).filter(|max_connections| *max_connections != 0),
+        Some(max_connections_per_address)
+            .filter(|max_connections_per_address| *max_connections_per_address != 0),
+        Some(header_timeout)
+            .filter(|header_timeout| *header_timeout != 0)
+            .map(std::time::Duration::from_secs),
+        Some(connection_timeout)
+            .filter(|connection_timeout| *connection_timeout != 0)
+            .map(std::time::Duration::from_secs),
+        max_header_size,
+        parse_int_arg::<u64>(&args, "max_body_size", &mut errors),
+    );
+
+    let mut parse_cidrs_arg = |name: &str| {
+        parse_cidrs(args.value_of(name).unwrap_or_default()).unwrap_or_else(|error| {
+            errors.push(ConfigError::ValueError(name.to_owned(), error));
+            Vec::new()
+        })
+    };
+    let access_list = AccessList::new(parse_cidrs_arg("allow"), parse_cidrs_arg("deny"));
+
     if !errors.is_empty() {
         return Err(errors);
     }
 
-    match format!("{}:{}", host.as_str(), port.as_str()).parse() {
-        Ok(socket_address) => Ok(Config::new(host, port, socket_address)),
-        Err(error) => Err(vec![ConfigError::ParseError(host, port, error)]),
+    let listen = args.value_of("listen").unwrap_or_default();
+    let socket_addresses = if listen.is_empty() {
+        match format!("{}:{}", host.as_str(), port.as_str()).parse() {
+            Ok(socket_address) => vec![socket_address],
+            Err(error) => return Err(vec![ConfigError::ParseError(host, port, error)]),
+        }
+    } else {
+        match listen
+            .split(',')
+            .map(|socket_address| socket_address.trim().parse())
+            .collect()
+        {
+            Ok(socket_addresses) => socket_addresses,
+            Err(_) => {
+                return Err(vec![ConfigError::ValueError(
+                    "listen".to_owned(),
+                    listen.to_owned(),
+                )])
+            }
+        }
+    };
+    let hostname = |name| {
+        args.value_of(name)
+            .filter(|hostname| !hostname.is_empty())
+            .map(|hostname| hostname.to_ascii_lowercase())
+    };
+    let listen = ListenConfig::new(
+        socket_addresses,
+        hostname("v4_hostname"),
+        hostname("v6_hostname"),
+    );
+
+    Ok(Config {
+        host,
+        port,
+        listen,
+        tls,
+        auth,
+        signing_key,
+        proxy: ProxyConfig::new(trusted_proxies, forwarded_header, proxy_protocol),
+        limits,
+        access_list,
+        server_id: args
+            .value_of("server_id")
+            .filter(|server_id| !server_id.is_empty())
+            .map(|server_id| server_id.to_owned()),
+    })
+}
+
+/// Signs a response for the nonce of the request it answers.
+fn sign_response(
+    signer: &ResponseSigner,
+    request: &hyper::Request<hyper::Body>,
+    address_response: &mut AddressResponse,
+) {
+    let nonce = request
+        .headers()
+        .get(NONCE_HEADER)
+        .and_then(|nonce| nonce.to_str().ok());
+    if let Err(error) = signer.sign(address_response, nonce) {
+        log::error!("Failed to sign response: {:?}", error);
+    }
+}
+
+/// State shared by the connections of the server.
+struct Server {
+    auth: Option<ServerAuth>,
+    signer: Option<ResponseSigner>,
+    trusted_proxies: TrustedProxies,
+    access_list: AccessList,
+    rate_limiter: Option<RateLimiter>,
+    max_body_size: u64,
+    server_id: Option<String>,
+    capabilities: Capabilities,
+    v4_hostname: Option<String>,
+    v6_hostname: Option<String>,
+}
+
+/// Returns the address family a request asks for by the family-specific
+/// hostname it was made to, if any.
+fn requested_family(
+    request: &hyper::Request<hyper::Body>,
+    server: &Server,
+) -> Option<AddressFamily> {
+    let host = request
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<hyper::http::uri::Authority>().ok())
+        .map(|host| host.host().to_ascii_lowercase())?;
+    if server.v4_hostname.as_ref() == Some(&host) {
+        Some(AddressFamily::V4)
+    } else if server.v6_hostname.as_ref() == Some(&host) {
+        Some(AddressFamily::V6)
+    } else {
+        None
+    }
+}
+
+/// Reads a request's body, resolving to the request without it and whether the
+/// body is larger than the server accepts. Reading stops as soon as it is, and
+/// bodies declaring a larger `Content-Length` are not read at all.
+fn read_body(
+    request: hyper::Request<hyper::Body>,
+    max_body_size: u64,
+) -> impl Future<Item = (hyper::Request<hyper::Body>, bool), Error = hyper::Error> {
+    let (parts, body) = request.into_parts();
+    let declared_too_large = parts
+        .headers
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok())
+        .is_some_and(|length| length > max_body_size);
+    let body = if declared_too_large {
+        hyper::Body::empty()
+    } else {
+        body
+    };
+    body.map_err(Some)
+        .fold(0, move |size, chunk| {
+            let size = size + chunk.len() as u64;
+            if size > max_body_size {
+                Err(None)
+            } else {
+                Ok(size)
+            }
+        })
+        .then(move |result| match result {
+            Ok(_) => Ok(declared_too_large),
+            Err(None) => Ok(true),
+            Err(Some(error)) => Err(error),
+        })
+        .map(move |too_large| {
+            (
+                hyper::Request::from_parts(parts, hyper::Body::empty()),
+                too_large,
+            )
+        })
+}
+
+/// Checks a request against the server's abuse protections, returning the
+/// response to send instead of handling it when it is refused.
+fn check_limits(
+    client_address: &std::net::IpAddr,
+    body_too_large: bool,
+    server: &Server,
+) -> Option<hyper::Response<hyper::Body>> {
+    if !server.access_list.is_allowed(client_address) {
+        log::info!("Refused request from {}: not allowed", client_address);
+        return Some(make_error_response(
+            hyper::StatusCode::FORBIDDEN,
+            &ErrorResponse::new(ErrorCode::Forbidden, "Forbidden"),
+        ));
+    }
+
+    if let Some(rate_limiter) = server.rate_limiter.as_ref() {
+        if let Err(retry_after) = rate_limiter.check(client_address) {
+            log::info!("Refused request from {}: rate limited", client_address);
+            return Some(make_too_many_requests_response(retry_after));
+        }
+    }
+
+    if body_too_large {
+        log::info!("Refused request from {}: body too large", client_address);
+        return Some(make_error_response(
+            hyper::StatusCode::PAYLOAD_TOO_LARGE,
+            &ErrorResponse::new(ErrorCode::PayloadTooLarge, "Request body too large")
+                .with_details(serde_json::json!({ "max_body_size": server.max_body_size })),
+        ));
     }
+    None
 }
 
 fn respond(
     request: hyper::Request<hyper::Body>,
     remote_addr: std::net::SocketAddr,
+    body_too_large: bool,
+    server: &Server,
 ) -> hyper::Response<hyper::Body> {
-    println!("{:?}", request);
     log::trace!(
-        "fn respond(request={:?}, remote_addr={:?})",
+        "fn respond(request={:?}, remote_addr={:?}, body_too_large={:?})",
         &request,
         &remote_addr,
+        body_too_large,
     );
 
-    let address_response = AddressResponse {
-        ip: remote_addr.ip().to_string(),
+    let client_address = server
+        .trusted_proxies
+        .client_address(&request, remote_addr.ip());
+    if let Some(response) = check_limits(&client_address, body_too_large, server) {
+        log::debug!("{:?}", response);
+        return response;
+    }
+
+    if let Some(response) = server.auth.as_ref().and_then(|auth| auth.check(&request)) {
+        log::debug!("{:?}", response);
+        return response;
+    }
+
+    let family = match Endpoint::from_path(request.uri().path()) {
+        Some(Endpoint::Address(family)) => family.or_else(|| requested_family(&request, server)),
+        Some(Endpoint::Capabilities) => {
+            let response = make_capabilities_response(&server.capabilities);
+            log::debug!("{:?}", response);
+            return response;
+        }
+        None => {
+            let response = make_error_response(
+                hyper::StatusCode::NOT_FOUND,
+                &ErrorResponse::new(
+                    ErrorCode::NotFound,
+                    format!("Unknown endpoint '{}'", request.uri().path()).as_str(),
+                )
+                .with_details(serde_json::json!({ "path": request.uri().path() })),
+            );
+            log::debug!("{:?}", response);
+            return response;
+        }
     };
-    let body_json = serde_json::to_string(&address_response).unwrap();
-    let response = hyper::Response::builder()
-        .header("Content-Type", "application/json")
-        .body(hyper::Body::from(body_json))
-        .unwrap();
+    if let Some(family) = family {
+        if family != AddressFamily::of(&client_address) {
+            let connected_family = AddressFamily::of(&client_address);
+            let response = make_error_response(
+                hyper::StatusCode::NOT_FOUND,
+                &ErrorResponse::new(
+                    ErrorCode::AddressUnavailable,
+                    format!(
+                        "No {} address is known for this client, which connected over {}",
+                        family, connected_family,
+                    )
+                    .as_str(),
+                )
+                .with_details(serde_json::json!({
+                    "requested_family": family.name(),
+                    "family": connected_family.name(),
+                })),
+            );
+            log::debug!("{:?}", response);
+            return response;
+        }
+    }
+
+    let format = match ResponseFormat::negotiate(&request) {
+        Ok(format) => format,
+        Err(message) => {
+            let response = make_unsupported_format_response(message.as_str());
+            log::debug!("{:?}", response);
+            return response;
+        }
+    };
+
+    let mut address_response = AddressResponse::for_address(&client_address);
+    // The port of a forwarded client is not known, only that of its proxy.
+    if client_address == remote_addr.ip().to_canonical() {
+        address_response.port = Some(remote_addr.port());
+    }
+    address_response.server_id = server.server_id.clone();
+    if let Some(signer) = server.signer.as_ref() {
+        sign_response(signer, &request, &mut address_response);
+    }
+    let response = make_address_response(format, address_response);
 
     log::debug!("{:?}", response);
     response
@@ -129,19 +807,111 @@ fn main() {
         .unwrap();
     log::info!("Initialized with {:?}", config);
 
-    let socket_address = config.socket_address;
-
-    let new_service =
-        hyper::service::make_service_fn(|socket: &hyper::server::conn::AddrStream| {
-            let remote_addr = socket.remote_addr();
-            hyper::service::service_fn_ok(move |request| respond(request, remote_addr))
-        });
-
-    let server = hyper::Server::bind(&socket_address).serve(new_service);
-    log::info!("Listening on http://{}", socket_address);
+    let tls_acceptor = config.tls.map(|tls| {
+        std::sync::Arc::new(
+            TlsAcceptor::new(tls)
+                .map_err(|error| log::error!("{:?}", error))
+                .unwrap(),
+        )
+    });
+    let trusted_proxies =
+        TrustedProxies::new(config.proxy.trusted_proxies, config.proxy.forwarded_header);
+    let options = ServeOptions {
+        tls_acceptor,
+        proxy_protocol: if config.proxy.proxy_protocol {
+            Some(std::sync::Arc::new(trusted_proxies.clone()))
+        } else {
+            None
+        },
+        connection_limit: config
+            .limits
+            .max_connections
+            .map(|max_connections| std::sync::Arc::new(ConnectionLimit::new(max_connections))),
+        address_connection_limit: config.limits.max_connections_per_address.map(
+            |max_connections_per_address| {
+                std::sync::Arc::new(AddressConnectionLimit::new(max_connections_per_address))
+            },
+        ),
+        header_timeout: config.limits.header_timeout,
+        connection_timeout: config.limits.connection_timeout,
+        max_header_size: Some(config.limits.max_header_size),
+    };
+    let rate_limit_burst = config.limits.rate_limit_burst;
+    let listen_addresses = &config.listen.socket_addresses;
+    let capabilities = Capabilities {
+        versions: vec![PROTOCOL_VERSION],
+        families: [AddressFamily::V4, AddressFamily::V6]
+            .iter()
+            .filter(|family| {
+                listen_addresses
+                    .iter()
+                    .any(|socket_address| AddressFamily::of(&socket_address.ip()) == **family)
+            })
+            .map(|family| family.name().to_owned())
+            .collect(),
+        formats: ResponseFormat::ALL
+            .iter()
+            .map(|format| format.name().to_owned())
+            .collect(),
+        push: false,
+        signed: config.signing_key.is_some(),
+        server_id: config.server_id.clone(),
+    };
+    let server = std::sync::Arc::new(Server {
+        auth: Some(config.auth)
+            .filter(|auth| auth.is_enabled())
+            .map(|auth| {
+                ServerAuth::new(auth)
+                    .map_err(|error| log::error!("{:?}", error))
+                    .unwrap()
+            }),
+        signer: config.signing_key.map(|signing_key| {
+            ResponseSigner::from_file(signing_key.as_path())
+                .map_err(|error| log::error!("{:?}", error))
+                .unwrap()
+        }),
+        trusted_proxies,
+        access_list: config.access_list,
+        rate_limiter: config
+            .limits
+            .rate_limit
+            .map(|rate_limit| RateLimiter::new(rate_limit, rate_limit_burst)),
+        max_body_size: config.limits.max_body_size,
+        server_id: config.server_id,
+        capabilities,
+        v4_hostname: config.listen.v4_hostname,
+        v6_hostname: config.listen.v6_hostname,
+    });
+    let scheme = if options.tls_acceptor.is_some() {
+        "https"
+    } else {
+        "http"
+    };
 
-    use hyper::rt::Future;
-    hyper::rt::run(server.map_err(|error| {
-        log::error!("{:?}", error);
+    let socket_addresses = config.listen.socket_addresses;
+    hyper::rt::run(hyper::rt::lazy(move || {
+        for socket_address in socket_addresses {
+            let server = server.clone();
+            let listener =
+                ddns_common::server::serve(&socket_address, options.clone(), move |remote_addr| {
+                    let server = server.clone();
+                    hyper::service::service_fn(move |request| {
+                        let server = server.clone();
+                        read_body(request, server.max_body_size).map(
+                            move |(request, body_too_large)| {
+                                let mut response =
+                                    respond(request, remote_addr, body_too_large, &server);
+                                advertise_versions(&mut response);
+                                response
+                            },
+                        )
+                    })
+                })
+                .map_err(|error| log::error!("{:?}", error))
+                .unwrap();
+            log::info!("Listening on {}://{}", scheme, socket_address);
+            hyper::rt::spawn(listener);
+        }
+        Ok(())
     }));
 }
@@ -1,6 +1,3 @@
-#[macro_use]
-extern crate derive_new;
-
 extern crate clap;
 extern crate hyper;
 extern crate log;
@@ -10,17 +7,22 @@ extern crate serde_json;
 
 extern crate ddns_common;
 
-#[derive(Debug, new)]
+#[derive(Debug)]
 struct Config {
     interface: String,
     host: String,
     port: String,
     socket_address: std::net::SocketAddr,
+    tls: Option<TlsConfig>,
+    auth: AuthConfig,
+    signing_key: Option<std::path::PathBuf>,
+    server_id: Option<String>,
 }
 
 enum ConfigError {
     ArgumentError(String),
     ParseError(String, String, std::net::AddrParseError),
+    ParseIntError(String, String, std::num::ParseIntError),
 }
 
 impl std::fmt::Debug for ConfigError {
@@ -36,41 +38,28 @@ impl std::fmt::Debug for ConfigError {
                 "ConfigError(ParseError(Failed to parse socket address '{}:{}': {:?}))",
                 host, port, inner_error,
             ),
+            ConfigError::ParseIntError(argument, value, inner_error) => write!(
+                f,
+                "ConfigError(ParseIntError(Failed to parse argument '{}' value '{}': {:?}))",
+                argument, value, inner_error,
+            ),
         }
     }
 }
 
+use ddns_common::auth::{AuthConfig, ServerAuth};
+use ddns_common::format::{
+    make_address_response, make_unsupported_format_response, ResponseFormat,
+};
+use ddns_common::protocol::{
+    advertise_versions, make_capabilities_response, make_error_response, AddressFamily,
+    Capabilities, Endpoint, ErrorCode, ErrorResponse, PROTOCOL_VERSION,
+};
+use ddns_common::server::ServeOptions;
+use ddns_common::signing::{ResponseSigner, NONCE_HEADER};
+use ddns_common::tls::{TlsAcceptor, TlsConfig};
 use ddns_common::AddressResponse;
 
-#[derive(serde::Serialize)]
-struct NoAddressesResponse {
-    interface: String,
-    message: String,
-}
-
-#[derive(serde::Serialize)]
-struct MultipleAddressesResponse {
-    interface: String,
-    addresses: Vec<String>,
-    message: String,
-}
-
-enum Response {
-    Address(AddressResponse),
-    NoAddresses(NoAddressesResponse),
-    MultipleAddresses(MultipleAddressesResponse),
-}
-
-impl Response {
-    fn to_json(&self) -> serde_json::Result<String> {
-        match self {
-            Response::Address(response) => serde_json::to_string(response),
-            Response::NoAddresses(response) => serde_json::to_string(response),
-            Response::MultipleAddresses(response) => serde_json::to_string(response),
-        }
-    }
-}
-
 fn get_args() -> clap::ArgMatches<'static> {
     log::trace!("fn get_args()");
 
@@ -104,6 +93,89 @@ fn get_args() -> clap::ArgMatches<'static> {
                 .default_value("3000")
                 .help("Host port this server should listen on"),
         )
+        .arg(
+            clap::Arg::with_name("tls_certificate")
+                .long("tls_certificate")
+                .env("DDNS_GATEWAY_SERVER__TLS_CERTIFICATE")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded certificate chain to serve HTTPS with"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_key")
+                .long("tls_key")
+                .env("DDNS_GATEWAY_SERVER__TLS_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded private key of the TLS certificate"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_client_ca")
+                .long("tls_client_ca")
+                .env("DDNS_GATEWAY_SERVER__TLS_CLIENT_CA")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help(
+                    "Filepath of PEM-encoded CA certificates client certificates must be issued by",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("auth_tokens")
+                .long("auth_tokens")
+                .env("DDNS_GATEWAY_SERVER__AUTH_TOKENS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Comma-separated bearer tokens clients may authenticate with"),
+        )
+        .arg(
+            clap::Arg::with_name("auth_hmac_secret")
+                .long("auth_hmac_secret")
+                .env("DDNS_GATEWAY_SERVER__AUTH_HMAC_SECRET")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Shared secret clients may sign requests with"),
+        )
+        .arg(
+            clap::Arg::with_name("auth_api_keys")
+                .long("auth_api_keys")
+                .env("DDNS_GATEWAY_SERVER__AUTH_API_KEYS")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of JSON object mapping client names to their API keys"),
+        )
+        .arg(
+            clap::Arg::with_name("auth_max_skew")
+                .long("auth_max_skew")
+                .env("DDNS_GATEWAY_SERVER__AUTH_MAX_SKEW")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("300")
+                .help("Seconds the timestamps of signed requests may differ from the current time"),
+        )
+        .arg(
+            clap::Arg::with_name("signing_key")
+                .long("signing_key")
+                .env("DDNS_GATEWAY_SERVER__SIGNING_KEY")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of PEM-encoded Ed25519 private key to sign responses with"),
+        )
+        .arg(
+            clap::Arg::with_name("server_id")
+                .long("server_id")
+                .env("DDNS_GATEWAY_SERVER__SERVER_ID")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Identifier of this server to include in detailed responses"),
+        )
         .get_matches()
 }
 
@@ -132,12 +204,83 @@ fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
         None => errors.push(ConfigError::ArgumentError("port".to_owned())),
     }
 
+    let tls_certificate = args.value_of("tls_certificate").unwrap_or_default();
+    let tls_key = args.value_of("tls_key").unwrap_or_default();
+    let tls_client_ca = args.value_of("tls_client_ca").unwrap_or_default();
+    let tls = if tls_certificate.is_empty() && tls_key.is_empty() {
+        if !tls_client_ca.is_empty() {
+            errors.push(ConfigError::ArgumentError("tls_certificate".to_owned()));
+        }
+        None
+    } else if tls_certificate.is_empty() {
+        errors.push(ConfigError::ArgumentError("tls_certificate".to_owned()));
+        None
+    } else if tls_key.is_empty() {
+        errors.push(ConfigError::ArgumentError("tls_key".to_owned()));
+        None
+    } else {
+        Some(TlsConfig::new(
+            tls_certificate.into(),
+            tls_key.into(),
+            Some(tls_client_ca)
+                .filter(|tls_client_ca| !tls_client_ca.is_empty())
+                .map(|tls_client_ca| tls_client_ca.into()),
+        ))
+    };
+
+    let auth_max_skew = args.value_of("auth_max_skew").unwrap_or_default();
+    let auth = AuthConfig::new(
+        args.value_of("auth_tokens")
+            .unwrap_or_default()
+            .split(',')
+            .map(|token| token.trim())
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_owned())
+            .collect(),
+        args.value_of("auth_hmac_secret")
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| secret.to_owned()),
+        args.value_of("auth_api_keys")
+            .filter(|path| !path.is_empty())
+            .map(|path| path.into()),
+        match auth_max_skew.parse() {
+            Ok(seconds) => std::time::Duration::from_secs(seconds),
+            Err(error) => {
+                errors.push(ConfigError::ParseIntError(
+                    "auth_max_skew".to_owned(),
+                    auth_max_skew.to_owned(),
+                    error,
+                ));
+                std::time::Duration::default()
+            }
+        },
+    );
+
+    let signing_key = args
+        .value_of("signing_key")
+        .filter(|path| !path.is_empty())
+        .map(|path| path.into());
+
+    let server_id = args
+        .value_of("server_id")
+        .filter(|server_id| !server_id.is_empty())
+        .map(|server_id| server_id.to_owned());
+
     if !errors.is_empty() {
         return Err(errors);
     }
 
     match format!("{}:{}", host.as_str(), port.as_str()).parse() {
-        Ok(socket_address) => Ok(Config::new(interface, host, port, socket_address)),
+        Ok(socket_address) => Ok(Config {
+            interface,
+            host,
+            port,
+            socket_address,
+            tls,
+            auth,
+            signing_key,
+            server_id,
+        }),
         Err(error) => Err(vec![ConfigError::ParseError(host, port, error)]),
     }
 }
@@ -171,7 +314,10 @@ fn matching_inet_ip_addrs(interface: &str) -> Vec<nix::sys::socket::IpAddr> {
         .collect()
 }
 
-fn make_response(interface: &str, ip_addresses: &[nix::sys::socket::IpAddr]) -> (u16, Response) {
+fn make_response(
+    interface: &str,
+    ip_addresses: &[nix::sys::socket::IpAddr],
+) -> Result<AddressResponse, ErrorResponse> {
     log::trace!(
         "fn make_response(interface={:?}, ip_addresses={:?})",
         interface,
@@ -179,55 +325,132 @@ fn make_response(interface: &str, ip_addresses: &[nix::sys::socket::IpAddr]) ->
     );
 
     if ip_addresses.len() == 0 {
-        (
-            500,
-            Response::NoAddresses(NoAddressesResponse {
-                interface: interface.to_string(),
-                message: format!("No inet addresses found for interface {}", interface),
-            }),
+        Err(ErrorResponse::new(
+            ErrorCode::NoAddresses,
+            format!("No inet addresses found for interface {}", interface).as_str(),
         )
+        .with_details(serde_json::json!({ "interface": interface })))
     } else if ip_addresses.len() > 1 {
-        let ip_address_strings = ip_addresses.iter().map(|ip_addr| format!("{}", ip_addr));
-        (
-            500,
-            Response::MultipleAddresses(MultipleAddressesResponse {
-                interface: interface.to_string(),
-                message: format!(
-                    "Multiple inet addresses found for interface {}: {}",
-                    interface,
-                    ip_address_strings
-                        .to_owned()
-                        .fold(String::new(), |accumulator, ip_addr| {
-                            accumulator + ip_addr.as_str()
-                        })
-                ),
-                addresses: ip_address_strings.collect(),
-            }),
+        let addresses = ip_addresses
+            .iter()
+            .map(|ip_addr| format!("{}", ip_addr))
+            .collect::<Vec<_>>();
+        Err(ErrorResponse::new(
+            ErrorCode::MultipleAddresses,
+            format!(
+                "Multiple inet addresses found for interface {}: {}",
+                interface,
+                addresses.join(", "),
+            )
+            .as_str(),
         )
+        .with_details(serde_json::json!({ "interface": interface, "addresses": addresses })))
     } else {
-        (
-            200,
-            Response::Address(AddressResponse {
-                ip: format!("{}", ip_addresses.iter().next().unwrap()),
-            }),
-        )
+        Ok(AddressResponse::for_address(
+            &ip_addresses.iter().next().unwrap().to_std(),
+        ))
     }
 }
 
-fn respond(request: hyper::Request<hyper::Body>, interface: &str) -> hyper::Response<hyper::Body> {
+/// Signs a response for the nonce of the request it answers.
+fn sign_response(
+    signer: &ResponseSigner,
+    request: &hyper::Request<hyper::Body>,
+    address_response: &mut AddressResponse,
+) {
+    let nonce = request
+        .headers()
+        .get(NONCE_HEADER)
+        .and_then(|nonce| nonce.to_str().ok());
+    if let Err(error) = signer.sign(address_response, nonce) {
+        log::error!("Failed to sign response: {:?}", error);
+    }
+}
+
+fn respond(
+    request: hyper::Request<hyper::Body>,
+    interface: &str,
+    server_auth: Option<&ServerAuth>,
+    signer: Option<&ResponseSigner>,
+    server_id: Option<&str>,
+) -> hyper::Response<hyper::Body> {
     log::trace!(
         "fn respond(request={:?}, interface={:?})",
         &request,
         interface,
     );
 
-    let ip_addresses = matching_inet_ip_addrs(interface);
-    let (status, response) = make_response(interface, ip_addresses.as_slice());
-    let response = hyper::Response::builder()
-        .header("Content-Type", "application/json")
-        .status(status)
-        .body(hyper::Body::from(response.to_json().unwrap()))
-        .unwrap();
+    if let Some(response) = server_auth.and_then(|server_auth| server_auth.check(&request)) {
+        log::debug!("{:?}", response);
+        return response;
+    }
+
+    let family = match Endpoint::from_path(request.uri().path()) {
+        Some(Endpoint::Address(family)) => family,
+        Some(Endpoint::Capabilities) => {
+            let mut families = matching_inet_ip_addrs(interface)
+                .iter()
+                .map(|ip_address| AddressFamily::of(&ip_address.to_std()).name().to_owned())
+                .collect::<Vec<_>>();
+            families.sort();
+            families.dedup();
+            let capabilities = Capabilities {
+                versions: vec![PROTOCOL_VERSION],
+                families,
+                formats: ResponseFormat::ALL
+                    .iter()
+                    .map(|format| format.name().to_owned())
+                    .collect(),
+                push: false,
+                signed: signer.is_some(),
+                server_id: server_id.map(|server_id| server_id.to_owned()),
+            };
+            let response = make_capabilities_response(&capabilities);
+            log::debug!("{:?}", response);
+            return response;
+        }
+        None => {
+            let response = make_error_response(
+                hyper::StatusCode::NOT_FOUND,
+                &ErrorResponse::new(
+                    ErrorCode::NotFound,
+                    format!("Unknown endpoint '{}'", request.uri().path()).as_str(),
+                )
+                .with_details(serde_json::json!({ "path": request.uri().path() })),
+            );
+            log::debug!("{:?}", response);
+            return response;
+        }
+    };
+
+    let format = match ResponseFormat::negotiate(&request) {
+        Ok(format) => format,
+        Err(message) => {
+            let response = make_unsupported_format_response(message.as_str());
+            log::debug!("{:?}", response);
+            return response;
+        }
+    };
+
+    // Only the addresses of the requested family are candidates, so that an
+    // interface with one address of each family can answer for either.
+    let ip_addresses = matching_inet_ip_addrs(interface)
+        .into_iter()
+        .filter(|ip_address| {
+            family.is_none_or(|family| AddressFamily::of(&ip_address.to_std()) == family)
+        })
+        .collect::<Vec<_>>();
+    let response = match make_response(interface, ip_addresses.as_slice()) {
+        Ok(mut address_response) => {
+            address_response.server_id = server_id.map(|server_id| server_id.to_owned());
+            address_response.interface = Some(interface.to_owned());
+            if let Some(signer) = signer {
+                sign_response(signer, &request, &mut address_response);
+            }
+            make_address_response(format, address_response)
+        }
+        Err(error) => make_error_response(hyper::StatusCode::INTERNAL_SERVER_ERROR, &error),
+    };
 
     log::debug!("{:?}", response);
     response
@@ -242,18 +465,61 @@ fn main() {
     log::info!("Initialized with {:?}", config);
 
     let interface = config.interface;
+    let server_id = config.server_id;
     let socket_address = config.socket_address;
-
-    let new_service = move || {
-        let interface_clone = interface.to_owned();
-        hyper::service::service_fn_ok(move |request| respond(request, interface_clone.as_str()))
+    let tls_acceptor = config.tls.map(|tls| {
+        std::sync::Arc::new(
+            TlsAcceptor::new(tls)
+                .map_err(|error| log::error!("{:?}", error))
+                .unwrap(),
+        )
+    });
+    let server_auth = Some(config.auth)
+        .filter(|auth| auth.is_enabled())
+        .map(|auth| {
+            std::sync::Arc::new(
+                ServerAuth::new(auth)
+                    .map_err(|error| log::error!("{:?}", error))
+                    .unwrap(),
+            )
+        });
+    let signer = config.signing_key.map(|signing_key| {
+        std::sync::Arc::new(
+            ResponseSigner::from_file(signing_key.as_path())
+                .map_err(|error| log::error!("{:?}", error))
+                .unwrap(),
+        )
+    });
+    let scheme = if tls_acceptor.is_some() {
+        "https"
+    } else {
+        "http"
     };
 
-    let server = hyper::Server::bind(&socket_address).serve(new_service);
-    log::info!("Listening on http://{}", socket_address);
+    let options = ServeOptions {
+        tls_acceptor,
+        ..ServeOptions::default()
+    };
+    let server = ddns_common::server::serve(&socket_address, options, move |_| {
+        let interface_clone = interface.to_owned();
+        let server_auth = server_auth.clone();
+        let signer = signer.clone();
+        let server_id = server_id.clone();
+        hyper::service::service_fn_ok(move |request| {
+            let mut response = respond(
+                request,
+                interface_clone.as_str(),
+                server_auth.as_deref(),
+                signer.as_deref(),
+                server_id.as_deref(),
+            );
+            advertise_versions(&mut response);
+            response
+        })
+    })
+    .map_err(|error| log::error!("{:?}", error))
+    .unwrap();
+    log::info!("Listening on {}://{}", scheme, socket_address);
 
-    use hyper::rt::Future;
-    hyper::rt::run(server.map_err(|error| {
-        log::error!("{:?}", error);
-    }));
+    hyper::rt::run(server);
 }
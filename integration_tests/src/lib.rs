@@ -0,0 +1,849 @@
+//! Harness for end-to-end tests of the ddns binaries.
+//!
+//! Each test launches the binaries it needs as child processes listening on
+//! ephemeral ports, and inspects their behavior through the recorded requests
+//! of `ddns_mock_server` and the status API of `ddns_client`.
+
+extern crate openssl;
+extern crate serde_json;
+
+use std::io::{Read, Write};
+
+/// How long to wait for a condition before failing a test.
+pub const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+fn get_repository_path() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// Returns the path of the binary built from the package in the given
+/// directory of the repository, building it first if necessary. The path can be
+/// overridden with the `DDNS_INTEGRATION_TESTS__<PACKAGE>` environment
+/// variable, e.g. `DDNS_INTEGRATION_TESTS__MOCK_SERVER`.
+pub fn get_binary_path(package: &str) -> std::path::PathBuf {
+    static BUILD_LOCK: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    let variable = format!("DDNS_INTEGRATION_TESTS__{}", package.to_uppercase());
+    if let Ok(path) = std::env::var(variable) {
+        return std::path::PathBuf::from(path);
+    }
+
+    let package_path = get_repository_path().join(package);
+    let binary_path = package_path
+        .join("target")
+        .join("debug")
+        .join(format!("ddns_{}", package));
+
+    let mut built = BUILD_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    if !built.iter().any(|name| name == package) {
+        let output = std::process::Command::new(
+            std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()),
+        )
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(package_path.join("Cargo.toml"))
+        .env_remove("CARGO_TARGET_DIR")
+        .output()
+        .expect("Failed to run cargo");
+        assert!(
+            output.status.success(),
+            "Failed to build package '{}':\n{}",
+            package,
+            String::from_utf8_lossy(output.stderr.as_slice()),
+        );
+        built.push(package.to_owned());
+    }
+
+    binary_path
+}
+
+/// Returns a port on the loopback interface that was free at the time of the
+/// call.
+pub fn get_free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .unwrap()
+}
+
+/// Calls `condition` until it returns a value, panicking with `description` if
+/// that takes longer than `TIMEOUT`.
+pub fn wait_until<T>(description: &str, mut condition: impl FnMut() -> Option<T>) -> T {
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = condition() {
+            return value;
+        }
+        assert!(
+            std::time::Instant::now() < deadline,
+            "Timed out waiting until {}",
+            description,
+        );
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+/// Calls `condition` until it holds, panicking with `description` if that
+/// takes longer than `TIMEOUT`.
+pub fn wait_for(description: &str, mut condition: impl FnMut() -> bool) {
+    wait_until(description, || condition().then_some(()))
+}
+
+fn wait_for_port(port: u16) {
+    wait_until(&format!("port {} accepts connections", port), || {
+        std::net::TcpStream::connect(("127.0.0.1", port)).ok()
+    });
+}
+
+/// A child process that is killed when dropped.
+pub struct Process {
+    child: std::process::Child,
+}
+
+impl Process {
+    /// Launches the binary of the given package. Output is discarded unless the
+    /// `DDNS_INTEGRATION_TESTS__LOG` environment variable is set.
+    pub fn spawn(package: &str, args: &[String], envs: &[(&str, &str)]) -> Self {
+        let log = std::env::var_os("DDNS_INTEGRATION_TESTS__LOG").is_some();
+        let output = || {
+            if log {
+                std::process::Stdio::inherit()
+            } else {
+                std::process::Stdio::null()
+            }
+        };
+
+        let child = std::process::Command::new(get_binary_path(package))
+            .args(args)
+            .envs(envs.iter().cloned())
+            .stdout(output())
+            .stderr(output())
+            .spawn()
+            .unwrap_or_else(|error| panic!("Failed to launch '{}': {:?}", package, error));
+        Process { child }
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        self.child.try_wait().unwrap().is_none()
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A directory of files that is removed when dropped.
+pub struct TempDir {
+    path: std::path::PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "ddns_integration_tests-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn write(&self, name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = self.path.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Default for TempDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A response read by `http_request`.
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Returns the value of a response header, if it is present.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(self.body.as_str())
+            .unwrap_or_else(|error| panic!("Invalid JSON response {:?}: {:?}", self.body, error))
+    }
+}
+
+/// Makes a plain HTTP/1.1 request to a server on the loopback interface.
+pub fn http_request(port: u16, method: &str, path: &str, body: &str) -> HttpResponse {
+    http_request_with_headers(port, method, path, &[], body)
+}
+
+/// Makes a plain HTTP/1.1 request with additional headers to a server on the
+/// loopback interface.
+pub fn http_request_with_headers(
+    port: u16,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> HttpResponse {
+    http_request_with_preamble(port, &[], method, path, headers, body)
+}
+
+/// Makes a plain HTTP/1.1 request to a server on the loopback interface, after
+/// sending the given bytes on the connection, e.g. a PROXY protocol header.
+pub fn http_request_with_preamble(
+    port: u16,
+    preamble: &[u8],
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> HttpResponse {
+    http_request_to(
+        ([127, 0, 0, 1], port).into(),
+        preamble,
+        method,
+        path,
+        headers,
+        body,
+    )
+}
+
+/// Makes a plain HTTP/1.1 request to a server at the given socket address,
+/// after sending the given bytes on the connection. The `Host` header names
+/// the socket address unless the given headers include one.
+pub fn http_request_to(
+    socket_address: std::net::SocketAddr,
+    preamble: &[u8],
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> HttpResponse {
+    let mut stream = std::net::TcpStream::connect(socket_address).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream.write_all(preamble).unwrap();
+    let host = if headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("host"))
+    {
+        String::new()
+    } else {
+        format!("Host: {}\r\n", socket_address)
+    };
+    let headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\n{}Connection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        host,
+        headers,
+        body.len(),
+        body,
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or_else(|| panic!("Invalid HTTP response {:?}", response));
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .unwrap_or((response.as_str(), ""));
+    let headers = head
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|header| header.split_once(':'))
+        .map(|(name, value)| (name.to_owned(), value.trim().to_owned()))
+        .collect();
+    HttpResponse {
+        status,
+        headers,
+        body: body.to_owned(),
+    }
+}
+
+/// A running `ddns_mock_server`.
+pub struct MockServer {
+    pub port: u16,
+    _process: Process,
+    _dir: TempDir,
+}
+
+impl MockServer {
+    /// Launches a mock server answering requests according to the given
+    /// scenario, or with its default response when the scenario is `null`.
+    pub fn start(scenario: serde_json::Value, args: &[&str]) -> Self {
+        let dir = TempDir::new();
+        let port = get_free_port();
+
+        let mut all_args = vec!["--host=127.0.0.1".to_owned(), format!("--port={}", port)];
+        if !scenario.is_null() {
+            let path = dir.write("scenario.json", scenario.to_string().as_bytes());
+            all_args.push(format!("--scenario={}", path.display()));
+        }
+        all_args.extend(args.iter().map(|arg| (*arg).to_owned()));
+
+        let process = Process::spawn("mock_server", all_args.as_slice(), &[]);
+        wait_for_port(port);
+        MockServer {
+            port,
+            _process: process,
+            _dir: dir,
+        }
+    }
+
+    /// Launches a mock server acting as an address source. Each request is
+    /// answered with the next of the given addresses, repeating the last.
+    pub fn address_source(addresses: &[&str]) -> Self {
+        let responses = addresses
+            .iter()
+            .map(|address| serde_json::json!({ "body": serde_json::json!({ "ip": address }).to_string() }))
+            .collect::<Vec<_>>();
+        Self::start(
+            serde_json::json!({ "routes": [{ "name": "address", "responses": responses }] }),
+            &[],
+        )
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Returns all requests this server received.
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        let response = http_request(self.port, "GET", "/__requests", "");
+        match response.json() {
+            serde_json::Value::Array(requests) => requests,
+            value => panic!("Invalid recorded requests {:?}", value),
+        }
+    }
+
+    /// Returns the requests this server received at the given path.
+    pub fn requests_to(&self, path: &str) -> Vec<serde_json::Value> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request["path"] == path)
+            .collect()
+    }
+
+    /// Returns the records of the emulated registrar zone.
+    pub fn zone(&self) -> Vec<serde_json::Value> {
+        match http_request(self.port, "GET", "/__zone", "").json() {
+            serde_json::Value::Array(records) => records,
+            value => panic!("Invalid zone {:?}", value),
+        }
+    }
+
+    /// Creates or updates a record of the emulated registrar zone.
+    pub fn set_record(&self, name: &str, content: &str) {
+        let body = serde_json::json!({ "name": name, "content": content }).to_string();
+        let response = http_request(self.port, "POST", "/__zone", body.as_str());
+        assert_eq!(response.status, 200, "Failed to set record '{}'", name);
+    }
+
+    /// Returns the content of the zone record with the given name.
+    pub fn record_content(&self, name: &str) -> Option<String> {
+        self.zone()
+            .into_iter()
+            .find(|record| record["name"] == name)
+            .and_then(|record| record["content"].as_str().map(|content| content.to_owned()))
+    }
+}
+
+/// A running `ddns_external_server`.
+pub struct ExternalServer {
+    pub port: u16,
+    _process: Process,
+}
+
+impl ExternalServer {
+    pub fn start() -> Self {
+        Self::start_with_args(&[])
+    }
+
+    /// Launches a server with additional command-line arguments.
+    pub fn start_with_args(args: &[&str]) -> Self {
+        let port = get_free_port();
+        let mut all_args = vec!["--host=127.0.0.1".to_owned(), format!("--port={}", port)];
+        all_args.extend(args.iter().map(|arg| (*arg).to_owned()));
+        let process = Process::spawn("external_server", all_args.as_slice(), &[]);
+        wait_for_port(port);
+        ExternalServer {
+            port,
+            _process: process,
+        }
+    }
+
+    /// Launches a server listening on both `127.0.0.1` and `::1`, on the same
+    /// port, with additional command-line arguments.
+    pub fn start_dual_stack(args: &[&str]) -> Self {
+        let port = get_free_port();
+        let mut all_args = vec![format!("--listen=127.0.0.1:{},[::1]:{}", port, port)];
+        all_args.extend(args.iter().map(|arg| (*arg).to_owned()));
+        let process = Process::spawn("external_server", all_args.as_slice(), &[]);
+        wait_for_port(port);
+        wait_until("the IPv6 listener accepts connections", || {
+            std::net::TcpStream::connect((std::net::Ipv6Addr::LOCALHOST, port)).ok()
+        });
+        ExternalServer {
+            port,
+            _process: process,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    pub fn https_url(&self) -> String {
+        format!("https://127.0.0.1:{}", self.port)
+    }
+}
+
+/// A running `ddns_client`, polling its service every second.
+pub struct Client {
+    pub status_port: u16,
+    process: Process,
+    _dir: TempDir,
+}
+
+impl Client {
+    /// Launches a client with the given service address and registrar request
+    /// template. The template is written out verbatim, so `${ip_address}`
+    /// placeholders are rendered by the client. The address is checked every
+    /// second unless `args` sets another `--update_interval`.
+    pub fn start(
+        service_address: &str,
+        registrar_requests: &serde_json::Value,
+        args: &[&str],
+        envs: &[(&str, &str)],
+    ) -> Self {
+        let dir = TempDir::new();
+        let request_path = dir.write(
+            "registrar_requests.json",
+            serde_json::to_string_pretty(registrar_requests)
+                .unwrap()
+                .as_bytes(),
+        );
+        let status_port = get_free_port();
+
+        let mut all_args = vec![
+            format!("--service_address={}", service_address),
+            format!("--registrar_request={}", request_path.display()),
+            format!("--status_address=127.0.0.1:{}", status_port),
+        ];
+        if !args.iter().any(|arg| arg.starts_with("--update_interval")) {
+            all_args.push("--update_interval=1".to_owned());
+        }
+        all_args.extend(args.iter().map(|arg| (*arg).to_owned()));
+
+        let process = Process::spawn("client", all_args.as_slice(), envs);
+        wait_for_port(status_port);
+        Client {
+            status_port,
+            process,
+            _dir: dir,
+        }
+    }
+
+    /// Returns the client's status API document.
+    pub fn status(&self) -> serde_json::Value {
+        http_request(self.status_port, "GET", "/status", "").json()
+    }
+
+    /// Returns the status of the record with the given name.
+    pub fn record_status(&self, name: &str) -> serde_json::Value {
+        self.status()["records"]
+            .as_array()
+            .and_then(|records| {
+                records
+                    .iter()
+                    .find(|record| record["name"] == name)
+                    .cloned()
+            })
+            .unwrap_or_else(|| panic!("Missing status of record '{}'", name))
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        self.process.is_running()
+    }
+}
+
+/// Returns a registrar request template entry updating the record with the
+/// given name through the JSON emulator of `registrar`.
+pub fn json_registrar_request(registrar: &MockServer, name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "method": "PUT",
+        "address": registrar.url(&format!("/records/{}", name)),
+        "headers": { "Content-Type": "application/json" },
+        "body": { "answer": "${ip_address}" },
+    })
+}
+
+/// An HTTPS address source with a freshly generated self-signed certificate
+/// for `127.0.0.1`. Every request is answered with `{"ip": <address>}`.
+pub struct TlsAddressServer {
+    pub port: u16,
+    pub certificate_path: std::path::PathBuf,
+    connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    _dir: TempDir,
+}
+
+fn make_certificate() -> (
+    openssl::pkey::PKey<openssl::pkey::Private>,
+    openssl::x509::X509,
+) {
+    let key = openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "127.0.0.1").unwrap();
+    let name = name.build();
+
+    let mut builder = openssl::x509::X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(
+            &openssl::bn::BigNum::from_u32(1)
+                .unwrap()
+                .to_asn1_integer()
+                .unwrap(),
+        )
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    let basic_constraints = openssl::x509::extension::BasicConstraints::new()
+        .critical()
+        .ca()
+        .build()
+        .unwrap();
+    builder.append_extension(basic_constraints).unwrap();
+    let subject_alternative_name = openssl::x509::extension::SubjectAlternativeName::new()
+        .ip("127.0.0.1")
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(subject_alternative_name).unwrap();
+    builder
+        .sign(&key, openssl::hash::MessageDigest::sha256())
+        .unwrap();
+
+    (key, builder.build())
+}
+
+/// Writes a freshly generated self-signed certificate for `127.0.0.1` to
+/// `<name>.pem`, and its PKCS #8 private key to `<name>.key`.
+pub fn write_certificate(dir: &TempDir, name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let (key, certificate) = make_certificate();
+    (
+        dir.write(
+            format!("{}.pem", name).as_str(),
+            &certificate.to_pem().unwrap(),
+        ),
+        dir.write(
+            format!("{}.key", name).as_str(),
+            &key.private_key_to_pem_pkcs8().unwrap(),
+        ),
+    )
+}
+
+/// Writes a generated Ed25519 key pair to `<name>.key` and `<name>.pub` in the
+/// given directory, returning their paths and the private key.
+pub fn write_signing_key(
+    dir: &TempDir,
+    name: &str,
+) -> (
+    std::path::PathBuf,
+    std::path::PathBuf,
+    openssl::pkey::PKey<openssl::pkey::Private>,
+) {
+    let key = openssl::pkey::PKey::generate_ed25519().unwrap();
+    (
+        dir.write(
+            format!("{}.key", name).as_str(),
+            &key.private_key_to_pem_pkcs8().unwrap(),
+        ),
+        dir.write(
+            format!("{}.pub", name).as_str(),
+            &key.public_key_to_pem().unwrap(),
+        ),
+        key,
+    )
+}
+
+/// Performs a TLS handshake with the server on the given port, trusting any
+/// certificate, and returns the certificate it presented.
+pub fn get_server_certificate(port: u16) -> openssl::x509::X509 {
+    let mut connector =
+        openssl::ssl::SslConnector::builder(openssl::ssl::SslMethod::tls()).unwrap();
+    connector.set_verify(openssl::ssl::SslVerifyMode::NONE);
+    let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let stream = connector.build().connect("127.0.0.1", stream).unwrap();
+    stream.ssl().peer_certificate().unwrap()
+}
+
+fn serve_tls_connection(
+    acceptor: &openssl::ssl::SslAcceptor,
+    stream: std::net::TcpStream,
+    body: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut stream = acceptor.accept(stream)?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let length = stream.read(&mut buffer)?;
+        if length == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..length]);
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body,
+    )?;
+    stream.shutdown()?;
+    Ok(())
+}
+
+impl TlsAddressServer {
+    pub fn start(address: &str) -> Self {
+        let dir = TempDir::new();
+        let (key, certificate) = make_certificate();
+        let certificate_path = dir.write("certificate.pem", &certificate.to_pem().unwrap());
+
+        let mut acceptor =
+            openssl::ssl::SslAcceptor::mozilla_intermediate(openssl::ssl::SslMethod::tls())
+                .unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_certificate(&certificate).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let body = serde_json::json!({ "ip": address }).to_string();
+        let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let thread_connections = connections.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                thread_connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let _ = serve_tls_connection(&acceptor, stream, body.as_str());
+            }
+        });
+
+        TlsAddressServer {
+            port,
+            certificate_path,
+            connections,
+            _dir: dir,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://127.0.0.1:{}", self.port)
+    }
+
+    /// Returns the number of connections accepted, successful or not.
+    pub fn connections(&self) -> usize {
+        self.connections.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+const STUN_MAGIC_COOKIE: u32 = 0x2112_a442;
+
+/// A STUN server answering every binding request with the given address, as
+/// public STUN servers answer clients behind NAT with their public address.
+pub struct StunServer {
+    pub address: std::net::SocketAddr,
+    requests: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+/// Returns the success response to a binding request, mapping its client to
+/// the given address and the client's own port.
+fn make_binding_response(
+    request: &[u8],
+    mapped_address: &std::net::IpAddr,
+    source_address: &std::net::SocketAddr,
+) -> Option<Vec<u8>> {
+    if request.len() < 20
+        || request[0..2] != [0x00, 0x01]
+        || request[4..8] != STUN_MAGIC_COOKIE.to_be_bytes()
+    {
+        return None;
+    }
+    let mut mask = STUN_MAGIC_COOKIE.to_be_bytes().to_vec();
+    mask.extend_from_slice(&request[8..20]);
+    let (family, octets) = match mapped_address {
+        std::net::IpAddr::V4(address) => (0x01, address.octets().to_vec()),
+        std::net::IpAddr::V6(address) => (0x02, address.octets().to_vec()),
+    };
+
+    let mut value = vec![0x00, family];
+    value.extend_from_slice(
+        &(source_address.port() ^ (STUN_MAGIC_COOKIE >> 16) as u16).to_be_bytes(),
+    );
+    value.extend(
+        octets
+            .iter()
+            .zip(mask.iter())
+            .map(|(byte, mask)| byte ^ mask),
+    );
+
+    let mut response = vec![0x01, 0x01];
+    response.extend_from_slice(&(4 + value.len() as u16).to_be_bytes());
+    response.extend_from_slice(&request[4..20]);
+    response.extend_from_slice(&[0x00, 0x20]);
+    response.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    response.extend_from_slice(value.as_slice());
+    Some(response)
+}
+
+impl StunServer {
+    pub fn start(mapped_address: &str) -> Self {
+        Self::start_on("127.0.0.1:0", mapped_address)
+    }
+
+    /// Launches a server listening on the given socket address.
+    pub fn start_on(listen_address: &str, mapped_address: &str) -> Self {
+        let socket = std::net::UdpSocket::bind(listen_address).unwrap();
+        let address = socket.local_addr().unwrap();
+        let mapped_address = mapped_address.parse::<std::net::IpAddr>().unwrap();
+        let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let thread_requests = requests.clone();
+        std::thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            while let Ok((length, source_address)) = socket.recv_from(&mut buffer) {
+                thread_requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                if let Some(response) =
+                    make_binding_response(&buffer[..length], &mapped_address, &source_address)
+                {
+                    let _ = socket.send_to(response.as_slice(), source_address);
+                }
+            }
+        });
+
+        StunServer { address, requests }
+    }
+
+    /// Returns the number of datagrams received, binding requests or not.
+    pub fn requests(&self) -> usize {
+        self.requests.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+/// An SMTP relay accepting every message, as a local mail transfer agent
+/// relays mail without authentication.
+pub struct SmtpServer {
+    pub port: u16,
+    messages: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+/// Holds an SMTP session with a client, recording the data of each message.
+fn serve_smtp_session(
+    stream: std::net::TcpStream,
+    messages: &std::sync::Mutex<Vec<String>>,
+) -> std::io::Result<()> {
+    use std::io::{BufRead, Write};
+
+    let mut reader = std::io::BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.write_all(b"220 localhost ESMTP\r\n")?;
+    let mut data: Option<Vec<String>> = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end_matches('\n').trim_end_matches('\r');
+        if let Some(lines) = data.as_mut() {
+            if line == "." {
+                messages.lock().unwrap().push(lines.join("\r\n"));
+                data.take();
+                writer.write_all(b"250 OK\r\n")?;
+            } else {
+                lines.push(line.to_owned());
+            }
+            continue;
+        }
+        let command = line.split(' ').next().unwrap_or_default();
+        match command.to_ascii_uppercase().as_str() {
+            "DATA" => {
+                data.replace(Vec::new());
+                writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")?;
+            }
+            "QUIT" => return writer.write_all(b"221 Bye\r\n"),
+            _ => writer.write_all(b"250 OK\r\n")?,
+        }
+    }
+}
+
+impl SmtpServer {
+    pub fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let thread_messages = messages.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                let messages = thread_messages.clone();
+                std::thread::spawn(move || serve_smtp_session(stream, &messages));
+            }
+        });
+
+        SmtpServer { port, messages }
+    }
+
+    /// Returns the data of each message received, with the leading period of
+    /// escaped lines still in place.
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().unwrap().clone()
+    }
+}
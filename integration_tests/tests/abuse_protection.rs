@@ -0,0 +1,239 @@
+extern crate ddns_integration_tests;
+
+use std::io::{Read, Write};
+
+use ddns_integration_tests::*;
+
+fn request_as(server: &ExternalServer, client_address: &str) -> HttpResponse {
+    http_request_with_headers(
+        server.port,
+        "GET",
+        "/",
+        &[("X-Forwarded-For", client_address)],
+        "",
+    )
+}
+
+#[test]
+fn rate_limits_each_client_address() {
+    let server = ExternalServer::start_with_args(&[
+        "--trusted_proxies=127.0.0.1",
+        "--rate_limit=1",
+        "--rate_limit_burst=2",
+    ]);
+
+    assert_eq!(request_as(&server, "198.51.100.7").status, 200);
+    assert_eq!(request_as(&server, "198.51.100.7").status, 200);
+    let response = request_as(&server, "198.51.100.7");
+    assert_eq!(response.status, 429);
+    let retry_after = response
+        .header("Retry-After")
+        .and_then(|retry_after| retry_after.parse::<u64>().ok())
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60, "{}", retry_after);
+
+    assert_eq!(request_as(&server, "198.51.100.8").status, 200);
+}
+
+#[test]
+fn refuses_denied_and_unlisted_addresses() {
+    let server = ExternalServer::start_with_args(&[
+        "--trusted_proxies=127.0.0.1",
+        "--allow=198.51.100.0/24",
+        "--deny=198.51.100.7",
+    ]);
+
+    assert_eq!(request_as(&server, "198.51.100.8").status, 200);
+    assert_eq!(request_as(&server, "198.51.100.7").status, 403);
+    assert_eq!(request_as(&server, "203.0.113.1").status, 403);
+}
+
+#[test]
+fn refuses_oversized_requests() {
+    let server = ExternalServer::start_with_args(&["--max_body_size=16"]);
+
+    // The head is left unfinished at exactly the limit, so that the server has
+    // read all of it when it answers, and does not reset the connection.
+    let mut head = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+    head.resize(8192, b'x');
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream.write_all(&head).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 431 "),
+        "Unexpected response {:?}",
+        response,
+    );
+
+    assert_eq!(
+        http_request(server.port, "POST", "/", &"x".repeat(17)).status,
+        413
+    );
+    assert_eq!(http_request(server.port, "POST", "/", "small").status, 200);
+}
+
+#[test]
+fn refuses_connections_over_the_limit() {
+    let server = ExternalServer::start_with_args(&["--max_connections=1"]);
+
+    let idle = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    let response = wait_until("connection is refused", || {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        Some(response).filter(|response| !response.is_empty())
+    });
+    assert!(
+        response.starts_with("HTTP/1.1 429 "),
+        "Unexpected response {:?}",
+        response,
+    );
+
+    drop(idle);
+    wait_for("connection is accepted", || {
+        http_request(server.port, "GET", "/", "").status == 200
+    });
+}
+
+/// Reads a single response from a kept-alive connection, returning its status
+/// line.
+fn read_status_line(stream: &mut std::net::TcpStream) -> std::io::Result<String> {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head).to_string();
+    let content_length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.eq_ignore_ascii_case("content-length") {
+                value.trim().parse::<usize>().ok()
+            } else {
+                None
+            }
+        })
+        .unwrap_or(0);
+    stream.read_exact(&mut vec![0; content_length])?;
+    Ok(head.lines().next().unwrap_or_default().to_owned())
+}
+
+/// Makes a request over a new connection, and returns the connection once it
+/// has been answered, to be kept alive.
+fn keep_alive(server: &ExternalServer) -> std::net::TcpStream {
+    wait_until("a connection is served", || {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .ok()?;
+        read_status_line(&mut stream)
+            .ok()
+            .filter(|status_line| status_line == "HTTP/1.1 200 OK")
+            .map(|_| stream)
+    })
+}
+
+#[test]
+fn refuses_connections_over_the_limit_per_address() {
+    let server = ExternalServer::start_with_args(&["--max_connections_per_address=1"]);
+
+    // A served connection surely holds the address's only connection.
+    let idle = keep_alive(&server);
+    let response = wait_until("connection is refused", || {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        Some(response).filter(|response| !response.is_empty())
+    });
+    assert!(
+        response.starts_with("HTTP/1.1 429 "),
+        "Unexpected response {:?}",
+        response,
+    );
+
+    drop(idle);
+    wait_for("connection is accepted", || {
+        http_request(server.port, "GET", "/", "").status == 200
+    });
+}
+
+#[test]
+fn closes_slow_connections() {
+    let server = ExternalServer::start_with_args(&["--header_timeout=1"]);
+
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let started_at = std::time::Instant::now();
+    stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert!(
+        response.is_empty(),
+        "{:?}",
+        String::from_utf8_lossy(&response)
+    );
+    assert!(started_at.elapsed() < TIMEOUT);
+
+    assert_eq!(http_request(server.port, "GET", "/", "").status, 200);
+}
+
+#[test]
+fn keeps_connections_alive_past_the_header_timeout() {
+    let server = ExternalServer::start_with_args(&["--header_timeout=1"]);
+
+    let mut stream = keep_alive(&server);
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_status_line(&mut stream).unwrap(), "HTTP/1.1 200 OK");
+}
+
+#[test]
+fn closes_connections_after_the_connection_timeout() {
+    let server = ExternalServer::start_with_args(&["--connection_timeout=1"]);
+
+    let mut stream = keep_alive(&server);
+    let started_at = std::time::Instant::now();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty(), "{:?}", String::from_utf8_lossy(&rest));
+    assert!(started_at.elapsed() < TIMEOUT);
+}
+
+#[test]
+fn reads_chunked_bodies_up_to_the_limit() {
+    let server = ExternalServer::start_with_args(&["--max_body_size=16"]);
+    let send = |request: &[u8]| {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream.write_all(request).unwrap();
+        read_status_line(&mut stream).unwrap()
+    };
+
+    assert_eq!(
+        send(b"GET / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"),
+        "HTTP/1.1 200 OK",
+    );
+    assert_eq!(
+        send(
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+               8\r\nxxxxxxxx\r\n8\r\nxxxxxxxx\r\n0\r\n\r\n"
+        ),
+        "HTTP/1.1 200 OK",
+    );
+    assert_eq!(
+        send(
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+               8\r\nxxxxxxxx\r\n9\r\nxxxxxxxxx\r\n0\r\n\r\n"
+        ),
+        "HTTP/1.1 413 Payload Too Large",
+    );
+}
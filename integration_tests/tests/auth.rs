@@ -0,0 +1,132 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn authenticated_request(registrar: &MockServer, auth: serde_json::Value) -> serde_json::Value {
+    let mut request = json_registrar_request(registrar, "www");
+    request["auth"] = auth;
+    request
+}
+
+#[test]
+fn caches_oauth2_client_credentials_tokens() {
+    let source = MockServer::address_source(&["192.0.2.16", "192.0.2.16", "192.0.2.17"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [
+                {
+                    "method": "POST",
+                    "path": "^/oauth/token$",
+                    "headers": { "authorization": "^Basic ZGRuczpzM2NyZXQ%3D$|^Basic ZGRuczpzM2NyZXQlM0Q=$" },
+                    "body": "^grant_type=client_credentials&scope=dns%3Awrite$",
+                    "responses": [{ "body": "{\"access_token\":\"tok-1\",\"token_type\":\"Bearer\",\"expires_in\":3600}" }],
+                },
+                {
+                    "method": "PUT",
+                    "path": "^/records/www$",
+                    "headers": { "authorization": "^Bearer tok-1$" },
+                    "responses": [{ "status": 200 }],
+                },
+                { "responses": [{ "status": 401 }] },
+            ],
+        }),
+        &[],
+    );
+    let token_url = registrar.url("/oauth/token");
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([authenticated_request(
+            &registrar,
+            serde_json::json!({
+                "type": "oauth2_client_credentials",
+                "token_url": token_url,
+                "client_id": "ddns",
+                "client_secret": "s3cret=",
+                "scope": "dns:write",
+            }),
+        )]),
+        &[],
+        &[],
+    );
+
+    wait_for("the second address is applied", || {
+        client.record_status("www")["applied_address"] == "192.0.2.17"
+    });
+    assert_eq!(registrar.requests_to("/oauth/token").len(), 1);
+    assert_eq!(registrar.requests_to("/records/www").len(), 2);
+}
+
+#[test]
+fn sends_basic_and_bearer_credentials() {
+    let source = MockServer::address_source(&["192.0.2.18"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let mut api = authenticated_request(
+        &registrar,
+        serde_json::json!({ "type": "basic", "username": "user", "password": "pass" }),
+    );
+    api["name"] = serde_json::json!("api");
+    api["address"] = serde_json::json!(registrar.url("/records/api"));
+    let www = authenticated_request(
+        &registrar,
+        serde_json::json!({ "type": "bearer", "token": "static-token" }),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([api, www]),
+        &[],
+        &[],
+    );
+
+    wait_for("every record is updated", || {
+        ["api", "www"]
+            .iter()
+            .all(|name| client.record_status(name)["applied_address"] == "192.0.2.18")
+    });
+    assert_eq!(
+        registrar.requests_to("/records/api")[0]["headers"]["authorization"],
+        "Basic dXNlcjpwYXNz",
+    );
+    assert_eq!(
+        registrar.requests_to("/records/www")[0]["headers"]["authorization"],
+        "Bearer static-token",
+    );
+}
+
+#[test]
+fn signs_requests_with_sigv4() {
+    let source = MockServer::address_source(&["192.0.2.19"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([authenticated_request(
+            &registrar,
+            serde_json::json!({
+                "type": "sigv4",
+                "access_key_id": "AKIDEXAMPLE",
+                "secret_access_key": "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "region": "us-east-1",
+                "service": "route53",
+            }),
+        )]),
+        &[],
+        &[],
+    );
+
+    wait_for("the record is updated", || {
+        client.record_status("www")["applied_address"] == "192.0.2.19"
+    });
+    let headers = &registrar.requests_to("/records/www")[0]["headers"];
+    let authorization = headers["authorization"].as_str().unwrap();
+    assert!(
+        authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"),
+        "{}",
+        authorization,
+    );
+    assert!(
+        authorization.contains("/us-east-1/route53/aws4_request, SignedHeaders=content-type;host;x-amz-date, Signature="),
+        "{}",
+        authorization,
+    );
+    assert!(headers["x-amz-date"].is_string());
+}
@@ -0,0 +1,88 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn get_answers(registrar: &MockServer, path: &str) -> Vec<String> {
+    registrar
+        .requests_to(path)
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value =
+                serde_json::from_str(request["body"].as_str().unwrap()).unwrap();
+            body["answer"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn updates_records_when_address_changes() {
+    let source = MockServer::address_source(&["192.0.2.1", "192.0.2.1", "192.0.2.2"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([
+            json_registrar_request(&registrar, "root"),
+            json_registrar_request(&registrar, "www"),
+        ]),
+        &[],
+        &[],
+    );
+
+    wait_for("both records hold the new address", || {
+        registrar.record_content("root").as_deref() == Some("192.0.2.2")
+            && registrar.record_content("www").as_deref() == Some("192.0.2.2")
+    });
+    wait_for("the address source is polled again", || {
+        source.requests().len() >= 5
+    });
+
+    assert_eq!(
+        get_answers(&registrar, "/records/root"),
+        ["192.0.2.1", "192.0.2.2"]
+    );
+    assert_eq!(
+        get_answers(&registrar, "/records/www"),
+        ["192.0.2.1", "192.0.2.2"]
+    );
+}
+
+#[test]
+fn skips_update_when_address_matches_initial_address() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--initial_address=192.0.2.1"],
+        &[],
+    );
+
+    wait_for("the address source is polled repeatedly", || {
+        source.requests().len() >= 3
+    });
+
+    assert!(registrar.requests().is_empty());
+    // The initial address was never written by this client, so it is not
+    // reported as applied.
+    assert!(client.record_status("www")["applied_address"].is_null());
+}
+
+#[test]
+fn updates_records_with_address_from_external_server() {
+    let external_server = ExternalServer::start();
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        external_server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_until("the record holds the remote address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "127.0.0.1")
+    });
+    assert_eq!(client.status()["desired_address"], "127.0.0.1");
+}
@@ -0,0 +1,87 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn v4_address(server: &ExternalServer) -> std::net::SocketAddr {
+    (std::net::Ipv4Addr::LOCALHOST, server.port).into()
+}
+
+fn v6_address(server: &ExternalServer) -> std::net::SocketAddr {
+    (std::net::Ipv6Addr::LOCALHOST, server.port).into()
+}
+
+fn request(
+    socket_address: std::net::SocketAddr,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> HttpResponse {
+    http_request_to(socket_address, &[], "GET", path, headers, "")
+}
+
+#[test]
+fn answers_on_every_listen_address() {
+    let server = ExternalServer::start_dual_stack(&[]);
+
+    let response = request(v4_address(&server), "/", &[]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "127.0.0.1");
+
+    let response = request(v6_address(&server), "/", &[]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "::1");
+}
+
+#[test]
+fn answers_family_endpoints_only_over_their_family() {
+    let server = ExternalServer::start_dual_stack(&[]);
+
+    let response = request(v4_address(&server), "/v4", &[]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "127.0.0.1");
+    let response = request(v6_address(&server), "/v6", &[]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "::1");
+
+    assert_eq!(request(v6_address(&server), "/v4", &[]).status, 404);
+    assert_eq!(request(v4_address(&server), "/v6", &[]).status, 404);
+}
+
+#[test]
+fn answers_family_hostnames_only_over_their_family() {
+    let server = ExternalServer::start_dual_stack(&[
+        "--v4_hostname=v4.ddns.test",
+        "--v6_hostname=v6.ddns.test",
+    ]);
+
+    let response = request(v4_address(&server), "/", &[("Host", "v4.ddns.test")]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "127.0.0.1");
+    let response = request(v6_address(&server), "/", &[("Host", "V6.ddns.test:3000")]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "::1");
+
+    assert_eq!(
+        request(v6_address(&server), "/", &[("Host", "v4.ddns.test")]).status,
+        404,
+    );
+    assert_eq!(
+        request(v4_address(&server), "/", &[("Host", "v6.ddns.test")]).status,
+        404,
+    );
+}
+
+#[test]
+fn reports_ipv4_mapped_addresses_as_ipv4() {
+    let server = ExternalServer::start_with_args(&["--trusted_proxies=127.0.0.1"]);
+
+    let response = http_request_with_headers(
+        server.port,
+        "GET",
+        "/v4",
+        &[("X-Forwarded-For", "::ffff:198.51.100.7")],
+        "",
+    );
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "198.51.100.7");
+}
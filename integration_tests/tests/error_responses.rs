@@ -0,0 +1,166 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn request_as(server: &ExternalServer, client_address: &str, path: &str) -> HttpResponse {
+    http_request_with_headers(
+        server.port,
+        "GET",
+        path,
+        &[("X-Forwarded-For", client_address)],
+        "",
+    )
+}
+
+fn assert_error(response: &HttpResponse, status: u16, code: &str) {
+    assert_eq!(response.status, status, "{:?}", response);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    let error = response.json();
+    assert_eq!(error["code"], code, "{:?}", error);
+    assert!(error["message"].is_string(), "{:?}", error);
+}
+
+fn start_client_of(source: &MockServer) -> Client {
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    )
+}
+
+fn wait_for_last_error(client: &Client) -> String {
+    wait_until("the client reports an error", || {
+        client.status()["last_error"]
+            .as_str()
+            .map(|last_error| last_error.to_owned())
+    })
+}
+
+#[test]
+fn servers_answer_errors_in_a_common_envelope() {
+    let server = ExternalServer::start_with_args(&[
+        "--trusted_proxies=127.0.0.1",
+        "--deny=198.51.100.7",
+        "--rate_limit=1",
+        "--rate_limit_burst=1",
+        "--max_body_size=16",
+    ]);
+
+    assert_error(&request_as(&server, "198.51.100.7", "/"), 403, "forbidden");
+
+    let response = request_as(&server, "198.51.100.8", "/v2/address");
+    assert_error(&response, 404, "not_found");
+    assert_eq!(response.json()["details"]["path"], "/v2/address");
+
+    let response = request_as(&server, "198.51.100.9", "/v1/address/v6");
+    assert_error(&response, 404, "address_unavailable");
+    assert_eq!(
+        response.json()["details"],
+        serde_json::json!({ "requested_family": "ipv6", "family": "ipv4" }),
+    );
+
+    let response = request_as(&server, "198.51.100.10", "/?format=xml");
+    assert_error(&response, 400, "unsupported_format");
+    assert_eq!(
+        response.json()["details"]["formats"],
+        serde_json::json!(["text", "json", "detailed"]),
+    );
+
+    assert_eq!(request_as(&server, "198.51.100.11", "/").status, 200);
+    let response = request_as(&server, "198.51.100.11", "/");
+    assert_error(&response, 429, "rate_limited");
+    assert_eq!(
+        response.json()["details"]["retry_after"].as_u64(),
+        response
+            .header("Retry-After")
+            .and_then(|retry_after| retry_after.parse::<u64>().ok()),
+    );
+
+    let response = http_request(server.port, "POST", "/", &"x".repeat(17));
+    assert_error(&response, 413, "payload_too_large");
+    assert_eq!(response.json()["details"]["max_body_size"], 16);
+}
+
+#[test]
+fn client_reports_rate_limited_services() {
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "responses": [{
+                    "status": 429,
+                    "headers": { "Retry-After": "7" },
+                    "body": serde_json::json!({
+                        "code": "rate_limited",
+                        "message": "Too many requests",
+                        "details": { "retry_after": 7 },
+                    })
+                    .to_string(),
+                }],
+            }],
+        }),
+        &[],
+    );
+    let client = start_client_of(&source);
+
+    let last_error = wait_for_last_error(&client);
+    assert!(
+        last_error.contains("RateLimitedError(") && last_error.contains("Some(7)"),
+        "{}",
+        last_error,
+    );
+}
+
+#[test]
+fn client_reports_ambiguous_addresses() {
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "responses": [{
+                    "status": 500,
+                    "body": serde_json::json!({
+                        "code": "multiple_addresses",
+                        "message": "Multiple inet addresses found for interface eth0",
+                        "details": {
+                            "interface": "eth0",
+                            "addresses": ["192.0.2.1", "192.0.2.2"],
+                        },
+                    })
+                    .to_string(),
+                }],
+            }],
+        }),
+        &[],
+    );
+    let client = start_client_of(&source);
+
+    let last_error = wait_for_last_error(&client);
+    assert!(
+        last_error.contains("AmbiguousAddressError(")
+            && last_error.contains("[\"192.0.2.1\", \"192.0.2.2\"]"),
+        "{}",
+        last_error,
+    );
+}
+
+#[test]
+fn client_reports_other_error_bodies_as_they_are() {
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "responses": [{ "status": 503, "body": "upstream unavailable" }],
+            }],
+        }),
+        &[],
+    );
+    let client = start_client_of(&source);
+
+    let last_error = wait_for_last_error(&client);
+    assert!(
+        last_error.contains("StatusError(") && last_error.contains("upstream unavailable"),
+        "{}",
+        last_error,
+    );
+}
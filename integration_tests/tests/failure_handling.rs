@@ -0,0 +1,134 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+#[test]
+fn ignores_failed_address_lookups() {
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "responses": [
+                    { "status": 500, "body": "internal error" },
+                    { "body": "not json" },
+                    { "body": "{\"ip\":\"192.0.2.3\"}" },
+                ],
+            }],
+        }),
+        &[],
+    );
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let mut client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.3")
+    });
+
+    assert!(client.is_running());
+    assert_eq!(registrar.requests().len(), 1);
+    assert!(client.status()["last_error"].is_null());
+}
+
+#[test]
+fn reports_registrar_failures_in_status() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "method": "PUT",
+                "path": "^/records/www$",
+                "responses": [{ "status": 500, "body": "internal error" }, { "status": 200 }],
+            }],
+        }),
+        &[],
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    let failed_status = wait_until("the first update fails", || {
+        let record = client.record_status("www");
+        Some(record).filter(|record| !record["last_error"].is_null())
+    });
+    assert!(failed_status["applied_address"].is_null());
+
+    let record = wait_until("the retried update is applied", || {
+        let record = client.record_status("www");
+        Some(record).filter(|record| record["applied_address"] == "192.0.2.1")
+    });
+    assert!(record["last_error"].is_null());
+    assert_eq!(registrar.requests_to("/records/www").len(), 2);
+}
+
+#[test]
+fn retries_failed_updates_after_the_minimum_update_interval() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "method": "PUT",
+                "path": "^/records/www$",
+                "responses": [{ "status": 500, "body": "internal error" }, { "status": 200 }],
+            }],
+        }),
+        &[],
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--min_update_interval=4"],
+        &[],
+    );
+
+    wait_for("the first update fails", || {
+        !client.record_status("www")["last_error"].is_null()
+    });
+    // The address is checked every second, but the record is not retried until
+    // the interval has elapsed.
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert_eq!(registrar.requests_to("/records/www").len(), 1);
+    assert!(client.status()["deferred_updates"].as_u64().unwrap() > 0);
+
+    wait_for("the retried update is applied", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+    assert_eq!(registrar.requests_to("/records/www").len(), 2);
+}
+
+#[test]
+fn retries_rate_limited_registrar_requests() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "method": "PUT",
+                "responses": [
+                    { "status": 429, "headers": { "Retry-After": "1" } },
+                    { "status": 200 },
+                ],
+            }],
+        }),
+        &[],
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_for("the retried update is applied", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+    assert_eq!(registrar.requests_to("/records/www").len(), 2);
+}
@@ -0,0 +1,160 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+/// Returns the `DDNS_*` variables a hook wrote with `env`, once it has run.
+fn read_environment(path: &std::path::Path) -> Vec<String> {
+    let output = wait_until("the hook runs", || {
+        std::fs::read_to_string(path)
+            .ok()
+            .filter(|output| !output.is_empty())
+    });
+    let mut variables = output
+        .lines()
+        .filter(|line| line.starts_with("DDNS_"))
+        .map(|line| line.to_owned())
+        .collect::<Vec<_>>();
+    variables.sort();
+    variables
+}
+
+#[test]
+fn passes_the_update_to_hooks_in_their_environment() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let failing_registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{ "method": "PUT", "responses": [{ "status": 500, "body": "down" }] }],
+        }),
+        &[],
+    );
+    let dir = TempDir::new();
+    let pre_path = dir.write("pre", b"");
+    let post_path = dir.write("post", b"");
+    let pre_update_hook = format!("--pre_update_hook=env > {}", pre_path.display());
+    let post_update_hook = format!("--post_update_hook=env > {}", post_path.display());
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([
+            json_registrar_request(&registrar, "www"),
+            json_registrar_request(&failing_registrar, "api"),
+        ]),
+        &[
+            pre_update_hook.as_str(),
+            post_update_hook.as_str(),
+            "--min_update_interval=60",
+        ],
+        &[],
+    );
+
+    assert_eq!(
+        read_environment(&pre_path),
+        vec![
+            "DDNS_FAMILY=ipv4",
+            "DDNS_NEW_ADDRESS=192.0.2.1",
+            "DDNS_OLD_ADDRESS=",
+        ],
+    );
+
+    let environment = read_environment(&post_path);
+    assert_eq!(
+        environment
+            .iter()
+            .filter(|variable| !variable.starts_with("DDNS_RESULTS="))
+            .collect::<Vec<_>>(),
+        vec![
+            "DDNS_FAILED_RECORDS=api",
+            "DDNS_FAMILY=ipv4",
+            "DDNS_NEW_ADDRESS=192.0.2.1",
+            "DDNS_OLD_ADDRESS=",
+            "DDNS_SUCCEEDED_RECORDS=www",
+        ],
+    );
+    let results = environment
+        .iter()
+        .find_map(|variable| variable.strip_prefix("DDNS_RESULTS="))
+        .unwrap();
+    let results: serde_json::Value = serde_json::from_str(results).unwrap();
+    assert_eq!(results[0]["name"], "www");
+    assert_eq!(results[0]["success"], true);
+    assert!(results[0]["error"].is_null());
+    assert_eq!(results[1]["name"], "api");
+    assert_eq!(results[1]["success"], false);
+    assert!(results[1]["error"].as_str().unwrap().contains("500"));
+}
+
+#[test]
+fn aborts_updates_while_the_pre_update_hook_fails() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let dir = TempDir::new();
+    let runs_path = dir.write("runs", b"");
+    // Fails the first two times it runs.
+    let pre_update_hook = format!(
+        "--pre_update_hook=echo run >> {path}; test $(wc -l < {path}) -gt 2",
+        path = runs_path.display(),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[pre_update_hook.as_str(), "--hook_failure_policy=abort"],
+        &[],
+    );
+
+    wait_for("the update is applied once the hook succeeds", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+    assert_eq!(
+        std::fs::read_to_string(&runs_path).unwrap().lines().count(),
+        3
+    );
+    assert_eq!(registrar.requests_to("/records/www").len(), 1);
+}
+
+#[test]
+fn continues_updates_after_the_pre_update_hook_fails() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--pre_update_hook=exit 3", "--hook_failure_policy=continue"],
+        &[],
+    );
+
+    wait_for("the update is applied", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+    assert_eq!(registrar.requests_to("/records/www").len(), 1);
+}
+
+#[test]
+fn kills_hooks_that_time_out() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let dir = TempDir::new();
+    let late_path = dir.write("late", b"");
+    let pre_update_hook = format!(
+        "--pre_update_hook=sleep 3; echo late > {}",
+        late_path.display(),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[
+            pre_update_hook.as_str(),
+            "--hook_timeout=1",
+            "--hook_failure_policy=continue",
+        ],
+        &[],
+    );
+
+    wait_for("the update is applied after the timeout", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+
+    // The hook would have finished by now had it not been killed.
+    std::thread::sleep(std::time::Duration::from_secs(4));
+    assert_eq!(std::fs::read_to_string(&late_path).unwrap(), "");
+}
@@ -0,0 +1,58 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+#[test]
+fn rejects_scenarios_with_invalid_responses() {
+    for response in &[
+        serde_json::json!({ "headers": { "Bad Header": "value" } }),
+        serde_json::json!({ "headers": { "X-Header": "line\nbreak" } }),
+        serde_json::json!({ "status": 1000 }),
+    ] {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "scenario.json",
+            serde_json::json!({ "routes": [{ "responses": [response] }] })
+                .to_string()
+                .as_bytes(),
+        );
+        let mut process = Process::spawn(
+            "mock_server",
+            &[
+                "--host=127.0.0.1".to_owned(),
+                format!("--port={}", get_free_port()),
+                format!("--scenario={}", path.display()),
+            ],
+            &[],
+        );
+        wait_for("the mock server exits", || !process.is_running());
+    }
+}
+
+#[test]
+fn decodes_query_parameters() {
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=dyndns2"]);
+
+    let response = http_request(
+        registrar.port,
+        "GET",
+        "/nic/update?hostname=www%2Cmail&myip=2001%3Adb8%3A%3A1",
+        "",
+    );
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "good 2001:db8::1\ngood 2001:db8::1");
+    assert_eq!(
+        registrar.record_content("mail").as_deref(),
+        Some("2001:db8::1")
+    );
+
+    let echo = MockServer::start(
+        serde_json::json!({
+            "routes": [{ "responses": [{ "body": "${query.name}" }] }],
+        }),
+        &[],
+    );
+    let response = http_request(echo.port, "GET", "/?name=a%20b+c%3D", "");
+    assert_eq!(response.body, "a b c=");
+}
@@ -0,0 +1,143 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+/// Launches a client with the given notifiers, updating the `www` record of
+/// `registrar` to the addresses of `source`.
+fn start_client(
+    source: &MockServer,
+    registrar: &MockServer,
+    dir: &TempDir,
+    notifiers: serde_json::Value,
+) -> Client {
+    let notifiers_path = dir.write("notifiers.json", notifiers.to_string().as_bytes());
+    let notifiers_arg = format!("--notifiers={}", notifiers_path.display());
+    Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(registrar, "www")]),
+        &[notifiers_arg.as_str()],
+        &[],
+    )
+}
+
+#[test]
+fn fires_webhooks_on_address_changes() {
+    let source = MockServer::address_source(&["192.0.2.1", "192.0.2.1", "192.0.2.2"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let webhook = MockServer::start(serde_json::Value::Null, &[]);
+    let dir = TempDir::new();
+    let _client = start_client(
+        &source,
+        &registrar,
+        &dir,
+        serde_json::json!([{
+            "type": "webhook",
+            "address": webhook.url("/hooks/${event}"),
+            "headers": { "Content-Type": "application/json", "X-New-Address": "${new_address}" },
+            "body": { "text": "${message}", "old": "${old_address}", "new": "${new_address}" },
+            "events": ["address_changed"],
+            "min_interval": 0,
+        }]),
+    );
+
+    let requests = wait_until("both address changes are notified", || {
+        Some(webhook.requests_to("/hooks/address_changed")).filter(|requests| requests.len() == 2)
+    });
+    let bodies = requests
+        .iter()
+        .map(|request| serde_json::from_str(request["body"].as_str().unwrap()).unwrap())
+        .collect::<Vec<serde_json::Value>>();
+    assert_eq!(
+        bodies,
+        vec![
+            serde_json::json!({
+                "text": "IP address has changed from (none) to 192.0.2.1",
+                "old": "",
+                "new": "192.0.2.1",
+            }),
+            serde_json::json!({
+                "text": "IP address has changed from 192.0.2.1 to 192.0.2.2",
+                "old": "192.0.2.1",
+                "new": "192.0.2.2",
+            }),
+        ],
+    );
+    assert_eq!(requests[1]["method"], "POST");
+    assert_eq!(requests[1]["headers"]["x-new-address"], "192.0.2.2");
+}
+
+#[test]
+fn runs_commands_with_the_event_in_their_environment() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{ "method": "PUT", "responses": [{ "status": 500, "body": "down" }] }],
+        }),
+        &[],
+    );
+    let dir = TempDir::new();
+    let output_path = dir.write("event", b"");
+    let _client = start_client(
+        &source,
+        &registrar,
+        &dir,
+        serde_json::json!([{
+            "type": "command",
+            "command": format!(
+                "echo \"$DDNS_EVENT $DDNS_RECORD $DDNS_NEW_ADDRESS\" > {}",
+                output_path.display(),
+            ),
+            "events": ["update_failed"],
+        }]),
+    );
+
+    let output = wait_until("the failure is notified", || {
+        std::fs::read_to_string(&output_path)
+            .ok()
+            .filter(|output| !output.is_empty())
+    });
+    assert_eq!(output, "update_failed www 192.0.2.1\n");
+}
+
+#[test]
+fn mails_notifications_through_smtp_relays() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "method": "PUT",
+                "responses": [{ "status": 500, "body": "down\r\nBcc: victim@example.org" }],
+            }],
+        }),
+        &[],
+    );
+    let relay = SmtpServer::start();
+    let dir = TempDir::new();
+    let _client = start_client(
+        &source,
+        &registrar,
+        &dir,
+        serde_json::json!([{
+            "type": "smtp",
+            "host": "localhost",
+            "port": relay.port,
+            "from": "ddns@example.org",
+            "to": ["admin@example.org"],
+            "subject": "ddns: ${error}",
+            "events": ["update_failed"],
+        }]),
+    );
+
+    let messages = wait_until("the failure is mailed", || {
+        Some(relay.messages()).filter(|messages| !messages.is_empty())
+    });
+    let (head, _) = messages[0].split_once("\r\n\r\n").unwrap();
+    let headers = head.split("\r\n").collect::<Vec<_>>();
+    assert_eq!(headers.len(), 4, "Unexpected headers {:?}", headers);
+    assert!(headers[0].starts_with("Date: "));
+    assert_eq!(headers[1], "From: <ddns@example.org>");
+    assert_eq!(headers[2], "To: <admin@example.org>");
+    assert!(headers[3].starts_with("Subject: ddns: "));
+    assert!(headers[3].contains("Bcc: victim@example.org"));
+}
@@ -0,0 +1,123 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn address_response(address: &str, headers: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "headers": headers,
+        "body": serde_json::json!({ "ip": address }).to_string(),
+    })
+}
+
+#[test]
+fn serves_capabilities() {
+    let server = ExternalServer::start_with_args(&["--server_id=external-1"]);
+
+    let response = http_request(server.port, "GET", "/v1/capabilities", "");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("X-DDNS-Protocol-Versions"), Some("1"));
+    assert_eq!(
+        response.json(),
+        serde_json::json!({
+            "versions": [1],
+            "families": ["ipv4"],
+            "formats": ["text", "json", "detailed"],
+            "push": false,
+            "signed": false,
+            "server_id": "external-1",
+        }),
+    );
+
+    let server = ExternalServer::start_dual_stack(&[]);
+    let response = http_request(server.port, "GET", "/v1/capabilities", "");
+    assert_eq!(
+        response.json()["families"],
+        serde_json::json!(["ipv4", "ipv6"])
+    );
+}
+
+#[test]
+fn serves_versioned_address_endpoints() {
+    let server = ExternalServer::start();
+
+    for path in &["/", "/v1/address", "/v1/address/v4", "/v4"] {
+        let response = http_request(server.port, "GET", path, "");
+        assert_eq!(response.status, 200, "{}", path);
+        assert_eq!(response.header("X-DDNS-Protocol-Versions"), Some("1"));
+        assert_eq!(response.json()["ip"], "127.0.0.1", "{}", path);
+    }
+    for path in &["/v1/address/v6", "/v1/unknown", "/v2/address"] {
+        let response = http_request(server.port, "GET", path, "");
+        assert_eq!(response.status, 404, "{}", path);
+        assert!(response.json()["message"].is_string(), "{}", path);
+    }
+}
+
+#[test]
+fn client_adopts_versioned_endpoints_of_advertising_services() {
+    let versions = serde_json::json!({ "X-DDNS-Protocol-Versions": "1" });
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [
+                {
+                    "path": "^/v1/capabilities$",
+                    "responses": [{
+                        "headers": versions,
+                        "body": serde_json::json!({
+                            "versions": [1, 2],
+                            "families": ["ipv4"],
+                            "formats": ["json"],
+                            "transports": ["quic"],
+                        })
+                        .to_string(),
+                    }],
+                },
+                {
+                    "path": "^/v1/address$",
+                    "responses": [address_response("192.0.2.2", versions.clone())],
+                },
+                {
+                    "path": "^/$",
+                    "responses": [address_response("192.0.2.1", versions.clone())],
+                },
+            ],
+        }),
+        &[],
+    );
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_until("the record holds the versioned address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.2")
+    });
+    assert_eq!(source.requests_to("/").len(), 1);
+    assert_eq!(source.requests_to("/v1/capabilities").len(), 1);
+}
+
+#[test]
+fn client_keeps_address_of_older_services() {
+    let source = MockServer::address_source(&["192.0.2.1", "192.0.2.2"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_until("the record holds the second address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.2")
+    });
+    assert!(source.requests_to("/v1/capabilities").is_empty());
+    assert!(source.requests_to("/v1/address").is_empty());
+}
@@ -0,0 +1,109 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+#[test]
+fn updates_records_of_every_provider() {
+    let source = MockServer::address_source(&["192.0.2.8"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let dir = TempDir::new();
+    let providers_path = dir.write(
+        "providers.json",
+        serde_json::json!([
+            {
+                "provider": "generic_http",
+                "requests": [json_registrar_request(&registrar, "api")],
+            },
+            {
+                "provider": "generic_http",
+                "requests": serde_json::json!([json_registrar_request(&registrar, "mail")])
+                    .to_string(),
+            },
+        ])
+        .to_string()
+        .as_bytes(),
+    );
+    let providers_arg = format!("--providers={}", providers_path.display());
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[providers_arg.as_str()],
+        &[],
+    );
+
+    for name in &["www", "api", "mail"] {
+        wait_until("the record holds the address", || {
+            registrar
+                .record_content(name)
+                .filter(|content| content == "192.0.2.8")
+        });
+    }
+    wait_for("every record is reported as updated", || {
+        ["www", "api", "mail"]
+            .iter()
+            .all(|name| client.record_status(name)["applied_address"] == "192.0.2.8")
+    });
+}
+
+#[test]
+fn removes_records_on_request_until_they_are_synced() {
+    let source = MockServer::address_source(&["192.0.2.8"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let mut www = json_registrar_request(&registrar, "www");
+    www["delete"] = serde_json::json!({ "method": "DELETE" });
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([www, json_registrar_request(&registrar, "api")]),
+        &[],
+        &[],
+    );
+    wait_for("the records are reported as updated", || {
+        ["www", "api"]
+            .iter()
+            .all(|name| client.record_status(name)["applied_address"] == "192.0.2.8")
+    });
+
+    let response = http_request(client.status_port, "DELETE", "/records/www", "");
+    assert_eq!(response.status, 202);
+    wait_for("the record is reported as removed", || {
+        client.record_status("www")["applied_address"].is_null()
+    });
+    assert_eq!(client.record_status("www")["removed"], true);
+    assert_eq!(registrar.record_content("www"), None);
+    assert_eq!(
+        registrar.requests_to("/records/www").last().unwrap()["method"],
+        "DELETE"
+    );
+
+    // Removed records are not recreated by later checks.
+    let checks = source.requests().len();
+    wait_for("the address is checked again", || {
+        source.requests().len() > checks + 1
+    });
+    assert_eq!(registrar.record_content("www"), None);
+
+    let response = http_request(client.status_port, "POST", "/records/www/sync", "");
+    assert_eq!(response.status, 202);
+    wait_until("the record is recreated", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.8")
+    });
+    assert_eq!(client.record_status("www")["removed"], false);
+
+    // Records without a delete request stay in place and keep being updated.
+    let response = http_request(client.status_port, "DELETE", "/records/api", "");
+    assert_eq!(response.status, 202);
+    wait_for("the removal is reported as failed", || {
+        !client.record_status("api")["last_error"].is_null()
+    });
+    assert_eq!(client.record_status("api")["removed"], false);
+    assert_eq!(
+        registrar.record_content("api").as_deref(),
+        Some("192.0.2.8")
+    );
+
+    let response = http_request(client.status_port, "DELETE", "/records/mail", "");
+    assert_eq!(response.status, 404);
+}
@@ -0,0 +1,92 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+/// Returns a JSON emulator request entry that reads the record before writing it.
+fn checked_registrar_request(
+    registrar: &MockServer,
+    name: &str,
+    get: serde_json::Value,
+) -> serde_json::Value {
+    let mut request = json_registrar_request(registrar, name);
+    request["get"] = get;
+    request
+}
+
+fn get_methods(registrar: &MockServer, name: &str) -> Vec<String> {
+    registrar
+        .requests_to(&format!("/records/{}", name))
+        .iter()
+        .map(|request| request["method"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn skips_records_that_already_match() {
+    let source = MockServer::address_source(&["192.0.2.9"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    registrar.set_record("www", "192.0.2.9");
+    registrar.set_record("api", "192.0.2.9");
+    registrar.set_record("mail", "192.0.2.1");
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([
+            checked_registrar_request(
+                &registrar,
+                "www",
+                serde_json::json!({ "json_path": "$[?(@.type == 'A')].content" }),
+            ),
+            checked_registrar_request(
+                &registrar,
+                "api",
+                serde_json::json!({ "regex": "\"content\":\"([^\"]*)\"" }),
+            ),
+            checked_registrar_request(
+                &registrar,
+                "mail",
+                serde_json::json!({ "json_path": "$[0].content" }),
+            ),
+        ]),
+        &[],
+        &[],
+    );
+
+    wait_until("the changed record is updated", || {
+        registrar
+            .record_content("mail")
+            .filter(|content| content == "192.0.2.9")
+    });
+    wait_for("every record is reported as updated", || {
+        ["www", "api", "mail"]
+            .iter()
+            .all(|name| client.record_status(name)["applied_address"] == "192.0.2.9")
+    });
+
+    assert_eq!(get_methods(&registrar, "www"), ["GET"]);
+    assert_eq!(get_methods(&registrar, "api"), ["GET"]);
+    assert_eq!(get_methods(&registrar, "mail"), ["GET", "PUT"]);
+}
+
+#[test]
+fn creates_missing_records() {
+    let source = MockServer::address_source(&["192.0.2.10"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([checked_registrar_request(
+            &registrar,
+            "www",
+            serde_json::json!({ "json_path": "$[0].content" }),
+        )]),
+        &[],
+        &[],
+    );
+
+    wait_until("the record is created", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.10")
+    });
+    assert_eq!(get_methods(&registrar, "www"), ["GET", "PUT"]);
+}
@@ -0,0 +1,93 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn request(server: &ExternalServer, path: &str, headers: &[(&str, &str)]) -> HttpResponse {
+    let response = http_request_with_headers(server.port, "GET", path, headers, "");
+    assert_eq!(response.status, 200, "{}", response.body);
+    response
+}
+
+#[test]
+fn negotiates_format_from_accept_header() {
+    let server = ExternalServer::start_with_args(&["--server_id=external-1"]);
+
+    let response = request(&server, "/", &[]);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    assert_eq!(response.json(), serde_json::json!({ "ip": "127.0.0.1" }));
+
+    let response = request(&server, "/", &[("Accept", "text/plain")]);
+    assert_eq!(
+        response.header("Content-Type"),
+        Some("text/plain; charset=utf-8"),
+    );
+    assert_eq!(response.body, "127.0.0.1\n");
+
+    let response = request(
+        &server,
+        "/",
+        &[("Accept", "text/plain;q=0.5, application/json, */*;q=0.1")],
+    );
+    assert_eq!(response.json(), serde_json::json!({ "ip": "127.0.0.1" }));
+
+    let response = request(
+        &server,
+        "/",
+        &[("Accept", "application/vnd.ddns.address+json")],
+    );
+    assert_eq!(
+        response.header("Content-Type"),
+        Some("application/vnd.ddns.address+json"),
+    );
+    let body = response.json();
+    assert_eq!(body["ip"], "127.0.0.1");
+    assert_eq!(body["family"], "ipv4");
+    assert_eq!(body["server_id"], "external-1");
+    assert!(body["port"].as_u64().is_some(), "{}", body);
+    assert!(body["timestamp"].as_u64().is_some(), "{}", body);
+}
+
+#[test]
+fn format_query_parameter_overrides_accept_header() {
+    let server = ExternalServer::start();
+
+    let response = request(&server, "/?format=text", &[("Accept", "application/json")]);
+    assert_eq!(response.body, "127.0.0.1\n");
+
+    let response = request(&server, "/v4?format=detailed", &[]);
+    assert_eq!(response.json()["family"], "ipv4");
+
+    let response = http_request(server.port, "GET", "/?format=xml", "");
+    assert_eq!(response.status, 400);
+}
+
+#[test]
+fn omits_port_of_forwarded_clients() {
+    let server = ExternalServer::start_with_args(&["--trusted_proxies=127.0.0.1"]);
+
+    let body = request(
+        &server,
+        "/?format=detailed",
+        &[("X-Forwarded-For", "2001:db8::7")],
+    )
+    .json();
+    assert_eq!(body["ip"], "2001:db8::7");
+    assert_eq!(body["family"], "ipv6");
+    assert!(body.get("port").is_none(), "{}", body);
+}
+
+#[test]
+fn signs_detailed_responses() {
+    let dir = TempDir::new();
+    let (key_path, _, _) = write_signing_key(&dir, "signing");
+    let server =
+        ExternalServer::start_with_args(&[&format!("--signing_key={}", key_path.display())]);
+
+    let body = request(&server, "/?format=detailed", &[]).json();
+    assert!(body["signature"].is_string(), "{}", body);
+    assert!(body["family"].is_string(), "{}", body);
+
+    let response = request(&server, "/", &[("Accept", "text/plain")]);
+    assert_eq!(response.body, "127.0.0.1\n");
+}
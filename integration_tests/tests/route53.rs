@@ -0,0 +1,260 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn route53_provider_arg(
+    dir: &TempDir,
+    registrar: &MockServer,
+    options: serde_json::Value,
+) -> String {
+    let mut provider = serde_json::json!({
+        "provider": "route53",
+        "endpoint": registrar.url(""),
+        "hosted_zone_id": "Z0123456789ABCDEFGHIJ",
+        "records": ["www.example.com"],
+        "sync_interval": 1,
+    });
+    provider
+        .as_object_mut()
+        .unwrap()
+        .extend(options.as_object().unwrap().clone());
+    let path = dir.write(
+        "providers.json",
+        serde_json::json!([provider]).to_string().as_bytes(),
+    );
+    format!("--providers={}", path.display())
+}
+
+#[test]
+fn upserts_records_and_waits_for_changes_to_sync() {
+    let source = MockServer::address_source(&["192.0.2.20"]);
+    let registrar = MockServer::start(
+        serde_json::Value::Null,
+        &[
+            "--emulator=route53",
+            "--emulator_token=AKIDEXAMPLE",
+            "--emulator_secret=wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        ],
+    );
+    let dir = TempDir::new();
+    let providers_arg = route53_provider_arg(
+        &dir,
+        &registrar,
+        serde_json::json!({
+            "access_key_id": "AKIDEXAMPLE",
+            "secret_access_key": "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "ttl": 60,
+        }),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([]),
+        &[providers_arg.as_str()],
+        &[],
+    );
+
+    wait_for("the record is reported as updated", || {
+        client.record_status("www.example.com")["applied_address"] == "192.0.2.20"
+    });
+    let record = registrar
+        .zone()
+        .into_iter()
+        .find(|record| record["name"] == "www.example.com")
+        .unwrap();
+    assert_eq!(record["type"], "A");
+    assert_eq!(record["content"], "192.0.2.20");
+    assert_eq!(record["ttl"], 60);
+
+    let requests = registrar.requests();
+    let change = requests
+        .iter()
+        .find(|request| request["method"] == "POST")
+        .unwrap();
+    assert!(change["body"]
+        .as_str()
+        .unwrap()
+        .contains("<Action>UPSERT</Action><ResourceRecordSet><Name>www.example.com.</Name>"));
+    assert!(change["headers"]["authorization"]
+        .as_str()
+        .unwrap()
+        .contains("/us-east-1/route53/aws4_request"));
+    assert!(requests.iter().any(|request| request["path"]
+        .as_str()
+        .unwrap()
+        .starts_with("/2013-04-01/change/")));
+}
+
+#[test]
+fn reads_credentials_from_the_environment() {
+    let source = MockServer::address_source(&["2001:db8::20"]);
+    let registrar = MockServer::start(
+        serde_json::Value::Null,
+        &[
+            "--emulator=route53",
+            "--emulator_token=AKIDENVIRONMENT",
+            "--emulator_secret=secret",
+        ],
+    );
+    registrar.set_record("www.example.com", "2001:db8::1");
+    let dir = TempDir::new();
+    let providers_arg = route53_provider_arg(&dir, &registrar, serde_json::json!({}));
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([]),
+        &[providers_arg.as_str()],
+        &[
+            ("AWS_ACCESS_KEY_ID", "AKIDENVIRONMENT"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ],
+    );
+
+    wait_for("the record is reported as updated", || {
+        client.record_status("www.example.com")["applied_address"] == "2001:db8::20"
+    });
+    let records = registrar.zone();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["type"], "AAAA");
+    assert_eq!(records[0]["content"], "2001:db8::20");
+}
+
+#[test]
+fn reports_route53_errors() {
+    let source = MockServer::address_source(&["192.0.2.21"]);
+    let registrar = MockServer::start(
+        serde_json::Value::Null,
+        &["--emulator=route53", "--emulator_token=AKIDEXAMPLE"],
+    );
+    let dir = TempDir::new();
+    let providers_arg = route53_provider_arg(
+        &dir,
+        &registrar,
+        serde_json::json!({ "access_key_id": "AKIDOTHER", "secret_access_key": "secret" }),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([]),
+        &[providers_arg.as_str()],
+        &[],
+    );
+
+    wait_for("the update failure is reported", || {
+        client.record_status("www.example.com")["last_error"]
+            .as_str()
+            .is_some_and(|error| {
+                error.contains("The security token included in the request is invalid.")
+            })
+    });
+    assert!(registrar.zone().is_empty());
+}
+
+#[test]
+fn rejects_requests_signed_with_another_secret() {
+    let source = MockServer::address_source(&["192.0.2.22"]);
+    let registrar = MockServer::start(
+        serde_json::Value::Null,
+        &[
+            "--emulator=route53",
+            "--emulator_token=AKIDEXAMPLE",
+            "--emulator_secret=secret",
+        ],
+    );
+    let dir = TempDir::new();
+    let providers_arg = route53_provider_arg(
+        &dir,
+        &registrar,
+        serde_json::json!({ "access_key_id": "AKIDEXAMPLE", "secret_access_key": "other" }),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([]),
+        &[providers_arg.as_str()],
+        &[],
+    );
+
+    wait_for("the update failure is reported", || {
+        client.record_status("www.example.com")["last_error"]
+            .as_str()
+            .is_some_and(|error| {
+                error.contains("The request signature we calculated does not match")
+            })
+    });
+    assert!(registrar.zone().is_empty());
+}
+
+#[test]
+fn compares_the_record_set_of_the_published_family() {
+    let source = MockServer::address_source(&["2001:db8::23"]);
+    let registrar = MockServer::start(
+        serde_json::Value::Null,
+        &["--emulator=route53", "--emulator_token=AKIDEXAMPLE"],
+    );
+    registrar.set_record("www.example.com", "192.0.2.23");
+    registrar.set_record("www.example.com", "2001:db8::23");
+    let dir = TempDir::new();
+    let providers_arg = route53_provider_arg(
+        &dir,
+        &registrar,
+        serde_json::json!({ "access_key_id": "AKIDEXAMPLE", "secret_access_key": "secret" }),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([]),
+        &[providers_arg.as_str()],
+        &[],
+    );
+
+    wait_for("the record is reported as updated", || {
+        client.record_status("www.example.com")["applied_address"] == "2001:db8::23"
+    });
+    assert_eq!(registrar.zone().len(), 2);
+    assert!(registrar
+        .requests()
+        .iter()
+        .all(|request| request["method"] == "GET"));
+}
+
+#[test]
+fn deletes_record_sets_on_request() {
+    let source = MockServer::address_source(&["192.0.2.20"]);
+    let registrar = MockServer::start(
+        serde_json::Value::Null,
+        &[
+            "--emulator=route53",
+            "--emulator_token=AKIDEXAMPLE",
+            "--emulator_secret=wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        ],
+    );
+    let dir = TempDir::new();
+    let providers_arg = route53_provider_arg(
+        &dir,
+        &registrar,
+        serde_json::json!({
+            "access_key_id": "AKIDEXAMPLE",
+            "secret_access_key": "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        }),
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([]),
+        &[providers_arg.as_str()],
+        &[],
+    );
+    wait_for("the record is reported as updated", || {
+        client.record_status("www.example.com")["applied_address"] == "192.0.2.20"
+    });
+
+    let response = http_request(client.status_port, "DELETE", "/records/www.example.com", "");
+    assert_eq!(response.status, 202);
+    let record = wait_until("the record is reported as removed", || {
+        let record = client.record_status("www.example.com");
+        Some(record).filter(|record| record["applied_address"].is_null())
+    });
+    assert_eq!(record["removed"], true);
+    assert!(record["last_error"].is_null());
+    assert!(registrar.zone().is_empty());
+    assert!(registrar.requests().iter().any(|request| request["body"]
+        .as_str()
+        .unwrap()
+        .contains("<Action>DELETE</Action>")));
+}
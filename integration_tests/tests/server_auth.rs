@@ -0,0 +1,159 @@
+extern crate ddns_integration_tests;
+extern crate openssl;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn sign_request(secret: &str, path: &str, timestamp: u64, nonce: &str) -> String {
+    let key = openssl::pkey::PKey::hmac(secret.as_bytes()).unwrap();
+    let mut signer =
+        openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key).unwrap();
+    signer
+        .update(format!("GET\n{}\n{}\n{}", path, timestamp, nonce).as_bytes())
+        .unwrap();
+    let signature: String = signer
+        .sign_to_vec()
+        .unwrap()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "DDNS-HMAC-SHA256 Timestamp={}, Nonce={}, Signature={}",
+        timestamp, nonce, signature,
+    )
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+fn requires_bearer_tokens() {
+    let server = ExternalServer::start_with_args(&["--auth_tokens=first,second"]);
+
+    let response = http_request(server.port, "GET", "/", "");
+    assert_eq!(response.status, 401);
+    let response = http_request_with_headers(
+        server.port,
+        "GET",
+        "/",
+        &[("Authorization", "Bearer third")],
+        "",
+    );
+    assert_eq!(response.status, 401);
+
+    let anonymous_registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let anonymous_client = Client::start(
+        server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&anonymous_registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--service_token=second"],
+        &[],
+    );
+
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "127.0.0.1")
+    });
+    wait_for("the anonymous client reports an error", || {
+        !anonymous_client.status()["last_error"].is_null()
+    });
+    assert!(anonymous_registrar.requests().is_empty());
+}
+
+#[test]
+fn accepts_per_client_api_keys() {
+    let dir = TempDir::new();
+    let api_keys_path = dir.write(
+        "api_keys.json",
+        br#"{"laptop": "laptop-key", "router": "router-key"}"#,
+    );
+    let api_keys_arg = format!("--auth_api_keys={}", api_keys_path.display());
+    let server = ExternalServer::start_with_args(&[api_keys_arg.as_str()]);
+
+    let response =
+        http_request_with_headers(server.port, "GET", "/", &[("X-API-Key", "unknown-key")], "");
+    assert_eq!(response.status, 401);
+
+    let api_key_registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _api_key_client = Client::start(
+        server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&api_key_registrar, "www")]),
+        &["--service_api_key=laptop-key"],
+        &[],
+    );
+
+    let signing_registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _signing_client = Client::start(
+        server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&signing_registrar, "www")]),
+        &[
+            "--service_hmac_key_id=router",
+            "--service_hmac_secret=router-key",
+        ],
+        &[],
+    );
+
+    for registrar in [&api_key_registrar, &signing_registrar] {
+        wait_until("the record holds the address", || {
+            registrar
+                .record_content("www")
+                .filter(|content| content == "127.0.0.1")
+        });
+    }
+
+    // Revoked keys are rejected without a restart.
+    std::fs::write(&api_keys_path, br#"{"router": "router-key"}"#).unwrap();
+    wait_for("the revoked key is rejected", || {
+        http_request_with_headers(server.port, "GET", "/", &[("X-API-Key", "laptop-key")], "")
+            .status
+            == 401
+    });
+}
+
+#[test]
+fn rejects_stale_and_replayed_signatures() {
+    let server = ExternalServer::start_with_args(&["--auth_hmac_secret=secret"]);
+
+    let authorization = sign_request("secret", "/", now(), "first");
+    let headers = [("Authorization", authorization.as_str())];
+    let response = http_request_with_headers(server.port, "GET", "/", &headers, "");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["ip"], "127.0.0.1");
+    let response = http_request_with_headers(server.port, "GET", "/", &headers, "");
+    assert_eq!(response.status, 401);
+
+    let authorization = sign_request("secret", "/", now() - 3600, "second");
+    let headers = [("Authorization", authorization.as_str())];
+    let response = http_request_with_headers(server.port, "GET", "/", &headers, "");
+    assert_eq!(response.status, 401);
+
+    let authorization = sign_request("other", "/", now(), "third");
+    let headers = [("Authorization", authorization.as_str())];
+    let response = http_request_with_headers(server.port, "GET", "/", &headers, "");
+    assert_eq!(response.status, 401);
+
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--service_hmac_secret=secret"],
+        &[],
+    );
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "127.0.0.1")
+    });
+}
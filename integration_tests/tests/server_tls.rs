@@ -0,0 +1,98 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+#[test]
+fn serves_addresses_over_tls() {
+    let dir = TempDir::new();
+    let (certificate_path, key_path) = write_certificate(&dir, "server");
+    let certificate_arg = format!("--tls_certificate={}", certificate_path.display());
+    let key_arg = format!("--tls_key={}", key_path.display());
+    let server = ExternalServer::start_with_args(&[certificate_arg.as_str(), key_arg.as_str()]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        server.https_url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[("SSL_CERT_FILE", certificate_path.to_str().unwrap())],
+    );
+
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "127.0.0.1")
+    });
+}
+
+#[test]
+fn reloads_rotated_certificates() {
+    let dir = TempDir::new();
+    let (certificate_path, key_path) = write_certificate(&dir, "server");
+    let certificate_arg = format!("--tls_certificate={}", certificate_path.display());
+    let key_arg = format!("--tls_key={}", key_path.display());
+    let server = ExternalServer::start_with_args(&[certificate_arg.as_str(), key_arg.as_str()]);
+    let original = get_server_certificate(server.port);
+    assert_eq!(
+        original.to_pem().unwrap(),
+        std::fs::read(&certificate_path).unwrap(),
+    );
+
+    let (rotated_certificate_path, rotated_key_path) = write_certificate(&dir, "rotated");
+    std::fs::rename(&rotated_key_path, &key_path).unwrap();
+    std::fs::rename(&rotated_certificate_path, &certificate_path).unwrap();
+
+    let rotated = get_server_certificate(server.port);
+    assert_eq!(
+        rotated.to_pem().unwrap(),
+        std::fs::read(&certificate_path).unwrap(),
+    );
+    assert_ne!(rotated.to_pem().unwrap(), original.to_pem().unwrap());
+}
+
+#[test]
+fn requires_client_certificates_for_mutual_tls() {
+    let dir = TempDir::new();
+    let (certificate_path, key_path) = write_certificate(&dir, "server");
+    let (client_certificate_path, client_key_path) = write_certificate(&dir, "client");
+    let certificate_arg = format!("--tls_certificate={}", certificate_path.display());
+    let key_arg = format!("--tls_key={}", key_path.display());
+    let client_ca_arg = format!("--tls_client_ca={}", client_certificate_path.display());
+    let server = ExternalServer::start_with_args(&[
+        certificate_arg.as_str(),
+        key_arg.as_str(),
+        client_ca_arg.as_str(),
+    ]);
+    let envs = [("SSL_CERT_FILE", certificate_path.to_str().unwrap())];
+
+    let anonymous_registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let anonymous_client = Client::start(
+        server.https_url().as_str(),
+        &serde_json::json!([json_registrar_request(&anonymous_registrar, "www")]),
+        &[],
+        &envs,
+    );
+
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client_certificate_arg = format!(
+        "--service_client_certificate={}",
+        client_certificate_path.display(),
+    );
+    let client_key_arg = format!("--service_client_key={}", client_key_path.display());
+    let _client = Client::start(
+        server.https_url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[client_certificate_arg.as_str(), client_key_arg.as_str()],
+        &envs,
+    );
+
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "127.0.0.1")
+    });
+    wait_for("the anonymous client reports an error", || {
+        !anonymous_client.status()["last_error"].is_null()
+    });
+    assert!(anonymous_registrar.requests().is_empty());
+}
@@ -0,0 +1,117 @@
+extern crate ddns_integration_tests;
+extern crate openssl;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn start_signing_server(dir: &TempDir) -> (ExternalServer, std::path::PathBuf) {
+    let (private_key_path, public_key_path, _) = write_signing_key(dir, "server");
+    let signing_key_arg = format!("--signing_key={}", private_key_path.display());
+    let server = ExternalServer::start_with_args(&[signing_key_arg.as_str()]);
+    (server, public_key_path)
+}
+
+#[test]
+fn signs_responses_for_request_nonces() {
+    let dir = TempDir::new();
+    let (server, _) = start_signing_server(&dir);
+
+    let response = http_request_with_headers(
+        server.port,
+        "GET",
+        "/",
+        &[("X-DDNS-Nonce", "0123456789abcdef")],
+        "",
+    );
+    assert_eq!(response.status, 200);
+    let response = response.json();
+    assert_eq!(response["ip"], "127.0.0.1");
+    assert_eq!(response["nonce"], "0123456789abcdef");
+    assert!(response["timestamp"].is_u64());
+    assert!(response["signature"].is_string());
+}
+
+#[test]
+fn accepts_verified_responses() {
+    let dir = TempDir::new();
+    let (server, public_key_path) = start_signing_server(&dir);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let public_key_arg = format!("--service_public_key={}", public_key_path.display());
+    let _client = Client::start(
+        server.url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[public_key_arg.as_str()],
+        &[],
+    );
+
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "127.0.0.1")
+    });
+}
+
+#[test]
+fn rejects_unsigned_and_forged_responses() {
+    let dir = TempDir::new();
+    let (signing_server, _) = start_signing_server(&dir);
+    let unsigned_server = ExternalServer::start();
+    let (_, other_public_key_path, _) = write_signing_key(&dir, "other");
+    let public_key_arg = format!("--service_public_key={}", other_public_key_path.display());
+
+    for server in [&signing_server, &unsigned_server] {
+        let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+        let client = Client::start(
+            server.url().as_str(),
+            &serde_json::json!([json_registrar_request(&registrar, "www")]),
+            &[public_key_arg.as_str()],
+            &[],
+        );
+        wait_for("the client reports an error", || {
+            !client.status()["last_error"].is_null()
+        });
+        assert!(registrar.requests().is_empty());
+    }
+}
+
+#[test]
+fn rejects_replayed_responses() {
+    let dir = TempDir::new();
+    let (_, public_key_path, key) = write_signing_key(&dir, "server");
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let message = format!("10.0.0.1\n{}\ncaptured", timestamp);
+    let signature = openssl::sign::Signer::new_without_digest(&key)
+        .unwrap()
+        .sign_oneshot_to_vec(message.as_bytes())
+        .unwrap();
+    let body = serde_json::json!({
+        "ip": "10.0.0.1",
+        "timestamp": timestamp,
+        "nonce": "captured",
+        "signature": openssl::base64::encode_block(signature.as_slice()),
+    });
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [{ "name": "address", "responses": [{ "body": body.to_string() }] }],
+        }),
+        &[],
+    );
+
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let public_key_arg = format!("--service_public_key={}", public_key_path.display());
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[public_key_arg.as_str()],
+        &[],
+    );
+    wait_for("the client reports an error", || {
+        client.status()["last_error"]
+            .as_str()
+            .is_some_and(|error| error.contains("another request"))
+    });
+    assert!(registrar.requests().is_empty());
+}
@@ -0,0 +1,84 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+#[test]
+fn checks_the_address_when_triggered() {
+    let source = MockServer::address_source(&["192.0.2.1", "192.0.2.2"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--update_interval=3600"],
+        &[],
+    );
+
+    wait_for("the first address is applied", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+    assert_eq!(source.requests().len(), 1);
+
+    let response = http_request(client.status_port, "POST", "/trigger", "");
+    assert_eq!(response.status, 202);
+
+    wait_until("the record holds the new address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.2")
+    });
+    assert_eq!(source.requests().len(), 2);
+}
+
+#[test]
+fn syncs_records_on_request() {
+    let source = MockServer::address_source(&["192.0.2.1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &["--initial_address=192.0.2.1"],
+        &[],
+    );
+
+    wait_for("the address source is polled repeatedly", || {
+        source.requests().len() >= 2
+    });
+    assert!(registrar.requests().is_empty());
+    assert!(client.record_status("www")["applied_address"].is_null());
+
+    let response = http_request(client.status_port, "POST", "/records/www/sync", "");
+    assert_eq!(response.status, 202);
+
+    wait_for("the synced address is applied", || {
+        client.record_status("www")["applied_address"] == "192.0.2.1"
+    });
+    assert_eq!(registrar.record_content("www").unwrap(), "192.0.2.1");
+    assert_eq!(registrar.requests_to("/records/www").len(), 1);
+
+    let response = http_request(client.status_port, "POST", "/records/ftp/sync", "");
+    assert_eq!(response.status, 404);
+}
+
+#[test]
+fn refuses_to_sync_records_before_an_address_is_known() {
+    let source = MockServer::start(
+        serde_json::json!({
+            "routes": [{ "responses": [{ "status": 500, "body": "internal error" }] }],
+        }),
+        &[],
+    );
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_for("the address lookup fails", || !source.requests().is_empty());
+
+    let response = http_request(client.status_port, "POST", "/records/www/sync", "");
+    assert_eq!(response.status, 409);
+    assert!(registrar.requests().is_empty());
+}
@@ -0,0 +1,129 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn start_client_of(servers: &[&StunServer], args: &[&str]) -> (Client, MockServer) {
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let stun_servers = servers
+        .iter()
+        .map(|server| server.address.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let stun_servers_arg = format!("--stun_servers={}", stun_servers);
+    let mut all_args = vec![stun_servers_arg.as_str()];
+    all_args.extend_from_slice(args);
+    // The service address is never requested, as STUN servers replace it.
+    let client = Client::start(
+        "http://127.0.0.1:9",
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        all_args.as_slice(),
+        &[],
+    );
+    (client, registrar)
+}
+
+#[test]
+fn discovers_address_from_stun_servers() {
+    let servers = [
+        StunServer::start("192.0.2.1"),
+        StunServer::start("192.0.2.1"),
+        StunServer::start("192.0.2.1"),
+    ];
+    let (_client, registrar) = start_client_of(&servers.iter().collect::<Vec<_>>(), &[]);
+
+    wait_until("the record holds the mapped address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.1")
+    });
+    assert!(servers.iter().all(|server| server.requests() > 0));
+}
+
+#[test]
+fn reads_ipv6_mapped_addresses() {
+    let servers = [
+        StunServer::start_on("[::1]:0", "2001:db8::1"),
+        StunServer::start("2001:db8::1"),
+    ];
+    let (_client, registrar) = start_client_of(&servers.iter().collect::<Vec<_>>(), &[]);
+
+    wait_until("the record holds the mapped address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "2001:db8::1")
+    });
+}
+
+#[test]
+fn follows_the_majority_of_stun_servers() {
+    let servers = [
+        StunServer::start("192.0.2.1"),
+        StunServer::start("192.0.2.2"),
+        StunServer::start("192.0.2.2"),
+    ];
+    let (_client, registrar) = start_client_of(&servers.iter().collect::<Vec<_>>(), &[]);
+
+    wait_until("the record holds the majority address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.2")
+    });
+}
+
+#[test]
+fn reports_stun_servers_without_consensus() {
+    let servers = [
+        StunServer::start("192.0.2.1"),
+        StunServer::start("192.0.2.2"),
+        StunServer::start("192.0.2.2"),
+    ];
+    let (client, registrar) =
+        start_client_of(&servers.iter().collect::<Vec<_>>(), &["--stun_quorum=3"]);
+
+    let last_error = wait_until("the client reports an error", || {
+        client.status()["last_error"]
+            .as_str()
+            .map(|last_error| last_error.to_owned())
+    });
+    assert!(last_error.contains("ConsensusError(3, "), "{}", last_error);
+    assert!(registrar.record_content("www").is_none());
+}
+
+#[test]
+fn resolves_stun_server_names() {
+    let server = StunServer::start("192.0.2.3");
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let stun_servers_arg = format!("--stun_servers=stun:localhost:{}", server.address.port());
+    let _client = Client::start(
+        "http://127.0.0.1:9",
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[stun_servers_arg.as_str()],
+        &[],
+    );
+
+    wait_until("the record holds the mapped address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.3")
+    });
+}
+
+#[test]
+fn asks_stun_servers_over_the_given_family() {
+    let servers = [
+        StunServer::start("192.0.2.4"),
+        StunServer::start_on("[::1]:0", "2001:db8::4"),
+    ];
+    let (_client, registrar) = start_client_of(
+        &servers.iter().collect::<Vec<_>>(),
+        &["--stun_family=ipv6", "--stun_quorum=1"],
+    );
+
+    wait_until("the record holds the IPv6 mapped address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "2001:db8::4")
+    });
+    assert_eq!(servers[0].requests(), 0);
+}
@@ -0,0 +1,131 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn wait_for_requests(registrar: &MockServer, count: usize) -> Vec<serde_json::Value> {
+    wait_until("the registrar receives all requests", || {
+        Some(registrar.requests()).filter(|requests| requests.len() >= count)
+    })
+}
+
+#[test]
+fn renders_address_into_url_headers_and_body() {
+    let source = MockServer::address_source(&["192.0.2.5"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--body="]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([{
+            "name": "www",
+            "method": "POST",
+            "address": registrar.url("/update/${ip_address}?hostname=www.example.com&myip=${ip_address}"),
+            "headers": {
+                "Authorization": "Bearer secret",
+                "X-Address": "${ip_address}",
+            },
+            "body": {
+                "host": "www",
+                "answers": ["${ip_address}", "static"],
+                "meta": { "ttl": 300, "proxied": false, "comment": "set to ${ip_address}" },
+            },
+        }]),
+        &[],
+        &[],
+    );
+
+    let requests = wait_for_requests(&registrar, 1);
+    let request = &requests[0];
+    assert_eq!(request["method"], "POST");
+    assert_eq!(request["path"], "/update/192.0.2.5");
+    assert_eq!(request["query"], "hostname=www.example.com&myip=192.0.2.5");
+    assert_eq!(request["headers"]["authorization"], "Bearer secret");
+    assert_eq!(request["headers"]["x-address"], "192.0.2.5");
+
+    let body: serde_json::Value = serde_json::from_str(request["body"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "host": "www",
+            "answers": ["192.0.2.5", "static"],
+            "meta": { "ttl": 300, "proxied": false, "comment": "set to 192.0.2.5" },
+        }),
+    );
+}
+
+#[test]
+fn renders_ipv6_addresses() {
+    let source = MockServer::address_source(&["2001:db8::1"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    let record = wait_until("the record holds the address", || {
+        registrar
+            .zone()
+            .into_iter()
+            .find(|record| record["name"] == "www")
+    });
+    assert_eq!(record["type"], "AAAA");
+    assert_eq!(record["content"], "2001:db8::1");
+}
+
+#[test]
+fn sends_each_record_its_own_request() {
+    let source = MockServer::address_source(&["192.0.2.6"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--body="]);
+    let _client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([
+            {
+                "name": "@",
+                "method": "PATCH",
+                "address": registrar.url("/zones/example.com/records/1"),
+                "headers": {},
+                "body": { "host": "", "answer": "${ip_address}" },
+            },
+            {
+                "name": "*",
+                "method": "PUT",
+                "address": registrar.url("/zones/example.com/records/2"),
+                "headers": {},
+                "body": { "host": "*", "answer": "${ip_address}" },
+            },
+        ]),
+        &[],
+        &[],
+    );
+
+    let mut requests = wait_for_requests(&registrar, 2);
+    requests.sort_by_key(|request| request["path"].as_str().unwrap().to_owned());
+    let summary = requests
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value =
+                serde_json::from_str(request["body"].as_str().unwrap()).unwrap();
+            (
+                request["method"].as_str().unwrap().to_owned(),
+                request["path"].as_str().unwrap().to_owned(),
+                body,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (
+                "PATCH".to_owned(),
+                "/zones/example.com/records/1".to_owned(),
+                serde_json::json!({ "host": "", "answer": "192.0.2.6" }),
+            ),
+            (
+                "PUT".to_owned(),
+                "/zones/example.com/records/2".to_owned(),
+                serde_json::json!({ "host": "*", "answer": "192.0.2.6" }),
+            ),
+        ],
+    );
+}
@@ -0,0 +1,43 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+#[test]
+fn fetches_address_over_tls() {
+    let source = TlsAddressServer::start("192.0.2.4");
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let certificate_path = source.certificate_path.to_str().unwrap().to_owned();
+    let _client = Client::start(
+        source.url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[("SSL_CERT_FILE", certificate_path.as_str())],
+    );
+
+    wait_until("the record holds the address", || {
+        registrar
+            .record_content("www")
+            .filter(|content| content == "192.0.2.4")
+    });
+}
+
+#[test]
+fn rejects_untrusted_certificate() {
+    let source = TlsAddressServer::start("192.0.2.4");
+    let registrar = MockServer::start(serde_json::Value::Null, &["--emulator=json"]);
+    let mut client = Client::start(
+        source.url().as_str(),
+        &serde_json::json!([json_registrar_request(&registrar, "www")]),
+        &[],
+        &[],
+    );
+
+    wait_for("the address source is contacted repeatedly", || {
+        source.connections() >= 3
+    });
+
+    assert!(client.is_running());
+    assert!(registrar.requests().is_empty());
+    assert!(!client.status()["last_error"].is_null());
+}
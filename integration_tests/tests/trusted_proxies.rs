@@ -0,0 +1,164 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn request_address(server: &ExternalServer, headers: &[(&str, &str)]) -> serde_json::Value {
+    let response = http_request_with_headers(server.port, "GET", "/", headers, "");
+    assert_eq!(response.status, 200);
+    response.json()["ip"].clone()
+}
+
+fn request_address_with_preamble(server: &ExternalServer, preamble: &[u8]) -> serde_json::Value {
+    let response = http_request_with_preamble(server.port, preamble, "GET", "/", &[], "");
+    assert_eq!(response.status, 200);
+    response.json()["ip"].clone()
+}
+
+/// Makes a PROXY protocol v2 header for a TCP over IPv4 connection.
+fn proxy_v2_header(source: [u8; 4], source_port: u16) -> Vec<u8> {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0, 12]);
+    header.extend_from_slice(&source);
+    header.extend_from_slice(&[127, 0, 0, 1]);
+    header.extend_from_slice(&source_port.to_be_bytes());
+    header.extend_from_slice(&80u16.to_be_bytes());
+    header
+}
+
+#[test]
+fn honors_forwarding_headers_from_trusted_proxies() {
+    let server = ExternalServer::start_with_args(&["--trusted_proxies=127.0.0.1,10.0.0.0/8"]);
+
+    assert_eq!(
+        request_address(&server, &[("X-Forwarded-For", "198.51.100.7, 10.0.0.5")]),
+        "198.51.100.7",
+    );
+    assert_eq!(
+        request_address(
+            &server,
+            &[("X-Forwarded-For", "192.0.2.1, 198.51.100.7, 10.0.0.5")],
+        ),
+        "198.51.100.7",
+    );
+    assert_eq!(request_address(&server, &[]), "127.0.0.1");
+}
+
+#[test]
+fn honors_the_configured_forwarding_header() {
+    let server = ExternalServer::start_with_args(&[
+        "--trusted_proxies=127.0.0.1,10.0.0.0/8",
+        "--forwarded_header=forwarded",
+    ]);
+    assert_eq!(
+        request_address(
+            &server,
+            &[
+                (
+                    "Forwarded",
+                    "for=\"[2001:db8::7]:4711\";proto=https, for=10.0.0.5"
+                ),
+                ("X-Forwarded-For", "198.51.100.7"),
+            ],
+        ),
+        "2001:db8::7",
+    );
+    assert_eq!(
+        request_address(&server, &[("X-Forwarded-For", "198.51.100.7")]),
+        "127.0.0.1",
+    );
+
+    let server = ExternalServer::start_with_args(&[
+        "--trusted_proxies=127.0.0.1",
+        "--forwarded_header=x-real-ip",
+    ]);
+    assert_eq!(
+        request_address(
+            &server,
+            &[
+                ("X-Real-IP", "203.0.113.9"),
+                ("X-Forwarded-For", "198.51.100.7"),
+            ],
+        ),
+        "203.0.113.9",
+    );
+}
+
+#[test]
+fn ignores_forwarded_headers_of_clients_behind_x_forwarded_for_proxies() {
+    let server = ExternalServer::start_with_args(&["--trusted_proxies=127.0.0.1"]);
+
+    // The proxy appended the client address to X-Forwarded-For, and passed the
+    // client's own Forwarded and X-Real-IP headers on unchanged.
+    assert_eq!(
+        request_address(
+            &server,
+            &[
+                ("Forwarded", "for=192.0.2.1"),
+                ("X-Real-IP", "192.0.2.1"),
+                ("X-Forwarded-For", "198.51.100.7"),
+            ],
+        ),
+        "198.51.100.7",
+    );
+}
+
+#[test]
+fn ignores_forwarding_headers_from_untrusted_peers() {
+    let server = ExternalServer::start_with_args(&["--trusted_proxies=192.0.2.0/24"]);
+
+    assert_eq!(
+        request_address(
+            &server,
+            &[
+                ("Forwarded", "for=198.51.100.7"),
+                ("X-Forwarded-For", "198.51.100.7"),
+                ("X-Real-IP", "198.51.100.7"),
+            ],
+        ),
+        "127.0.0.1",
+    );
+}
+
+#[test]
+fn reads_proxy_protocol_headers_from_trusted_proxies() {
+    let server =
+        ExternalServer::start_with_args(&["--trusted_proxies=127.0.0.1", "--proxy_protocol=true"]);
+
+    assert_eq!(
+        request_address_with_preamble(&server, b"PROXY TCP4 198.51.100.7 127.0.0.1 56324 80\r\n",),
+        "198.51.100.7",
+    );
+    assert_eq!(
+        request_address_with_preamble(&server, b"PROXY TCP6 2001:db8::7 ::1 56324 80\r\n",),
+        "2001:db8::7",
+    );
+    assert_eq!(
+        request_address_with_preamble(&server, proxy_v2_header([203, 0, 113, 9], 56324).as_slice()),
+        "203.0.113.9",
+    );
+    // LOCAL connections, such as health checks, are made by the proxy itself.
+    assert_eq!(
+        request_address_with_preamble(&server, b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00"),
+        "127.0.0.1",
+    );
+}
+
+#[test]
+fn serves_untrusted_peers_without_proxy_protocol_headers() {
+    let server = ExternalServer::start_with_args(&[
+        "--trusted_proxies=192.0.2.0/24",
+        "--proxy_protocol=true",
+    ]);
+
+    assert_eq!(request_address(&server, &[]), "127.0.0.1");
+    let response = http_request_with_preamble(
+        server.port,
+        b"PROXY TCP4 198.51.100.7 127.0.0.1 56324 80\r\n",
+        "GET",
+        "/",
+        &[],
+        "",
+    );
+    assert_eq!(response.status, 400);
+}
@@ -0,0 +1,95 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn wait_for_record_error(client: &Client, name: &str) -> String {
+    let record = wait_until("the update fails", || {
+        let record = client.record_status(name);
+        Some(record).filter(|record| !record["last_error"].is_null())
+    });
+    assert!(record["applied_address"].is_null());
+    record["last_error"].as_str().unwrap().to_owned()
+}
+
+#[test]
+fn reports_registrar_error_messages_from_successful_statuses() {
+    let source = MockServer::address_source(&["192.0.2.11"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{
+                "path": "^/records/www$",
+                "responses": [{
+                    "body": "{\"success\":false,\"errors\":[{\"code\":1003,\"message\":\"Invalid zone identifier\"}]}",
+                }],
+            }],
+        }),
+        &[],
+    );
+    let mut request = json_registrar_request(&registrar, "www");
+    request["response"] = serde_json::json!({
+        "status": [200, 201],
+        "json_path": { "$.success": true },
+        "error_message": { "json_path": "$.errors[0].message" },
+    });
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([request]),
+        &[],
+        &[],
+    );
+
+    let error = wait_for_record_error(&client, "www");
+    assert!(error.contains("status: 200"), "{}", error);
+    assert!(
+        error.contains("Some(\"Invalid zone identifier\")"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn rejects_text_responses_not_matching_regex() {
+    let source = MockServer::address_source(&["192.0.2.12"]);
+    let registrar = MockServer::start(serde_json::Value::Null, &["--body=nohost"]);
+    let mut request = json_registrar_request(&registrar, "www");
+    request["response"] = serde_json::json!({ "regex": "^(good|nochg)" });
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([request]),
+        &[],
+        &[],
+    );
+
+    let error = wait_for_record_error(&client, "www");
+    assert!(
+        error.contains("Expected response to match /^(good|nochg)/"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn accepts_configured_statuses() {
+    let source = MockServer::address_source(&["192.0.2.13"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [{ "path": "^/records/www$", "responses": [{ "status": 409 }] }],
+        }),
+        &[],
+    );
+    let mut request = json_registrar_request(&registrar, "www");
+    request["response"] = serde_json::json!({ "status": 409 });
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([request]),
+        &[],
+        &[],
+    );
+
+    let record = wait_until("the update succeeds", || {
+        let record = client.record_status("www");
+        Some(record).filter(|record| record["applied_address"] == "192.0.2.13")
+    });
+    assert!(record["last_error"].is_null());
+}
@@ -0,0 +1,195 @@
+extern crate ddns_integration_tests;
+extern crate serde_json;
+
+use ddns_integration_tests::*;
+
+fn login_then_patch_request(registrar: &MockServer) -> serde_json::Value {
+    serde_json::json!({
+        "name": "www",
+        "method": "PATCH",
+        "address": registrar.url("/zones/1/records/${steps.lookup.body.result.0.id}"),
+        "headers": { "Authorization": "Bearer ${steps.login.body.token}" },
+        "body": { "content": "${ip_address}", "session": "${steps.login.headers.x-session}" },
+        "steps": [
+            {
+                "name": "login",
+                "method": "POST",
+                "address": registrar.url("/login"),
+                "headers": {},
+                "body": { "user": "ddns" },
+            },
+            {
+                "name": "lookup",
+                "address": registrar.url("/zones/1/records?name=www"),
+                "headers": { "Authorization": "Bearer ${steps.login.body.token}" },
+                "response": { "json_path": { "$.success": true } },
+            },
+        ],
+    })
+}
+
+#[test]
+fn captures_values_from_earlier_steps() {
+    let source = MockServer::address_source(&["192.0.2.14"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [
+                {
+                    "method": "POST",
+                    "path": "^/login$",
+                    "responses": [{
+                        "headers": { "X-Session": "s-1" },
+                        "body": "{\"token\":\"t\\\"42\"}",
+                    }],
+                },
+                {
+                    "method": "GET",
+                    "path": "^/zones/1/records$",
+                    "headers": { "authorization": "^Bearer t\"42$" },
+                    "responses": [{ "body": "{\"success\":true,\"result\":[{\"id\":\"r7\"}]}" }],
+                },
+                {
+                    "method": "PATCH",
+                    "path": "^/zones/1/records/r7$",
+                    "headers": { "authorization": "^Bearer t\"42$" },
+                    "responses": [{ "status": 200 }],
+                },
+                { "responses": [{ "status": 403 }] },
+            ],
+        }),
+        &[],
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([login_then_patch_request(&registrar)]),
+        &[],
+        &[],
+    );
+
+    wait_for("the record is updated", || {
+        client.record_status("www")["applied_address"] == "192.0.2.14"
+    });
+
+    let requests = registrar.requests();
+    let paths = requests
+        .iter()
+        .map(|request| request["path"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, ["/login", "/zones/1/records", "/zones/1/records/r7"]);
+    let body: serde_json::Value =
+        serde_json::from_str(requests[2]["body"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({ "content": "192.0.2.14", "session": "s-1" }),
+    );
+}
+
+#[test]
+fn aborts_workflow_when_a_step_fails() {
+    let source = MockServer::address_source(&["192.0.2.15"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [
+                {
+                    "method": "POST",
+                    "path": "^/login$",
+                    "responses": [{ "body": "{\"token\":\"t1\"}" }],
+                },
+                {
+                    "method": "GET",
+                    "path": "^/zones/1/records$",
+                    "responses": [{ "body": "{\"success\":false,\"result\":[]}" }],
+                },
+            ],
+        }),
+        &[],
+    );
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([login_then_patch_request(&registrar)]),
+        &[],
+        &[],
+    );
+
+    let record = wait_until("the update fails", || {
+        let record = client.record_status("www");
+        Some(record).filter(|record| !record["last_error"].is_null())
+    });
+    assert!(record["applied_address"].is_null());
+    let paths = registrar
+        .requests()
+        .iter()
+        .map(|request| request["path"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, ["/login", "/zones/1/records"]);
+}
+
+#[test]
+fn runs_steps_once_for_both_get_and_write_requests() {
+    let source = MockServer::address_source(&["192.0.2.16"]);
+    let registrar = MockServer::start(
+        serde_json::json!({
+            "routes": [
+                {
+                    "method": "POST",
+                    "path": "^/login$",
+                    "responses": [{
+                        "headers": { "X-Session": "s-1" },
+                        "body": "{\"token\":\"t1\"}",
+                    }],
+                },
+                {
+                    "method": "GET",
+                    "path": "^/zones/1/records$",
+                    "responses": [{ "body": "{\"success\":true,\"result\":[{\"id\":\"r7\"}]}" }],
+                },
+                {
+                    "method": "GET",
+                    "path": "^/zones/1/records/r7$",
+                    "headers": { "authorization": "^Bearer t1$" },
+                    "responses": [{ "body": "{\"content\":\"192.0.2.1\"}" }],
+                },
+                {
+                    "method": "PATCH",
+                    "path": "^/zones/1/records/r7$",
+                    "headers": { "authorization": "^Bearer t1$" },
+                    "responses": [{ "status": 200 }],
+                },
+            ],
+        }),
+        &[],
+    );
+    let mut request = login_then_patch_request(&registrar);
+    request["get"] = serde_json::json!({ "json_path": "$.content" });
+    let client = Client::start(
+        source.url("/").as_str(),
+        &serde_json::json!([request]),
+        &[],
+        &[],
+    );
+
+    wait_for("the record is updated", || {
+        client.record_status("www")["applied_address"] == "192.0.2.16"
+    });
+
+    let requests = registrar
+        .requests()
+        .iter()
+        .map(|request| {
+            format!(
+                "{} {}",
+                request["method"].as_str().unwrap(),
+                request["path"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        requests,
+        [
+            "POST /login",
+            "GET /zones/1/records",
+            "GET /zones/1/records/r7",
+            "PATCH /zones/1/records/r7",
+        ],
+    );
+}
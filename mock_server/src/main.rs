@@ -1,10 +1,19 @@
 #[macro_use]
 extern crate derive_new;
 
+extern crate base64;
 extern crate clap;
 extern crate hyper;
 extern crate log;
+extern crate openssl;
 extern crate pretty_env_logger;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate tokio;
+
+use hyper::rt::{Future, Stream};
+use tokio::prelude::future;
 
 #[derive(Debug, new)]
 struct Config {
@@ -13,15 +22,27 @@ struct Config {
     status: String,
     headers: Vec<String>,
     body: String,
+    scenario: String,
+    emulator: String,
+    emulator_token: String,
+    emulator_secret: String,
     socket_address: std::net::SocketAddr,
     response_status: u16,
     response_headers: Vec<(String, String)>,
+    routes: Vec<Route>,
+    emulators: Vec<Emulator>,
 }
 
 enum ConfigError {
     ArgumentError(String),
     SocketAddressParseError(String, String, std::net::AddrParseError),
     StatusCodeParseError(String, std::num::ParseIntError),
+    ScenarioReadError(String, std::io::Error),
+    ScenarioParseError(String, serde_json::Error),
+    ScenarioRegexError(String, regex::Error),
+    ScenarioStatusError(u16),
+    ScenarioHeaderError(String, String),
+    EmulatorParseError(String),
 }
 
 impl std::fmt::Debug for ConfigError {
@@ -42,6 +63,36 @@ impl std::fmt::Debug for ConfigError {
                 "ConfigError(StatusCodeParseError(Failed to parse status code '{}': {:?}))",
                 status, inner_error,
             ),
+            ConfigError::ScenarioReadError(scenario, inner_error) => write!(
+                f,
+                "ConfigError(ScenarioReadError(Failed to read scenario file '{}': {:?}))",
+                scenario, inner_error,
+            ),
+            ConfigError::ScenarioParseError(scenario, inner_error) => write!(
+                f,
+                "ConfigError(ScenarioParseError(Failed to parse scenario file '{}': {:?}))",
+                scenario, inner_error,
+            ),
+            ConfigError::ScenarioRegexError(pattern, inner_error) => write!(
+                f,
+                "ConfigError(ScenarioRegexError(Failed to compile scenario pattern '{}': {:?}))",
+                pattern, inner_error,
+            ),
+            ConfigError::ScenarioStatusError(status) => write!(
+                f,
+                "ConfigError(ScenarioStatusError(Invalid scenario status code '{}'))",
+                status,
+            ),
+            ConfigError::ScenarioHeaderError(name, value) => write!(
+                f,
+                "ConfigError(ScenarioHeaderError(Invalid scenario header '{}: {}'))",
+                name, value,
+            ),
+            ConfigError::EmulatorParseError(emulator) => write!(
+                f,
+                "ConfigError(EmulatorParseError(Unknown emulator '{}'))",
+                emulator,
+            ),
         }
     }
 }
@@ -99,6 +150,42 @@ fn get_args() -> clap::ArgMatches<'static> {
                 .default_value("{\"ip\":\"0.0.0.0\"}")
                 .help("Response body this server should respond with"),
         )
+        .arg(
+            clap::Arg::with_name("scenario")
+                .long("scenario")
+                .env("DDNS_MOCK_SERVER__SCENARIO")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Filepath of scenario this server should respond according to"),
+        )
+        .arg(
+            clap::Arg::with_name("emulator")
+                .long("emulator")
+                .env("DDNS_MOCK_SERVER__EMULATOR")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Comma-separated registrar APIs this server should emulate"),
+        )
+        .arg(
+            clap::Arg::with_name("emulator_token")
+                .long("emulator_token")
+                .env("DDNS_MOCK_SERVER__EMULATOR_TOKEN")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Token emulated registrar APIs should require"),
+        )
+        .arg(
+            clap::Arg::with_name("emulator_secret")
+                .long("emulator_secret")
+                .env("DDNS_MOCK_SERVER__EMULATOR_SECRET")
+                .case_insensitive(true)
+                .takes_value(true)
+                .default_value("")
+                .help("Secret access key emulated AWS APIs should verify signatures with"),
+        )
         .get_matches()
 }
 
@@ -139,6 +226,30 @@ fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
         None => errors.push(ConfigError::ArgumentError("body".to_owned())),
     }
 
+    let mut scenario = String::default();
+    match args.value_of("scenario") {
+        Some(value) => scenario = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("scenario".to_owned())),
+    }
+
+    let mut emulator = String::default();
+    match args.value_of("emulator") {
+        Some(value) => emulator = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("emulator".to_owned())),
+    }
+
+    let mut emulator_token = String::default();
+    match args.value_of("emulator_token") {
+        Some(value) => emulator_token = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("emulator_token".to_owned())),
+    }
+
+    let mut emulator_secret = String::default();
+    match args.value_of("emulator_secret") {
+        Some(value) => emulator_secret = value.to_owned(),
+        None => errors.push(ConfigError::ArgumentError("emulator_secret".to_owned())),
+    }
+
     if !errors.is_empty() {
         return Err(errors);
     }
@@ -162,6 +273,24 @@ fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
         Err(error) => errors.push(ConfigError::StatusCodeParseError(status.clone(), error)),
     }
 
+    let mut routes = vec![];
+    if !scenario.is_empty() {
+        match read_scenario(scenario.as_str()) {
+            Ok(value) => routes = value,
+            Err(error) => errors.push(error),
+        }
+    }
+
+    let mut emulators = vec![];
+    emulator
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .for_each(|name| match name.parse() {
+            Ok(value) => emulators.push(value),
+            Err(error) => errors.push(error),
+        });
+
     if !errors.is_empty() {
         return Err(errors);
     }
@@ -180,39 +309,1257 @@ fn make_config_from_args() -> Result<Config, Vec<ConfigError>> {
         status,
         headers,
         body,
+        scenario,
+        emulator,
+        emulator_token,
+        emulator_secret,
         socket_address,
         response_status,
         response_headers,
+        routes,
+        emulators,
     ))
 }
 
+fn default_scenario_status() -> u16 {
+    200
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ScenarioResponse {
+    #[serde(default = "default_scenario_status")]
+    status: u16,
+    #[serde(default)]
+    headers: std::collections::BTreeMap<String, String>,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    delay_ms: u64,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ScenarioRepeat {
+    #[default]
+    Last,
+    Cycle,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ScenarioRoute {
+    name: Option<String>,
+    method: Option<String>,
+    path: Option<String>,
+    #[serde(default)]
+    headers: std::collections::BTreeMap<String, String>,
+    body: Option<String>,
+    responses: Vec<ScenarioResponse>,
+    #[serde(default)]
+    repeat: ScenarioRepeat,
+    times: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Scenario {
+    routes: Vec<ScenarioRoute>,
+}
+
+#[derive(Debug)]
+struct Route {
+    name: String,
+    scenario_route: ScenarioRoute,
+    path: Option<regex::Regex>,
+    headers: Vec<(String, regex::Regex)>,
+    body: Option<regex::Regex>,
+}
+
+fn make_regex(pattern: &str) -> Result<regex::Regex, ConfigError> {
+    regex::Regex::new(pattern)
+        .map_err(|error| ConfigError::ScenarioRegexError(pattern.to_owned(), error))
+}
+
+/// Checks that a scenario response can be sent. Header values are checked as
+/// templates, before their placeholders are rendered.
+fn check_scenario_response(scenario_response: &ScenarioResponse) -> Result<(), ConfigError> {
+    hyper::StatusCode::from_u16(scenario_response.status)
+        .map_err(|_| ConfigError::ScenarioStatusError(scenario_response.status))?;
+    scenario_response
+        .headers
+        .iter()
+        .try_for_each(|(name, value)| {
+            match (
+                hyper::header::HeaderName::from_bytes(name.as_bytes()),
+                hyper::header::HeaderValue::from_str(value),
+            ) {
+                (Ok(_), Ok(_)) => Ok(()),
+                _ => Err(ConfigError::ScenarioHeaderError(
+                    name.to_owned(),
+                    value.to_owned(),
+                )),
+            }
+        })
+}
+
+fn make_route(index: usize, scenario_route: ScenarioRoute) -> Result<Route, ConfigError> {
+    scenario_route
+        .responses
+        .iter()
+        .try_for_each(check_scenario_response)?;
+    let path = match scenario_route.path.as_ref() {
+        Some(pattern) => Some(make_regex(pattern)?),
+        None => None,
+    };
+    let headers = scenario_route
+        .headers
+        .iter()
+        .map(|(key, pattern)| make_regex(pattern).map(|regex| (key.to_lowercase(), regex)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let body = match scenario_route.body.as_ref() {
+        Some(pattern) => Some(make_regex(pattern)?),
+        None => None,
+    };
+
+    Ok(Route {
+        name: scenario_route
+            .name
+            .clone()
+            .unwrap_or_else(|| index.to_string()),
+        scenario_route,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn read_scenario(scenario: &str) -> Result<Vec<Route>, ConfigError> {
+    log::trace!("fn read_scenario(scenario={:?})", scenario);
+
+    let scenario_str = std::fs::read_to_string(scenario)
+        .map_err(|error| ConfigError::ScenarioReadError(scenario.to_owned(), error))?;
+    let scenario_json = serde_json::from_str::<Scenario>(scenario_str.as_str())
+        .map_err(|error| ConfigError::ScenarioParseError(scenario.to_owned(), error))?;
+
+    scenario_json
+        .routes
+        .into_iter()
+        .enumerate()
+        .map(|(index, scenario_route)| make_route(index, scenario_route))
+        .collect()
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    query: String,
+    headers: std::collections::BTreeMap<String, String>,
+    body: String,
+    remote_address: String,
+    route: Option<String>,
+}
+
+impl RecordedRequest {
+    fn new(
+        parts: &hyper::http::request::Parts,
+        body: String,
+        remote_addr: &std::net::SocketAddr,
+    ) -> Self {
+        let mut headers = std::collections::BTreeMap::<String, String>::new();
+        parts.headers.iter().for_each(|(key, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+            headers
+                .entry(key.as_str().to_owned())
+                .and_modify(|existing| {
+                    existing.push_str(", ");
+                    existing.push_str(value.as_str());
+                })
+                .or_insert(value);
+        });
+
+        RecordedRequest {
+            method: parts.method.as_str().to_owned(),
+            path: parts.uri.path().to_owned(),
+            query: parts.uri.query().unwrap_or_default().to_owned(),
+            headers,
+            body,
+            remote_address: remote_addr.ip().to_string(),
+            route: None,
+        }
+    }
+
+    /// Returns the percent-decoded value of the first query parameter with the
+    /// given name.
+    fn query_parameter(&self, name: &str) -> Option<String> {
+        self.query
+            .split('&')
+            .filter_map(|pair| {
+                let mut split = pair.splitn(2, '=');
+                match (split.next().map(percent_decode), split.next()) {
+                    (Some(key), value) if key == name => {
+                        Some(percent_decode(value.unwrap_or_default()))
+                    }
+                    _ => None,
+                }
+            })
+            .next()
+    }
+
+    fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "method" => Some(self.method.to_owned()),
+            "path" => Some(self.path.to_owned()),
+            "query" => Some(self.query.to_owned()),
+            "body" => Some(self.body.to_owned()),
+            "remote_address" => Some(self.remote_address.to_owned()),
+            _ if name.starts_with("header.") => self
+                .headers
+                .get(name["header.".len()..].to_lowercase().as_str())
+                .cloned(),
+            _ if name.starts_with("query.") => self.query_parameter(&name["query.".len()..]),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, route: &Route) -> bool {
+        route
+            .scenario_route
+            .method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(self.method.as_str()))
+            && route
+                .path
+                .as_ref()
+                .is_none_or(|path| path.is_match(self.path.as_str()))
+            && route.headers.iter().all(|(key, pattern)| {
+                self.headers
+                    .get(key)
+                    .is_some_and(|value| pattern.is_match(value))
+            })
+            && route
+                .body
+                .as_ref()
+                .is_none_or(|body| body.is_match(self.body.as_str()))
+    }
+}
+
+/// Decodes `%XX` escapes and `+` (as a space) in a query string component.
+/// Malformed escapes are left as they are.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(decoded.as_slice()).to_string()
+}
+
+/// Replaces `${name}` placeholders in the template with fields of the request.
+/// Placeholders that don't name a known field are left untouched.
+fn render_template(template: &str, request: &RecordedRequest) -> String {
+    let mut output = String::new();
+    let mut remaining = template;
+    while let Some(start) = remaining.find("${") {
+        output.push_str(&remaining[..start]);
+        match remaining[start..].find('}') {
+            Some(length) => {
+                let placeholder = &remaining[start..start + length + 1];
+                match request.variable(&placeholder[2..placeholder.len() - 1]) {
+                    Some(value) => output.push_str(value.as_str()),
+                    None => output.push_str(placeholder),
+                }
+                remaining = &remaining[start + length + 1..];
+            }
+            None => {
+                output.push_str(&remaining[start..]);
+                remaining = "";
+            }
+        }
+    }
+    output.push_str(remaining);
+    output
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    requests: Vec<RecordedRequest>,
+    route_hits: std::collections::HashMap<String, usize>,
+    zone: Zone,
+}
+
+type SharedState = std::sync::Arc<std::sync::Mutex<MockState>>;
+
+#[derive(Debug, new)]
+struct DefaultResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+type ResponseFuture =
+    Box<dyn Future<Item = hyper::Response<hyper::Body>, Error = hyper::Error> + Send>;
+
+fn make_response(
+    status: u16,
+    headers: &[(String, String)],
+    body: String,
+) -> hyper::Response<hyper::Body> {
+    let mut builder = hyper::Response::builder();
+    builder.status(status);
+    headers.iter().for_each(|(key, value)| {
+        builder.header(key.as_str(), value.as_str());
+    });
+    // Scenario headers are checked when the scenario is read, but rendering
+    // their placeholders can still make them invalid.
+    builder
+        .body(hyper::Body::from(body))
+        .unwrap_or_else(|error| {
+            log::error!("Invalid response: {:?}", error);
+            hyper::Response::builder()
+                .status(500)
+                .body(hyper::Body::from(format!("Invalid response: {}", error)))
+                .unwrap()
+        })
+}
+
+fn make_json_response(status: u16, value: &impl serde::Serialize) -> hyper::Response<hyper::Body> {
+    make_response(
+        status,
+        &[("Content-Type".to_owned(), "application/json".to_owned())],
+        serde_json::to_string(value).unwrap(),
+    )
+}
+
+fn respond_admin(
+    request: &RecordedRequest,
+    routes: &[Route],
+    state: &SharedState,
+) -> Option<hyper::Response<hyper::Body>> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/__requests") => Some(make_json_response(200, &state.lock().unwrap().requests)),
+        ("DELETE", "/__requests") => {
+            let mut state = state.lock().unwrap();
+            state.requests.clear();
+            state.route_hits.clear();
+            Some(make_response(204, &[], String::new()))
+        }
+        ("GET", "/__expectations") => {
+            let state = state.lock().unwrap();
+            let expectations = routes
+                .iter()
+                .map(|route| {
+                    let hits = state
+                        .route_hits
+                        .get(&route.name)
+                        .cloned()
+                        .unwrap_or_default();
+                    serde_json::json!({
+                        "route": route.name,
+                        "hits": hits,
+                        "times": route.scenario_route.times,
+                        "satisfied": route.scenario_route.times.is_none_or(|times| times == hits),
+                    })
+                })
+                .collect::<Vec<_>>();
+            Some(make_json_response(200, &expectations))
+        }
+        ("GET", "/__zone") => Some(make_json_response(200, &state.lock().unwrap().zone.records)),
+        ("POST", "/__zone") => match serde_json::from_str::<ZoneRecordRequest>(&request.body) {
+            Ok(record) => match record.content {
+                Some(content) => {
+                    let (record, _) = state.lock().unwrap().zone.upsert(
+                        record.name.as_str(),
+                        record.record_type.as_deref(),
+                        content.as_str(),
+                        record.ttl,
+                    );
+                    Some(make_json_response(200, &record))
+                }
+                None => Some(make_json_error_response(400, "Missing record content")),
+            },
+            Err(error) => Some(make_json_error_response(400, &error.to_string())),
+        },
+        ("DELETE", "/__zone") => {
+            state.lock().unwrap().zone.records.clear();
+            Some(make_response(204, &[], String::new()))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Emulator {
+    Cloudflare,
+    Dyndns2,
+    Json,
+    Route53,
+}
+
+impl Emulator {
+    fn name(&self) -> &'static str {
+        match self {
+            Emulator::Cloudflare => "cloudflare",
+            Emulator::Dyndns2 => "dyndns2",
+            Emulator::Json => "json",
+            Emulator::Route53 => "route53",
+        }
+    }
+}
+
+impl std::str::FromStr for Emulator {
+    type Err = ConfigError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "cloudflare" => Ok(Emulator::Cloudflare),
+            "dyndns2" => Ok(Emulator::Dyndns2),
+            "json" => Ok(Emulator::Json),
+            "route53" => Ok(Emulator::Route53),
+            _ => Err(ConfigError::EmulatorParseError(value.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct ZoneRecord {
+    id: String,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    content: String,
+    ttl: u32,
+    proxied: bool,
+    modified_on: u64,
+}
+
+/// Fields of a record create or update request. Emulated APIs disagree on what
+/// to call the record's address, so all of the common names are accepted.
+#[derive(Debug, serde::Deserialize)]
+struct ZoneRecordRequest {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    record_type: Option<String>,
+    #[serde(
+        alias = "answer",
+        alias = "address",
+        alias = "ip",
+        alias = "ip_address"
+    )]
+    content: Option<String>,
+    ttl: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct Zone {
+    records: Vec<ZoneRecord>,
+    next_id: u64,
+    /// IDs of submitted Route 53 changes.
+    changes: Vec<String>,
+}
+
+fn get_record_type(content: &str) -> &'static str {
+    match content.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V6(_)) => "AAAA",
+        _ => "A",
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl Zone {
+    fn get(&self, id: &str) -> Option<&ZoneRecord> {
+        self.records.iter().find(|record| record.id == id)
+    }
+
+    fn find(&self, name: Option<&str>, record_type: Option<&str>) -> Vec<ZoneRecord> {
+        self.records
+            .iter()
+            .filter(|record| name.is_none_or(|name| record.name.eq_ignore_ascii_case(name)))
+            .filter(|record| {
+                record_type
+                    .is_none_or(|record_type| record.record_type.eq_ignore_ascii_case(record_type))
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn create(&mut self, name: &str, record_type: &str, content: &str, ttl: u32) -> ZoneRecord {
+        self.next_id += 1;
+        let record = ZoneRecord {
+            id: format!("{:032x}", self.next_id),
+            name: name.to_owned(),
+            record_type: record_type.to_uppercase(),
+            content: content.to_owned(),
+            ttl,
+            proxied: false,
+            modified_on: unix_timestamp(),
+        };
+        self.records.push(record.clone());
+        record
+    }
+
+    fn update(&mut self, id: &str, update: &ZoneRecordRequest) -> Option<ZoneRecord> {
+        let record = self.records.iter_mut().find(|record| record.id == id)?;
+        if !update.name.is_empty() {
+            record.name = update.name.to_owned();
+        }
+        if let Some(record_type) = update.record_type.as_ref() {
+            record.record_type = record_type.to_uppercase();
+        }
+        if let Some(content) = update.content.as_ref() {
+            record.content = content.to_owned();
+        }
+        if let Some(ttl) = update.ttl {
+            record.ttl = ttl;
+        }
+        record.modified_on = unix_timestamp();
+        Some(record.clone())
+    }
+
+    /// Sets the content of the record with the given name and type, creating it
+    /// if necessary. Returns the record and whether it changed.
+    fn upsert(
+        &mut self,
+        name: &str,
+        record_type: Option<&str>,
+        content: &str,
+        ttl: Option<u32>,
+    ) -> (ZoneRecord, bool) {
+        let record_type = record_type.unwrap_or_else(|| get_record_type(content));
+        let existing = self.find(Some(name), Some(record_type)).into_iter().next();
+        match existing {
+            Some(record)
+                if record.content == content && ttl.is_none_or(|ttl| ttl == record.ttl) =>
+            {
+                (record, false)
+            }
+            Some(record) => {
+                let update = ZoneRecordRequest {
+                    name: String::default(),
+                    record_type: None,
+                    content: Some(content.to_owned()),
+                    ttl,
+                };
+                (self.update(record.id.as_str(), &update).unwrap(), true)
+            }
+            None => (
+                self.create(name, record_type, content, ttl.unwrap_or(300)),
+                true,
+            ),
+        }
+    }
+
+    fn delete(&mut self, id: &str) -> Option<ZoneRecord> {
+        let index = self.records.iter().position(|record| record.id == id)?;
+        Some(self.records.remove(index))
+    }
+}
+
+fn make_json_error_response(status: u16, message: &str) -> hyper::Response<hyper::Body> {
+    make_json_response(status, &serde_json::json!({ "error": message }))
+}
+
+fn get_bearer_token(request: &RecordedRequest) -> Option<&str> {
+    request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn get_basic_password(request: &RecordedRequest) -> Option<String> {
+    let encoded = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Basic "))?;
+    let decoded = base64::decode(encoded).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    credentials
+        .split_once(':')
+        .map(|(_, password)| password.to_owned())
+}
+
+fn make_cloudflare_response(
+    status: u16,
+    result: serde_json::Value,
+) -> hyper::Response<hyper::Body> {
+    make_json_response(
+        status,
+        &serde_json::json!({
+            "success": true,
+            "errors": [],
+            "messages": [],
+            "result": result,
+        }),
+    )
+}
+
+fn make_cloudflare_error_response(
+    status: u16,
+    code: u32,
+    message: &str,
+) -> hyper::Response<hyper::Body> {
+    make_json_response(
+        status,
+        &serde_json::json!({
+            "success": false,
+            "errors": [{ "code": code, "message": message }],
+            "messages": [],
+            "result": null,
+        }),
+    )
+}
+
+/// Emulates the DNS records endpoints of the Cloudflare v4 API:
+/// `/client/v4/zones/{zone_id}/dns_records[/{record_id}]`.
+fn respond_cloudflare(
+    request: &RecordedRequest,
+    token: &str,
+    zone: &mut Zone,
+) -> Option<hyper::Response<hyper::Body>> {
+    let segments = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+    let record_id = match segments.as_slice() {
+        ["client", "v4", "zones", _, "dns_records"] => None,
+        ["client", "v4", "zones", _, "dns_records", record_id] => Some(*record_id),
+        _ => return None,
+    };
+
+    if !token.is_empty() && get_bearer_token(request) != Some(token) {
+        return Some(make_cloudflare_error_response(
+            403,
+            10000,
+            "Authentication error",
+        ));
+    }
+
+    let record_request = || serde_json::from_str::<ZoneRecordRequest>(&request.body);
+    let to_value = |record: &ZoneRecord| serde_json::to_value(record).unwrap();
+    let not_found = || make_cloudflare_error_response(404, 81044, "Record does not exist.");
+
+    let response = match (request.method.as_str(), record_id) {
+        ("GET", None) => {
+            let records = zone.find(
+                request.query_parameter("name").as_deref(),
+                request.query_parameter("type").as_deref(),
+            );
+            make_cloudflare_response(200, serde_json::to_value(records).unwrap())
+        }
+        ("POST", None) => match record_request() {
+            Ok(ZoneRecordRequest {
+                name,
+                record_type: Some(record_type),
+                content: Some(content),
+                ttl,
+            }) if !name.is_empty() => {
+                let exists = zone
+                    .find(Some(name.as_str()), Some(record_type.as_str()))
+                    .iter()
+                    .any(|record| record.content == content);
+                if exists {
+                    make_cloudflare_error_response(400, 81057, "Record already exists.")
+                } else {
+                    let record = zone.create(&name, &record_type, &content, ttl.unwrap_or(1));
+                    make_cloudflare_response(200, to_value(&record))
+                }
+            }
+            Ok(_) => make_cloudflare_error_response(400, 9000, "Missing name, type or content"),
+            Err(error) => make_cloudflare_error_response(400, 9207, &error.to_string()),
+        },
+        ("GET", Some(record_id)) => match zone.get(record_id) {
+            Some(record) => make_cloudflare_response(200, to_value(record)),
+            None => not_found(),
+        },
+        ("PUT", Some(record_id)) | ("PATCH", Some(record_id)) => match record_request() {
+            Ok(update) => match zone.update(record_id, &update) {
+                Some(record) => make_cloudflare_response(200, to_value(&record)),
+                None => not_found(),
+            },
+            Err(error) => make_cloudflare_error_response(400, 9207, &error.to_string()),
+        },
+        ("DELETE", Some(record_id)) => match zone.delete(record_id) {
+            Some(record) => make_cloudflare_response(200, serde_json::json!({ "id": record.id })),
+            None => not_found(),
+        },
+        _ => make_cloudflare_error_response(405, 10000, "Method not allowed"),
+    };
+    Some(response)
+}
+
+/// Emulates the dyndns2 update protocol: `/nic/update?hostname=...&myip=...`.
+/// Responds with one return code per hostname, as plain text.
+fn respond_dyndns2(
+    request: &RecordedRequest,
+    token: &str,
+    zone: &mut Zone,
+) -> Option<hyper::Response<hyper::Body>> {
+    if request.path != "/nic/update" {
+        return None;
+    }
+
+    let text = |status: u16, body: String| {
+        make_response(
+            status,
+            &[("Content-Type".to_owned(), "text/plain".to_owned())],
+            body,
+        )
+    };
+
+    if !token.is_empty() && get_basic_password(request).as_deref() != Some(token) {
+        return Some(text(401, "badauth".to_owned()));
+    }
+
+    let hostnames = request.query_parameter("hostname").unwrap_or_default();
+    let hostnames = hostnames
+        .split(',')
+        .map(|hostname| hostname.trim())
+        .filter(|hostname| !hostname.is_empty())
+        .collect::<Vec<_>>();
+    if hostnames.is_empty() {
+        return Some(text(200, "notfqdn".to_owned()));
+    }
+
+    let address = request
+        .query_parameter("myip")
+        .unwrap_or_else(|| request.remote_address.to_owned());
+    if address.parse::<std::net::IpAddr>().is_err() {
+        return Some(text(200, "dnserr".to_owned()));
+    }
+
+    let lines = hostnames
+        .iter()
+        .map(|hostname| {
+            let (_, changed) = zone.upsert(hostname, None, address.as_str(), None);
+            let code = if changed { "good" } else { "nochg" };
+            format!("{} {}", code, address)
+        })
+        .collect::<Vec<_>>();
+    Some(text(200, lines.join("\n")))
+}
+
+/// Emulates a generic JSON API addressing records by name: `/records/{name}`.
+fn respond_json(
+    request: &RecordedRequest,
+    token: &str,
+    zone: &mut Zone,
+) -> Option<hyper::Response<hyper::Body>> {
+    let name = request.path.strip_prefix("/records/")?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+
+    if !token.is_empty() && get_bearer_token(request) != Some(token) {
+        return Some(make_json_error_response(401, "Unauthorized"));
+    }
+
+    let response = match request.method.as_str() {
+        "GET" => match zone.find(Some(name), request.query_parameter("type").as_deref()) {
+            records if records.is_empty() => make_json_error_response(404, "Record not found"),
+            records => make_json_response(200, &records),
+        },
+        "PUT" => match serde_json::from_str::<ZoneRecordRequest>(&request.body) {
+            Ok(ZoneRecordRequest {
+                record_type,
+                content: Some(content),
+                ttl,
+                ..
+            }) if content.parse::<std::net::IpAddr>().is_ok() => {
+                let (record, _) = zone.upsert(name, record_type.as_deref(), &content, ttl);
+                make_json_response(200, &record)
+            }
+            Ok(_) => make_json_error_response(400, "Missing or invalid record address"),
+            Err(error) => make_json_error_response(400, &error.to_string()),
+        },
+        "DELETE" => {
+            let records = zone.find(Some(name), request.query_parameter("type").as_deref());
+            if records.is_empty() {
+                make_json_error_response(404, "Record not found")
+            } else {
+                records.iter().for_each(|record| {
+                    zone.delete(record.id.as_str());
+                });
+                make_response(204, &[], String::new())
+            }
+        }
+        _ => make_json_error_response(405, "Method not allowed"),
+    };
+    Some(response)
+}
+
+const ROUTE53_NAMESPACE: &str = "https://route53.amazonaws.com/doc/2013-04-01/";
+
+fn make_xml_response(status: u16, body: String) -> hyper::Response<hyper::Body> {
+    make_response(
+        status,
+        &[("Content-Type".to_owned(), "text/xml".to_owned())],
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", body),
+    )
+}
+
+fn make_route53_error_response(
+    status: u16,
+    code: &str,
+    message: &str,
+) -> hyper::Response<hyper::Body> {
+    make_xml_response(
+        status,
+        format!(
+            "<ErrorResponse xmlns=\"{}\"><Error><Type>Sender</Type><Code>{}</Code>\
+             <Message>{}</Message></Error></ErrorResponse>",
+            ROUTE53_NAMESPACE, code, message,
+        ),
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let key = openssl::pkey::PKey::hmac(key).ok()?;
+    let mut signer =
+        openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key).ok()?;
+    signer.update(data).ok()?;
+    signer.sign_to_vec().ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Recomputes the AWS Signature Version 4 signature of a request from the
+/// credential scope and signed headers of its `Authorization` header, given
+/// without its `AWS4-HMAC-SHA256` prefix, and compares it to the signature the
+/// request carries.
+fn is_valid_sigv4_signature(request: &RecordedRequest, authorization: &str, secret: &str) -> bool {
+    let mut fields = std::collections::HashMap::new();
+    for field in authorization.split(',') {
+        if let Some((name, value)) = field.trim().split_once('=') {
+            fields.insert(name, value);
+        }
+    }
+    let (credential, signed_headers, signature) = match (
+        fields.get("Credential"),
+        fields.get("SignedHeaders"),
+        fields.get("Signature"),
+    ) {
+        (Some(credential), Some(signed_headers), Some(signature)) => {
+            (*credential, *signed_headers, *signature)
+        }
+        _ => return false,
+    };
+    let scope = match credential.split_once('/') {
+        Some((_, scope)) => scope,
+        None => return false,
+    };
+    let scope_parts = scope.split('/').collect::<Vec<_>>();
+    let timestamp = match request.headers.get("x-amz-date") {
+        Some(timestamp) if scope_parts.len() == 4 => timestamp,
+        _ => return false,
+    };
+
+    let canonical_uri = match request.path.as_str() {
+        "" => "/".to_owned(),
+        path => path
+            .split('/')
+            .map(percent_encode)
+            .collect::<Vec<_>>()
+            .join("/"),
+    };
+    let mut query = request
+        .query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                percent_encode(percent_decode(key).as_str()),
+                percent_encode(percent_decode(value).as_str()),
+            )
+        })
+        .collect::<Vec<_>>();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    let canonical_headers: String = signed_headers
+        .split(';')
+        .map(|name| {
+            let value = request.headers.get(name).map(String::as_str).unwrap_or("");
+            format!(
+                "{}:{}\n",
+                name,
+                value.split_whitespace().collect::<Vec<_>>().join(" "),
+            )
+        })
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method,
+        canonical_uri,
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        to_hex(&openssl::sha::sha256(request.body.as_bytes())),
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        to_hex(&openssl::sha::sha256(canonical_request.as_bytes())),
+    );
+
+    let mut key = Some(format!("AWS4{}", secret).into_bytes());
+    for part in scope_parts {
+        key = key.and_then(|key| hmac_sha256(key.as_slice(), part.as_bytes()));
+    }
+    match key.and_then(|key| hmac_sha256(key.as_slice(), string_to_sign.as_bytes())) {
+        Some(expected) => {
+            let expected = to_hex(expected.as_slice());
+            expected.len() == signature.len()
+                && openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes())
+        }
+        None => false,
+    }
+}
+
+fn make_route53_change_response(
+    element: &str,
+    id: &str,
+    status: &str,
+) -> hyper::Response<hyper::Body> {
+    make_xml_response(
+        200,
+        format!(
+            "<{element} xmlns=\"{}\"><ChangeInfo><Id>/change/{}</Id><Status>{}</Status>\
+             <SubmittedAt>{}</SubmittedAt></ChangeInfo></{element}>",
+            ROUTE53_NAMESPACE,
+            id,
+            status,
+            unix_timestamp(),
+            element = element,
+        ),
+    )
+}
+
+/// Returns the text of the first element with the given name in an XML
+/// fragment.
+fn get_xml_element(xml: &str, name: &str) -> Option<String> {
+    let start_tag = format!("<{}>", name);
+    let end_tag = format!("</{}>", name);
+    let start = xml.find(start_tag.as_str())? + start_tag.len();
+    let end = start + xml[start..].find(end_tag.as_str())?;
+    Some(xml[start..end].trim().to_owned())
+}
+
+/// Applies a single change of a `ChangeResourceRecordSets` request, returning
+/// an error message if the change is invalid.
+fn apply_route53_change(change: &str, zone: &mut Zone) -> Result<(), String> {
+    let action = get_xml_element(change, "Action").unwrap_or_default();
+    let name = get_xml_element(change, "Name").ok_or("Missing Name")?;
+    let name = name.trim_end_matches('.');
+    let record_type = get_xml_element(change, "Type").ok_or("Missing Type")?;
+    let ttl = get_xml_element(change, "TTL").and_then(|ttl| ttl.parse().ok());
+    let value = get_xml_element(change, "Value").ok_or("Missing Value")?;
+    let existing = zone.find(Some(name), Some(record_type.as_str()));
+    match action.as_str() {
+        "CREATE" if existing.is_empty() => {
+            zone.create(name, &record_type, &value, ttl.unwrap_or(300));
+            Ok(())
+        }
+        "CREATE" => Err(format!(
+            "Tried to create resource record set [name='{}.', type='{}'] but it already exists",
+            name, record_type,
+        )),
+        "UPSERT" => {
+            zone.upsert(name, Some(record_type.as_str()), &value, ttl);
+            Ok(())
+        }
+        "DELETE" => match existing.into_iter().find(|record| record.content == value) {
+            Some(record) => {
+                zone.delete(record.id.as_str());
+                Ok(())
+            }
+            None => Err(format!(
+                "Tried to delete resource record set [name='{}.', type='{}'] but it was not found",
+                name, record_type,
+            )),
+        },
+        _ => Err(format!("Invalid Action '{}'", action)),
+    }
+}
+
+/// Emulates the resource record set endpoints of the AWS Route 53 API:
+/// `/2013-04-01/hostedzone/{zone_id}/rrset` and `/2013-04-01/change/{id}`.
+/// Changes are reported as `PENDING` when submitted, and as `INSYNC`
+/// afterwards.
+fn respond_route53(
+    request: &RecordedRequest,
+    token: &str,
+    secret: &str,
+    zone: &mut Zone,
+) -> Option<hyper::Response<hyper::Body>> {
+    let segments = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["2013-04-01", "hostedzone", _, "rrset"] | ["2013-04-01", "change", _] => {}
+        _ => return None,
+    }
+
+    let authorization = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("AWS4-HMAC-SHA256 "));
+    let credential = authorization
+        .and_then(|value| value.strip_prefix("Credential="))
+        .and_then(|value| value.split('/').next());
+    if credential.is_none() || (!token.is_empty() && credential != Some(token)) {
+        return Some(make_route53_error_response(
+            403,
+            "InvalidClientTokenId",
+            "The security token included in the request is invalid.",
+        ));
+    }
+    if !secret.is_empty()
+        && !is_valid_sigv4_signature(request, authorization.unwrap_or_default(), secret)
+    {
+        return Some(make_route53_error_response(
+            403,
+            "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided.",
+        ));
+    }
+
+    let response = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["2013-04-01", "hostedzone", _, "rrset"]) => {
+            let name = request.query_parameter("name");
+            let name = name.as_deref().map(|name| name.trim_end_matches('.'));
+            let record_sets: String = zone
+                .find(name, request.query_parameter("type").as_deref())
+                .iter()
+                .map(|record| {
+                    format!(
+                        "<ResourceRecordSet><Name>{}.</Name><Type>{}</Type><TTL>{}</TTL>\
+                         <ResourceRecords><ResourceRecord><Value>{}</Value></ResourceRecord>\
+                         </ResourceRecords></ResourceRecordSet>",
+                        record.name, record.record_type, record.ttl, record.content,
+                    )
+                })
+                .collect();
+            make_xml_response(
+                200,
+                format!(
+                    "<ListResourceRecordSetsResponse xmlns=\"{}\"><ResourceRecordSets>{}\
+                     </ResourceRecordSets><IsTruncated>false</IsTruncated>\
+                     <MaxItems>100</MaxItems></ListResourceRecordSetsResponse>",
+                    ROUTE53_NAMESPACE, record_sets,
+                ),
+            )
+        }
+        ("POST", ["2013-04-01", "hostedzone", _, "rrset"]) => {
+            let result = request
+                .body
+                .split("<Change>")
+                .skip(1)
+                .try_for_each(|change| apply_route53_change(change, zone));
+            match result {
+                Ok(()) => {
+                    zone.next_id += 1;
+                    let id = format!("C{:012X}", zone.next_id);
+                    zone.changes.push(id.clone());
+                    make_route53_change_response(
+                        "ChangeResourceRecordSetsResponse",
+                        id.as_str(),
+                        "PENDING",
+                    )
+                }
+                Err(message) => make_route53_error_response(400, "InvalidChangeBatch", &message),
+            }
+        }
+        ("GET", ["2013-04-01", "change", id]) => {
+            if zone.changes.iter().any(|change| change == id) {
+                make_route53_change_response("GetChangeResponse", id, "INSYNC")
+            } else {
+                make_route53_error_response(
+                    404,
+                    "NoSuchChange",
+                    &format!(
+                        "A change with the specified change ID does not exist: {}",
+                        id
+                    ),
+                )
+            }
+        }
+        _ => make_route53_error_response(405, "InvalidInput", "Method not allowed"),
+    };
+    Some(response)
+}
+
+fn respond_emulator(
+    emulator: Emulator,
+    request: &RecordedRequest,
+    token: &str,
+    secret: &str,
+    zone: &mut Zone,
+) -> Option<hyper::Response<hyper::Body>> {
+    match emulator {
+        Emulator::Cloudflare => respond_cloudflare(request, token, zone),
+        Emulator::Dyndns2 => respond_dyndns2(request, token, zone),
+        Emulator::Json => respond_json(request, token, zone),
+        Emulator::Route53 => respond_route53(request, token, secret, zone),
+    }
+}
+
+#[derive(Debug, new)]
+struct ServerContext {
+    routes: Vec<Route>,
+    default_response: DefaultResponse,
+    emulators: Vec<Emulator>,
+    emulator_token: String,
+    emulator_secret: String,
+    state: SharedState,
+}
+
 fn respond(
     request: hyper::Request<hyper::Body>,
-    response_status: &u16,
-    response_headers: &[(String, String)],
-    response_body: &str,
-) -> hyper::Response<hyper::Body> {
+    remote_addr: std::net::SocketAddr,
+    context: std::sync::Arc<ServerContext>,
+) -> ResponseFuture {
     log::trace!(
-        "fn respond(request={:?}, response_status={:?}, response_headers={:?}, response_body={:?})",
+        "fn respond(request={:?}, remote_addr={:?})",
         &request,
-        response_status,
-        response_headers,
-        response_body,
+        &remote_addr,
     );
 
     log::debug!("{:?}", request);
 
-    let mut builder = hyper::Response::builder();
-    builder.status(response_status.to_owned());
-    response_headers.iter().for_each(|(key, value)| {
-        builder.header(key, value);
-    });
-    let response = builder
-        .body(hyper::Body::from(response_body.to_owned()))
-        .unwrap();
+    let (parts, body) = request.into_parts();
+    Box::new(body.concat2().and_then(move |body| {
+        let mut recorded_request = RecordedRequest::new(
+            &parts,
+            String::from_utf8_lossy(body.as_ref()).to_string(),
+            &remote_addr,
+        );
+        log::debug!("{:?}", recorded_request);
 
-    log::debug!("{:?}", response);
-    response
+        let state = &context.state;
+        if let Some(response) = respond_admin(&recorded_request, context.routes.as_slice(), state) {
+            log::debug!("{:?}", response);
+            return future::Either::A(future::ok(response));
+        }
+
+        let route = context
+            .routes
+            .iter()
+            .find(|route| recorded_request.matches(route));
+        let (response, delay) = match route {
+            Some(route) => {
+                let mut state = state.lock().unwrap();
+                let hits = state.route_hits.entry(route.name.to_owned()).or_default();
+                let responses = &route.scenario_route.responses;
+                let index = match route.scenario_route.repeat {
+                    ScenarioRepeat::Last => (*hits).min(responses.len().saturating_sub(1)),
+                    ScenarioRepeat::Cycle => *hits % responses.len().max(1),
+                };
+                *hits += 1;
+                recorded_request.route.replace(route.name.to_owned());
+                state.requests.push(recorded_request.clone());
+
+                match responses.get(index) {
+                    Some(scenario_response) => {
+                        let headers = scenario_response
+                            .headers
+                            .iter()
+                            .map(|(key, value)| {
+                                (key.to_owned(), render_template(value, &recorded_request))
+                            })
+                            .collect::<Vec<_>>();
+                        (
+                            make_response(
+                                scenario_response.status,
+                                headers.as_slice(),
+                                render_template(scenario_response.body.as_str(), &recorded_request),
+                            ),
+                            std::time::Duration::from_millis(scenario_response.delay_ms),
+                        )
+                    }
+                    None => (
+                        make_response(204, &[], String::new()),
+                        std::time::Duration::default(),
+                    ),
+                }
+            }
+            None => {
+                let mut state = state.lock().unwrap();
+                let emulated_response = context.emulators.iter().find_map(|emulator| {
+                    respond_emulator(
+                        *emulator,
+                        &recorded_request,
+                        context.emulator_token.as_str(),
+                        context.emulator_secret.as_str(),
+                        &mut state.zone,
+                    )
+                    .map(|response| (emulator.name(), response))
+                });
+                let response = match emulated_response {
+                    Some((name, response)) => {
+                        recorded_request.route.replace(name.to_owned());
+                        response
+                    }
+                    None => make_response(
+                        context.default_response.status,
+                        context.default_response.headers.as_slice(),
+                        context.default_response.body.to_owned(),
+                    ),
+                };
+                state.requests.push(recorded_request.clone());
+                (response, std::time::Duration::default())
+            }
+        };
+
+        log::debug!("{:?}", response);
+        future::Either::B(
+            tokio::timer::Delay::new(std::time::Instant::now() + delay).then(move |_| Ok(response)),
+        )
+    }))
 }
 
 fn main() {
@@ -224,28 +1571,27 @@ fn main() {
     log::info!("Initialized with {:?}", config);
 
     let socket_address = config.socket_address;
-    let response_status = config.response_status;
-    let response_headers = config.response_headers;
-    let response_body = config.body;
-
-    let new_service = move || {
-        let response_status_clone = response_status.clone();
-        let response_headers_clone = response_headers.clone();
-        let response_body_clone = response_body.clone();
-        hyper::service::service_fn_ok(move |request| {
-            respond(
-                request,
-                &response_status_clone,
-                response_headers_clone.as_slice(),
-                response_body_clone.as_str(),
-            )
-        })
-    };
+    let context = std::sync::Arc::new(ServerContext::new(
+        config.routes,
+        DefaultResponse::new(config.response_status, config.response_headers, config.body),
+        config.emulators,
+        config.emulator_token,
+        config.emulator_secret,
+        SharedState::default(),
+    ));
+
+    let new_service =
+        hyper::service::make_service_fn(move |socket: &hyper::server::conn::AddrStream| {
+            let remote_addr = socket.remote_addr();
+            let context = context.clone();
+            hyper::service::service_fn(move |request| {
+                respond(request, remote_addr, context.clone())
+            })
+        });
 
     let server = hyper::Server::bind(&socket_address).serve(new_service);
     log::info!("Listening on http://{}", socket_address);
 
-    use hyper::rt::Future;
     hyper::rt::run(server.map_err(|error| {
         log::error!("{:?}", error);
     }));